@@ -1,15 +1,34 @@
 use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::backup;
+use crate::app::security;
+use crate::app::users;
 
 pub fn render_admin(app: &mut PharmacyApp, ui: &mut egui::Ui) {
     ui.heading("Admin Panel");
-    ui.label("Manage administrative settings here.");
+    ui.label("Manage who can use the app and what their role allows.");
     ui.separator();
-    ui.horizontal(|ui| {
-        ui.label("Application Updates");
-        if ui.button("Check for Updates").clicked() {
-            app.check_for_update();
-        }
-    });
 
-    app.render_update_status_detailed(ui);
+    egui::ScrollArea::vertical()
+        .id_salt("admin_scroll")
+        .show(ui, |ui| {
+            users::render_users(app, ui);
+
+            ui.separator();
+            egui::CollapsingHeader::new("Application Updates").show(ui, |ui| {
+                if ui.button("Check for Updates").clicked() {
+                    app.check_for_update();
+                }
+                app.render_update_status_detailed(ui);
+            });
+            egui::CollapsingHeader::new("Backups").show(ui, |ui| {
+                backup::render_backups(app, ui);
+            });
+            egui::CollapsingHeader::new("Encryption Keys").show(ui, |ui| {
+                security::render_key_rotation(app, ui);
+            });
+            egui::CollapsingHeader::new("Audit Log").show(ui, |ui| {
+                audit::render_audit_log(app, ui);
+            });
+        });
 }
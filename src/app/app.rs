@@ -1,28 +1,77 @@
 use self::employee::Employee;
+use crate::app::audit;
+use crate::app::audit::AuditForm;
+use crate::app::backup;
+use crate::app::backup::BackupForm;
+use crate::app::cache::DataCache;
+use crate::app::companies;
+use crate::app::companies::render_companies;
+use crate::app::companies::CompanyForm;
+use crate::app::companies::ProfileForm;
+use crate::app::continuing_education::render_continuing_education;
+use crate::app::continuing_education::CeForm;
 use crate::app::database;
+use crate::app::deductions::DeductionForm;
+use crate::app::earnings::EarningLine;
 use crate::app::employee::render_employees;
+use crate::app::employee::PaySetupForm;
+use crate::app::employee::PayType;
+use crate::app::garnishments::render_garnishments;
+use crate::app::garnishments::GarnishmentForm;
 use crate::app::home::render_home;
+use crate::app::import::{HistoryImportForm, ImportForm};
+use crate::app::jobs;
+use crate::app::jobs::Job;
+use crate::app::ledger::render_ledger;
+use crate::app::ledger::LedgerForm;
+use crate::app::licenses::render_licenses;
+use crate::app::licenses::LicenseForm;
+use crate::app::locations::render_locations;
+use crate::app::locations::LocationForm;
 use crate::app::settings::render_settings;
 use crate::app::settings::UiSettings;
 use crate::app::update::perform_update;
+use crate::app::users;
+use crate::app::users::{LoginForm, User, UserForm};
 use chrono::NaiveDate;
 use poll_promise::Promise;
 
 pub use super::employee;
 use crate::app::admin::render_admin;
 use crate::app::payroll::render_payroll;
+use crate::app::payroll::OffCycleReason;
+use crate::app::pto::render_time_off;
+use crate::app::pto::PtoForm;
+use crate::app::retirement::render_retirement;
+use crate::app::retirement::RetirementForm;
+use crate::app::retro::RetroForm;
+use crate::app::security::PassphraseForm;
+use crate::app::session;
+use crate::app::session::SessionLock;
+use crate::app::ssn::SsnForm;
 use crate::app::update::check_for_updates_blocking;
 use chrono::Datelike;
 use eframe::egui;
 use rusqlite::Connection;
 
-#[derive(PartialEq)]
+pub type UpdateCheckResult =
+    Result<(Option<String>, String), Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ActivePanel {
     Home = 0,
     Admin = 1,
     Payroll = 2,
     Employees = 3,
     Settings = 4,
+    Licenses = 5,
+    ContinuingEducation = 6,
+    TimeOff = 7,
+    Retirement = 8,
+    Garnishments = 9,
+    Locations = 10,
+    Companies = 11,
+    Ledger = 12,
 }
 
 #[allow(dead_code)]
@@ -45,15 +94,25 @@ pub struct PharmacyApp {
     pub filing_status: String,
     pub dependents: String,
 
-    pub hours_worked: f32,
+    pub earning_lines: Vec<EarningLine>,
+    pub earning_lines_employee_id: Option<i32>,
+    pub expanded_payroll_id: Option<i64>,
+    pub pto_policy_id: Option<i64>,
     pub withholding: f32,
-    pub roth_ira: f32,
     pub social_security: f32,
     pub selected_friday: String,
+    pub off_cycle: bool,
+    pub off_cycle_date: String,
+    pub off_cycle_reason: OffCycleReason,
+    pub payroll_status: String,
     pub pay_rate: String,
+    pub pay_type: PayType,
+    pub flsa_exempt: bool,
+    pub hire_date: String,
+    pub ssn: String,
+    pub pay_setup_form: PaySetupForm,
 
-    pub update_check:
-        Option<Promise<Result<(Option<String>, String), Box<dyn std::error::Error + Send + Sync>>>>,
+    pub update_check: Option<Promise<UpdateCheckResult>>,
     pub update_available: Option<String>,
     pub release_notes: Option<String>,
     pub update_error: Option<String>,
@@ -64,22 +123,39 @@ pub struct PharmacyApp {
     pub net: f32,
 
     pub ui_settings: UiSettings,
-}
-
-impl Default for PharmacyApp {
-    fn default() -> Self {
-        Self::new()
-    }
+    pub license_form: LicenseForm,
+    pub ce_form: CeForm,
+    pub pto_form: PtoForm,
+    pub deduction_form: DeductionForm,
+    pub retirement_form: RetirementForm,
+    pub garnishment_form: GarnishmentForm,
+    pub retro_form: RetroForm,
+    pub location_filter: Option<i64>,
+    pub location_form: LocationForm,
+    pub company_id: Option<i64>,
+    pub company_form: CompanyForm,
+    pub profile_form: ProfileForm,
+    pub ledger_form: LedgerForm,
+    pub backup_form: BackupForm,
+    pub passphrase_form: PassphraseForm,
+    /// Employee whose sensitive fields are shown unmasked.
+    pub revealed_employee_id: Option<i32>,
+    pub ssn_form: SsnForm,
+    /// Signed-in user; the panels stay hidden behind the login screen until set.
+    pub current_user: Option<User>,
+    pub login_form: LoginForm,
+    pub user_form: UserForm,
+    pub import_form: ImportForm,
+    pub history_import_form: HistoryImportForm,
+    pub audit_form: AuditForm,
+    pub session: SessionLock,
+    pub cache: DataCache,
+    pub jobs: Vec<Job>,
 }
 
 impl PharmacyApp {
-    pub fn new() -> Self {
-        let db_path = database::get_db_path();
-        let conn = rusqlite::Connection::open(&db_path).unwrap_or_else(|e| {
-            eprintln!("Database error: {}", e);
-            std::process::exit(1);
-        });
-
+    /// Takes the connection the unlock screen opened with the admin passphrase.
+    pub fn new(conn: Connection) -> Self {
         database::initialize_tables(&conn).expect("Failed to initialize tables");
 
         let fridays = get_fridays_of_year();
@@ -106,12 +182,23 @@ impl PharmacyApp {
             filing_status: String::new(),
             dependents: String::new(),
             pay_rate: String::new(),
-
-            hours_worked: 0.0,
+            pay_type: PayType::Hourly,
+            flsa_exempt: false,
+            hire_date: String::new(),
+            ssn: String::new(),
+            pay_setup_form: PaySetupForm::default(),
+
+            earning_lines: Vec::new(),
+            earning_lines_employee_id: None,
+            expanded_payroll_id: None,
+            pto_policy_id: None,
             withholding: 0.0,
-            roth_ira: 0.0,
             social_security: 0.0,
             selected_friday,
+            off_cycle: false,
+            off_cycle_date: String::new(),
+            off_cycle_reason: OffCycleReason::FinalPay,
+            payroll_status: String::new(),
             net: 0.0,
             gross: 0.0,
             update_check: None,
@@ -123,12 +210,118 @@ impl PharmacyApp {
             release_notes: None,
 
             ui_settings,
+            license_form: LicenseForm::default(),
+            ce_form: CeForm::default(),
+            pto_form: PtoForm::default(),
+            deduction_form: DeductionForm::default(),
+            retirement_form: RetirementForm::default(),
+            garnishment_form: GarnishmentForm::default(),
+            retro_form: RetroForm::default(),
+            location_filter: None,
+            location_form: LocationForm::default(),
+            company_id: None,
+            company_form: CompanyForm::default(),
+            profile_form: ProfileForm::default(),
+            ledger_form: LedgerForm::default(),
+            backup_form: BackupForm {
+                settings: backup::BackupSettings::load(),
+                ..Default::default()
+            },
+            passphrase_form: PassphraseForm::default(),
+            revealed_employee_id: None,
+            ssn_form: SsnForm::default(),
+            current_user: None,
+            login_form: LoginForm::default(),
+            user_form: UserForm::default(),
+            import_form: ImportForm::default(),
+            history_import_form: HistoryImportForm::default(),
+            audit_form: AuditForm::default(),
+            session: SessionLock::default(),
+            cache: DataCache::default(),
+            jobs: Vec::new(),
         };
 
-        app.employees = database::get_all_employees(&app.conn).expect("Failed to get employees");
+        let first_company = companies::get_companies(&app.conn)
+            .expect("Failed to get companies")
+            .first()
+            .map(|company| company.id);
+        companies::select_company(&mut app, first_company);
         app
     }
 
+    /// Ends the session for Sign Out and Switch User. Everything the previous user had open in
+    /// the panels is cleared so the next user starts from empty forms; the connection,
+    /// settings, company and running jobs are kept.
+    pub fn sign_out(&mut self) {
+        audit::set_actor("");
+        self.current_user = None;
+        self.session = SessionLock::default();
+        self.active_panel = ActivePanel::Home;
+
+        self.admin_text.clear();
+        self.employee_name.clear();
+        self.employee_position.clear();
+        self.search_name.clear();
+        self.search_result = None;
+        self.search_status.clear();
+        self.selected_employee = None;
+        self.payroll_entries = [0.0; 7];
+        self.address.clear();
+        self.city.clear();
+        self.state.clear();
+        self.phone.clear();
+        self.filing_status.clear();
+        self.dependents.clear();
+        self.pay_rate.clear();
+        self.pay_type = PayType::Hourly;
+        self.flsa_exempt = false;
+        self.hire_date.clear();
+        self.ssn.clear();
+        self.pay_setup_form = PaySetupForm::default();
+
+        self.earning_lines.clear();
+        self.earning_lines_employee_id = None;
+        self.expanded_payroll_id = None;
+        self.pto_policy_id = None;
+        self.withholding = 0.0;
+        self.social_security = 0.0;
+        self.selected_friday = get_fridays_of_year().first().cloned().unwrap_or_default();
+        self.off_cycle = false;
+        self.off_cycle_date.clear();
+        self.off_cycle_reason = OffCycleReason::FinalPay;
+        self.payroll_status.clear();
+        self.net = 0.0;
+        self.gross = 0.0;
+        self.selected_employee_id = None;
+        self.show_add_employee_popup = false;
+        self.selected_employee_index = 0;
+
+        self.license_form = LicenseForm::default();
+        self.ce_form = CeForm::default();
+        self.pto_form = PtoForm::default();
+        self.deduction_form = DeductionForm::default();
+        self.retirement_form = RetirementForm::default();
+        self.garnishment_form = GarnishmentForm::default();
+        self.retro_form = RetroForm::default();
+        self.location_filter = None;
+        self.location_form = LocationForm::default();
+        self.company_form = CompanyForm::default();
+        self.profile_form = ProfileForm::default();
+        self.ledger_form = LedgerForm::default();
+        self.backup_form = BackupForm {
+            settings: self.backup_form.settings.clone(),
+            ..Default::default()
+        };
+        self.passphrase_form = PassphraseForm::default();
+        self.revealed_employee_id = None;
+        self.ssn_form = SsnForm::default();
+        self.login_form = LoginForm::default();
+        self.user_form = UserForm::default();
+        self.import_form = ImportForm::default();
+        self.history_import_form = HistoryImportForm::default();
+        self.audit_form = AuditForm::default();
+    }
+
     pub fn apply_text_style(&self, ctx: &egui::Context) {
         let mut style = (*ctx.style()).clone();
 
@@ -236,7 +429,7 @@ impl PharmacyApp {
     pub fn refresh_available_fridays(&mut self) {
         if self.selected_employee_index < self.employees.len() {
             let employee_id = self.employees[self.selected_employee_index].id;
-            let available_fridays = get_available_fridays(&self.conn, employee_id);
+            let available_fridays = self.cache.available_fridays(&self.conn, employee_id);
             if !available_fridays.is_empty() {
                 self.selected_friday = available_fridays[0].clone();
             }
@@ -244,22 +437,20 @@ impl PharmacyApp {
     }
 }
 
-pub fn get_available_fridays(conn: &Connection, employee_id: i32) -> Vec<String> {
-    let all_fridays = get_fridays_of_year();
-    let used_dates = match database::get_payroll_dates_for_employee(conn, employee_id) {
-        Ok(dates) => dates,
-        Err(_) => Vec::new(),
-    };
-
-    all_fridays
+/// This year's Fridays that aren't in `used_dates`.
+pub fn unused_fridays(used_dates: &[String]) -> Vec<String> {
+    get_fridays_of_year()
         .into_iter()
         .filter(|date| !used_dates.contains(date))
         .collect()
 }
 
 pub fn get_fridays_of_year() -> Vec<String> {
-    let today = chrono::Local::now().date_naive();
-    let year = today.year();
+    get_fridays_for_year(chrono::Local::now().year())
+}
+
+/// The weekly pay calendar: every Friday in `year`.
+pub fn get_fridays_for_year(year: i32) -> Vec<String> {
     let mut fridays = Vec::new();
 
     for month in 1..=12 {
@@ -277,23 +468,33 @@ pub fn get_fridays_of_year() -> Vec<String> {
 impl eframe::App for PharmacyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.apply_text_style(ctx);
+        backup::run_scheduled(self);
+        session::track_activity(self, ctx);
+        let signed_in = self.current_user.is_some() && !self.session.locked;
         // Create the top bar with buttons
         egui::TopBottomPanel::top("top_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                if ui.button("Admin").clicked() {
-                    self.active_panel = ActivePanel::Admin;
-                }
-                if ui.button("Payroll").clicked() {
-                    self.active_panel = ActivePanel::Payroll;
-                }
-                if ui.button("Employees").clicked() {
-                    self.active_panel = ActivePanel::Employees;
-                }
-                if ui.button("Home").clicked() {
-                    self.active_panel = ActivePanel::Home;
-                }
-                if ui.button("⚙ Settings").clicked() {
-                    self.active_panel = ActivePanel::Settings;
+                if signed_in {
+                    for (label, panel) in [
+                        ("Admin", ActivePanel::Admin),
+                        ("Payroll", ActivePanel::Payroll),
+                        ("Employees", ActivePanel::Employees),
+                        ("Home", ActivePanel::Home),
+                        ("Licenses", ActivePanel::Licenses),
+                        ("CE Credits", ActivePanel::ContinuingEducation),
+                        ("Time Off", ActivePanel::TimeOff),
+                        ("Retirement", ActivePanel::Retirement),
+                        ("Garnishments", ActivePanel::Garnishments),
+                        ("Locations", ActivePanel::Locations),
+                        ("Companies", ActivePanel::Companies),
+                        ("Ledger", ActivePanel::Ledger),
+                        ("⚙ Settings", ActivePanel::Settings),
+                    ] {
+                        let allowed = users::view_permission(panel).is_none_or(|p| self.can(p));
+                        if allowed && ui.button(label).clicked() {
+                            self.active_panel = panel;
+                        }
+                    }
                 }
                 if ui.button("Close").clicked() {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
@@ -301,17 +502,59 @@ impl eframe::App for PharmacyApp {
             });
 
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if let Some(user) = self.current_user.as_ref().filter(|_| signed_in) {
+                    if ui.button("Sign Out").clicked() {
+                        self.sign_out();
+                        return;
+                    }
+                    ui.label(format!("{} ({})", user.username, user.role.label()));
+                }
                 self.render_update_status_brief(ui);
+                if signed_in {
+                    companies::render_company_switcher(self, ui);
+                }
             });
         });
 
+        jobs::render_jobs(self, ctx);
+
         // Dynamically change the entire CentralPanel based on the selected button
-        egui::CentralPanel::default().show(ctx, |ui| match self.active_panel {
-            ActivePanel::Home => render_home(ui),
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.current_user.is_none() {
+                users::render_login(self, ui);
+                return;
+            }
+            if self.session.locked {
+                session::render_lock_screen(self, ui);
+                return;
+            }
+            if let Some(permission) = users::view_permission(self.active_panel) {
+                if !self.can(permission) {
+                    ui.label("Your role doesn't have access to this panel.");
+                    return;
+                }
+            }
+            self.render_active_panel(ui);
+        });
+    }
+}
+
+impl PharmacyApp {
+    fn render_active_panel(&mut self, ui: &mut egui::Ui) {
+        match self.active_panel {
+            ActivePanel::Home => render_home(self, ui),
             ActivePanel::Admin => render_admin(self, ui),
             ActivePanel::Payroll => render_payroll(self, ui),
             ActivePanel::Employees => render_employees(self, ui),
             ActivePanel::Settings => render_settings(self, ui),
-        });
+            ActivePanel::Licenses => render_licenses(self, ui),
+            ActivePanel::ContinuingEducation => render_continuing_education(self, ui),
+            ActivePanel::TimeOff => render_time_off(self, ui),
+            ActivePanel::Retirement => render_retirement(self, ui),
+            ActivePanel::Garnishments => render_garnishments(self, ui),
+            ActivePanel::Locations => render_locations(self, ui),
+            ActivePanel::Companies => render_companies(self, ui),
+            ActivePanel::Ledger => render_ledger(self, ui),
+        }
     }
 }
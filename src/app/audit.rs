@@ -0,0 +1,453 @@
+use crate::app::app::PharmacyApp;
+use crate::app::employee::Employee;
+use crate::app::retirement::get_exports_dir;
+use crate::app::security;
+use crate::app::ssn;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Who changes are attributed to; set when someone signs in or out.
+static ACTOR: RwLock<String> = RwLock::new(String::new());
+
+pub fn set_actor(username: &str) {
+    *ACTOR.write().expect("Audit actor lock poisoned") = username.to_string();
+}
+
+fn current_actor() -> String {
+    let actor = ACTOR.read().expect("Audit actor lock poisoned");
+    if actor.is_empty() {
+        "system".to_string()
+    } else {
+        actor.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 3] = [
+        AuditAction::Insert,
+        AuditAction::Update,
+        AuditAction::Delete,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Insert => "insert",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "insert" => AuditAction::Insert,
+            "delete" => AuditAction::Delete,
+            _ => AuditAction::Update,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuditAction::Insert => "Insert",
+            AuditAction::Update => "Update",
+            AuditAction::Delete => "Delete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub occurred_at: String,
+    pub actor: String,
+    pub entity: String,
+    pub entity_id: i64,
+    pub action: AuditAction,
+    pub before_json: String,
+    pub after_json: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// SHA-256 over the previous entry's hash and every field of this one, so changing or
+    /// removing any earlier entry breaks every hash after it.
+    pub fn compute_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            self.prev_hash.as_str(),
+            &self.occurred_at,
+            &self.actor,
+            &self.entity,
+            &self.entity_id.to_string(),
+            self.action.as_str(),
+            &self.before_json,
+            &self.after_json,
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0u8]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// The JSON stored for a value; `None` is stored as empty.
+pub fn to_json<T: Serialize>(value: Option<&T>) -> String {
+    value
+        .and_then(|value| serde_json::to_string(value).ok())
+        .unwrap_or_default()
+}
+
+/// An employee with the sealed fields masked, so the log never holds them in plain text.
+pub fn redacted_employee(employee: &Employee) -> Value {
+    let ssn = if employee.ssn.is_empty() {
+        String::new()
+    } else {
+        ssn::mask_ssn(&employee.ssn)
+    };
+    serde_json::to_value(Employee {
+        address: security::mask(&employee.address),
+        phone: security::mask(&employee.phone),
+        ssn,
+        ..employee.clone()
+    })
+    .unwrap_or_default()
+}
+
+/// Appends an entry for a change that was just made.
+pub fn record(
+    conn: &Connection,
+    entity: &str,
+    entity_id: i64,
+    action: AuditAction,
+    before: Option<&Value>,
+    after: Option<&Value>,
+) -> Result<(), rusqlite::Error> {
+    let prev_hash: String = conn
+        .query_row(
+            "SELECT hash FROM audit_log ORDER BY id DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap_or_default();
+    let mut entry = AuditEntry {
+        id: 0,
+        occurred_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        actor: current_actor(),
+        entity: entity.to_string(),
+        entity_id,
+        action,
+        before_json: to_json(before),
+        after_json: to_json(after),
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash();
+    conn.execute(
+        "INSERT INTO audit_log (occurred_at, actor, entity, entity_id, action, before_json,
+            after_json, prev_hash, hash)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            &entry.occurred_at,
+            &entry.actor,
+            &entry.entity,
+            entry.entity_id,
+            entry.action.as_str(),
+            &entry.before_json,
+            &entry.after_json,
+            &entry.prev_hash,
+            &entry.hash
+        ],
+    )?;
+    Ok(())
+}
+
+/// One row of `table` as a JSON object of its columns, or `None` if there is no such row.
+/// Blobs are logged by size only.
+pub fn snapshot(conn: &Connection, table: &str, id: i64) -> Result<Option<Value>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {} WHERE id = ?1", table))?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query(params![id])?;
+    let Some(row) = rows.next()? else {
+        return Ok(None);
+    };
+    let mut object = serde_json::Map::new();
+    for (i, column) in columns.into_iter().enumerate() {
+        let value = match row.get_ref(i)? {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(n) => Value::from(n),
+            ValueRef::Real(n) => Value::from(n),
+            ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text).into_owned()),
+            ValueRef::Blob(blob) => Value::from(format!("<{} bytes>", blob.len())),
+        };
+        object.insert(column, value);
+    }
+    Ok(Some(Value::Object(object)))
+}
+
+/// Appends an entry for a change to one row of `table`, taking the row as it now stands
+/// as the after state; `before` is a [`snapshot`] taken ahead of the change.
+pub fn record_row(
+    conn: &Connection,
+    entity: &str,
+    table: &str,
+    id: i64,
+    action: AuditAction,
+    before: Option<&Value>,
+) -> Result<(), rusqlite::Error> {
+    let after = match action {
+        AuditAction::Delete => None,
+        _ => snapshot(conn, table, id)?,
+    };
+    record(conn, entity, id, action, before, after.as_ref())
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub entity: String,
+    pub actor: String,
+    pub action: Option<AuditAction>,
+    /// `YYYY-MM-DD`; empty for no bound.
+    pub from: String,
+    pub to: String,
+}
+
+/// Entries matching the filter, oldest first.
+pub fn get_entries(
+    conn: &Connection,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEntry>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, occurred_at, actor, entity, entity_id, action, before_json, after_json,
+            prev_hash, hash
+        FROM audit_log
+        WHERE (?1 = '' OR entity = ?1)
+        AND (?2 = '' OR actor LIKE '%' || ?2 || '%')
+        AND (?3 IS NULL OR action = ?3)
+        AND (?4 = '' OR substr(occurred_at, 1, 10) >= ?4)
+        AND (?5 = '' OR substr(occurred_at, 1, 10) <= ?5)
+        ORDER BY id",
+    )?;
+    let entries = stmt
+        .query_map(
+            params![
+                &filter.entity,
+                filter.actor.trim(),
+                filter.action.map(|action| action.as_str()),
+                filter.from.trim(),
+                filter.to.trim()
+            ],
+            |row| {
+                Ok(AuditEntry {
+                    id: row.get(0)?,
+                    occurred_at: row.get(1)?,
+                    actor: row.get(2)?,
+                    entity: row.get(3)?,
+                    entity_id: row.get(4)?,
+                    action: AuditAction::parse(&row.get::<_, String>(5)?),
+                    before_json: row.get(6)?,
+                    after_json: row.get(7)?,
+                    prev_hash: row.get(8)?,
+                    hash: row.get(9)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+pub fn get_entities(conn: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT DISTINCT entity FROM audit_log ORDER BY entity")?;
+    let entities = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entities)
+}
+
+/// Walks the whole chain and returns the id of the first entry that doesn't match, if any.
+pub fn verify_chain(conn: &Connection) -> Result<Option<i64>, rusqlite::Error> {
+    let mut prev_hash = String::new();
+    for entry in get_entries(conn, &AuditFilter::default())? {
+        if entry.prev_hash != prev_hash || entry.compute_hash() != entry.hash {
+            return Ok(Some(entry.id));
+        }
+        prev_hash = entry.hash;
+    }
+    Ok(None)
+}
+
+pub fn audit_csv(entries: &[AuditEntry]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "ID",
+        "Time",
+        "Actor",
+        "Entity",
+        "Entity ID",
+        "Action",
+        "Before",
+        "After",
+        "Hash",
+    ])?;
+    for entry in entries {
+        writer.write_record([
+            entry.id.to_string(),
+            entry.occurred_at.clone(),
+            entry.actor.clone(),
+            entry.entity.clone(),
+            entry.entity_id.to_string(),
+            entry.action.label().to_string(),
+            entry.before_json.clone(),
+            entry.after_json.clone(),
+            entry.hash.clone(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+pub fn export_audit_log(
+    conn: &Connection,
+    filter: &AuditFilter,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let entries = get_entries(conn, filter)?;
+    if entries.is_empty() {
+        return Err("No audit entries match the filters".into());
+    }
+    let exports_dir = get_exports_dir();
+    std::fs::create_dir_all(&exports_dir)?;
+    let path = exports_dir.join(format!(
+        "audit_log_{}.csv",
+        chrono::Local::now().format("%Y%m%d_%H%M%S")
+    ));
+    std::fs::write(&path, audit_csv(&entries)?)?;
+    Ok(path)
+}
+
+#[derive(Default)]
+pub struct AuditForm {
+    pub filter: AuditFilter,
+    pub status: String,
+}
+
+pub fn render_audit_log(app: &mut PharmacyApp, ui: &mut Ui) {
+    let form = &mut app.audit_form;
+    let entities = app
+        .cache
+        .query(&app.conn, "audit_entities".to_string(), get_entities)
+        .unwrap_or_default();
+    ui.horizontal(|ui| {
+        ui.label("Entity");
+        egui::ComboBox::from_id_salt("audit_entity")
+            .selected_text(if form.filter.entity.is_empty() {
+                "All"
+            } else {
+                &form.filter.entity
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut form.filter.entity, String::new(), "All");
+                for entity in &entities {
+                    ui.selectable_value(&mut form.filter.entity, entity.clone(), entity);
+                }
+            });
+        ui.label("Action");
+        egui::ComboBox::from_id_salt("audit_action")
+            .selected_text(form.filter.action.map_or("All", |action| action.label()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut form.filter.action, None, "All");
+                for action in AuditAction::ALL {
+                    ui.selectable_value(&mut form.filter.action, Some(action), action.label());
+                }
+            });
+        ui.label("Actor");
+        ui.add(egui::TextEdit::singleline(&mut form.filter.actor).desired_width(100.0));
+        ui.label("From");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.filter.from)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(90.0),
+        );
+        ui.label("To");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.filter.to)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(90.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Verify Chain").clicked() {
+            form.status = match verify_chain(&app.conn) {
+                Ok(None) => "Audit log intact".to_string(),
+                Ok(Some(id)) => format!("Audit log has been altered at entry {}", id),
+                Err(e) => format!("Error verifying audit log: {}", e),
+            };
+        }
+        if ui.button("Export CSV").clicked() {
+            form.status = match export_audit_log(&app.conn, &form.filter) {
+                Ok(path) => format!("Exported to {}", path.display()),
+                Err(e) => format!("Export failed: {}", e),
+            };
+        }
+    });
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+
+    let key = format!("audit_entries:{:?}", form.filter);
+    let entries = match app
+        .cache
+        .query(&app.conn, key, |conn| get_entries(conn, &form.filter))
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            ui.label(format!("Error loading audit log: {}", e));
+            return;
+        }
+    };
+    if entries.is_empty() {
+        ui.label("No audit entries");
+        return;
+    }
+    egui::Grid::new("audit_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Time");
+            ui.strong("Actor");
+            ui.strong("Entity");
+            ui.strong("ID");
+            ui.strong("Action");
+            ui.strong("Before");
+            ui.strong("After");
+            ui.end_row();
+            // Newest first on screen.
+            for entry in entries.iter().rev() {
+                ui.label(&entry.occurred_at);
+                ui.label(&entry.actor);
+                ui.label(&entry.entity);
+                ui.label(entry.entity_id.to_string());
+                ui.label(entry.action.label());
+                for json in [&entry.before_json, &entry.after_json] {
+                    let short: String = json.chars().take(60).collect();
+                    ui.label(short).on_hover_text(json);
+                }
+                ui.end_row();
+            }
+        });
+}
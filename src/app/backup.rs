@@ -0,0 +1,406 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::companies;
+use crate::app::jobs::{Job, Progress};
+use crate::app::security;
+use crate::app::settings::get_config_dir;
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BACKUP_JOB: &str = "Backup";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSettings {
+    pub enabled: bool,
+    /// Where backups go, such as a USB drive or network share.
+    pub directory: String,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub keep_monthly: usize,
+    /// `YYYY-MM-DD` of the last scheduled backup.
+    pub last_backup: String,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: String::new(),
+            keep_daily: 7,
+            keep_weekly: 4,
+            keep_monthly: 12,
+            last_backup: String::new(),
+        }
+    }
+}
+
+impl BackupSettings {
+    /// Kept next to `settings.json` rather than in the database, so restoring a backup
+    /// doesn't change where backups go.
+    pub fn load() -> Self {
+        let path = get_config_dir().join("backup.json");
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config_dir = get_config_dir();
+        fs::create_dir_all(&config_dir)?;
+        fs::write(
+            config_dir.join("backup.json"),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub date: NaiveDate,
+    pub size: u64,
+}
+
+fn backup_name(date: NaiveDate) -> String {
+    format!("payroll_{}.db.enc", date.format("%Y-%m-%d"))
+}
+
+fn parse_backup_name(name: &str) -> Option<NaiveDate> {
+    let date = name.strip_prefix("payroll_")?.strip_suffix(".db.enc")?;
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// Backups in `dir`, newest first. Other files in the directory are ignored.
+pub fn list_backups(dir: &Path) -> std::io::Result<Vec<BackupFile>> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(date) = parse_backup_name(&name) {
+            backups.push(BackupFile {
+                path: entry.path(),
+                date,
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.date));
+    Ok(backups)
+}
+
+/// Exports the live database into the day's backup file in `dir`, a SQLCipher database keyed
+/// with the backup passphrase. There is no plain-text option. The export goes to a partial file that is renamed into place, so a
+/// failed backup never replaces a good one from earlier the same day. This is the slow part
+/// of a backup, so it runs on the worker thread with its own connection.
+pub fn write_backup(
+    conn: &Connection,
+    dir: &Path,
+    date: NaiveDate,
+    passphrase: &str,
+    progress: &Progress,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if passphrase.is_empty() {
+        return Err("Enter the backup passphrase".into());
+    }
+    fs::create_dir_all(dir)?;
+    let name = backup_name(date);
+    let partial = dir.join(format!("{}.partial", name));
+    progress.set(0.3, "Exporting database");
+    if let Err(e) = security::export_encrypted(conn, &partial, passphrase) {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    progress.set(0.8, "Writing backup file");
+    let path = dir.join(name);
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Hands the backup and the rotation to a worker, which opens its own keyed connection.
+fn start_backup(app: &mut PharmacyApp, dir: PathBuf, date: NaiveDate) {
+    let settings = app.backup_form.settings.clone();
+    let passphrase = app.backup_form.passphrase.clone();
+    app.jobs.push(Job::spawn(BACKUP_JOB, move |progress| {
+        progress.set(0.1, "Opening database");
+        let path = security::open_worker_connection()
+            .and_then(|conn| write_backup(&conn, &dir, date, &passphrase, progress))
+            .map_err(|e| format!("Backup failed: {}", e))?;
+        progress.set(0.9, "Removing old backups");
+        let rotated = rotate(&dir, &settings).unwrap_or(0);
+        Ok(format!(
+            "Backed up to {} ({} old backups removed)",
+            path.display(),
+            rotated
+        ))
+    }));
+}
+
+/// Dates to keep under grandfather-father-son rotation: the newest `keep_daily` backups,
+/// the newest backup in each of the last `keep_weekly` weeks, and the newest in each of the
+/// last `keep_monthly` months.
+pub fn backups_to_keep(dates: &[NaiveDate], settings: &BackupSettings) -> HashSet<NaiveDate> {
+    let mut dates = dates.to_vec();
+    dates.sort_by_key(|date| std::cmp::Reverse(*date));
+    dates.dedup();
+
+    let mut keep: HashSet<NaiveDate> = dates.iter().take(settings.keep_daily).copied().collect();
+    let mut newest_per = |period: &dyn Fn(&NaiveDate) -> (i32, u32), count: usize| {
+        let mut seen = Vec::new();
+        for date in &dates {
+            let key = period(date);
+            if !seen.contains(&key) {
+                if seen.len() == count {
+                    break;
+                }
+                seen.push(key);
+                keep.insert(*date);
+            }
+        }
+    };
+    newest_per(
+        &|date| (date.iso_week().year(), date.iso_week().week()),
+        settings.keep_weekly,
+    );
+    newest_per(&|date| (date.year(), date.month()), settings.keep_monthly);
+    keep
+}
+
+/// Deletes backups that fall outside the retention policy and returns how many were removed.
+pub fn rotate(dir: &Path, settings: &BackupSettings) -> Result<usize, Box<dyn std::error::Error>> {
+    let backups = list_backups(dir)?;
+    let dates: Vec<NaiveDate> = backups.iter().map(|backup| backup.date).collect();
+    let keep = backups_to_keep(&dates, settings);
+    let mut removed = 0;
+    for backup in backups.iter().filter(|b| !keep.contains(&b.date)) {
+        fs::remove_file(&backup.path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Opens the encrypted backup read-only and checks it with `PRAGMA integrity_check`.
+/// Nothing is decrypted to disk.
+pub fn verify_backup(
+    backup: &BackupFile,
+    passphrase: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if passphrase.is_empty() {
+        return Err("Enter the backup passphrase".into());
+    }
+    let conn = Connection::open_with_flags(&backup.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.pragma_update(None, "key", passphrase)?;
+    let result: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|_| "Wrong passphrase or damaged backup")?;
+    if result != "ok" {
+        return Err(format!("Integrity check failed: {}", result).into());
+    }
+    let has_employees: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'employees'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_employees {
+        return Err("Not a payroll database".into());
+    }
+    Ok(())
+}
+
+/// Replaces the live database with a verified backup.
+pub fn restore_backup(
+    conn: &Connection,
+    backup: &BackupFile,
+    passphrase: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    verify_backup(backup, passphrase)?;
+    security::import_database(conn, &backup.path, passphrase)?;
+    // The live audit log survives the restore; note the restore at the end of it.
+    let after = serde_json::json!({ "restored_backup": backup.date.to_string() });
+    audit::record(conn, "database", 0, AuditAction::Update, None, Some(&after))?;
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct BackupForm {
+    pub settings: BackupSettings,
+    /// Only kept in memory; scheduled backups wait until it has been entered.
+    pub passphrase: String,
+    pub confirm_restore: Option<BackupFile>,
+    pub status: String,
+}
+
+/// Takes the day's backup the first time it runs on a new day.
+pub fn run_scheduled(app: &mut PharmacyApp) {
+    if app.job_running(BACKUP_JOB) {
+        return;
+    }
+    let form = &mut app.backup_form;
+    let settings = &mut form.settings;
+    let today = chrono::Local::now().date_naive();
+    let today_str = today.format("%Y-%m-%d").to_string();
+    if !settings.enabled
+        || settings.directory.trim().is_empty()
+        || settings.last_backup == today_str
+        || form.passphrase.is_empty()
+    {
+        return;
+    }
+    // Mark the day as done even on failure so a missing drive isn't retried every frame.
+    settings.last_backup = today_str;
+    if let Err(e) = settings.save() {
+        eprintln!("Error saving backup settings: {}", e);
+    }
+    let dir = PathBuf::from(settings.directory.trim());
+    start_backup(app, dir, today);
+    app.backup_form.status = "Scheduled backup started".to_string();
+}
+
+pub fn render_backups(app: &mut PharmacyApp, ui: &mut egui::Ui) {
+    let backup_running = app.job_running(BACKUP_JOB);
+    let form = &mut app.backup_form;
+    let settings = &mut form.settings;
+    let mut changed = false;
+    let mut restored = false;
+    let mut back_up_now = false;
+    ui.horizontal(|ui| {
+        changed |= ui
+            .checkbox(&mut settings.enabled, "Back up daily")
+            .changed();
+        ui.label("Directory");
+        changed |= ui
+            .add(
+                egui::TextEdit::singleline(&mut settings.directory)
+                    .hint_text("USB drive or network share")
+                    .desired_width(300.0),
+            )
+            .lost_focus();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Keep daily");
+        changed |= ui
+            .add(egui::DragValue::new(&mut settings.keep_daily).range(1..=60))
+            .changed();
+        ui.label("weekly");
+        changed |= ui
+            .add(egui::DragValue::new(&mut settings.keep_weekly).range(0..=52))
+            .changed();
+        ui.label("monthly");
+        changed |= ui
+            .add(egui::DragValue::new(&mut settings.keep_monthly).range(0..=120))
+            .changed();
+    });
+    ui.horizontal(|ui| {
+        ui.label("Backup passphrase");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.passphrase)
+                .password(true)
+                .hint_text("Passphrase")
+                .desired_width(200.0),
+        );
+    });
+    if settings.enabled && form.passphrase.is_empty() {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            "Scheduled backups are paused until the passphrase is entered",
+        );
+    }
+    if changed {
+        if let Err(e) = settings.save() {
+            form.status = format!("Error saving backup settings: {}", e);
+        }
+    }
+
+    let dir = PathBuf::from(settings.directory.trim());
+    if ui.button("Back Up Now").clicked() {
+        form.status = if settings.directory.trim().is_empty() {
+            "Choose a backup directory first".to_string()
+        } else if form.passphrase.is_empty() {
+            "Enter the backup passphrase first".to_string()
+        } else if backup_running {
+            "A backup is already running".to_string()
+        } else {
+            back_up_now = true;
+            "Backup started".to_string()
+        };
+    }
+
+    if !settings.directory.trim().is_empty() {
+        match list_backups(&dir) {
+            Ok(backups) if backups.is_empty() => {
+                ui.label("No backups in this directory");
+            }
+            Ok(backups) => {
+                egui::Grid::new("backups_grid")
+                    .striped(true)
+                    .spacing([10.0, 10.0])
+                    .show(ui, |ui| {
+                        ui.strong("Date");
+                        ui.strong("Size");
+                        ui.end_row();
+                        for backup in backups {
+                            ui.label(backup.date.to_string());
+                            ui.label(format!("{} KB", backup.size.div_ceil(1024)));
+                            if ui.button("Restore").clicked() {
+                                form.confirm_restore = Some(backup);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+            Err(e) => {
+                ui.label(format!("Error reading backup directory: {}", e));
+            }
+        }
+    }
+
+    if let Some(backup) = form.confirm_restore.clone() {
+        ui.separator();
+        ui.colored_label(
+            egui::Color32::RED,
+            format!(
+                "Restore the {} backup? Everything entered since then will be replaced.",
+                backup.date
+            ),
+        );
+        ui.horizontal(|ui| {
+            if ui.button("Yes, Restore").clicked() {
+                form.status = match restore_backup(&app.conn, &backup, &form.passphrase) {
+                    Ok(()) => {
+                        restored = true;
+                        format!("Restored the {} backup", backup.date)
+                    }
+                    Err(e) => format!("Restore cancelled: {}", e),
+                };
+                form.confirm_restore = None;
+            }
+            if ui.button("Cancel").clicked() {
+                form.confirm_restore = None;
+            }
+        });
+    }
+
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+
+    if back_up_now {
+        let today = chrono::Local::now().date_naive();
+        start_backup(app, dir, today);
+    }
+    if restored {
+        // The restored database may not have the company that was selected.
+        let companies = companies::get_companies(&app.conn).unwrap_or_default();
+        let company_id = app
+            .company_id
+            .filter(|id| companies.iter().any(|c| c.id == *id))
+            .or_else(|| companies.first().map(|c| c.id));
+        companies::select_company(app, company_id);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::app::app::unused_fridays;
+use crate::app::companies;
+use crate::app::deductions::{DeductionElection, DeductionLine};
+use crate::app::earnings::EarningLine;
+use crate::app::employee::Employee;
+use crate::app::garnishments::GarnishmentOrder;
+use crate::app::payroll::PayrollEntry;
+use crate::app::repository::{EmployeeRepository, PayrollRepository, SqliteRepository};
+use crate::app::retirement::{EmployerContribution, EmployerContributionRule};
+use rusqlite::Connection;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Query results the panels reuse from frame to frame instead of asking SQLite again.
+/// Every read first compares SQLite's `total_changes()` with the last value seen, so any
+/// write through the connection clears the cache without the write path having to know.
+#[derive(Default)]
+pub struct DataCache {
+    changes: i64,
+    employees: HashMap<Option<i64>, Vec<Employee>>,
+    checks: HashMap<i32, Vec<PayrollEntry>>,
+    pay_dates: HashMap<i32, Vec<String>>,
+    results: HashMap<String, Box<dyn Any>>,
+}
+
+impl DataCache {
+    pub fn invalidate(&mut self) {
+        self.employees.clear();
+        self.checks.clear();
+        self.pay_dates.clear();
+        self.results.clear();
+    }
+
+    /// `total_changes()` counts rows written on this connection; it costs no disk access.
+    fn sync(&mut self, conn: &Connection) {
+        let changes = conn
+            .query_row("SELECT total_changes()", [], |row| row.get(0))
+            .unwrap_or(-1);
+        if changes != self.changes || changes < 0 {
+            self.invalidate();
+            self.changes = changes;
+        }
+    }
+
+    pub fn company_employees(
+        &mut self,
+        conn: &Connection,
+        company_id: Option<i64>,
+    ) -> Result<Vec<Employee>, rusqlite::Error> {
+        self.sync(conn);
+        if let Some(employees) = self.employees.get(&company_id) {
+            return Ok(employees.clone());
+        }
+        let employees = companies::company_employees(conn, company_id)?;
+        self.employees.insert(company_id, employees.clone());
+        Ok(employees)
+    }
+
+    pub fn checks_for_employee(
+        &mut self,
+        conn: &Connection,
+        employee_id: i32,
+    ) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
+        self.sync(conn);
+        if let Some(checks) = self.checks.get(&employee_id) {
+            return Ok(checks.clone());
+        }
+        let checks = SqliteRepository::new(conn).checks_for_employee(employee_id)?;
+        self.checks.insert(employee_id, checks.clone());
+        Ok(checks)
+    }
+
+    /// This year's Fridays the employee has no regular check on yet.
+    pub fn available_fridays(&mut self, conn: &Connection, employee_id: i32) -> Vec<String> {
+        self.sync(conn);
+        let used = self.pay_dates.entry(employee_id).or_insert_with(|| {
+            SqliteRepository::new(conn)
+                .pay_dates_for_employee(employee_id)
+                .unwrap_or_default()
+        });
+        unused_fridays(used)
+    }
+
+    /// Any other read, kept under `key` until the next write. The key has to name the query
+    /// and every argument it depends on. Errors aren't kept, so the next frame asks again.
+    pub fn query<T: Clone + 'static>(
+        &mut self,
+        conn: &Connection,
+        key: String,
+        load: impl FnOnce(&Connection) -> Result<T, rusqlite::Error>,
+    ) -> Result<T, rusqlite::Error> {
+        self.sync(conn);
+        if let Some(value) = self
+            .results
+            .get(&key)
+            .and_then(|value| value.downcast_ref())
+        {
+            return Ok(T::clone(value));
+        }
+        let value = load(conn)?;
+        self.results.insert(key, Box::new(value.clone()));
+        Ok(value)
+    }
+}
+
+/// The app's repository with reads answered from a [`DataCache`], for panels that ask the
+/// same questions every frame. Writes go straight through, and the cache sees them.
+pub struct CachedRepository<'a> {
+    conn: &'a Connection,
+    cache: RefCell<&'a mut DataCache>,
+}
+
+impl<'a> CachedRepository<'a> {
+    pub fn new(conn: &'a Connection, cache: &'a mut DataCache) -> Self {
+        Self {
+            conn,
+            cache: RefCell::new(cache),
+        }
+    }
+
+    fn repo(&self) -> SqliteRepository<'a> {
+        SqliteRepository::new(self.conn)
+    }
+
+    fn query<T: Clone + 'static>(
+        &self,
+        key: String,
+        load: impl FnOnce(&SqliteRepository) -> Result<T, rusqlite::Error>,
+    ) -> Result<T, rusqlite::Error> {
+        self.cache
+            .borrow_mut()
+            .query(self.conn, key, |conn| load(&SqliteRepository::new(conn)))
+    }
+}
+
+impl EmployeeRepository for CachedRepository<'_> {
+    fn employee(&self, id: i32) -> Result<Employee, rusqlite::Error> {
+        self.query(format!("employee:{}", id), |repo| repo.employee(id))
+    }
+
+    fn employees(&self) -> Result<Vec<Employee>, rusqlite::Error> {
+        self.query("employees".to_string(), |repo| repo.employees())
+    }
+
+    fn add_employee(&self, employee: &Employee) -> Result<i64, rusqlite::Error> {
+        self.repo().add_employee(employee)
+    }
+
+    fn update_pay_setup(&self, employee: &Employee) -> Result<(), rusqlite::Error> {
+        self.repo().update_pay_setup(employee)
+    }
+}
+
+impl PayrollRepository for CachedRepository<'_> {
+    fn checks_for_employee(&self, employee_id: i32) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
+        self.cache
+            .borrow_mut()
+            .checks_for_employee(self.conn, employee_id)
+    }
+
+    fn pay_dates_for_employee(&self, employee_id: i32) -> Result<Vec<String>, rusqlite::Error> {
+        self.query(format!("pay_dates:{}", employee_id), |repo| {
+            repo.pay_dates_for_employee(employee_id)
+        })
+    }
+
+    fn earnings_for_check(&self, payroll_id: i64) -> Result<Vec<EarningLine>, rusqlite::Error> {
+        self.query(format!("earnings:{}", payroll_id), |repo| {
+            repo.earnings_for_check(payroll_id)
+        })
+    }
+
+    fn save_check(
+        &self,
+        entry: &PayrollEntry,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+        pto_policy_id: Option<i64>,
+    ) -> Result<i64, rusqlite::Error> {
+        self.repo()
+            .save_check(entry, lines, deductions, contributions, pto_policy_id)
+    }
+
+    fn delete_check(&self, id: i64) -> Result<(), rusqlite::Error> {
+        self.repo().delete_check(id)
+    }
+
+    fn deductions_for_check(&self, payroll_id: i64) -> Result<Vec<DeductionLine>, rusqlite::Error> {
+        self.query(format!("deductions:{}", payroll_id), |repo| {
+            repo.deductions_for_check(payroll_id)
+        })
+    }
+
+    fn contributions_for_check(
+        &self,
+        payroll_id: i64,
+    ) -> Result<Vec<EmployerContribution>, rusqlite::Error> {
+        self.query(format!("contributions:{}", payroll_id), |repo| {
+            repo.contributions_for_check(payroll_id)
+        })
+    }
+
+    fn deduction_elections(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<DeductionElection>, rusqlite::Error> {
+        self.query(format!("elections:{}", employee_id), |repo| {
+            repo.deduction_elections(employee_id)
+        })
+    }
+
+    fn deduction_ytd(
+        &self,
+        employee_id: i32,
+        year: i32,
+    ) -> Result<HashMap<String, f32>, rusqlite::Error> {
+        self.query(format!("deduction_ytd:{}:{}", employee_id, year), |repo| {
+            repo.deduction_ytd(employee_id, year)
+        })
+    }
+
+    fn garnishment_orders(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<GarnishmentOrder>, rusqlite::Error> {
+        self.query(format!("garnishment_orders:{}", employee_id), |repo| {
+            repo.garnishment_orders(employee_id)
+        })
+    }
+
+    fn garnishment_paid(&self) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+        self.query("garnishment_paid".to_string(), |repo| {
+            repo.garnishment_paid()
+        })
+    }
+
+    fn retirement_rules(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<EmployerContributionRule>, rusqlite::Error> {
+        self.query(format!("retirement_rules:{}", employee_id), |repo| {
+            repo.retirement_rules(employee_id)
+        })
+    }
+
+    fn pending_retro(&self, employee_id: i32) -> Result<f32, rusqlite::Error> {
+        self.query(format!("pending_retro:{}", employee_id), |repo| {
+            repo.pending_retro(employee_id)
+        })
+    }
+}
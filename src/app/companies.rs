@@ -0,0 +1,852 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::database;
+use crate::app::deposits::DepositSchedule;
+use crate::app::deposits::DEFAULT_SUTA_WAGE_BASE;
+use crate::app::employee::Employee;
+use crate::app::jobs::Job;
+use crate::app::retirement::get_exports_dir;
+use crate::app::security;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::Datelike;
+use chrono::NaiveDate;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Logos are stored in the database, so keep them small.
+const MAX_LOGO_BYTES: usize = 512 * 1024;
+
+/// A legal employer and the identity printed on its stubs, checks and tax forms. Employees,
+/// pay runs and reports all belong to one company.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Company {
+    pub id: i64,
+    pub legal_name: String,
+    /// Doing-business-as name, empty when the pharmacy trades under its legal name.
+    pub dba: String,
+    pub ein: String,
+    pub address: String,
+    pub city: String,
+    pub state: String,
+    pub zip: String,
+    pub phone: String,
+    /// State withholding account number.
+    pub state_tax_id: String,
+    pub state_unemployment_id: String,
+    /// State unemployment (SUTA) contribution rate, in percent.
+    pub suta_rate: f32,
+    /// Wages per employee per calendar year that SUTA is owed on.
+    pub suta_wage_base: f32,
+    /// PNG or JPEG bytes, empty when no logo has been uploaded.
+    pub logo: Vec<u8>,
+    /// 1 for a calendar fiscal year, 7 for July through June, and so on.
+    pub fiscal_year_start_month: u32,
+    /// Federal deposit schedule from the IRS lookback period.
+    pub deposit_schedule: DepositSchedule,
+}
+
+impl Default for Company {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            legal_name: String::new(),
+            dba: String::new(),
+            ein: String::new(),
+            address: String::new(),
+            city: String::new(),
+            state: String::new(),
+            zip: String::new(),
+            phone: String::new(),
+            state_tax_id: String::new(),
+            state_unemployment_id: String::new(),
+            suta_rate: 0.0,
+            suta_wage_base: DEFAULT_SUTA_WAGE_BASE,
+            logo: Vec::new(),
+            fiscal_year_start_month: 1,
+            deposit_schedule: DepositSchedule::Monthly,
+        }
+    }
+}
+
+impl Company {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO companies (
+                legal_name,
+                dba,
+                ein,
+                address,
+                city,
+                state,
+                zip,
+                phone,
+                state_tax_id,
+                state_unemployment_id,
+                suta_rate,
+                logo,
+                fiscal_year_start_month,
+                deposit_schedule,
+                suta_wage_base)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                &self.legal_name,
+                &self.dba,
+                &self.ein,
+                &self.address,
+                &self.city,
+                &self.state,
+                &self.zip,
+                &self.phone,
+                &self.state_tax_id,
+                &self.state_unemployment_id,
+                self.suta_rate,
+                &self.logo,
+                self.fiscal_year_start_month,
+                self.deposit_schedule.as_str(),
+                self.suta_wage_base
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(conn, "company", "companies", id, AuditAction::Insert, None)?;
+        Ok(id)
+    }
+
+    pub fn update_in_db(&self, conn: &Connection) -> Result<(), rusqlite::Error> {
+        let before = audit::snapshot(conn, "companies", self.id)?;
+        conn.execute(
+            "UPDATE companies SET
+                legal_name = ?1,
+                dba = ?2,
+                ein = ?3,
+                address = ?4,
+                city = ?5,
+                state = ?6,
+                zip = ?7,
+                phone = ?8,
+                state_tax_id = ?9,
+                state_unemployment_id = ?10,
+                suta_rate = ?11,
+                logo = ?12,
+                fiscal_year_start_month = ?13,
+                deposit_schedule = ?14,
+                suta_wage_base = ?15
+            WHERE id = ?16",
+            params![
+                &self.legal_name,
+                &self.dba,
+                &self.ein,
+                &self.address,
+                &self.city,
+                &self.state,
+                &self.zip,
+                &self.phone,
+                &self.state_tax_id,
+                &self.state_unemployment_id,
+                self.suta_rate,
+                &self.logo,
+                self.fiscal_year_start_month,
+                self.deposit_schedule.as_str(),
+                self.suta_wage_base,
+                self.id
+            ],
+        )?;
+        audit::record_row(
+            conn,
+            "company",
+            "companies",
+            self.id,
+            AuditAction::Update,
+            before.as_ref(),
+        )
+    }
+
+    /// Street, city, state and ZIP on one line for reports and export files.
+    pub fn full_address(&self) -> String {
+        let city_line = format!("{}, {} {}", self.city, self.state, self.zip);
+        [self.address.trim(), city_line.trim_matches([',', ' '])]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// First and last day of fiscal `year`, which is named for the calendar year it ends in.
+    /// A July start makes fiscal 2027 run from 2026-07-01 through 2027-06-30.
+    pub fn fiscal_year(&self, year: i32) -> (NaiveDate, NaiveDate) {
+        let month = self.fiscal_year_start_month.clamp(1, 12);
+        let start_year = if month == 1 { year } else { year - 1 };
+        let start = NaiveDate::from_ymd_opt(start_year, month, 1).unwrap_or_default();
+        let end = NaiveDate::from_ymd_opt(start_year + 1, month, 1)
+            .and_then(|next| next.pred_opt())
+            .unwrap_or_default();
+        (start, end)
+    }
+
+    /// Everything wrong with the profile, empty when it can be saved. The EIN is normalized
+    /// to `NN-NNNNNNN` as a side effect.
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.legal_name.trim().is_empty() {
+            problems.push("Legal name is required".to_string());
+        }
+        match normalize_ein(&self.ein) {
+            Ok(ein) => self.ein = ein,
+            Err(e) => problems.push(e),
+        }
+        let phone_digits = self.phone.chars().filter(|c| c.is_ascii_digit()).count();
+        if !self.phone.trim().is_empty() && phone_digits != 10 {
+            problems.push("Phone must have 10 digits".to_string());
+        }
+        let state_ok = self.state.is_empty()
+            || (self.state.len() == 2 && self.state.chars().all(|c| c.is_ascii_alphabetic()));
+        if !state_ok {
+            problems.push("State must be a two-letter code".to_string());
+        }
+        self.state = self.state.to_ascii_uppercase();
+        let zip_ok =
+            match self.zip.len() {
+                0 => true,
+                5 => self.zip.chars().all(|c| c.is_ascii_digit()),
+                10 => self.zip.chars().enumerate().all(|(i, c)| {
+                    if i == 5 {
+                        c == '-'
+                    } else {
+                        c.is_ascii_digit()
+                    }
+                }),
+                _ => false,
+            };
+        if !zip_ok {
+            problems.push("ZIP must be 12345 or 12345-6789".to_string());
+        }
+        if !(0.0..=15.0).contains(&self.suta_rate) {
+            problems.push("SUTA rate must be between 0% and 15%".to_string());
+        }
+        if self.suta_wage_base < 0.0 {
+            problems.push("SUTA wage base can't be negative".to_string());
+        }
+        if !(1..=12).contains(&self.fiscal_year_start_month) {
+            problems.push("Fiscal year must start in a month from 1 to 12".to_string());
+        }
+        problems
+    }
+}
+
+/// Checks an EIN and returns it as `NN-NNNNNNN`. The dash is optional on input, and prefixes
+/// the IRS has never assigned are rejected.
+pub fn normalize_ein(ein: &str) -> Result<String, String> {
+    let ein = ein.trim();
+    let digits: String = ein.chars().filter(|c| *c != '-').collect();
+    let dash_ok = match ein.find('-') {
+        None => true,
+        Some(index) => index == 2 && ein.matches('-').count() == 1,
+    };
+    if digits.len() != 9 || !digits.chars().all(|c| c.is_ascii_digit()) || !dash_ok {
+        return Err("EIN must be nine digits, as NN-NNNNNNN".to_string());
+    }
+    const UNASSIGNED_PREFIXES: [&str; 17] = [
+        "00", "07", "08", "09", "17", "18", "19", "28", "29", "49", "69", "70", "78", "79", "89",
+        "96", "97",
+    ];
+    if UNASSIGNED_PREFIXES.contains(&&digits[..2]) {
+        return Err(format!("{} is not a valid EIN prefix", &digits[..2]));
+    }
+    Ok(format!("{}-{}", &digits[..2], &digits[2..]))
+}
+
+/// Reads a logo file, accepting only PNG and JPEG images under the size limit.
+pub fn load_logo(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = std::fs::read(path.trim()).map_err(|e| format!("Error reading logo: {}", e))?;
+    let is_png = bytes.starts_with(&[0x89, b'P', b'N', b'G']);
+    let is_jpeg = bytes.starts_with(&[0xFF, 0xD8, 0xFF]);
+    if !is_png && !is_jpeg {
+        return Err("Logo must be a PNG or JPEG image".to_string());
+    }
+    if bytes.len() > MAX_LOGO_BYTES {
+        return Err(format!(
+            "Logo is {} KB; the limit is {} KB",
+            bytes.len() / 1024,
+            MAX_LOGO_BYTES / 1024
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Saves a new company. The first company created takes over the employees and checks that
+/// were entered before companies existed.
+pub fn add_company(conn: &Connection, company: &Company) -> Result<i64, rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    let first = get_companies(&tx)?.is_empty();
+    let company_id = company.save_to_db(&tx)?;
+    if first {
+        let employees = tx.execute(
+            "UPDATE employees SET company_id = ?1 WHERE company_id IS NULL",
+            [company_id],
+        )?;
+        let checks = tx.execute(
+            "UPDATE payroll SET company_id = ?1 WHERE company_id IS NULL",
+            [company_id],
+        )?;
+        let retirement_rules = tx.execute(
+            "UPDATE employer_contribution_rules SET company_id = ?1 WHERE company_id IS NULL",
+            [company_id],
+        )?;
+        audit::record(
+            &tx,
+            "company",
+            company_id,
+            AuditAction::Update,
+            Some(&serde_json::json!({ "company_id": null })),
+            Some(&serde_json::json!({
+                "company_id": company_id,
+                "employees": employees,
+                "checks": checks,
+                "retirement_rules": retirement_rules,
+            })),
+        )?;
+    }
+    tx.commit()?;
+    Ok(company_id)
+}
+
+pub fn get_companies(conn: &Connection) -> Result<Vec<Company>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, legal_name, dba, ein, address, city, state, zip, phone, state_tax_id,
+            state_unemployment_id, suta_rate, logo, fiscal_year_start_month, deposit_schedule,
+            suta_wage_base
+        FROM companies ORDER BY legal_name",
+    )?;
+    let companies = stmt
+        .query_map([], |row| {
+            Ok(Company {
+                id: row.get(0)?,
+                legal_name: row.get(1)?,
+                dba: row.get(2)?,
+                ein: row.get(3)?,
+                address: row.get(4)?,
+                city: row.get(5)?,
+                state: row.get(6)?,
+                zip: row.get(7)?,
+                phone: row.get(8)?,
+                state_tax_id: row.get(9)?,
+                state_unemployment_id: row.get(10)?,
+                suta_rate: row.get(11)?,
+                logo: row.get::<_, Option<Vec<u8>>>(12)?.unwrap_or_default(),
+                fiscal_year_start_month: row.get(13)?,
+                deposit_schedule: DepositSchedule::parse(&row.get::<_, String>(14)?),
+                suta_wage_base: row.get(15)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(companies)
+}
+
+/// Employees of the company, or everyone when no company is selected.
+pub fn company_employees(
+    conn: &Connection,
+    company_id: Option<i64>,
+) -> Result<Vec<Employee>, rusqlite::Error> {
+    let employees = database::get_all_employees(conn)?;
+    Ok(match company_id {
+        Some(_) => employees
+            .into_iter()
+            .filter(|e| e.company_id == company_id)
+            .collect(),
+        None => employees,
+    })
+}
+
+/// One employee's wage and tax totals for the year, as reported on their W-2.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearEndSummary {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub checks: i64,
+    pub gross: f32,
+    pub federal_wages: f32,
+    pub federal_withholding: f32,
+    pub fica_wages: f32,
+    pub social_security: f32,
+}
+
+/// Year-end totals per employee for checks the company paid in `year`. Pre-tax deductions
+/// come out of federal and FICA wages the same way they did on each check.
+pub fn get_year_end_summaries(
+    conn: &Connection,
+    company_id: i64,
+    year: i32,
+) -> Result<Vec<YearEndSummary>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT e.id, e.name, SUM(p.imported IS NOT 'ytd'), SUM(p.gross), SUM(p.withholding),
+            SUM(p.social_security),
+            COALESCE(SUM((SELECT SUM(d.amount) FROM deduction_lines d
+                WHERE d.payroll_id = p.id AND d.pre_tax_fit)), 0),
+            COALESCE(SUM((SELECT SUM(d.amount) FROM deduction_lines d
+                WHERE d.payroll_id = p.id AND d.pre_tax_fica)), 0)
+        FROM payroll p
+        JOIN employees e ON e.id = p.employee_id
+        WHERE p.company_id = ?1 AND p.date_of_pay BETWEEN ?2 AND ?3
+        GROUP BY e.id
+        ORDER BY e.name",
+    )?;
+    let summaries = stmt
+        .query_map(
+            params![
+                company_id,
+                format!("{}-01-01", year),
+                format!("{}-12-31", year)
+            ],
+            |row| {
+                let gross: f32 = row.get(3)?;
+                Ok(YearEndSummary {
+                    employee_id: row.get(0)?,
+                    employee_name: row.get(1)?,
+                    checks: row.get(2)?,
+                    gross,
+                    federal_wages: (gross - row.get::<_, f32>(6)?).max(0.0),
+                    federal_withholding: row.get(4)?,
+                    fica_wages: (gross - row.get::<_, f32>(7)?).max(0.0),
+                    social_security: row.get(5)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(summaries)
+}
+
+/// Year-end file for the company's filings, one row per employee with the employer's
+/// identifiers repeated on each row.
+pub fn year_end_csv(
+    company: &Company,
+    year: i32,
+    summaries: &[YearEndSummary],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Year",
+        "Employer",
+        "EIN",
+        "Employer Address",
+        "State Withholding Account",
+        "State Unemployment Account",
+        "Employee ID",
+        "Employee Name",
+        "Checks",
+        "Gross",
+        "Federal Wages",
+        "Federal Withholding",
+        "FICA Wages",
+        "Social Security",
+    ])?;
+    for summary in summaries {
+        writer.write_record([
+            year.to_string(),
+            company.legal_name.clone(),
+            company.ein.clone(),
+            company.full_address(),
+            company.state_tax_id.clone(),
+            company.state_unemployment_id.clone(),
+            summary.employee_id.to_string(),
+            summary.employee_name.clone(),
+            summary.checks.to_string(),
+            format!("{:.2}", summary.gross),
+            format!("{:.2}", summary.federal_wages),
+            format!("{:.2}", summary.federal_withholding),
+            format!("{:.2}", summary.fica_wages),
+            format!("{:.2}", summary.social_security),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+pub fn export_year_end(
+    conn: &Connection,
+    company: &Company,
+    year: i32,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let summaries = get_year_end_summaries(conn, company.id, year)?;
+    if summaries.is_empty() {
+        return Err(format!("No checks for {} in {}", company.legal_name, year).into());
+    }
+    let exports_dir = get_exports_dir();
+    std::fs::create_dir_all(&exports_dir)?;
+    let path = exports_dir.join(format!("year_end_{}_{}.csv", company.id, year));
+    std::fs::write(&path, year_end_csv(company, year, &summaries)?)?;
+    Ok(path)
+}
+
+/// Switches the active company and drops any selection that belonged to the old one.
+pub fn select_company(app: &mut PharmacyApp, company_id: Option<i64>) {
+    app.company_id = company_id;
+    app.selected_employee = None;
+    app.selected_employee_index = 0;
+    app.earning_lines_employee_id = None;
+    app.location_filter = None;
+    app.employees = company_employees(&app.conn, company_id).unwrap_or_default();
+}
+
+/// The company picker in the top bar. Hidden until a company has been set up.
+pub fn render_company_switcher(app: &mut PharmacyApp, ui: &mut Ui) {
+    let companies = get_companies(&app.conn).unwrap_or_default();
+    if companies.is_empty() {
+        return;
+    }
+    let mut selected = app.company_id;
+    let selected_name = companies
+        .iter()
+        .find(|c| Some(c.id) == selected)
+        .map_or("Select company".to_string(), |c| c.legal_name.clone());
+    egui::ComboBox::from_id_salt("company_switcher")
+        .selected_text(selected_name)
+        .show_ui(ui, |ui| {
+            for company in &companies {
+                ui.selectable_value(&mut selected, Some(company.id), &company.legal_name);
+            }
+        });
+    if selected != app.company_id {
+        select_company(app, selected);
+    }
+}
+
+pub struct CompanyForm {
+    pub company: Company,
+    pub report_year: i32,
+    pub status: String,
+}
+
+impl Default for CompanyForm {
+    fn default() -> Self {
+        Self {
+            company: Company::default(),
+            report_year: chrono::Local::now().year(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn render_companies(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_manage_system = app.can(Permission::ManageSystem);
+    ui.heading("Companies");
+    let companies = get_companies(&app.conn).unwrap_or_default();
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if companies.is_empty() {
+            ui.label("No companies. Adding the first one assigns all existing employees to it.");
+        } else {
+            egui::Grid::new("companies_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Legal Name");
+                    ui.strong("EIN");
+                    ui.strong("DBA");
+                    ui.strong("Address");
+                    ui.strong("State Withholding Account");
+                    ui.end_row();
+                    for company in &companies {
+                        ui.label(&company.legal_name);
+                        ui.label(&company.ein);
+                        ui.label(&company.dba);
+                        ui.label(company.full_address());
+                        ui.label(&company.state_tax_id);
+                        ui.end_row();
+                    }
+                });
+        }
+
+        ui.add_space(10.0);
+        let mut added = None;
+        let form = &mut app.company_form;
+        egui::Grid::new("company_form_grid")
+            .spacing([10.0, 10.0])
+            .show(ui, |ui| {
+                ui.label("Legal name");
+                ui.text_edit_singleline(&mut form.company.legal_name);
+                ui.end_row();
+                ui.label("EIN");
+                ui.text_edit_singleline(&mut form.company.ein);
+                ui.end_row();
+            });
+        ui.small("Address, tax accounts and the logo are edited under Settings.");
+        if users::guarded_button(ui, can_manage_system, "Add Company").clicked() {
+            let problems = form.company.validate();
+            if !problems.is_empty() {
+                form.status = problems.join(", ");
+            } else {
+                form.status = match add_company(&app.conn, &form.company) {
+                    Ok(id) => {
+                        added = Some(id);
+                        form.company = Company::default();
+                        "Company added".to_string()
+                    }
+                    Err(e) => format!("Error saving company: {}", e),
+                };
+            }
+        }
+        if added.is_some() && app.company_id.is_none() {
+            select_company(app, added);
+        }
+
+        ui.add_space(20.0);
+        ui.separator();
+        render_year_end(app, ui, &companies);
+
+        if !app.company_form.status.is_empty() {
+            ui.label(&app.company_form.status);
+        }
+    });
+}
+
+fn render_year_end(app: &mut PharmacyApp, ui: &mut Ui, companies: &[Company]) {
+    let Some(company) = companies.iter().find(|c| Some(c.id) == app.company_id) else {
+        ui.label("Select a company in the top bar for year-end totals");
+        return;
+    };
+    let form = &mut app.company_form;
+    ui.heading(format!("Year-End Summary: {}", company.legal_name));
+    ui.horizontal(|ui| {
+        ui.label("Year");
+        ui.add(egui::DragValue::new(&mut form.report_year).range(2000..=2100));
+        if ui.button("Export Year-End File").clicked() {
+            let company = company.clone();
+            let year = form.report_year;
+            app.jobs
+                .push(Job::spawn("Year-end export", move |progress| {
+                    progress.set(0.2, "Totaling checks");
+                    security::open_worker_connection()
+                        .and_then(|conn| export_year_end(&conn, &company, year))
+                        .map(|path| format!("Year-end file saved to {}", path.display()))
+                        .map_err(|e| format!("Error exporting year-end file: {}", e))
+                }));
+            form.status = "Year-end export started".to_string();
+        }
+    });
+    match get_year_end_summaries(&app.conn, company.id, form.report_year) {
+        Ok(summaries) if summaries.is_empty() => {
+            ui.label("No checks for this company and year");
+        }
+        Ok(summaries) => {
+            egui::Grid::new("year_end_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Employee");
+                    ui.strong("Checks");
+                    ui.strong("Gross");
+                    ui.strong("Federal Wages");
+                    ui.strong("Federal Withholding");
+                    ui.strong("FICA Wages");
+                    ui.strong("Social Security");
+                    ui.end_row();
+                    for summary in &summaries {
+                        ui.label(&summary.employee_name);
+                        ui.label(summary.checks.to_string());
+                        ui.label(format!("{:.2}", summary.gross));
+                        ui.label(format!("{:.2}", summary.federal_wages));
+                        ui.label(format!("{:.2}", summary.federal_withholding));
+                        ui.label(format!("{:.2}", summary.fica_wages));
+                        ui.label(format!("{:.2}", summary.social_security));
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching year-end totals: {}", e));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ProfileForm {
+    pub company: Company,
+    /// Which company `company` was loaded from, so switching companies reloads the editor.
+    pub loaded_id: Option<i64>,
+    pub logo_path: String,
+    pub status: String,
+}
+
+/// Editor for the active company's employer profile, shown in Settings. With no companies
+/// yet, saving creates the first one.
+pub fn render_employer_profile(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_manage_system = app.can(Permission::ManageSystem);
+    ui.heading("Employer Profile");
+    let companies = get_companies(&app.conn).unwrap_or_default();
+    let form = &mut app.profile_form;
+    if form.loaded_id != app.company_id {
+        form.company = companies
+            .iter()
+            .find(|c| Some(c.id) == app.company_id)
+            .cloned()
+            .unwrap_or_default();
+        form.loaded_id = app.company_id;
+        form.logo_path.clear();
+        form.status.clear();
+    }
+
+    let company = &mut form.company;
+    egui::Grid::new("employer_profile_grid")
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.label("Legal name");
+            ui.text_edit_singleline(&mut company.legal_name);
+            ui.end_row();
+            ui.label("DBA");
+            ui.text_edit_singleline(&mut company.dba);
+            ui.end_row();
+            ui.label("EIN");
+            ui.add(egui::TextEdit::singleline(&mut company.ein).hint_text("NN-NNNNNNN"));
+            ui.end_row();
+            ui.label("Street address");
+            ui.text_edit_singleline(&mut company.address);
+            ui.end_row();
+            ui.label("City");
+            ui.text_edit_singleline(&mut company.city);
+            ui.end_row();
+            ui.label("State");
+            ui.add(egui::TextEdit::singleline(&mut company.state).desired_width(40.0));
+            ui.end_row();
+            ui.label("ZIP");
+            ui.add(egui::TextEdit::singleline(&mut company.zip).desired_width(100.0));
+            ui.end_row();
+            ui.label("Phone");
+            ui.text_edit_singleline(&mut company.phone);
+            ui.end_row();
+            ui.label("State withholding account");
+            ui.text_edit_singleline(&mut company.state_tax_id);
+            ui.end_row();
+            ui.label("State unemployment account");
+            ui.text_edit_singleline(&mut company.state_unemployment_id);
+            ui.end_row();
+            ui.label("SUTA rate");
+            ui.add(
+                egui::DragValue::new(&mut company.suta_rate)
+                    .speed(0.01)
+                    .suffix("%"),
+            );
+            ui.end_row();
+            ui.label("SUTA wage base");
+            ui.add(
+                egui::DragValue::new(&mut company.suta_wage_base)
+                    .speed(100.0)
+                    .prefix("$"),
+            )
+            .on_hover_text("Wages per employee per year that SUTA is owed on");
+            ui.end_row();
+            ui.label("Fiscal year starts");
+            let month_index = company.fiscal_year_start_month.clamp(1, 12) as usize - 1;
+            egui::ComboBox::from_id_salt("fiscal_year_start")
+                .selected_text(MONTHS[month_index])
+                .show_ui(ui, |ui| {
+                    for (index, month) in MONTHS.iter().enumerate() {
+                        ui.selectable_value(
+                            &mut company.fiscal_year_start_month,
+                            index as u32 + 1,
+                            *month,
+                        );
+                    }
+                });
+            ui.end_row();
+            ui.label("Federal deposit schedule");
+            egui::ComboBox::from_id_salt("deposit_schedule")
+                .selected_text(company.deposit_schedule.label())
+                .show_ui(ui, |ui| {
+                    for schedule in DepositSchedule::ALL {
+                        ui.selectable_value(
+                            &mut company.deposit_schedule,
+                            schedule,
+                            schedule.label(),
+                        );
+                    }
+                });
+            ui.end_row();
+        });
+
+    let today = chrono::Local::now().date_naive();
+    let fiscal_label = if company.fiscal_year_start_month > 1
+        && today.month() >= company.fiscal_year_start_month
+    {
+        today.year() + 1
+    } else {
+        today.year()
+    };
+    let (start, end) = company.fiscal_year(fiscal_label);
+    ui.small(format!("Fiscal {} runs {} to {}", fiscal_label, start, end));
+
+    ui.horizontal(|ui| {
+        ui.label("Logo");
+        if company.logo.is_empty() {
+            ui.label("None");
+        } else {
+            ui.label(format!("{} KB", company.logo.len().div_ceil(1024)));
+            if users::guarded_button(ui, can_manage_system, "Remove").clicked() {
+                company.logo.clear();
+            }
+        }
+        ui.add(
+            egui::TextEdit::singleline(&mut form.logo_path)
+                .hint_text("Path to a PNG or JPEG")
+                .desired_width(250.0),
+        );
+        if users::guarded_button(ui, can_manage_system, "Load").clicked() {
+            match load_logo(&form.logo_path) {
+                Ok(bytes) => {
+                    company.logo = bytes;
+                    form.logo_path.clear();
+                    form.status = "Logo loaded; save to keep it".to_string();
+                }
+                Err(e) => form.status = e,
+            }
+        }
+    });
+
+    let mut created = None;
+    if users::guarded_button(ui, can_manage_system, "Save Employer Profile").clicked() {
+        let problems = company.validate();
+        form.status = if !problems.is_empty() {
+            problems.join(", ")
+        } else if company.id == 0 {
+            match add_company(&app.conn, company) {
+                Ok(id) => {
+                    created = Some(id);
+                    "Employer profile saved".to_string()
+                }
+                Err(e) => format!("Error saving employer profile: {}", e),
+            }
+        } else {
+            match company.update_in_db(&app.conn) {
+                Ok(()) => "Employer profile saved".to_string(),
+                Err(e) => format!("Error saving employer profile: {}", e),
+            }
+        };
+    }
+    if let Some(id) = created {
+        form.company.id = id;
+        form.loaded_id = created;
+    }
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+    if created.is_some() {
+        select_company(app, created);
+    }
+}
@@ -0,0 +1,726 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::database;
+use crate::app::licenses;
+use crate::app::licenses::License;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::NaiveDate;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Requirement rows with this topic count every CE hour in the cycle, regardless of category.
+pub const TOTAL_TOPIC: &str = "Total";
+
+pub const CE_TOPICS: [&str; 6] = [
+    "General",
+    "Pharmacy Law",
+    "Patient Safety",
+    "Immunizations",
+    "Opioids / Pain Management",
+    "Medication Errors",
+];
+
+const DEFAULT_REQUIREMENTS: [(&str, &str, f32); 6] = [
+    ("Pharmacist License", TOTAL_TOPIC, 30.0),
+    ("Pharmacist License", "Pharmacy Law", 1.0),
+    ("Pharmacist License", "Patient Safety", 1.0),
+    ("Pharmacy Technician Registration", TOTAL_TOPIC, 20.0),
+    ("Pharmacy Technician Registration", "Pharmacy Law", 1.0),
+    ("PTCB Certification (CPhT)", TOTAL_TOPIC, 20.0),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CeRecord {
+    pub id: i64,
+    pub employee_id: i32,
+    pub course_name: String,
+    pub provider: String,
+    pub hours: f32,
+    pub topic: String,
+    pub completion_date: String,
+    pub certificate_path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CeRequirement {
+    pub id: i64,
+    pub license_type: String,
+    pub topic: String,
+    pub hours: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct TopicProgress {
+    pub topic: String,
+    pub required: f32,
+    pub completed: f32,
+}
+
+/// CE hours earned inside one license's renewal cycle, measured against that license type's requirements.
+#[derive(Debug, Clone)]
+pub struct CeRollup {
+    pub license: License,
+    pub employee_name: String,
+    pub topics: Vec<TopicProgress>,
+}
+
+#[derive(Default)]
+pub struct CeForm {
+    pub employee_id: Option<i32>,
+    pub course_name: String,
+    pub provider: String,
+    pub hours: f32,
+    pub topic: String,
+    pub completion_date: String,
+    pub certificate_path: String,
+    pub requirement_license_type: String,
+    pub requirement_topic: String,
+    pub requirement_hours: f32,
+    pub status: String,
+}
+
+impl TopicProgress {
+    pub fn hours_short(&self) -> f32 {
+        (self.required - self.completed).max(0.0)
+    }
+}
+
+impl CeRollup {
+    /// Hours still needed this cycle. Topic hours count toward the total, so the
+    /// shortfall is whichever is larger: the total gap or the sum of the topic gaps.
+    pub fn hours_short(&self) -> f32 {
+        let total_short = self
+            .topics
+            .iter()
+            .filter(|t| t.topic == TOTAL_TOPIC)
+            .map(TopicProgress::hours_short)
+            .sum::<f32>();
+        let topic_short = self
+            .topics
+            .iter()
+            .filter(|t| t.topic != TOTAL_TOPIC)
+            .map(TopicProgress::hours_short)
+            .sum::<f32>();
+        total_short.max(topic_short)
+    }
+}
+
+impl CeRecord {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO ce_records (
+                employee_id,
+                course_name,
+                provider,
+                hours,
+                topic,
+                completion_date,
+                certificate_path)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                self.employee_id,
+                &self.course_name,
+                &self.provider,
+                self.hours,
+                &self.topic,
+                &self.completion_date,
+                &self.certificate_path
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+/// The defaults go in when the table is created, so they aren't anyone's change to log.
+pub fn seed_default_requirements(conn: &Connection) -> Result<(), rusqlite::Error> {
+    for (license_type, topic, hours) in DEFAULT_REQUIREMENTS {
+        insert_requirement(conn, license_type, topic, hours)?;
+    }
+    Ok(())
+}
+
+fn insert_requirement(
+    conn: &Connection,
+    license_type: &str,
+    topic: &str,
+    hours: f32,
+) -> Result<i64, rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO ce_requirements (license_type, topic, hours) VALUES (?1, ?2, ?3)",
+        params![license_type, topic, hours],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn add_requirement(
+    conn: &Connection,
+    license_type: &str,
+    topic: &str,
+    hours: f32,
+) -> Result<i64, rusqlite::Error> {
+    let id = insert_requirement(conn, license_type, topic, hours)?;
+    audit::record_row(
+        conn,
+        "ce_requirement",
+        "ce_requirements",
+        id,
+        AuditAction::Insert,
+        None,
+    )?;
+    Ok(id)
+}
+
+pub fn delete_requirement(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "ce_requirements", id)?;
+    conn.execute("DELETE FROM ce_requirements WHERE id = ?1", [id])?;
+    audit::record_row(
+        conn,
+        "ce_requirement",
+        "ce_requirements",
+        id,
+        AuditAction::Delete,
+        before.as_ref(),
+    )
+}
+
+pub fn get_requirements(conn: &Connection) -> Result<Vec<CeRequirement>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, license_type, topic, hours FROM ce_requirements ORDER BY license_type, topic",
+    )?;
+    let requirements = stmt
+        .query_map([], |row| {
+            Ok(CeRequirement {
+                id: row.get(0)?,
+                license_type: row.get(1)?,
+                topic: row.get(2)?,
+                hours: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(requirements)
+}
+
+pub fn get_ce_records_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<CeRecord>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, course_name, provider, hours, topic, completion_date, certificate_path
+        FROM ce_records WHERE employee_id = ?1 ORDER BY completion_date",
+    )?;
+    let records = stmt
+        .query_map([employee_id], |row| {
+            Ok(CeRecord {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                course_name: row.get(2)?,
+                provider: row.get(3)?,
+                hours: row.get(4)?,
+                topic: row.get(5)?,
+                completion_date: row.get(6)?,
+                certificate_path: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(records)
+}
+
+pub fn delete_ce_record(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let certificate_paths = conn
+        .prepare("SELECT certificate_path FROM ce_records WHERE id = ?1")?
+        .query_map([id], |row| row.get::<_, Option<String>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let before = audit::snapshot(conn, "ce_records", id)?;
+    conn.execute("DELETE FROM ce_records WHERE id = ?1", [id])?;
+    audit::record_row(
+        conn,
+        "ce_record",
+        "ce_records",
+        id,
+        AuditAction::Delete,
+        before.as_ref(),
+    )?;
+    for path in certificate_paths.into_iter().flatten() {
+        if path.is_empty() {
+            continue;
+        }
+        if let Err(e) = std::fs::remove_file(&path) {
+            println!("Error removing certificate {}: {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+/// Saves the record and copies its certificate, if any, into `documents_dir`, normally the
+/// license scans' `licenses::get_documents_dir()`. The stored file is named after the record so
+/// certificates with the same file name don't collide.
+pub fn add_ce_record(
+    conn: &Connection,
+    record: &CeRecord,
+    certificate: Option<&Path>,
+    documents_dir: &Path,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let tx = conn.unchecked_transaction()?;
+    let id = record.save_to_db(&tx)?;
+    if let Some(source) = certificate {
+        let stored_path = store_certificate(id, source, documents_dir)?;
+        if let Err(e) = tx
+            .execute(
+                "UPDATE ce_records SET certificate_path = ?1 WHERE id = ?2",
+                params![&stored_path, id],
+            )
+            .and_then(|_| record_insert(&tx, id))
+            .and_then(|_| tx.commit())
+        {
+            std::fs::remove_file(&stored_path).ok();
+            return Err(e.into());
+        }
+    } else {
+        record_insert(&tx, id)?;
+        tx.commit()?;
+    }
+    Ok(id)
+}
+
+fn record_insert(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    audit::record_row(
+        conn,
+        "ce_record",
+        "ce_records",
+        id,
+        AuditAction::Insert,
+        None,
+    )
+}
+
+fn store_certificate(
+    record_id: i64,
+    source: &Path,
+    documents_dir: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let file_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid certificate path")?;
+
+    std::fs::create_dir_all(documents_dir)?;
+    let stored_path = documents_dir.join(format!("ce_{}_{}", record_id, file_name));
+    std::fs::copy(source, &stored_path)?;
+    Ok(stored_path.to_string_lossy().to_string())
+}
+
+pub fn rollup_license(
+    license: &License,
+    employee_name: &str,
+    records: &[CeRecord],
+    requirements: &[CeRequirement],
+) -> CeRollup {
+    let cycle_start = NaiveDate::parse_from_str(&license.issue_date, "%Y-%m-%d").ok();
+    let cycle_end = NaiveDate::parse_from_str(&license.expiry_date, "%Y-%m-%d").ok();
+    let in_cycle: Vec<&CeRecord> = records
+        .iter()
+        .filter(|record| record.employee_id == license.employee_id)
+        .filter(
+            |record| match NaiveDate::parse_from_str(&record.completion_date, "%Y-%m-%d") {
+                Ok(completed) => {
+                    cycle_start.is_none_or(|start| completed >= start)
+                        && cycle_end.is_none_or(|end| completed <= end)
+                }
+                Err(_) => false,
+            },
+        )
+        .collect();
+
+    let topics = requirements
+        .iter()
+        .filter(|requirement| requirement.license_type == license.license_type)
+        .map(|requirement| TopicProgress {
+            topic: requirement.topic.clone(),
+            required: requirement.hours,
+            completed: in_cycle
+                .iter()
+                .filter(|record| {
+                    requirement.topic == TOTAL_TOPIC || record.topic == requirement.topic
+                })
+                .map(|record| record.hours)
+                .sum(),
+        })
+        .collect();
+
+    CeRollup {
+        license: license.clone(),
+        employee_name: employee_name.to_string(),
+        topics,
+    }
+}
+
+/// Rolls CE hours up against every license on file that has requirements configured.
+pub fn get_ce_rollups(conn: &Connection) -> Result<Vec<CeRollup>, rusqlite::Error> {
+    let requirements = get_requirements(conn)?;
+    let mut rollups = Vec::new();
+    for employee in database::get_all_employees(conn)? {
+        let records = get_ce_records_for_employee(conn, employee.id)?;
+        for license in licenses::get_licenses_for_employee(conn, employee.id)? {
+            let rollup = rollup_license(&license, &employee.name, &records, &requirements);
+            if !rollup.topics.is_empty() {
+                rollups.push(rollup);
+            }
+        }
+    }
+    Ok(rollups)
+}
+
+fn save_ce_record(app: &mut PharmacyApp) {
+    let form = &mut app.ce_form;
+    let Some(employee_id) = form.employee_id else {
+        form.status = "Please select an employee".to_string();
+        return;
+    };
+    if form.course_name.trim().is_empty() || form.topic.is_empty() {
+        form.status = "Please enter a course name and topic".to_string();
+        return;
+    }
+    if form.hours <= 0.0 {
+        form.status = "CE hours must be greater than zero".to_string();
+        return;
+    }
+    if NaiveDate::parse_from_str(&form.completion_date, "%Y-%m-%d").is_err() {
+        form.status = "Completion date must be in YYYY-MM-DD format".to_string();
+        return;
+    }
+
+    let record = CeRecord {
+        id: 0,
+        employee_id,
+        course_name: form.course_name.trim().to_string(),
+        provider: form.provider.trim().to_string(),
+        hours: form.hours,
+        topic: form.topic.clone(),
+        completion_date: form.completion_date.clone(),
+        certificate_path: String::new(),
+    };
+    let certificate = Some(form.certificate_path.trim())
+        .filter(|path| !path.is_empty())
+        .map(Path::new);
+
+    match add_ce_record(
+        &app.conn,
+        &record,
+        certificate,
+        &licenses::get_documents_dir(),
+    ) {
+        Ok(_) => {
+            form.status = "CE record added successfully".to_string();
+            form.course_name.clear();
+            form.provider.clear();
+            form.hours = 0.0;
+            form.completion_date.clear();
+            form.certificate_path.clear();
+        }
+        Err(e) => form.status = format!("Error saving CE record: {}", e),
+    }
+}
+
+pub fn render_continuing_education(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Continuing Education");
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        render_ce_dashboard(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_ce_records(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_ce_requirements(app, ui);
+    });
+}
+
+fn render_ce_dashboard(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("CE Compliance");
+    let rollups = match app
+        .cache
+        .query(&app.conn, "ce_rollups".to_string(), get_ce_rollups)
+    {
+        Ok(rollups) => rollups,
+        Err(e) => {
+            ui.label(format!("Error computing CE totals: {}", e));
+            return;
+        }
+    };
+    let short: Vec<&CeRollup> = rollups.iter().filter(|r| r.hours_short() > 0.0).collect();
+
+    if short.is_empty() {
+        ui.colored_label(
+            egui::Color32::LIGHT_GREEN,
+            "Everyone is on track for their current renewal cycle",
+        );
+        return;
+    }
+
+    egui::Grid::new("ce_dashboard_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Employee");
+            ui.strong("License");
+            ui.strong("Cycle");
+            ui.strong("Hours Short");
+            ui.strong("Details");
+            ui.end_row();
+            for rollup in short {
+                ui.label(&rollup.employee_name);
+                ui.label(&rollup.license.license_type);
+                ui.label(format!(
+                    "{} to {}",
+                    rollup.license.issue_date, rollup.license.expiry_date
+                ));
+                ui.colored_label(egui::Color32::RED, format!("{:.1}", rollup.hours_short()));
+                let details: Vec<String> = rollup
+                    .topics
+                    .iter()
+                    .filter(|t| t.hours_short() > 0.0)
+                    .map(|t| format!("{}: {:.1}/{:.1}", t.topic, t.completed, t.required))
+                    .collect();
+                ui.label(details.join(", "));
+                ui.end_row();
+            }
+        });
+}
+
+fn render_ce_records(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    ui.heading("CE Records");
+    let employees = match app.cache.company_employees(&app.conn, app.company_id) {
+        Ok(e) => e,
+        Err(e) => {
+            ui.label(format!("Error: {}", e));
+            return;
+        }
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Select Employee");
+        egui::ComboBox::from_id_salt("ce_employee_select")
+            .selected_text(
+                employees
+                    .iter()
+                    .find(|emp| Some(emp.id) == app.ce_form.employee_id)
+                    .map(|emp| emp.name.clone())
+                    .unwrap_or_else(|| "Select an employee".to_string()),
+            )
+            .show_ui(ui, |ui| {
+                for emp in &employees {
+                    ui.selectable_value(&mut app.ce_form.employee_id, Some(emp.id), &emp.name);
+                }
+            });
+    });
+
+    let Some(employee_id) = app.ce_form.employee_id else {
+        return;
+    };
+
+    let records = app
+        .cache
+        .query(&app.conn, format!("ce_records:{}", employee_id), |conn| {
+            get_ce_records_for_employee(conn, employee_id)
+        });
+    match records {
+        Ok(records) if records.is_empty() => {
+            ui.label("No CE records found");
+        }
+        Ok(records) => {
+            egui::Grid::new("ce_records_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Completed");
+                    ui.strong("Course");
+                    ui.strong("Provider");
+                    ui.strong("Topic");
+                    ui.strong("Hours");
+                    ui.strong("Certificate");
+                    ui.end_row();
+                    for record in &records {
+                        ui.label(&record.completion_date);
+                        ui.label(&record.course_name);
+                        ui.label(&record.provider);
+                        ui.label(&record.topic);
+                        ui.label(format!("{:.1}", record.hours));
+                        if record.certificate_path.is_empty() {
+                            ui.label("-");
+                        } else {
+                            ui.label("On file").on_hover_text(&record.certificate_path);
+                        }
+                        if users::guarded_button(ui, can_edit_employees, "Delete").clicked() {
+                            delete_ce_record(&app.conn, record.id)
+                                .unwrap_or_else(|e| println!("Error deleting CE record: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching CE records: {}", e));
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.label("Add CE Record");
+    ui.horizontal(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Course");
+            ui.add_sized(
+                [180.0, 25.0],
+                egui::TextEdit::singleline(&mut app.ce_form.course_name),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Provider");
+            ui.add_sized(
+                [120.0, 25.0],
+                egui::TextEdit::singleline(&mut app.ce_form.provider).hint_text("ACPE"),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Topic");
+            egui::ComboBox::from_id_salt("ce_topic_select")
+                .selected_text(if app.ce_form.topic.is_empty() {
+                    "Select a topic".to_string()
+                } else {
+                    app.ce_form.topic.clone()
+                })
+                .show_ui(ui, |ui| {
+                    for topic in CE_TOPICS {
+                        ui.selectable_value(&mut app.ce_form.topic, topic.to_string(), topic);
+                    }
+                });
+        });
+        ui.vertical(|ui| {
+            ui.label("Hours");
+            ui.add(
+                egui::DragValue::new(&mut app.ce_form.hours)
+                    .speed(0.25)
+                    .range(0.0..=100.0),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Completed");
+            ui.add_sized(
+                [100.0, 25.0],
+                egui::TextEdit::singleline(&mut app.ce_form.completion_date)
+                    .hint_text("YYYY-MM-DD"),
+            );
+        });
+        ui.vertical(|ui| {
+            ui.label("Certificate File");
+            ui.add_sized(
+                [200.0, 25.0],
+                egui::TextEdit::singleline(&mut app.ce_form.certificate_path)
+                    .hint_text("/path/to/certificate.pdf"),
+            );
+        });
+    });
+    if users::guarded_button(ui, can_edit_employees, "Save CE Record").clicked() {
+        save_ce_record(app);
+    }
+    if !app.ce_form.status.is_empty() {
+        ui.label(&app.ce_form.status);
+    }
+}
+
+fn render_ce_requirements(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    ui.heading("Renewal Cycle Requirements");
+    match app
+        .cache
+        .query(&app.conn, "ce_requirements".to_string(), get_requirements)
+    {
+        Ok(requirements) => {
+            egui::Grid::new("ce_requirements_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("License Type");
+                    ui.strong("Topic");
+                    ui.strong("Hours per Cycle");
+                    ui.end_row();
+                    for requirement in &requirements {
+                        ui.label(&requirement.license_type);
+                        ui.label(&requirement.topic);
+                        ui.label(format!("{:.1}", requirement.hours));
+                        if users::guarded_button(ui, can_edit_employees, "Remove").clicked() {
+                            delete_requirement(&app.conn, requirement.id).unwrap_or_else(|e| {
+                                println!("Error deleting CE requirement: {}", e)
+                            });
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching CE requirements: {}", e));
+        }
+    }
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("ce_requirement_license_select")
+            .selected_text(if app.ce_form.requirement_license_type.is_empty() {
+                "License type".to_string()
+            } else {
+                app.ce_form.requirement_license_type.clone()
+            })
+            .show_ui(ui, |ui| {
+                for license_type in licenses::LICENSE_TYPES {
+                    ui.selectable_value(
+                        &mut app.ce_form.requirement_license_type,
+                        license_type.to_string(),
+                        license_type,
+                    );
+                }
+            });
+        egui::ComboBox::from_id_salt("ce_requirement_topic_select")
+            .selected_text(if app.ce_form.requirement_topic.is_empty() {
+                "Topic".to_string()
+            } else {
+                app.ce_form.requirement_topic.clone()
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut app.ce_form.requirement_topic,
+                    TOTAL_TOPIC.to_string(),
+                    TOTAL_TOPIC,
+                );
+                for topic in CE_TOPICS {
+                    ui.selectable_value(
+                        &mut app.ce_form.requirement_topic,
+                        topic.to_string(),
+                        topic,
+                    );
+                }
+            });
+        ui.add(
+            egui::DragValue::new(&mut app.ce_form.requirement_hours)
+                .speed(0.5)
+                .range(0.0..=200.0),
+        );
+        if users::guarded_button(ui, can_edit_employees, "Add Requirement").clicked() {
+            if app.ce_form.requirement_license_type.is_empty()
+                || app.ce_form.requirement_topic.is_empty()
+            {
+                app.ce_form.status = "Select a license type and topic".to_string();
+            } else if let Err(e) = add_requirement(
+                &app.conn,
+                &app.ce_form.requirement_license_type,
+                &app.ce_form.requirement_topic,
+                app.ce_form.requirement_hours,
+            ) {
+                app.ce_form.status = format!("Error adding requirement: {}", e);
+            }
+        }
+    });
+}
@@ -1,6 +1,20 @@
 use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::continuing_education;
+use crate::app::deductions;
+use crate::app::earnings;
 use crate::app::employee::Employee;
+use crate::app::employee::PayType;
+use crate::app::payroll::ImportedAs;
+use crate::app::payroll::OffCycleReason;
 use crate::app::payroll::PayrollEntry;
+use crate::app::pto;
+use crate::app::repository::EmployeeRepository;
+use crate::app::retirement;
+use crate::app::retro;
+use crate::app::security;
+use crate::app::ssn;
 use rusqlite::params;
 use rusqlite::Connection;
 use std::path::PathBuf;
@@ -11,96 +25,89 @@ pub fn get_employee_payroll_history(
     id: i32,
 ) -> Result<PayrollEntry, rusqlite::Error> {
     conn.query_row(
-        "SELECT 
-                    id,
-                    date_of_pay,
-                    employee_id,
-                    hours_worked,
-                    gross,
-                    withholding,
-                    roth_ira,
-                    social_security,
-                    net
-                    FROM payroll WHERE id = ?1",
+        &format!("SELECT {} FROM payroll WHERE id = ?1", PAYROLL_COLUMNS),
         [id],
-        |row| {
-            Ok(PayrollEntry {
-                id: row.get(0)?,
-                date_of_pay: row.get(1)?,
-                employee_id: row.get(2)?,
-                hours_worked: row.get(3)?,
-                gross: row.get(4)?,
-                withholding: row.get(5)?,
-                roth_ira: row.get(6)?,
-                social_security: row.get(7)?,
-                net: row.get(8)?,
-            })
-        },
+        payroll_entry_from_row,
     )
 }
 
+const PAYROLL_COLUMNS: &str = "id, date_of_pay, employee_id, hours_worked, gross, withholding,
+    roth_ira, social_security, net, pto_hours, off_cycle_reason, imported";
+
+fn payroll_entry_from_row(row: &rusqlite::Row) -> Result<PayrollEntry, rusqlite::Error> {
+    Ok(PayrollEntry {
+        id: row.get(0)?,
+        date_of_pay: row.get(1)?,
+        employee_id: row.get(2)?,
+        hours_worked: row.get(3)?,
+        gross: row.get(4)?,
+        withholding: row.get(5)?,
+        roth_ira: row.get(6)?,
+        social_security: row.get(7)?,
+        net: row.get(8)?,
+        pto_hours: row.get(9)?,
+        off_cycle_reason: row
+            .get::<_, Option<String>>(10)?
+            .map(|reason| OffCycleReason::parse(&reason)),
+        imported: row
+            .get::<_, Option<String>>(11)?
+            .map(|imported| ImportedAs::parse(&imported)),
+    })
+}
+
 #[allow(dead_code)]
 pub fn get_all_payroll_entries(conn: &Connection) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT * FROM payroll WHERE employee_id = ?1    ")?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM payroll", PAYROLL_COLUMNS))?;
     let payroll_entries: Vec<PayrollEntry> = stmt
-        .query_map([], |row| {
-            Ok(PayrollEntry {
-                id: row.get(0)?,
-                employee_id: row.get(1)?,
-                hours_worked: row.get(2)?,
-                date_of_pay: row.get(3)?,
-                gross: row.get(4)?,
-                withholding: row.get(5)?,
-                social_security: row.get(6)?,
-                net: row.get(7)?,
-                roth_ira: row.get(8)?,
-            })
-        })?
+        .query_map([], payroll_entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
     Ok(payroll_entries)
 }
 
 #[allow(dead_code)]
 pub fn get_payroll_by_id(conn: &Connection, id: i32) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
-    let mut stmt =
-        conn.prepare("SELECT * FROM payroll WHERE employee_id = ?1 ORDER BY date_of_pay")?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM payroll WHERE employee_id = ?1 ORDER BY date_of_pay",
+        PAYROLL_COLUMNS
+    ))?;
     let payroll_entries: Vec<PayrollEntry> = stmt
-        .query_map([id], |row| {
-            Ok(PayrollEntry {
-                id: row.get(0)?,
-                employee_id: row.get(1)?,
-                hours_worked: row.get(2)?,
-                date_of_pay: row.get(3)?,
-                gross: row.get(4)?,
-                withholding: row.get(5)?,
-                social_security: row.get(6)?,
-                net: row.get(7)?,
-                roth_ira: row.get(8)?,
-            })
-        })?
+        .query_map([id], payroll_entry_from_row)?
         .collect::<Result<Vec<_>, _>>()?;
     Ok(payroll_entries)
 }
 
+const EMPLOYEE_COLUMNS: &str = "id, name, position, address, city, state, phone, filing_status,
+    dependents, pay_rate, pay_type, flsa_exempt, hire_date, termination_date, home_location_id,
+    company_id, ssn";
+
+fn employee_from_row(row: &rusqlite::Row) -> Result<Employee, rusqlite::Error> {
+    Ok(Employee {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        position: row.get(2)?,
+        address: security::open_field(&row.get::<_, String>(3)?),
+        city: row.get(4)?,
+        state: row.get(5)?,
+        phone: security::open_field(&row.get::<_, String>(6)?),
+        filing_status: row.get(7)?,
+        dependents: row.get(8)?,
+        pay_rate: row.get(9)?,
+        pay_type: PayType::parse(&row.get::<_, String>(10)?),
+        flsa_exempt: row.get(11)?,
+        hire_date: row.get(12)?,
+        termination_date: row.get(13)?,
+        home_location_id: row.get(14)?,
+        company_id: row.get(15)?,
+        ssn: security::open_field(&row.get::<_, String>(16)?),
+    })
+}
+
 #[allow(dead_code)]
 pub fn get_employee_by_id(conn: &Connection, id: i32) -> Result<Employee, rusqlite::Error> {
     conn.query_row(
-        "SELECT id, name, position, address, city, state, phone, filing_status, dependents, pay_rate FROM employees WHERE id = ?1",
+        &format!("SELECT {} FROM employees WHERE id = ?1", EMPLOYEE_COLUMNS),
         [id],
-        |row| {
-            Ok(Employee{
-                id: row.get(0)?,
-                name: row.get(1)?,
-                position: row.get(2)?,
-                address: row.get(3)?,
-                city: row.get(4)?,
-                state: row.get(5)?,
-                phone: row.get(6)?,
-                filing_status: row.get(7)?,
-                dependents : row.get(8)?,
-                pay_rate : row.get(9)?,
-            })
-        }
+        employee_from_row,
     )
 }
 
@@ -127,33 +134,45 @@ pub fn add_employee(app: &mut PharmacyApp) {
         return;
     }
 
+    let ssn = if app.ssn.trim().is_empty() {
+        String::new()
+    } else {
+        let ssn = match ssn::validate_ssn(&app.ssn) {
+            Ok(ssn) => ssn,
+            Err(e) => {
+                app.search_status = e;
+                return;
+            }
+        };
+        if let Ok(Some(other)) = ssn::find_duplicate(&app.conn, &ssn, None) {
+            app.search_status = format!("SSN is already on file for {}", other.name);
+            return;
+        }
+        ssn
+    };
+
     if !app.employee_name.is_empty() && !app.employee_position.is_empty() {
-        app.conn
-            .execute(
-                "INSERT INTO employees (
-                        name,
-                        position,
-                        address,
-                        city,
-                        state,
-                        phone,
-                        filing_status,
-                        dependents,
-                        pay_rate)
-                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-                params![
-                    &app.employee_name,
-                    &app.employee_position,
-                    &app.address,
-                    &app.city,
-                    &app.state,
-                    &app.phone,
-                    &app.filing_status,
-                    &app.dependents,
-                    &app.pay_rate
-                ],
-            )
-            .expect("Failed to add employee");
+        let employee = Employee {
+            name: app.employee_name.clone(),
+            position: app.employee_position.clone(),
+            address: app.address.clone(),
+            city: app.city.clone(),
+            state: app.state.clone(),
+            phone: app.phone.clone(),
+            filing_status: app.filing_status.clone(),
+            dependents: app.dependents.clone(),
+            pay_rate: app.pay_rate.clone(),
+            pay_type: app.pay_type,
+            flsa_exempt: app.flsa_exempt,
+            hire_date: app.hire_date.clone(),
+            company_id: app.company_id,
+            ssn,
+            ..Default::default()
+        };
+        if let Err(e) = app.repo().add_employee(&employee) {
+            app.search_status = format!("Error adding employee: {}", e);
+            return;
+        }
         app.search_status = "Employee added successfully".to_string();
         app.employee_name.clear();
         app.employee_position.clear();
@@ -164,35 +183,105 @@ pub fn add_employee(app: &mut PharmacyApp) {
         app.filing_status.clear();
         app.dependents.clear();
         app.pay_rate.clear();
+        app.pay_type = PayType::Hourly;
+        app.flsa_exempt = false;
+        app.hire_date.clear();
+        app.ssn.clear();
     } else {
         app.search_status = "Please enter both name and position".to_string();
         println!("error adding employee");
     }
 }
 
+/// Inserts a new employee with the sensitive fields sealed and returns its id.
+pub fn insert_employee(conn: &Connection, employee: &Employee) -> Result<i64, rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO employees (
+                name,
+                position,
+                address,
+                city,
+                state,
+                phone,
+                filing_status,
+                dependents,
+                pay_rate,
+                pay_type,
+                flsa_exempt,
+                hire_date,
+                company_id,
+                ssn)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            &employee.name,
+            &employee.position,
+            security::seal_field(&employee.address),
+            &employee.city,
+            &employee.state,
+            security::seal_field(&employee.phone),
+            &employee.filing_status,
+            &employee.dependents,
+            &employee.pay_rate,
+            employee.pay_type.as_str(),
+            employee.flsa_exempt,
+            &employee.hire_date,
+            employee.company_id,
+            security::seal_field(&employee.ssn)
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    let after = audit::redacted_employee(&get_employee_by_id(conn, id as i32)?);
+    audit::record(
+        conn,
+        "employee",
+        id,
+        AuditAction::Insert,
+        None,
+        Some(&after),
+    )?;
+    Ok(id)
+}
+
 #[allow(dead_code)]
 pub fn get_all_employees(conn: &Connection) -> Result<Vec<Employee>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT * FROM employees")?;
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM employees", EMPLOYEE_COLUMNS))?;
     let employees: Vec<Employee> = stmt
-        .query_map([], |row| {
-            Ok(Employee {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                position: row.get(2)?,
-                address: row.get(3)?,
-                city: row.get(4)?,
-                state: row.get(5)?,
-                phone: row.get(6)?,
-                filing_status: row.get(7)?,
-                dependents: row.get(8)?,
-                pay_rate: row.get(9)?,
-            })
-        })?
-        .filter_map(Result::ok)
-        .collect();
+        .query_map([], employee_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(employees)
 }
 
+pub fn update_pay_setup(conn: &Connection, employee: &Employee) -> Result<(), rusqlite::Error> {
+    let before = get_employee_by_id(conn, employee.id)?;
+    conn.execute(
+        "UPDATE employees SET
+            pay_rate = ?1,
+            pay_type = ?2,
+            flsa_exempt = ?3,
+            hire_date = ?4,
+            termination_date = ?5
+        WHERE id = ?6",
+        params![
+            &employee.pay_rate,
+            employee.pay_type.as_str(),
+            employee.flsa_exempt,
+            &employee.hire_date,
+            &employee.termination_date,
+            employee.id
+        ],
+    )?;
+    let after = get_employee_by_id(conn, employee.id)?;
+    audit::record(
+        conn,
+        "employee",
+        employee.id as i64,
+        AuditAction::Update,
+        Some(&audit::redacted_employee(&before)),
+        Some(&audit::redacted_employee(&after)),
+    )?;
+    Ok(())
+}
+
 pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
     conn.execute(
         "CREATE TABLE IF NOT EXISTS employees (
@@ -208,6 +297,28 @@ pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
             pay_rate TEXT)",
         [],
     )?;
+    add_column_if_missing(
+        conn,
+        "employees",
+        "pay_type",
+        "TEXT NOT NULL DEFAULT 'hourly'",
+    )?;
+    add_column_if_missing(
+        conn,
+        "employees",
+        "flsa_exempt",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(conn, "employees", "hire_date", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(
+        conn,
+        "employees",
+        "termination_date",
+        "TEXT NOT NULL DEFAULT ''",
+    )?;
+    add_column_if_missing(conn, "employees", "home_location_id", "INTEGER")?;
+    add_column_if_missing(conn, "employees", "company_id", "INTEGER")?;
+    add_column_if_missing(conn, "employees", "ssn", "TEXT NOT NULL DEFAULT ''")?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS payroll (
@@ -222,36 +333,454 @@ pub fn initialize_tables(conn: &Connection) -> Result<(), rusqlite::Error> {
             roth_ira REAL)",
         [],
     )?;
+    add_column_if_missing(conn, "payroll", "pto_hours", "REAL NOT NULL DEFAULT 0")?;
+    add_column_if_missing(conn, "payroll", "off_cycle_reason", "TEXT")?;
+    add_column_if_missing(conn, "payroll", "company_id", "INTEGER")?;
+    add_column_if_missing(conn, "payroll", "imported", "TEXT")?;
+    // History from a previous provider is locked; its company can still be assigned.
+    conn.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS payroll_imported_no_update
+        BEFORE UPDATE OF employee_id, hours_worked, date_of_pay, gross, withholding,
+            social_security, net, roth_ira, pto_hours, off_cycle_reason, imported ON payroll
+        WHEN OLD.imported IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;
+        CREATE TRIGGER IF NOT EXISTS payroll_imported_no_delete BEFORE DELETE ON payroll
+        WHEN OLD.imported IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS earnings (
+            id INTEGER PRIMARY KEY,
+            payroll_id INTEGER,
+            earning_type TEXT,
+            hours REAL,
+            rate REAL,
+            amount REAL,
+            fit_taxable INTEGER,
+            fica_taxable INTEGER,
+            supplemental INTEGER)",
+        [],
+    )?;
+    add_column_if_missing(conn, "earnings", "location_id", "INTEGER")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS companies (
+            id INTEGER PRIMARY KEY,
+            legal_name TEXT,
+            ein TEXT,
+            address TEXT,
+            state_tax_id TEXT)",
+        [],
+    )?;
+    for (column, definition) in [
+        ("dba", "TEXT NOT NULL DEFAULT ''"),
+        ("city", "TEXT NOT NULL DEFAULT ''"),
+        ("state", "TEXT NOT NULL DEFAULT ''"),
+        ("zip", "TEXT NOT NULL DEFAULT ''"),
+        ("phone", "TEXT NOT NULL DEFAULT ''"),
+        ("state_unemployment_id", "TEXT NOT NULL DEFAULT ''"),
+        ("suta_rate", "REAL NOT NULL DEFAULT 0"),
+        ("logo", "BLOB"),
+        ("fiscal_year_start_month", "INTEGER NOT NULL DEFAULT 1"),
+        ("deposit_schedule", "TEXT NOT NULL DEFAULT 'monthly'"),
+        ("suta_wage_base", "REAL NOT NULL DEFAULT 9000"),
+    ] {
+        add_column_if_missing(conn, "companies", column, definition)?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tax_deposits (
+            id INTEGER PRIMARY KEY,
+            company_id INTEGER NOT NULL DEFAULT 0,
+            kind TEXT NOT NULL,
+            period_key TEXT NOT NULL,
+            amount REAL NOT NULL,
+            due_date TEXT NOT NULL,
+            paid_date TEXT NOT NULL,
+            UNIQUE (company_id, kind, period_key))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS gl_accounts (
+            company_id INTEGER NOT NULL DEFAULT 0,
+            key TEXT NOT NULL,
+            account TEXT NOT NULL,
+            PRIMARY KEY (company_id, key))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY,
+            occurred_at TEXT NOT NULL,
+            actor TEXT NOT NULL,
+            entity TEXT NOT NULL,
+            entity_id INTEGER NOT NULL,
+            action TEXT NOT NULL,
+            before_json TEXT NOT NULL,
+            after_json TEXT NOT NULL,
+            prev_hash TEXT NOT NULL,
+            hash TEXT NOT NULL)",
+        [],
+    )?;
+    // Entries can only be appended; the hash chain catches edits made outside the app.
+    conn.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'The audit log is append-only'); END;
+        CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'The audit log is append-only'); END;",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL,
+            active INTEGER NOT NULL DEFAULT 1)",
+        [],
+    )?;
+    add_column_if_missing(conn, "users", "pin_hash", "TEXT NOT NULL DEFAULT ''")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS sensitive_access_log (
+            id INTEGER PRIMARY KEY,
+            accessed_at TEXT NOT NULL,
+            employee_id INTEGER NOT NULL,
+            field TEXT NOT NULL)",
+        [],
+    )?;
+    add_column_if_missing(
+        conn,
+        "sensitive_access_log",
+        "username",
+        "TEXT NOT NULL DEFAULT ''",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS field_keys (
+            id INTEGER PRIMARY KEY,
+            salt BLOB NOT NULL,
+            wrapped_key BLOB NOT NULL)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS locations (
+            id INTEGER PRIMARY KEY,
+            name TEXT,
+            address TEXT,
+            active INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS deduction_elections (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            deduction_type TEXT,
+            method TEXT,
+            amount REAL,
+            catch_up_eligible INTEGER,
+            family_coverage INTEGER,
+            active INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS deduction_lines (
+            id INTEGER PRIMARY KEY,
+            payroll_id INTEGER,
+            code TEXT,
+            description TEXT,
+            amount REAL,
+            pre_tax_fit INTEGER,
+            pre_tax_fica INTEGER)",
+        [],
+    )?;
+    conn.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS earnings_imported_no_change BEFORE DELETE ON earnings
+        WHEN (SELECT imported FROM payroll WHERE id = OLD.payroll_id) IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;
+        CREATE TRIGGER IF NOT EXISTS deduction_lines_imported_no_change
+        BEFORE DELETE ON deduction_lines
+        WHEN (SELECT imported FROM payroll WHERE id = OLD.payroll_id) IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;
+        CREATE TRIGGER IF NOT EXISTS earnings_imported_no_update BEFORE UPDATE ON earnings
+        WHEN (SELECT imported FROM payroll WHERE id = OLD.payroll_id) IS NOT NULL
+            OR (SELECT imported FROM payroll WHERE id = NEW.payroll_id) IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;
+        CREATE TRIGGER IF NOT EXISTS deduction_lines_imported_no_update
+        BEFORE UPDATE ON deduction_lines
+        WHEN (SELECT imported FROM payroll WHERE id = OLD.payroll_id) IS NOT NULL
+            OR (SELECT imported FROM payroll WHERE id = NEW.payroll_id) IS NOT NULL
+        BEGIN SELECT RAISE(ABORT, 'Imported payroll history can''t be changed'); END;",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS employer_contribution_rules (
+            id INTEGER PRIMARY KEY,
+            name TEXT,
+            plan TEXT,
+            formula TEXT,
+            rate REAL,
+            pay_cap_percent REAL,
+            active INTEGER)",
+        [],
+    )?;
+    add_column_if_missing(conn, "employer_contribution_rules", "company_id", "INTEGER")?;
+    // Rules added before companies existed belong to the first company, like its employees.
+    conn.execute(
+        "UPDATE employer_contribution_rules SET company_id = (SELECT MIN(id) FROM companies)
+        WHERE company_id IS NULL",
+        [],
+    )?;
+
+    let enrollments_existed = table_exists(conn, "retirement_enrollments")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS retirement_enrollments (
+            employee_id INTEGER,
+            rule_id INTEGER,
+            PRIMARY KEY (employee_id, rule_id))",
+        [],
+    )?;
+    if !enrollments_existed {
+        // Rules used to cover everyone, so keep the employees they already applied to enrolled.
+        conn.execute(
+            "INSERT OR IGNORE INTO retirement_enrollments (employee_id, rule_id)
+            SELECT e.id, r.id FROM employees e JOIN employer_contribution_rules r
+            ON r.company_id IS e.company_id",
+            [],
+        )?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS employer_contributions (
+            id INTEGER PRIMARY KEY,
+            payroll_id INTEGER,
+            rule_id INTEGER,
+            plan TEXT,
+            description TEXT,
+            amount REAL)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS garnishment_orders (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            garnishment_type TEXT,
+            agency TEXT,
+            case_number TEXT,
+            amount_per_check REAL,
+            exempt_amount REAL,
+            total_owed REAL,
+            supports_other_family INTEGER,
+            arrears_over_12_weeks INTEGER,
+            active INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS licenses (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            license_type TEXT,
+            license_number TEXT,
+            issuing_state TEXT,
+            issue_date TEXT,
+            expiry_date TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS license_documents (
+            id INTEGER PRIMARY KEY,
+            license_id INTEGER,
+            file_name TEXT,
+            stored_path TEXT)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ce_records (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            course_name TEXT,
+            provider TEXT,
+            hours REAL,
+            topic TEXT,
+            completion_date TEXT,
+            certificate_path TEXT)",
+        [],
+    )?;
+
+    let requirements_existed = table_exists(conn, "ce_requirements")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ce_requirements (
+            id INTEGER PRIMARY KEY,
+            license_type TEXT,
+            topic TEXT,
+            hours REAL)",
+        [],
+    )?;
+    // Seeded only with the table so requirements the pharmacy deletes stay deleted.
+    if !requirements_existed {
+        continuing_education::seed_default_requirements(conn)?;
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pto_policies (
+            id INTEGER PRIMARY KEY,
+            name TEXT,
+            leave_type TEXT,
+            accrual_method TEXT,
+            accrual_hours REAL,
+            per_hours_worked REAL,
+            annual_accrual_cap REAL,
+            balance_cap REAL,
+            carryover_limit REAL)",
+        [],
+    )?;
+    add_column_if_missing(
+        conn,
+        "pto_policies",
+        "allow_negative",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pto_enrollments (
+            employee_id INTEGER,
+            policy_id INTEGER,
+            PRIMARY KEY (employee_id, policy_id))",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pto_ledger (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            policy_id INTEGER,
+            entry_date TEXT,
+            hours REAL,
+            kind TEXT,
+            payroll_id INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS pto_requests (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            policy_id INTEGER,
+            start_date TEXT,
+            end_date TEXT,
+            hours REAL,
+            status TEXT,
+            note TEXT,
+            payroll_id INTEGER)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS retro_adjustments (
+            id INTEGER PRIMARY KEY,
+            employee_id INTEGER,
+            new_rate REAL,
+            effective_date TEXT,
+            amount REAL,
+            payroll_id INTEGER)",
+        [],
+    )?;
+
+    // The checks each approved retro adjustment repriced, so a later approval credits it.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS retro_adjustment_checks (
+            adjustment_id INTEGER NOT NULL REFERENCES retro_adjustments(id),
+            payroll_id INTEGER NOT NULL,
+            amount REAL NOT NULL)",
+        [],
+    )?;
 
     Ok(())
 }
 
-pub fn get_db_path() -> PathBuf {
+/// Brings databases created by older versions up to the current schema.
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [table],
+        |row| row.get(0),
+    )
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .flatten()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn get_data_dir() -> PathBuf {
     let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
     path.push("my_payroll_app");
     std::fs::create_dir_all(&path).expect("Failed to create data directory");
+    path
+}
+
+pub fn get_db_path() -> PathBuf {
+    let mut path = get_data_dir();
     path.push("employees.db");
     path
 }
 
+/// Removes a check and everything posted with it. Imported history is refused by the
+/// payroll triggers, which rolls the whole delete back.
 pub fn delete_payroll_entry(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
-    conn.execute("DELETE FROM payroll WHERE id = ?", [id])?;
-    Ok(())
+    let tx = conn.unchecked_transaction()?;
+    let before = serde_json::json!({
+        "check": get_employee_payroll_history(&tx, id as i32).ok(),
+        "earnings": earnings::get_earnings_for_check(&tx, id)?,
+    });
+    pto::reverse_payroll(&tx, id)?;
+    earnings::delete_earnings_for_check(&tx, id)?;
+    deductions::delete_deductions_for_check(&tx, id)?;
+    retirement::delete_contributions_for_check(&tx, id)?;
+    retro::release_check(&tx, id)?;
+    tx.execute("DELETE FROM payroll WHERE id = ?", [id])?;
+    audit::record(&tx, "payroll", id, AuditAction::Delete, Some(&before), None)?;
+    tx.commit()
 }
 
 pub fn get_payroll_dates_for_employee(
     conn: &Connection,
     employee_id: i32,
 ) -> Result<Vec<String>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT date_of_pay FROM payroll WHERE employee_id = ?1")?;
+    // Only regular checks use up a Friday; off-cycle checks and imported history can share
+    // the date.
+    let mut stmt = conn.prepare(
+        "SELECT date_of_pay FROM payroll
+        WHERE employee_id = ?1 AND off_cycle_reason IS NULL AND imported IS NULL",
+    )?;
 
-    let dates_iter = stmt.query_map([employee_id], |row| Ok(row.get::<_, String>(0)?))?;
-    let mut dates = Vec::new();
-    for date in dates_iter {
-        if let Ok(date) = date {
-            dates.push(date)
-        }
-    }
+    let dates_iter = stmt.query_map([employee_id], |row| row.get::<_, String>(0))?;
+    let dates = dates_iter.flatten().collect();
 
     Ok(dates)
 }
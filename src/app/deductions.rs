@@ -0,0 +1,535 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::Datelike;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TaxTreatment {
+    /// Excluded from federal income tax wages but still subject to FICA (401(k) deferrals).
+    PreTaxFitOnly,
+    /// Excluded from both income tax and FICA wages (Section 125 cafeteria plan items).
+    PreTaxFitAndFica,
+    PostTax,
+}
+
+impl TaxTreatment {
+    pub fn reduces_fit(&self) -> bool {
+        matches!(
+            self,
+            TaxTreatment::PreTaxFitOnly | TaxTreatment::PreTaxFitAndFica
+        )
+    }
+
+    pub fn reduces_fica(&self) -> bool {
+        matches!(self, TaxTreatment::PreTaxFitAndFica)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TaxTreatment::PreTaxFitOnly => "Pre-tax (FIT only)",
+            TaxTreatment::PreTaxFitAndFica => "Pre-tax (FIT and FICA)",
+            TaxTreatment::PostTax => "Post-tax",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DeductionType {
+    Traditional401k,
+    Roth401k,
+    SimpleIra,
+    Hsa,
+    Section125Health,
+    HealthFsa,
+    RothIra,
+}
+
+impl DeductionType {
+    pub const ALL: [DeductionType; 7] = [
+        DeductionType::Traditional401k,
+        DeductionType::Roth401k,
+        DeductionType::SimpleIra,
+        DeductionType::Hsa,
+        DeductionType::Section125Health,
+        DeductionType::HealthFsa,
+        DeductionType::RothIra,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeductionType::Traditional401k => "401k",
+            DeductionType::Roth401k => "roth_401k",
+            DeductionType::SimpleIra => "simple_ira",
+            DeductionType::Hsa => "hsa",
+            DeductionType::Section125Health => "section_125_health",
+            DeductionType::HealthFsa => "health_fsa",
+            DeductionType::RothIra => "roth_ira",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        DeductionType::ALL
+            .into_iter()
+            .find(|deduction_type| deduction_type.as_str() == value)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DeductionType::Traditional401k => "401(k)",
+            DeductionType::Roth401k => "Roth 401(k)",
+            DeductionType::SimpleIra => "SIMPLE IRA",
+            DeductionType::Hsa => "HSA",
+            DeductionType::Section125Health => "Health Premium (Sec. 125)",
+            DeductionType::HealthFsa => "Health FSA",
+            DeductionType::RothIra => "Roth IRA",
+        }
+    }
+
+    pub fn tax_treatment(&self) -> TaxTreatment {
+        match self {
+            DeductionType::Traditional401k | DeductionType::SimpleIra => {
+                TaxTreatment::PreTaxFitOnly
+            }
+            DeductionType::Hsa | DeductionType::Section125Health | DeductionType::HealthFsa => {
+                TaxTreatment::PreTaxFitAndFica
+            }
+            DeductionType::Roth401k | DeductionType::RothIra => TaxTreatment::PostTax,
+        }
+    }
+
+    /// Deduction types that draw down the same IRS annual limit.
+    pub fn limit_group(&self) -> &'static str {
+        match self {
+            DeductionType::Traditional401k | DeductionType::Roth401k => "401k",
+            other => other.as_str(),
+        }
+    }
+
+    /// 2026 IRS annual limit as (base, catch-up), or `None` when the type has no limit.
+    pub fn annual_limit(&self, family_coverage: bool) -> Option<(f32, f32)> {
+        match self {
+            DeductionType::Traditional401k | DeductionType::Roth401k => Some((24_500.0, 8_000.0)),
+            DeductionType::SimpleIra => Some((17_000.0, 4_000.0)),
+            DeductionType::Hsa if family_coverage => Some((8_750.0, 1_000.0)),
+            DeductionType::Hsa => Some((4_400.0, 1_000.0)),
+            DeductionType::HealthFsa => Some((3_400.0, 0.0)),
+            DeductionType::RothIra => Some((7_500.0, 1_100.0)),
+            DeductionType::Section125Health => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DeductionMethod {
+    FixedAmount,
+    PercentOfGross,
+}
+
+impl DeductionMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeductionMethod::FixedAmount => "fixed",
+            DeductionMethod::PercentOfGross => "percent",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "percent" => DeductionMethod::PercentOfGross,
+            _ => DeductionMethod::FixedAmount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeductionElection {
+    pub id: i64,
+    pub employee_id: i32,
+    pub deduction_type: DeductionType,
+    pub method: DeductionMethod,
+    /// Dollars per check for `FixedAmount`, percent of gross for `PercentOfGross`.
+    pub amount: f32,
+    pub catch_up_eligible: bool,
+    pub family_coverage: bool,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeductionLine {
+    pub id: i64,
+    pub payroll_id: i64,
+    pub code: String,
+    pub description: String,
+    pub amount: f32,
+    pub pre_tax_fit: bool,
+    pub pre_tax_fica: bool,
+}
+
+impl DeductionElection {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO deduction_elections (
+                employee_id,
+                deduction_type,
+                method,
+                amount,
+                catch_up_eligible,
+                family_coverage,
+                active)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                self.employee_id,
+                self.deduction_type.as_str(),
+                self.method.as_str(),
+                self.amount,
+                self.catch_up_eligible,
+                self.family_coverage,
+                self.active
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(
+            conn,
+            "deduction_election",
+            "deduction_elections",
+            id,
+            AuditAction::Insert,
+            None,
+        )?;
+        Ok(id)
+    }
+
+    pub fn annual_limit(&self) -> Option<f32> {
+        self.deduction_type
+            .annual_limit(self.family_coverage)
+            .map(|(base, catch_up)| {
+                if self.catch_up_eligible {
+                    base + catch_up
+                } else {
+                    base
+                }
+            })
+    }
+
+    pub fn describe(&self) -> String {
+        match self.method {
+            DeductionMethod::FixedAmount => format!("${:.2} per check", self.amount),
+            DeductionMethod::PercentOfGross => format!("{:.2}% of gross", self.amount),
+        }
+    }
+}
+
+impl DeductionLine {
+    pub fn save_to_db(&self, conn: &Connection, payroll_id: i64) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO deduction_lines (
+                payroll_id,
+                code,
+                description,
+                amount,
+                pre_tax_fit,
+                pre_tax_fica)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                payroll_id,
+                &self.code,
+                &self.description,
+                self.amount,
+                self.pre_tax_fit,
+                self.pre_tax_fica
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+pub fn get_elections_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<DeductionElection>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, deduction_type, method, amount, catch_up_eligible, family_coverage, active
+        FROM deduction_elections WHERE employee_id = ?1 ORDER BY id",
+    )?;
+    let elections = stmt
+        .query_map([employee_id], |row| {
+            let code: String = row.get(2)?;
+            let Some(deduction_type) = DeductionType::parse(&code) else {
+                return Ok(None);
+            };
+            Ok(Some(DeductionElection {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                deduction_type,
+                method: DeductionMethod::parse(&row.get::<_, String>(3)?),
+                amount: row.get(4)?,
+                catch_up_eligible: row.get(5)?,
+                family_coverage: row.get(6)?,
+                active: row.get(7)?,
+            }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(elections.into_iter().flatten().collect())
+}
+
+pub fn set_election_active(
+    conn: &Connection,
+    id: i64,
+    active: bool,
+) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "deduction_elections", id)?;
+    conn.execute(
+        "UPDATE deduction_elections SET active = ?1 WHERE id = ?2",
+        params![active, id],
+    )?;
+    audit::record_row(
+        conn,
+        "deduction_election",
+        "deduction_elections",
+        id,
+        AuditAction::Update,
+        before.as_ref(),
+    )
+}
+
+pub fn get_deductions_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<Vec<DeductionLine>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, payroll_id, code, description, amount, pre_tax_fit, pre_tax_fica
+        FROM deduction_lines WHERE payroll_id = ?1 ORDER BY id",
+    )?;
+    let lines = stmt
+        .query_map([payroll_id], |row| {
+            Ok(DeductionLine {
+                id: row.get(0)?,
+                payroll_id: row.get(1)?,
+                code: row.get(2)?,
+                description: row.get(3)?,
+                amount: row.get(4)?,
+                pre_tax_fit: row.get(5)?,
+                pre_tax_fica: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines)
+}
+
+pub fn delete_deductions_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM deduction_lines WHERE payroll_id = ?1",
+        [payroll_id],
+    )?;
+    Ok(())
+}
+
+/// Year-to-date amounts withheld per limit group, for checks paid in `year`.
+pub fn get_ytd_by_limit_group(
+    conn: &Connection,
+    employee_id: i32,
+    year: i32,
+) -> Result<HashMap<String, f32>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT d.code, SUM(d.amount) FROM deduction_lines d
+        JOIN payroll p ON p.id = d.payroll_id
+        WHERE p.employee_id = ?1 AND substr(p.date_of_pay, 1, 4) = ?2
+        GROUP BY d.code",
+    )?;
+    let rows = stmt
+        .query_map(params![employee_id, year.to_string()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, f32>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut ytd = HashMap::new();
+    for (code, amount) in rows {
+        if let Some(deduction_type) = DeductionType::parse(&code) {
+            *ytd.entry(deduction_type.limit_group().to_string())
+                .or_insert(0.0) += amount;
+        }
+    }
+    Ok(ytd)
+}
+
+/// Turns an employee's active elections into this check's deduction lines, capping each one
+/// at whatever is left of its IRS annual limit and at the pay left on the check.
+pub fn calculate_deductions(
+    elections: &[DeductionElection],
+    gross: f32,
+    ytd: &HashMap<String, f32>,
+) -> Vec<DeductionLine> {
+    let mut taken: HashMap<&str, f32> = HashMap::new();
+    let mut remaining_pay = gross;
+    let mut lines = Vec::new();
+
+    for election in elections.iter().filter(|e| e.active) {
+        let requested = match election.method {
+            DeductionMethod::FixedAmount => election.amount,
+            DeductionMethod::PercentOfGross => gross * election.amount / 100.0,
+        };
+        let group = election.deduction_type.limit_group();
+        let mut amount = requested.min(remaining_pay);
+        if let Some(limit) = election.annual_limit() {
+            let used =
+                ytd.get(group).copied().unwrap_or(0.0) + taken.get(group).copied().unwrap_or(0.0);
+            amount = amount.min(limit - used);
+        }
+        let amount = (amount.max(0.0) * 100.0).round() / 100.0;
+        if amount <= 0.0 {
+            continue;
+        }
+
+        *taken.entry(group).or_insert(0.0) += amount;
+        remaining_pay -= amount;
+        let treatment = election.deduction_type.tax_treatment();
+        lines.push(DeductionLine {
+            id: 0,
+            payroll_id: 0,
+            code: election.deduction_type.as_str().to_string(),
+            description: election.deduction_type.label().to_string(),
+            amount,
+            pre_tax_fit: treatment.reduces_fit(),
+            pre_tax_fica: treatment.reduces_fica(),
+        });
+    }
+    lines
+}
+
+pub struct DeductionForm {
+    pub deduction_type: DeductionType,
+    pub method: DeductionMethod,
+    pub amount: f32,
+    pub catch_up_eligible: bool,
+    pub family_coverage: bool,
+    pub status: String,
+}
+
+impl Default for DeductionForm {
+    fn default() -> Self {
+        Self {
+            deduction_type: DeductionType::Traditional401k,
+            method: DeductionMethod::PercentOfGross,
+            amount: 0.0,
+            catch_up_eligible: false,
+            family_coverage: false,
+            status: String::new(),
+        }
+    }
+}
+
+pub fn render_elections(app: &mut PharmacyApp, ui: &mut Ui, employee_id: i32) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("Deduction Elections");
+    let year = chrono::Local::now().year();
+    let ytd = get_ytd_by_limit_group(&app.conn, employee_id, year).unwrap_or_default();
+
+    match get_elections_for_employee(&app.conn, employee_id) {
+        Ok(elections) if elections.is_empty() => {
+            ui.label("No deduction elections");
+        }
+        Ok(elections) => {
+            egui::Grid::new("deduction_elections_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Deduction");
+                    ui.strong("Election");
+                    ui.strong("Tax Treatment");
+                    ui.strong("YTD / Limit");
+                    ui.strong("Status");
+                    ui.end_row();
+                    for election in &elections {
+                        ui.label(election.deduction_type.label());
+                        ui.label(election.describe());
+                        ui.label(election.deduction_type.tax_treatment().label());
+                        let used = ytd
+                            .get(election.deduction_type.limit_group())
+                            .copied()
+                            .unwrap_or(0.0);
+                        match election.annual_limit() {
+                            Some(limit) => ui.label(format!("{:.2} / {:.2}", used, limit)),
+                            None => ui.label(format!("{:.2} / no limit", used)),
+                        };
+                        let label = if election.active { "Stop" } else { "Resume" };
+                        ui.label(if election.active { "Active" } else { "Stopped" });
+                        if users::guarded_button(ui, can_edit_payroll, label).clicked() {
+                            set_election_active(&app.conn, election.id, !election.active)
+                                .unwrap_or_else(|e| println!("Error updating election: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching elections: {}", e));
+        }
+    }
+
+    let form = &mut app.deduction_form;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("deduction_type_select")
+            .selected_text(form.deduction_type.label())
+            .show_ui(ui, |ui| {
+                for deduction_type in DeductionType::ALL {
+                    ui.selectable_value(
+                        &mut form.deduction_type,
+                        deduction_type,
+                        deduction_type.label(),
+                    );
+                }
+            });
+        ui.selectable_value(
+            &mut form.method,
+            DeductionMethod::PercentOfGross,
+            "% of gross",
+        );
+        ui.selectable_value(
+            &mut form.method,
+            DeductionMethod::FixedAmount,
+            "$ per check",
+        );
+        ui.add(
+            egui::DragValue::new(&mut form.amount)
+                .speed(0.1)
+                .range(0.0..=10_000.0),
+        );
+        ui.checkbox(&mut form.catch_up_eligible, "Catch-up eligible");
+        if form.deduction_type == DeductionType::Hsa {
+            ui.checkbox(&mut form.family_coverage, "Family coverage");
+        }
+        if users::guarded_button(ui, can_edit_payroll, "Add Election").clicked() {
+            if form.method == DeductionMethod::PercentOfGross && form.amount > 100.0 {
+                form.status = "A percent election can't exceed 100%".to_string();
+            } else {
+                let election = DeductionElection {
+                    id: 0,
+                    employee_id,
+                    deduction_type: form.deduction_type,
+                    method: form.method,
+                    amount: form.amount,
+                    catch_up_eligible: form.catch_up_eligible,
+                    family_coverage: form.family_coverage,
+                    active: true,
+                };
+                form.status = match election.save_to_db(&app.conn) {
+                    Ok(_) => "Election added".to_string(),
+                    Err(e) => format!("Error saving election: {}", e),
+                };
+            }
+        }
+    });
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+}
@@ -0,0 +1,446 @@
+use crate::app::app::PharmacyApp;
+use crate::app::companies;
+use crate::app::companies::Company;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Accumulated federal liability that must be deposited by the next business day (Pub. 15,
+/// section 11).
+pub const NEXT_DAY_THRESHOLD: f32 = 100_000.0;
+
+/// SUTA wage base for new companies, Texas's. Companies in other states enter their own.
+pub const DEFAULT_SUTA_WAGE_BASE: f32 = 9_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DepositSchedule {
+    Monthly,
+    Semiweekly,
+}
+
+impl DepositSchedule {
+    pub const ALL: [DepositSchedule; 2] = [DepositSchedule::Monthly, DepositSchedule::Semiweekly];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DepositSchedule::Monthly => "monthly",
+            DepositSchedule::Semiweekly => "semiweekly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "semiweekly" => DepositSchedule::Semiweekly,
+            _ => DepositSchedule::Monthly,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DepositSchedule::Monthly => "Monthly depositor",
+            DepositSchedule::Semiweekly => "Semiweekly depositor",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepositKind {
+    /// Federal withholding plus employee and employer FICA, reported on Form 941.
+    Federal,
+    /// State unemployment contributions, due quarterly.
+    StateUnemployment,
+}
+
+impl DepositKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DepositKind::Federal => "federal",
+            DepositKind::StateUnemployment => "suta",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DepositKind::Federal => "Federal 941",
+            DepositKind::StateUnemployment => "State unemployment",
+        }
+    }
+}
+
+/// Tax owed on one pay date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liability {
+    pub date_of_pay: NaiveDate,
+    pub federal: f32,
+    pub state_unemployment: f32,
+}
+
+/// One deposit: the liabilities from `first_pay_date` through `last_pay_date` that are due
+/// together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deposit {
+    pub kind: DepositKind,
+    pub first_pay_date: NaiveDate,
+    pub last_pay_date: NaiveDate,
+    pub amount: f32,
+    pub due_date: NaiveDate,
+    /// Due the next business day because the $100,000 threshold was reached.
+    pub next_day: bool,
+    pub paid_amount: f32,
+    pub paid_date: Option<String>,
+}
+
+impl Deposit {
+    pub fn period_key(&self) -> String {
+        self.first_pay_date.format("%Y-%m-%d").to_string()
+    }
+
+    pub fn outstanding(&self) -> f32 {
+        ((self.amount - self.paid_amount) * 100.0).round() / 100.0
+    }
+}
+
+/// Moves a due date that lands on a weekend to the following Monday. Federal holidays are
+/// not accounted for.
+fn business_day(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date + Duration::days(2),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+
+fn next_business_day(date: NaiveDate) -> NaiveDate {
+    business_day(date + Duration::days(1))
+}
+
+/// Start of the deposit period `date` falls in and the regular due date for that period.
+/// Monthly deposits are due the 15th of the following month. Semiweekly periods run
+/// Wednesday through Friday, due the following Wednesday, and Saturday through Tuesday,
+/// due the following Friday.
+fn deposit_period(date: NaiveDate, schedule: DepositSchedule) -> (NaiveDate, NaiveDate) {
+    match schedule {
+        DepositSchedule::Monthly => {
+            let start = date.with_day(1).unwrap_or(date);
+            let next_month = if date.month() == 12 {
+                NaiveDate::from_ymd_opt(date.year() + 1, 1, 15)
+            } else {
+                NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 15)
+            };
+            (start, business_day(next_month.unwrap_or(date)))
+        }
+        DepositSchedule::Semiweekly => {
+            let days_from_wednesday = (date.weekday().num_days_from_monday() + 5) % 7;
+            let (start, due) = if days_from_wednesday < 3 {
+                let start = date - Duration::days(days_from_wednesday as i64);
+                (start, start + Duration::days(7))
+            } else {
+                let start = date - Duration::days(days_from_wednesday as i64 - 3);
+                (start, start + Duration::days(6))
+            };
+            (start, business_day(due))
+        }
+    }
+}
+
+/// Groups per-pay-date federal liabilities into deposits. Liabilities accumulate over the
+/// deposit period; once they reach $100,000 the accumulated amount is due the next business
+/// day and accumulation starts over. A monthly depositor who hits the threshold becomes a
+/// semiweekly depositor for the rest of that year and all of the next.
+pub fn federal_deposits(liabilities: &[Liability], schedule: DepositSchedule) -> Vec<Deposit> {
+    let mut deposits: Vec<Deposit> = Vec::new();
+    let mut open: Option<(NaiveDate, Deposit)> = None;
+    let mut semiweekly_through: Option<i32> = None;
+
+    for liability in liabilities.iter().filter(|l| l.federal > 0.0) {
+        let date = liability.date_of_pay;
+        let schedule = match semiweekly_through {
+            Some(year) if date.year() <= year => DepositSchedule::Semiweekly,
+            _ => schedule,
+        };
+        let (period_start, due_date) = deposit_period(date, schedule);
+        if let Some((start, deposit)) = open.take() {
+            if start == period_start {
+                open = Some((start, deposit));
+            } else {
+                deposits.push(deposit);
+            }
+        }
+        let (_, deposit) = open.get_or_insert((
+            period_start,
+            Deposit {
+                kind: DepositKind::Federal,
+                first_pay_date: date,
+                last_pay_date: date,
+                amount: 0.0,
+                due_date,
+                next_day: false,
+                paid_amount: 0.0,
+                paid_date: None,
+            },
+        ));
+        deposit.amount += liability.federal;
+        deposit.last_pay_date = date;
+        if deposit.amount >= NEXT_DAY_THRESHOLD {
+            deposit.due_date = next_business_day(date);
+            deposit.next_day = true;
+            if schedule == DepositSchedule::Monthly {
+                semiweekly_through = Some(date.year() + 1);
+            }
+            if let Some((_, deposit)) = open.take() {
+                deposits.push(deposit);
+            }
+        }
+    }
+    deposits.extend(open.map(|(_, deposit)| deposit));
+    deposits
+}
+
+/// State unemployment is deposited once a quarter, by the last day of the month after the
+/// quarter ends.
+pub fn state_unemployment_deposits(liabilities: &[Liability]) -> Vec<Deposit> {
+    let mut deposits: Vec<Deposit> = Vec::new();
+    for liability in liabilities.iter().filter(|l| l.state_unemployment > 0.0) {
+        let date = liability.date_of_pay;
+        let quarter_end_month = (date.month() - 1) / 3 * 3 + 3;
+        let due_month_start = if quarter_end_month == 12 {
+            NaiveDate::from_ymd_opt(date.year() + 1, 2, 1)
+        } else {
+            NaiveDate::from_ymd_opt(date.year(), quarter_end_month + 2, 1)
+        }
+        .unwrap_or(date);
+        let due_date = business_day(due_month_start - Duration::days(1));
+        match deposits.last_mut() {
+            Some(deposit) if deposit.due_date == due_date => {
+                deposit.amount += liability.state_unemployment;
+                deposit.last_pay_date = date;
+            }
+            _ => deposits.push(Deposit {
+                kind: DepositKind::StateUnemployment,
+                first_pay_date: date,
+                last_pay_date: date,
+                amount: liability.state_unemployment,
+                due_date,
+                next_day: false,
+                paid_amount: 0.0,
+                paid_date: None,
+            }),
+        }
+    }
+    deposits
+}
+
+/// SUTA owed on each of the company's checks, by payroll id, rounded to the cent. Each
+/// employee's wages count toward the yearly wage base in pay-date order, imported history
+/// included, and a check owes only on the part of its gross still under the base. The deposit
+/// tracker and the GL journal both use this so they agree.
+pub fn get_suta_by_check(
+    conn: &Connection,
+    company: &Company,
+) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, date_of_pay, gross FROM payroll WHERE company_id = ?1
+        ORDER BY date_of_pay, id",
+    )?;
+    let rows = stmt
+        .query_map([company.id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f32>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut wages_to_date: HashMap<(i32, i32), f32> = HashMap::new();
+    let mut suta = HashMap::new();
+    for (payroll_id, employee_id, date, gross) in rows {
+        let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") else {
+            continue;
+        };
+        let wages = wages_to_date
+            .entry((employee_id, date.year()))
+            .or_insert(0.0);
+        let suta_wages = gross.min((company.suta_wage_base - *wages).max(0.0));
+        *wages += gross;
+        suta.insert(payroll_id, (suta_wages * company.suta_rate).round() / 100.0);
+    }
+    Ok(suta)
+}
+
+/// Withholding and employer taxes for each of the company's pay dates. Federal is income tax
+/// withheld plus both halves of FICA; state unemployment is `get_suta_by_check` summed over
+/// the date's checks. Imported history was deposited by the previous provider.
+pub fn get_liabilities(
+    conn: &Connection,
+    company: &Company,
+) -> Result<Vec<Liability>, rusqlite::Error> {
+    let suta = get_suta_by_check(conn, company)?;
+    let mut stmt = conn.prepare(
+        "SELECT id, date_of_pay, withholding, social_security FROM payroll
+        WHERE company_id = ?1 AND imported IS NULL
+        ORDER BY date_of_pay, id",
+    )?;
+    let rows = stmt
+        .query_map([company.id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f32>(2)?,
+                row.get::<_, f32>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut liabilities: Vec<Liability> = Vec::new();
+    for (payroll_id, date, withholding, social_security) in rows {
+        let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") else {
+            continue;
+        };
+        let federal = withholding + social_security * 2.0;
+        let state_unemployment = suta.get(&payroll_id).copied().unwrap_or(0.0);
+        match liabilities.last_mut() {
+            Some(liability) if liability.date_of_pay == date => {
+                liability.federal += federal;
+                liability.state_unemployment += state_unemployment;
+            }
+            _ => liabilities.push(Liability {
+                date_of_pay: date,
+                federal,
+                state_unemployment,
+            }),
+        }
+    }
+    for liability in &mut liabilities {
+        liability.state_unemployment = (liability.state_unemployment * 100.0).round() / 100.0;
+    }
+    Ok(liabilities)
+}
+
+/// Every federal and state deposit for the company, with what has been recorded as paid,
+/// ordered by due date. Deposits are made per EIN, so there is no combined view.
+pub fn get_deposits(conn: &Connection, company_id: i64) -> Result<Vec<Deposit>, rusqlite::Error> {
+    let Some(company) = companies::get_companies(conn)?
+        .into_iter()
+        .find(|company| company.id == company_id)
+    else {
+        return Ok(Vec::new());
+    };
+    let liabilities = get_liabilities(conn, &company)?;
+    let mut deposits = federal_deposits(&liabilities, company.deposit_schedule);
+    deposits.extend(state_unemployment_deposits(&liabilities));
+
+    let mut stmt = conn.prepare(
+        "SELECT kind, period_key, amount, paid_date FROM tax_deposits WHERE company_id = ?1",
+    )?;
+    let payments: HashMap<(String, String), (f32, String)> = stmt
+        .query_map([company_id], |row| {
+            Ok(((row.get(0)?, row.get(1)?), (row.get(2)?, row.get(3)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+    for deposit in &mut deposits {
+        let key = (deposit.kind.as_str().to_string(), deposit.period_key());
+        if let Some((amount, paid_date)) = payments.get(&key) {
+            deposit.paid_amount = *amount;
+            deposit.paid_date = Some(paid_date.clone());
+        }
+    }
+    deposits.sort_by_key(|deposit| deposit.due_date);
+    Ok(deposits)
+}
+
+/// Records the company's deposit as paid in full on `paid_date`.
+pub fn record_payment(
+    conn: &Connection,
+    company_id: i64,
+    deposit: &Deposit,
+    paid_date: &str,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO tax_deposits (company_id, kind, period_key, amount, due_date, paid_date)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ON CONFLICT(company_id, kind, period_key)
+        DO UPDATE SET amount = excluded.amount, paid_date = excluded.paid_date",
+        params![
+            company_id,
+            deposit.kind.as_str(),
+            deposit.period_key(),
+            deposit.amount,
+            deposit.due_date.format("%Y-%m-%d").to_string(),
+            paid_date
+        ],
+    )?;
+    Ok(())
+}
+
+/// Home panel list of deposits that still have a balance, oldest due date first.
+pub fn render_upcoming_deposits(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    let Some(company_id) = app.company_id else {
+        return;
+    };
+    let deposits = match get_deposits(&app.conn, company_id) {
+        Ok(deposits) => deposits,
+        Err(e) => {
+            ui.label(format!("Error fetching tax deposits: {}", e));
+            return;
+        }
+    };
+    let unpaid: Vec<&Deposit> = deposits.iter().filter(|d| d.outstanding() > 0.0).collect();
+    if unpaid.is_empty() {
+        return;
+    }
+
+    let today = chrono::Local::now().date_naive();
+    ui.separator();
+    ui.heading("Tax Deposits Due");
+    egui::Grid::new("tax_deposits_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Tax");
+            ui.strong("Pay Dates");
+            ui.strong("Amount");
+            ui.strong("Due");
+            ui.end_row();
+            for deposit in unpaid {
+                ui.label(deposit.kind.label());
+                ui.label(if deposit.first_pay_date == deposit.last_pay_date {
+                    deposit.first_pay_date.to_string()
+                } else {
+                    format!("{} to {}", deposit.first_pay_date, deposit.last_pay_date)
+                });
+                ui.label(format!("{:.2}", deposit.outstanding()));
+                let days_left = (deposit.due_date - today).num_days();
+                let color = if days_left < 0 {
+                    egui::Color32::RED
+                } else if days_left <= 3 {
+                    egui::Color32::YELLOW
+                } else {
+                    ui.visuals().text_color()
+                };
+                let mut due = deposit.due_date.to_string();
+                if deposit.next_day {
+                    due.push_str(" (next-day rule)");
+                }
+                ui.colored_label(color, due);
+                if users::guarded_button(ui, can_edit_payroll, "Mark Paid").clicked() {
+                    record_payment(
+                        &app.conn,
+                        company_id,
+                        deposit,
+                        &today.format("%Y-%m-%d").to_string(),
+                    )
+                    .unwrap_or_else(|e| println!("Error recording deposit: {}", e));
+                }
+                ui.end_row();
+            }
+        });
+    ui.separator();
+}
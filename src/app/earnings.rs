@@ -0,0 +1,238 @@
+use crate::app::payroll;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+/// IRS optional flat rate for federal withholding on supplemental wages (Pub. 15, section 7).
+pub const SUPPLEMENTAL_FEDERAL_RATE: f32 = 0.22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EarningType {
+    Regular,
+    Salary,
+    Overtime,
+    Holiday,
+    Pto,
+    Bonus,
+    Commission,
+    Retro,
+}
+
+impl EarningType {
+    pub const ALL: [EarningType; 8] = [
+        EarningType::Regular,
+        EarningType::Salary,
+        EarningType::Overtime,
+        EarningType::Holiday,
+        EarningType::Pto,
+        EarningType::Bonus,
+        EarningType::Commission,
+        EarningType::Retro,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EarningType::Regular => "regular",
+            EarningType::Salary => "salary",
+            EarningType::Overtime => "overtime",
+            EarningType::Holiday => "holiday",
+            EarningType::Pto => "pto",
+            EarningType::Bonus => "bonus",
+            EarningType::Commission => "commission",
+            EarningType::Retro => "retro",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "salary" => EarningType::Salary,
+            "overtime" => EarningType::Overtime,
+            "holiday" => EarningType::Holiday,
+            "pto" => EarningType::Pto,
+            "bonus" => EarningType::Bonus,
+            "commission" => EarningType::Commission,
+            "retro" => EarningType::Retro,
+            _ => EarningType::Regular,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EarningType::Regular => "Regular",
+            EarningType::Salary => "Salary",
+            EarningType::Overtime => "Overtime",
+            EarningType::Holiday => "Holiday",
+            EarningType::Pto => "PTO",
+            EarningType::Bonus => "Bonus",
+            EarningType::Commission => "Commission",
+            EarningType::Retro => "Retro Pay",
+        }
+    }
+
+    /// Hourly types are paid as hours times rate; the rest are flat amounts.
+    pub fn is_hourly(&self) -> bool {
+        matches!(
+            self,
+            EarningType::Regular | EarningType::Overtime | EarningType::Holiday | EarningType::Pto
+        )
+    }
+
+    /// Salary lines carry hours for the record, but their amount comes from the salary.
+    pub fn tracks_hours(&self) -> bool {
+        self.is_hourly() || matches!(self, EarningType::Salary)
+    }
+
+    /// Hours on these lines were actually worked, which matters for overtime and PTO accrual.
+    pub fn is_worked(&self) -> bool {
+        matches!(
+            self,
+            EarningType::Regular
+                | EarningType::Salary
+                | EarningType::Overtime
+                | EarningType::Holiday
+        )
+    }
+
+    pub fn rate_multiplier(&self) -> f32 {
+        match self {
+            EarningType::Overtime | EarningType::Holiday => 1.5,
+            _ => 1.0,
+        }
+    }
+
+    /// Retro pay for earlier periods is supplemental too (Pub. 15, section 7).
+    pub fn is_supplemental(&self) -> bool {
+        matches!(
+            self,
+            EarningType::Bonus | EarningType::Commission | EarningType::Retro
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EarningLine {
+    pub id: i64,
+    pub payroll_id: i64,
+    pub earning_type: EarningType,
+    pub hours: f32,
+    pub rate: f32,
+    pub amount: f32,
+    pub fit_taxable: bool,
+    pub fica_taxable: bool,
+    pub supplemental: bool,
+    /// Store the hours were worked at; `None` means the employee's home location.
+    pub location_id: Option<i64>,
+}
+
+impl EarningLine {
+    /// A new line with the type's default rate and tax treatment for an employee paid `base_rate`.
+    pub fn new(earning_type: EarningType, hours: f32, base_rate: f32) -> Self {
+        let mut line = Self {
+            id: 0,
+            payroll_id: 0,
+            earning_type,
+            hours: 0.0,
+            rate: 0.0,
+            amount: 0.0,
+            fit_taxable: true,
+            fica_taxable: true,
+            supplemental: earning_type.is_supplemental(),
+            location_id: None,
+        };
+        if earning_type.tracks_hours() {
+            line.hours = hours;
+        }
+        if earning_type.is_hourly() {
+            line.rate = base_rate * earning_type.rate_multiplier();
+            line.recalculate();
+        }
+        line
+    }
+
+    pub fn recalculate(&mut self) {
+        if self.earning_type.is_hourly() {
+            let amount = payroll::calculate_gross(self.hours, self.rate);
+            self.amount = (amount * 100.0).round() / 100.0;
+        }
+    }
+
+    pub fn save_to_db(&self, conn: &Connection, payroll_id: i64) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO earnings (
+                payroll_id,
+                earning_type,
+                hours,
+                rate,
+                amount,
+                fit_taxable,
+                fica_taxable,
+                supplemental,
+                location_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                payroll_id,
+                self.earning_type.as_str(),
+                self.hours,
+                self.rate,
+                self.amount,
+                self.fit_taxable,
+                self.fica_taxable,
+                self.supplemental,
+                self.location_id
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+pub fn get_earnings_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<Vec<EarningLine>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, payroll_id, earning_type, hours, rate, amount, fit_taxable, fica_taxable, supplemental,
+            location_id
+        FROM earnings WHERE payroll_id = ?1 ORDER BY id",
+    )?;
+    let lines = stmt
+        .query_map([payroll_id], |row| {
+            Ok(EarningLine {
+                id: row.get(0)?,
+                payroll_id: row.get(1)?,
+                earning_type: EarningType::parse(&row.get::<_, String>(2)?),
+                hours: row.get(3)?,
+                rate: row.get(4)?,
+                amount: row.get(5)?,
+                fit_taxable: row.get(6)?,
+                fica_taxable: row.get(7)?,
+                supplemental: row.get(8)?,
+                location_id: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lines)
+}
+
+pub fn delete_earnings_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM earnings WHERE payroll_id = ?1", [payroll_id])?;
+    Ok(())
+}
+
+pub fn worked_hours(lines: &[EarningLine]) -> f32 {
+    lines
+        .iter()
+        .filter(|line| line.earning_type.is_worked())
+        .map(|line| line.hours)
+        .sum()
+}
+
+pub fn hours_of_type(lines: &[EarningLine], earning_type: EarningType) -> f32 {
+    lines
+        .iter()
+        .filter(|line| line.earning_type == earning_type)
+        .map(|line| line.hours)
+        .sum()
+}
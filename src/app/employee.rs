@@ -1,11 +1,63 @@
-use super::database;
+use crate::app::app::get_fridays_for_year;
 use crate::app::app::PharmacyApp;
 use crate::app::database::add_employee;
-use crate::app::database::get_payroll_by_id;
+use crate::app::deductions;
+use crate::app::import;
+use crate::app::locations;
+use crate::app::repository::{EmployeeRepository, PayrollRepository, SqliteRepository};
+use crate::app::security;
+use crate::app::ssn;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::{Datelike, NaiveDate};
 use egui::Ui;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum PayType {
+    #[default]
+    Hourly,
+    SalaryAnnual,
+    SalaryPerPeriod,
+}
+
+impl PayType {
+    pub const ALL: [PayType; 3] = [
+        PayType::Hourly,
+        PayType::SalaryAnnual,
+        PayType::SalaryPerPeriod,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayType::Hourly => "hourly",
+            PayType::SalaryAnnual => "salary_annual",
+            PayType::SalaryPerPeriod => "salary_period",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "salary_annual" => PayType::SalaryAnnual,
+            "salary_period" => PayType::SalaryPerPeriod,
+            _ => PayType::Hourly,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PayType::Hourly => "Hourly",
+            PayType::SalaryAnnual => "Salary (per year)",
+            PayType::SalaryPerPeriod => "Salary (per period)",
+        }
+    }
+
+    pub fn is_salaried(&self) -> bool {
+        !matches!(self, PayType::Hourly)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Employee {
     pub id: i32,
     pub name: String,
@@ -17,14 +69,186 @@ pub struct Employee {
     pub filing_status: String,
     pub dependents: String,
     pub pay_rate: String,
+    pub pay_type: PayType,
+    pub flsa_exempt: bool,
+    /// `YYYY-MM-DD`, empty when not recorded.
+    pub hire_date: String,
+    pub termination_date: String,
+    pub home_location_id: Option<i64>,
+    pub company_id: Option<i64>,
+    /// `AAA-GG-SSSS`, empty when not on file. Sealed in the database.
+    pub ssn: String,
+}
+
+impl Employee {
+    pub fn rate(&self) -> f32 {
+        self.pay_rate.parse().unwrap_or(0.0)
+    }
+
+    /// Exempt staff are never paid overtime under the FLSA.
+    pub fn overtime_eligible(&self) -> bool {
+        !self.flsa_exempt
+    }
+
+    /// Straight-time hourly rate, used to price overtime and other hourly lines. For salaried
+    /// staff it is the weekly salary over a 40 hour week.
+    pub fn hourly_rate(&self) -> f32 {
+        match self.pay_type {
+            PayType::Hourly => self.rate(),
+            PayType::SalaryAnnual => self.rate() / 52.0 / 40.0,
+            PayType::SalaryPerPeriod => self.rate() / 40.0,
+        }
+    }
+
+    /// Salary for the weekly period ending on `date_of_pay`. An annual salary is split evenly
+    /// across the Fridays in that year, and a period in which the employee was hired or
+    /// terminated is prorated by the weekdays they were employed.
+    pub fn period_salary(&self, date_of_pay: NaiveDate) -> f32 {
+        let full_period = match self.pay_type {
+            PayType::Hourly => return 0.0,
+            PayType::SalaryAnnual => {
+                self.rate() / get_fridays_for_year(date_of_pay.year()).len() as f32
+            }
+            PayType::SalaryPerPeriod => self.rate(),
+        };
+        let hired = NaiveDate::parse_from_str(&self.hire_date, "%Y-%m-%d").ok();
+        let terminated = NaiveDate::parse_from_str(&self.termination_date, "%Y-%m-%d").ok();
+        let weekdays: Vec<NaiveDate> = (0..7)
+            .map(|offset| date_of_pay - chrono::Duration::days(offset))
+            .filter(|day| day.weekday().num_days_from_monday() < 5)
+            .collect();
+        let employed = weekdays
+            .iter()
+            .filter(|day| hired.is_none_or(|hired| **day >= hired))
+            .filter(|day| terminated.is_none_or(|terminated| **day <= terminated))
+            .count();
+        let salary = full_period * employed as f32 / weekdays.len() as f32;
+        (salary * 100.0).round() / 100.0
+    }
+}
+
+#[derive(Default)]
+pub struct PaySetupForm {
+    pub employee_id: Option<i32>,
+    pub pay_type: PayType,
+    pub pay_rate: String,
+    pub flsa_exempt: bool,
+    pub hire_date: String,
+    pub termination_date: String,
+    pub status: String,
 }
 
-impl Employee {}
+fn render_pay_setup(app: &mut PharmacyApp, ui: &mut Ui, employee: &Employee) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    let form = &mut app.pay_setup_form;
+    if form.employee_id != Some(employee.id) {
+        *form = PaySetupForm {
+            employee_id: Some(employee.id),
+            pay_type: employee.pay_type,
+            pay_rate: employee.pay_rate.clone(),
+            flsa_exempt: employee.flsa_exempt,
+            hire_date: employee.hire_date.clone(),
+            termination_date: employee.termination_date.clone(),
+            status: String::new(),
+        };
+    }
+
+    ui.heading("Pay Setup");
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("pay_setup_type")
+            .selected_text(form.pay_type.label())
+            .show_ui(ui, |ui| {
+                for pay_type in PayType::ALL {
+                    ui.selectable_value(&mut form.pay_type, pay_type, pay_type.label());
+                }
+            });
+        ui.label("Rate");
+        ui.add(egui::TextEdit::singleline(&mut form.pay_rate).desired_width(80.0));
+        ui.checkbox(&mut form.flsa_exempt, "FLSA exempt");
+        ui.label("Hired");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.hire_date)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+        ui.label("Terminated");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.termination_date)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+        ui.label("Home location");
+        let location_list = app
+            .cache
+            .query(&app.conn, "locations".to_string(), locations::get_locations)
+            .unwrap_or_default();
+        let mut home_location_id = employee.home_location_id;
+        let changed = ui
+            .add_enabled_ui(can_edit_employees, |ui| {
+                locations::location_combo(
+                    ui,
+                    "pay_setup_location",
+                    &location_list,
+                    &mut home_location_id,
+                    "Unassigned",
+                )
+            })
+            .inner;
+        if changed {
+            form.status =
+                match locations::set_home_location(&app.conn, employee.id, home_location_id) {
+                    Ok(()) => {
+                        app.selected_employee = Some(Employee {
+                            home_location_id,
+                            ..employee.clone()
+                        });
+                        "Home location saved".to_string()
+                    }
+                    Err(e) => format!("Error saving home location: {}", e),
+                };
+        }
+        if users::guarded_button(ui, can_edit_payroll, "Save Pay Setup").clicked() {
+            let dates_valid = [&form.hire_date, &form.termination_date]
+                .iter()
+                .all(|date| date.is_empty() || NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok());
+            if form.pay_rate.parse::<f32>().is_err() {
+                form.status = "Rate must be a number".to_string();
+            } else if !dates_valid {
+                form.status = "Dates must be YYYY-MM-DD".to_string();
+            } else {
+                let updated = Employee {
+                    pay_type: form.pay_type,
+                    pay_rate: form.pay_rate.trim().to_string(),
+                    flsa_exempt: form.flsa_exempt,
+                    hire_date: form.hire_date.trim().to_string(),
+                    termination_date: form.termination_date.trim().to_string(),
+                    ..employee.clone()
+                };
+                form.status = match SqliteRepository::new(&app.conn).update_pay_setup(&updated) {
+                    Ok(()) => {
+                        app.selected_employee = Some(updated);
+                        "Pay setup saved".to_string()
+                    }
+                    Err(e) => format!("Error saving pay setup: {}", e),
+                };
+            }
+        }
+    });
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+}
 
 pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    let can_view_payroll = app.can(Permission::ViewPayroll);
+    let can_reveal = app.can(Permission::RevealSensitive);
+    let username = app.current_username();
     ui.heading("Employees Panel");
 
-    let employees = match database::get_all_employees(&app.conn) {
+    let employees = match app.cache.company_employees(&app.conn, app.company_id) {
         Ok(e) => e,
         Err(e) => {
             ui.label(format!("Error: {}", e));
@@ -32,7 +256,10 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
         }
     };
 
+    let employees = locations::filter_employees(employees, app.location_filter);
+
     ui.horizontal(|ui| {
+        locations::render_location_filter(app, ui, "employees_location_filter");
         ui.label("Select Employee");
         egui::ComboBox::from_label("")
             .selected_text(
@@ -46,18 +273,19 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
                     ui.selectable_value(&mut app.selected_employee_id, Some(emp.id), &emp.name);
                 }
             });
-        if ui.button("Add New Employee").clicked() {
+        if users::guarded_button(ui, can_edit_employees, "Add New Employee").clicked() {
             app.show_add_employee_popup = true;
         }
+        import::render_import_button(app, ui);
     });
 
     if let Some(selected_id) = app.selected_employee_id {
         if app
             .selected_employee
             .as_ref()
-            .map_or(true, |emp| emp.id != selected_id)
+            .is_none_or(|emp| emp.id != selected_id)
         {
-            match database::get_employee_by_id(&app.conn, selected_id) {
+            match SqliteRepository::new(&app.conn).employee(selected_id) {
                 Ok(employee) => {
                     app.selected_employee = Some(employee);
                 }
@@ -76,15 +304,56 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
                 ui.label(format!("ID: {}", employee.id));
                 ui.label(format!("Name: {}", employee.name));
                 ui.label(format!("Position: {}", employee.position));
-                ui.label(format!("Address: {}", employee.address));
+                let revealed = can_reveal && app.revealed_employee_id == Some(employee.id);
+                let sensitive = |value: &str| {
+                    if revealed {
+                        value.to_string()
+                    } else {
+                        security::mask(value)
+                    }
+                };
+                ui.label(format!("Address: {}", sensitive(&employee.address)));
                 ui.label(format!("City: {}", employee.city));
                 ui.label(format!("State: {}", employee.state));
-                ui.label(format!("Phone: {}", employee.phone));
+                ui.label(format!("Phone: {}", sensitive(&employee.phone)));
+                if revealed {
+                    if ui.button("Hide").clicked() {
+                        app.revealed_employee_id = None;
+                    }
+                } else if users::guarded_button(ui, can_reveal, "Reveal")
+                    .on_hover_text("Show the encrypted fields")
+                    .clicked()
+                {
+                    match ssn::log_reveal(&app.conn, employee.id, "address", &username)
+                        .and_then(|_| ssn::log_reveal(&app.conn, employee.id, "phone", &username))
+                    {
+                        Ok(()) => app.revealed_employee_id = Some(employee.id),
+                        Err(e) => println!("Error logging reveal: {}", e),
+                    }
+                }
                 ui.label(format!("Filiing Status: {}", employee.filing_status));
                 ui.label(format!("Dependendts: {}", employee.dependents));
+                if can_view_payroll {
+                    ui.label(format!(
+                        "Pay: {} {}",
+                        employee.pay_type.label(),
+                        employee.pay_rate
+                    ));
+                }
+                ui.label(if employee.flsa_exempt {
+                    "FLSA: Exempt"
+                } else {
+                    "FLSA: Non-exempt"
+                });
+                if !employee.hire_date.is_empty() {
+                    ui.label(format!("Hired: {}", employee.hire_date));
+                }
+                if !employee.termination_date.is_empty() {
+                    ui.label(format!("Terminated: {}", employee.termination_date));
+                }
             });
-            if let Some(employee) = &app.selected_employee {
-                match get_payroll_by_id(&app.conn, employee.id) {
+            if let Some(employee) = app.selected_employee.as_ref().filter(|_| can_view_payroll) {
+                match app.cache.checks_for_employee(&app.conn, employee.id) {
                     Ok(payroll_entries) => {
                         egui::ScrollArea::vertical().show(ui, |ui| {
                             egui::Grid::new("payroll_entries_grid")
@@ -96,21 +365,28 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
                                     ui.strong("Net");
                                     //ui.strong("Employee Name");
                                     ui.strong("Hours Worked");
+                                    ui.strong("PTO Hours");
                                     ui.strong("Withholding");
                                     ui.strong("Roth IRA");
                                     ui.strong("Social Security");
+                                    ui.strong("Type");
                                     ui.end_row();
                                     for entry in &payroll_entries {
-                                        ui.label(format!("{}", entry.date_of_pay));
+                                        ui.label(&entry.date_of_pay);
                                         ui.label(format!("{:.2}", entry.gross));
                                         ui.label(format!("{:.2}", entry.net));
                                         ui.label(format!("{}", entry.hours_worked));
+                                        ui.label(format!("{}", entry.pto_hours));
                                         ui.label(format!("{:.2}", entry.withholding));
                                         ui.label(format!("{:.2}", entry.roth_ira));
                                         ui.label(format!("{:.2}", entry.social_security));
+                                        ui.label(entry.check_type_label());
 
-                                        if ui.button("Delete").clicked() {
-                                            database::delete_payroll_entry(&app.conn, entry.id)
+                                        if users::guarded_button(ui, can_edit_payroll, "Delete")
+                                            .clicked()
+                                        {
+                                            SqliteRepository::new(&app.conn)
+                                                .delete_check(entry.id)
                                                 .unwrap_or_else(|e| {
                                                     println!("Error deleting payroll entry: {}", e)
                                                 });
@@ -128,6 +404,19 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
         });
     }
 
+    if let Some(employee) = app.selected_employee.clone() {
+        ui.separator();
+        ssn::render_ssn(app, ui, &employee);
+        if can_view_payroll {
+            ui.separator();
+            render_pay_setup(app, ui, &employee);
+            ui.separator();
+            deductions::render_elections(app, ui, employee.id);
+        }
+    }
+
+    import::render_import(app, ui);
+
     if app.show_add_employee_popup {
         egui::Window::new("Add New Employee")
             .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
@@ -167,6 +456,21 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
                                 });
                                 ui.add_space(1.0);
 
+                                ui.label("Pay Type:");
+                                egui::ComboBox::from_id_salt("new_employee_pay_type")
+                                    .selected_text(app.pay_type.label())
+                                    .show_ui(ui, |ui| {
+                                        for pay_type in PayType::ALL {
+                                            ui.selectable_value(
+                                                &mut app.pay_type,
+                                                pay_type,
+                                                pay_type.label(),
+                                            );
+                                        }
+                                    });
+                                ui.checkbox(&mut app.flsa_exempt, "FLSA exempt");
+                                ui.add_space(1.0);
+
                                 ui.label("Filing Status:")
                                     .on_hover_text("Enter tax filing status");
                                 egui::Frame::default().show(ui, |ui| {
@@ -218,6 +522,26 @@ pub fn render_employees(app: &mut PharmacyApp, ui: &mut Ui) {
                                 });
                                 ui.add_space(1.0);
 
+                                ui.label("SSN:").on_hover_text("Social Security number");
+                                egui::Frame::default().show(ui, |ui| {
+                                    ui.add_sized(
+                                        [150.0, 25.0],
+                                        egui::TextEdit::singleline(&mut app.ssn)
+                                            .hint_text("123-45-6789"),
+                                    );
+                                });
+                                ui.add_space(1.0);
+
+                                ui.label("Hire Date:").on_hover_text("YYYY-MM-DD");
+                                egui::Frame::default().show(ui, |ui| {
+                                    ui.add_sized(
+                                        [150.0, 25.0],
+                                        egui::TextEdit::singleline(&mut app.hire_date)
+                                            .hint_text("2024-01-15"),
+                                    );
+                                });
+                                ui.add_space(1.0);
+
                                 ui.label("Dependents:")
                                     .on_hover_text("Enter number of dependents");
                                 egui::Frame::default().show(ui, |ui| {
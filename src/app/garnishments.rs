@@ -0,0 +1,582 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::deductions::DeductionLine;
+use crate::app::users;
+use crate::app::users::Permission;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const FEDERAL_MINIMUM_WAGE: f32 = 7.25;
+/// CCPA protects 30 times the federal minimum wage of weekly disposable earnings from creditors.
+pub const CREDITOR_PROTECTED_HOURS: f32 = 30.0;
+pub const CREDITOR_LIMIT_PERCENT: f32 = 25.0;
+
+/// Deduction line codes for garnishments are `garnishment:<order id>`.
+const CODE_PREFIX: &str = "garnishment:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GarnishmentType {
+    ChildSupport,
+    TaxLevy,
+    Creditor,
+}
+
+impl GarnishmentType {
+    /// Listed in the order they are satisfied when an employee has several orders.
+    pub const ALL: [GarnishmentType; 3] = [
+        GarnishmentType::ChildSupport,
+        GarnishmentType::TaxLevy,
+        GarnishmentType::Creditor,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GarnishmentType::ChildSupport => "child_support",
+            GarnishmentType::TaxLevy => "tax_levy",
+            GarnishmentType::Creditor => "creditor",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "child_support" => GarnishmentType::ChildSupport,
+            "tax_levy" => GarnishmentType::TaxLevy,
+            _ => GarnishmentType::Creditor,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GarnishmentType::ChildSupport => "Child Support",
+            GarnishmentType::TaxLevy => "Tax Levy",
+            GarnishmentType::Creditor => "Creditor",
+        }
+    }
+
+    pub fn priority(&self) -> u8 {
+        match self {
+            GarnishmentType::ChildSupport => 0,
+            GarnishmentType::TaxLevy => 1,
+            GarnishmentType::Creditor => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GarnishmentOrder {
+    pub id: i64,
+    pub employee_id: i32,
+    pub garnishment_type: GarnishmentType,
+    pub agency: String,
+    pub case_number: String,
+    /// Amount the order asks for each check. Unused for tax levies, which take everything
+    /// above `exempt_amount`.
+    pub amount_per_check: f32,
+    /// Weekly amount exempt from a tax levy, from the IRS Publication 1494 table on the notice.
+    pub exempt_amount: f32,
+    /// `None` for ongoing support with no fixed balance.
+    pub total_owed: Option<f32>,
+    pub supports_other_family: bool,
+    pub arrears_over_12_weeks: bool,
+    pub active: bool,
+}
+
+impl Default for GarnishmentOrder {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            employee_id: 0,
+            garnishment_type: GarnishmentType::ChildSupport,
+            agency: String::new(),
+            case_number: String::new(),
+            amount_per_check: 0.0,
+            exempt_amount: 0.0,
+            total_owed: None,
+            supports_other_family: false,
+            arrears_over_12_weeks: false,
+            active: true,
+        }
+    }
+}
+
+impl GarnishmentOrder {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO garnishment_orders (
+                employee_id,
+                garnishment_type,
+                agency,
+                case_number,
+                amount_per_check,
+                exempt_amount,
+                total_owed,
+                supports_other_family,
+                arrears_over_12_weeks,
+                active)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                self.employee_id,
+                self.garnishment_type.as_str(),
+                &self.agency,
+                &self.case_number,
+                self.amount_per_check,
+                self.exempt_amount,
+                self.total_owed,
+                self.supports_other_family,
+                self.arrears_over_12_weeks,
+                self.active
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(
+            conn,
+            "garnishment_order",
+            "garnishment_orders",
+            id,
+            AuditAction::Insert,
+            None,
+        )?;
+        Ok(id)
+    }
+
+    pub fn deduction_code(&self) -> String {
+        format!("{}{}", CODE_PREFIX, self.id)
+    }
+
+    /// Highest share of disposable earnings that support orders may take under CCPA.
+    pub fn support_limit_percent(&self) -> f32 {
+        let base = if self.supports_other_family {
+            50.0
+        } else {
+            60.0
+        };
+        if self.arrears_over_12_weeks {
+            base + 5.0
+        } else {
+            base
+        }
+    }
+}
+
+pub fn get_orders(conn: &Connection) -> Result<Vec<GarnishmentOrder>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, garnishment_type, agency, case_number, amount_per_check,
+            exempt_amount, total_owed, supports_other_family, arrears_over_12_weeks, active
+        FROM garnishment_orders ORDER BY id",
+    )?;
+    let orders = stmt
+        .query_map([], |row| {
+            Ok(GarnishmentOrder {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                garnishment_type: GarnishmentType::parse(&row.get::<_, String>(2)?),
+                agency: row.get(3)?,
+                case_number: row.get(4)?,
+                amount_per_check: row.get(5)?,
+                exempt_amount: row.get(6)?,
+                total_owed: row.get(7)?,
+                supports_other_family: row.get(8)?,
+                arrears_over_12_weeks: row.get(9)?,
+                active: row.get(10)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(orders)
+}
+
+pub fn get_orders_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<GarnishmentOrder>, rusqlite::Error> {
+    Ok(get_orders(conn)?
+        .into_iter()
+        .filter(|order| order.employee_id == employee_id)
+        .collect())
+}
+
+pub fn set_order_active(conn: &Connection, id: i64, active: bool) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "garnishment_orders", id)?;
+    conn.execute(
+        "UPDATE garnishment_orders SET active = ?1 WHERE id = ?2",
+        params![active, id],
+    )?;
+    audit::record_row(
+        conn,
+        "garnishment_order",
+        "garnishment_orders",
+        id,
+        AuditAction::Update,
+        before.as_ref(),
+    )
+}
+
+/// Total withheld so far against each order, keyed by order id.
+pub fn get_amounts_paid(conn: &Connection) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT code, SUM(amount) FROM deduction_lines WHERE code LIKE 'garnishment:%' GROUP BY code",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, f32>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(code, amount)| order_for_code(&code).map(|id| (id, amount)))
+        .collect())
+}
+
+/// The order a deduction line's code was withheld for, if it is a garnishment.
+pub fn order_for_code(code: &str) -> Option<i64> {
+    code.strip_prefix(CODE_PREFIX)?.parse().ok()
+}
+
+pub fn remaining_balance(order: &GarnishmentOrder, paid: &HashMap<i64, f32>) -> Option<f32> {
+    order
+        .total_owed
+        .map(|owed| (owed - paid.get(&order.id).copied().unwrap_or(0.0)).max(0.0))
+}
+
+/// Weekly disposable earnings are what is left after deductions required by law, which here
+/// means the taxes on the check. Voluntary deductions like 401(k) are not subtracted.
+pub fn disposable_earnings(gross: f32, withholding: f32, social_security: f32) -> f32 {
+    (gross - withholding - social_security).max(0.0)
+}
+
+/// Works through an employee's active orders in priority order and returns the deduction
+/// lines for this check. Support orders share the CCPA support limit, creditors are held to
+/// the lesser of 25% of disposable earnings or the amount above 30 times minimum wage, less
+/// whatever higher-priority orders already took, and nothing may exceed the order's remaining
+/// balance or the net pay left on the check.
+pub fn calculate_garnishments(
+    orders: &[GarnishmentOrder],
+    paid: &HashMap<i64, f32>,
+    disposable: f32,
+    available_net: f32,
+) -> Vec<DeductionLine> {
+    let mut active: Vec<&GarnishmentOrder> = orders.iter().filter(|o| o.active).collect();
+    active.sort_by_key(|order| (order.garnishment_type.priority(), order.id));
+
+    let mut taken = 0.0;
+    let mut lines = Vec::new();
+    for order in active {
+        let allowed = match order.garnishment_type {
+            GarnishmentType::ChildSupport => {
+                disposable * order.support_limit_percent() / 100.0 - taken
+            }
+            GarnishmentType::TaxLevy => disposable - order.exempt_amount - taken,
+            GarnishmentType::Creditor => {
+                let limit = (disposable * CREDITOR_LIMIT_PERCENT / 100.0)
+                    .min(disposable - CREDITOR_PROTECTED_HOURS * FEDERAL_MINIMUM_WAGE);
+                limit - taken
+            }
+        };
+        let mut amount = allowed.min(available_net - taken);
+        if order.garnishment_type != GarnishmentType::TaxLevy {
+            amount = amount.min(order.amount_per_check);
+        }
+        if let Some(balance) = remaining_balance(order, paid) {
+            amount = amount.min(balance);
+        }
+        let amount = (amount.max(0.0) * 100.0).round() / 100.0;
+        if amount <= 0.0 {
+            continue;
+        }
+
+        taken += amount;
+        lines.push(DeductionLine {
+            id: 0,
+            payroll_id: 0,
+            code: order.deduction_code(),
+            description: format!("{} ({})", order.garnishment_type.label(), order.agency),
+            amount,
+            pre_tax_fit: false,
+            pre_tax_fica: false,
+        });
+    }
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgencyRemittance {
+    pub agency: String,
+    pub case_number: String,
+    pub employee_name: String,
+    pub garnishment_type: GarnishmentType,
+    pub amount: f32,
+}
+
+/// Amounts withheld per order for checks paid between `from` and `to`, grouped by agency.
+pub fn get_agency_remittances(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    company_id: Option<i64>,
+) -> Result<Vec<AgencyRemittance>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT o.agency, o.case_number, e.name, o.garnishment_type, SUM(d.amount)
+        FROM deduction_lines d
+        JOIN payroll p ON p.id = d.payroll_id
+        JOIN garnishment_orders o ON d.code = 'garnishment:' || o.id
+        JOIN employees e ON e.id = o.employee_id
+        WHERE p.date_of_pay BETWEEN ?1 AND ?2 AND (?3 IS NULL OR p.company_id = ?3)
+        GROUP BY o.id
+        ORDER BY o.agency, e.name",
+    )?;
+    let remittances = stmt
+        .query_map(params![from, to, company_id], |row| {
+            Ok(AgencyRemittance {
+                agency: row.get(0)?,
+                case_number: row.get(1)?,
+                employee_name: row.get(2)?,
+                garnishment_type: GarnishmentType::parse(&row.get::<_, String>(3)?),
+                amount: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(remittances)
+}
+
+pub struct GarnishmentForm {
+    pub order: GarnishmentOrder,
+    pub has_total_owed: bool,
+    pub total_owed: f32,
+    pub report_from: String,
+    pub report_to: String,
+    pub status: String,
+}
+
+impl Default for GarnishmentForm {
+    fn default() -> Self {
+        Self {
+            order: GarnishmentOrder::default(),
+            has_total_owed: true,
+            total_owed: 0.0,
+            report_from: String::new(),
+            report_to: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn render_garnishments(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Garnishments");
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        render_orders(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_remittance_report(app, ui);
+        if !app.garnishment_form.status.is_empty() {
+            ui.label(&app.garnishment_form.status);
+        }
+    });
+}
+
+fn render_orders(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("Orders");
+    let employees = app
+        .cache
+        .company_employees(&app.conn, app.company_id)
+        .unwrap_or_default();
+    let employee_name = |id: i32| {
+        employees
+            .iter()
+            .find(|e| e.id == id)
+            .map_or("Unknown".to_string(), |e| e.name.clone())
+    };
+    let paid = get_amounts_paid(&app.conn).unwrap_or_default();
+
+    match get_orders(&app.conn) {
+        Ok(orders) if orders.is_empty() => {
+            ui.label("No garnishment orders");
+        }
+        Ok(orders) => {
+            egui::Grid::new("garnishment_orders_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Employee");
+                    ui.strong("Type");
+                    ui.strong("Agency");
+                    ui.strong("Case #");
+                    ui.strong("Per Check");
+                    ui.strong("Paid");
+                    ui.strong("Remaining");
+                    ui.strong("Status");
+                    ui.end_row();
+                    for order in &orders {
+                        ui.label(employee_name(order.employee_id));
+                        ui.label(order.garnishment_type.label());
+                        ui.label(&order.agency);
+                        ui.label(&order.case_number);
+                        if order.garnishment_type == GarnishmentType::TaxLevy {
+                            ui.label(format!("Exempt {:.2}", order.exempt_amount));
+                        } else {
+                            ui.label(format!("{:.2}", order.amount_per_check));
+                        }
+                        ui.label(format!(
+                            "{:.2}",
+                            paid.get(&order.id).copied().unwrap_or(0.0)
+                        ));
+                        match remaining_balance(order, &paid) {
+                            Some(balance) => ui.label(format!("{:.2}", balance)),
+                            None => ui.label("Ongoing"),
+                        };
+                        ui.label(if order.active { "Active" } else { "Released" });
+                        let label = if order.active { "Release" } else { "Reinstate" };
+                        if users::guarded_button(ui, can_edit_payroll, label).clicked() {
+                            set_order_active(&app.conn, order.id, !order.active)
+                                .unwrap_or_else(|e| println!("Error updating order: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching orders: {}", e));
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.label("New Order");
+    let form = &mut app.garnishment_form;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("garnishment_employee_select")
+            .selected_text(if form.order.employee_id == 0 {
+                "Select employee".to_string()
+            } else {
+                employee_name(form.order.employee_id)
+            })
+            .show_ui(ui, |ui| {
+                for employee in &employees {
+                    ui.selectable_value(&mut form.order.employee_id, employee.id, &employee.name);
+                }
+            });
+        egui::ComboBox::from_id_salt("garnishment_type_select")
+            .selected_text(form.order.garnishment_type.label())
+            .show_ui(ui, |ui| {
+                for garnishment_type in GarnishmentType::ALL {
+                    ui.selectable_value(
+                        &mut form.order.garnishment_type,
+                        garnishment_type,
+                        garnishment_type.label(),
+                    );
+                }
+            });
+        ui.label("Agency");
+        ui.text_edit_singleline(&mut form.order.agency);
+        ui.label("Case #");
+        ui.text_edit_singleline(&mut form.order.case_number);
+    });
+    ui.horizontal(|ui| {
+        if form.order.garnishment_type == GarnishmentType::TaxLevy {
+            ui.label("Exempt per week");
+            ui.add(egui::DragValue::new(&mut form.order.exempt_amount).speed(1.0));
+        } else {
+            ui.label("Per check");
+            ui.add(egui::DragValue::new(&mut form.order.amount_per_check).speed(1.0));
+        }
+        ui.checkbox(&mut form.has_total_owed, "Total owed");
+        if form.has_total_owed {
+            ui.add(egui::DragValue::new(&mut form.total_owed).speed(10.0));
+        }
+        if form.order.garnishment_type == GarnishmentType::ChildSupport {
+            ui.checkbox(
+                &mut form.order.supports_other_family,
+                "Supports other family",
+            );
+            ui.checkbox(&mut form.order.arrears_over_12_weeks, "Arrears > 12 weeks");
+        }
+        if users::guarded_button(ui, can_edit_payroll, "Add Order").clicked() {
+            if form.order.employee_id == 0 || form.order.agency.trim().is_empty() {
+                form.status = "Employee and agency are required".to_string();
+            } else {
+                form.order.total_owed = form.has_total_owed.then_some(form.total_owed);
+                form.status = match form.order.save_to_db(&app.conn) {
+                    Ok(_) => {
+                        form.order = GarnishmentOrder::default();
+                        "Order added".to_string()
+                    }
+                    Err(e) => format!("Error saving order: {}", e),
+                };
+            }
+        }
+    });
+}
+
+fn render_remittance_report(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Agency Remittance Report");
+    let form = &mut app.garnishment_form;
+    ui.horizontal(|ui| {
+        ui.label("From");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.report_from)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+        ui.label("To");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.report_to)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+    });
+    if form.report_from.is_empty() || form.report_to.is_empty() {
+        ui.label("Enter a pay date range");
+        return;
+    }
+
+    match get_agency_remittances(
+        &app.conn,
+        &form.report_from,
+        &form.report_to,
+        app.company_id,
+    ) {
+        Ok(remittances) if remittances.is_empty() => {
+            ui.label("Nothing withheld in this range");
+        }
+        Ok(remittances) => {
+            egui::Grid::new("garnishment_remittance_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Agency");
+                    ui.strong("Case #");
+                    ui.strong("Employee");
+                    ui.strong("Type");
+                    ui.strong("Amount");
+                    ui.end_row();
+                    let mut agency_total = 0.0;
+                    for (i, remittance) in remittances.iter().enumerate() {
+                        ui.label(&remittance.agency);
+                        ui.label(&remittance.case_number);
+                        ui.label(&remittance.employee_name);
+                        ui.label(remittance.garnishment_type.label());
+                        ui.label(format!("{:.2}", remittance.amount));
+                        ui.end_row();
+                        agency_total += remittance.amount;
+                        let last_for_agency = remittances
+                            .get(i + 1)
+                            .is_none_or(|next| next.agency != remittance.agency);
+                        if last_for_agency {
+                            ui.strong(format!("{} total", remittance.agency));
+                            ui.label("");
+                            ui.label("");
+                            ui.label("");
+                            ui.strong(format!("{:.2}", agency_total));
+                            ui.end_row();
+                            agency_total = 0.0;
+                        }
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching remittances: {}", e));
+        }
+    }
+}
@@ -1,7 +1,17 @@
+use super::deposits;
+use super::licenses;
 use super::stockservice;
+use crate::app::app::ActivePanel;
+use crate::app::app::PharmacyApp;
+use crate::app::users::Permission;
 
-pub fn render_home(ui: &mut egui::Ui) {
+pub fn render_home(app: &mut PharmacyApp, ui: &mut egui::Ui) {
     ui.heading("Welcome to the Home Page");
+    render_license_alerts(app, ui);
+    if app.can(Permission::ViewPayroll) {
+        deposits::render_upcoming_deposits(app, ui);
+    }
+
     ui.heading("Stock Prices");
 
     let stock_service = stockservice::StockService::instance();
@@ -11,7 +21,7 @@ pub fn render_home(ui: &mut egui::Ui) {
                 // Walgreens Stock
                 ui.vertical(|ui| {
                     let walgreens = &wba_data.quote;
-                    ui.label(format!("Walgreens (WBA)"));
+                    ui.label("Walgreens (WBA)");
                     ui.label(format!("Price: ${:.2}", walgreens.current_price));
                     ui.colored_label(
                         if walgreens.change >= 0.0 {
@@ -36,7 +46,7 @@ pub fn render_home(ui: &mut egui::Ui) {
                 // CVS Stock (similar implementation)
                 ui.vertical(|ui| {
                     let cvs = &cvs_data.quote;
-                    ui.label(format!("CVS (CVS)"));
+                    ui.label("CVS (CVS)");
                     ui.label(format!("Price: ${:.2}", cvs.current_price));
                     ui.colored_label(
                         if cvs.change >= 0.0 {
@@ -66,3 +76,39 @@ pub fn render_home(ui: &mut egui::Ui) {
     ui.heading("Welcome to the Medical Arts Pharmacy Payroll System");
     ui.heading("Manage your pharmacy's employee information and payroll with ease");
 }
+
+fn render_license_alerts(app: &mut PharmacyApp, ui: &mut egui::Ui) {
+    let today = chrono::Local::now().date_naive();
+    let alerts = match licenses::get_expiry_alerts(&app.conn, today) {
+        Ok(alerts) => alerts,
+        Err(e) => {
+            ui.label(format!("Error fetching license alerts: {}", e));
+            return;
+        }
+    };
+
+    if alerts.is_empty() {
+        return;
+    }
+
+    ui.separator();
+    ui.heading("License Expiry Alerts");
+    for alert in &alerts {
+        ui.colored_label(
+            licenses::alert_color(alert.days_left),
+            format!(
+                "{}: {} #{} ({}) expires {} - {} days left",
+                alert.employee_name,
+                alert.license.license_type,
+                alert.license.license_number,
+                alert.license.issuing_state,
+                alert.license.expiry_date,
+                alert.days_left
+            ),
+        );
+    }
+    if ui.button("View Licenses").clicked() {
+        app.active_panel = ActivePanel::Licenses;
+    }
+    ui.separator();
+}
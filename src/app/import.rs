@@ -0,0 +1,1101 @@
+use crate::app::app::PharmacyApp;
+use crate::app::companies;
+use crate::app::database;
+use crate::app::deductions::{DeductionLine, DeductionType};
+use crate::app::earnings::{EarningLine, EarningType};
+use crate::app::employee::{Employee, PayType};
+use crate::app::payroll::{ImportedAs, PayrollEntry};
+use crate::app::repository::{PayrollRepository, SqliteRepository};
+use crate::app::ssn;
+use crate::app::users;
+use crate::app::users::Permission;
+use calamine::Reader;
+use egui::Ui;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// USPS codes for the states, DC and the territories.
+const STATE_CODES: [&str; 56] = [
+    "AL", "AK", "AZ", "AR", "CA", "CO", "CT", "DE", "FL", "GA", "HI", "ID", "IL", "IN", "IA", "KS",
+    "KY", "LA", "ME", "MD", "MA", "MI", "MN", "MS", "MO", "MT", "NE", "NV", "NH", "NJ", "NM", "NY",
+    "NC", "ND", "OH", "OK", "OR", "PA", "RI", "SC", "SD", "TN", "TX", "UT", "VT", "VA", "WA", "WV",
+    "WI", "WY", "DC", "AS", "GU", "MP", "PR", "VI",
+];
+
+/// The `Employee` fields a column can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportField {
+    Name,
+    Position,
+    Address,
+    City,
+    State,
+    Phone,
+    FilingStatus,
+    Dependents,
+    PayRate,
+    PayType,
+    HireDate,
+    Ssn,
+}
+
+impl ImportField {
+    pub const ALL: [ImportField; 12] = [
+        ImportField::Name,
+        ImportField::Position,
+        ImportField::Address,
+        ImportField::City,
+        ImportField::State,
+        ImportField::Phone,
+        ImportField::FilingStatus,
+        ImportField::Dependents,
+        ImportField::PayRate,
+        ImportField::PayType,
+        ImportField::HireDate,
+        ImportField::Ssn,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportField::Name => "Name",
+            ImportField::Position => "Position",
+            ImportField::Address => "Address",
+            ImportField::City => "City",
+            ImportField::State => "State",
+            ImportField::Phone => "Phone",
+            ImportField::FilingStatus => "Filing Status",
+            ImportField::Dependents => "Dependents",
+            ImportField::PayRate => "Pay Rate",
+            ImportField::PayType => "Pay Type",
+            ImportField::HireDate => "Hire Date",
+            ImportField::Ssn => "SSN",
+        }
+    }
+
+    /// The value as it should be stored, or why it can't be.
+    pub fn normalize(&self, value: &str) -> Result<String, String> {
+        match self {
+            ImportField::Name
+            | ImportField::Position
+            | ImportField::Address
+            | ImportField::City => Ok(value.to_string()),
+            ImportField::State => {
+                let code = value.to_uppercase();
+                if STATE_CODES.contains(&code.as_str()) {
+                    Ok(code)
+                } else {
+                    Err(format!("{} isn't a state code", value))
+                }
+            }
+            ImportField::Phone => normalize_phone(value),
+            ImportField::FilingStatus => normalize_filing_status(value),
+            ImportField::Dependents => value
+                .parse::<u32>()
+                .map(|count| count.to_string())
+                .map_err(|_| format!("Dependents {} isn't a whole number", value)),
+            ImportField::PayRate => match value
+                .trim_start_matches('$')
+                .replace(',', "")
+                .parse::<f32>()
+            {
+                Ok(rate) if rate > 0.0 => Ok(rate.to_string()),
+                _ => Err(format!("Pay rate {} isn't a positive amount", value)),
+            },
+            ImportField::PayType => {
+                parse_pay_type(value).map(|pay_type| pay_type.as_str().to_string())
+            }
+            ImportField::HireDate => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|_| value.to_string())
+                .map_err(|_| format!("Hire date {} isn't YYYY-MM-DD", value)),
+            ImportField::Ssn => ssn::validate_ssn(value),
+        }
+    }
+
+    fn assign(&self, employee: &mut Employee, value: String) {
+        match self {
+            ImportField::Name => employee.name = value,
+            ImportField::Position => employee.position = value,
+            ImportField::Address => employee.address = value,
+            ImportField::City => employee.city = value,
+            ImportField::State => employee.state = value,
+            ImportField::Phone => employee.phone = value,
+            ImportField::FilingStatus => employee.filing_status = value,
+            ImportField::Dependents => employee.dependents = value,
+            ImportField::PayRate => employee.pay_rate = value,
+            ImportField::PayType => employee.pay_type = PayType::parse(&value),
+            ImportField::HireDate => employee.hire_date = value,
+            ImportField::Ssn => employee.ssn = value,
+        }
+    }
+
+    /// The field a column header most likely means, for the initial mapping.
+    pub fn guess(header: &str) -> Option<Self> {
+        Some(match header_key(header).as_str() {
+            "name" | "employee" | "employeename" | "fullname" => ImportField::Name,
+            "position" | "title" | "jobtitle" | "role" => ImportField::Position,
+            "address" | "street" | "streetaddress" => ImportField::Address,
+            "city" => ImportField::City,
+            "state" | "st" => ImportField::State,
+            "phone" | "phonenumber" | "telephone" | "mobile" => ImportField::Phone,
+            "filingstatus" | "filing" => ImportField::FilingStatus,
+            "dependents" => ImportField::Dependents,
+            "payrate" | "rate" | "wage" | "salary" => ImportField::PayRate,
+            "paytype" => ImportField::PayType,
+            "hiredate" | "hired" | "startdate" => ImportField::HireDate,
+            "ssn" | "socialsecuritynumber" => ImportField::Ssn,
+            _ => return None,
+        })
+    }
+}
+
+/// Lowercase letters and digits only, so `Pay Date`, `pay_date` and `PAYDATE` compare equal.
+fn header_key(header: &str) -> String {
+    header
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// A spreadsheet's header row and the text of every row under it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl ImportTable {
+    fn from_rows(mut rows: Vec<Vec<String>>) -> Result<Self, String> {
+        rows.retain(|row| row.iter().any(|cell| !cell.trim().is_empty()));
+        if rows.is_empty() {
+            return Err("The file is empty".to_string());
+        }
+        let headers = rows.remove(0);
+        Ok(Self { headers, rows })
+    }
+
+    /// The initial mapping, from the headers. Each field goes to the first column that
+    /// looks like it.
+    pub fn guess_mapping<F: Copy + PartialEq>(
+        &self,
+        guess: impl Fn(&str) -> Option<F>,
+    ) -> Vec<Option<F>> {
+        let mut taken = Vec::new();
+        self.headers
+            .iter()
+            .map(|header| {
+                let field = guess(header).filter(|field| !taken.contains(field));
+                taken.extend(field);
+                field
+            })
+            .collect()
+    }
+}
+
+pub fn parse_csv(text: &str) -> Result<ImportTable, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let rows = reader
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(|cell| cell.trim().to_string()).collect())
+                .map_err(|e| format!("Error reading CSV: {}", e))
+        })
+        .collect::<Result<Vec<Vec<String>>, String>>()?;
+    ImportTable::from_rows(rows)
+}
+
+/// Reads a CSV file, or the first sheet of an Excel workbook.
+pub fn read_table(path: &Path) -> Result<ImportTable, String> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "csv" => parse_csv(
+            &std::fs::read_to_string(path).map_err(|e| format!("Error reading file: {}", e))?,
+        ),
+        "xlsx" | "xlsm" | "xls" => {
+            let mut workbook = calamine::open_workbook_auto(path)
+                .map_err(|e| format!("Error opening workbook: {}", e))?;
+            let range = workbook
+                .worksheet_range_at(0)
+                .ok_or("The workbook has no sheets")?
+                .map_err(|e| format!("Error reading sheet: {}", e))?;
+            ImportTable::from_rows(
+                range
+                    .rows()
+                    .map(|row| {
+                        row.iter()
+                            .map(|cell| cell.to_string().trim().to_string())
+                            .collect()
+                    })
+                    .collect(),
+            )
+        }
+        _ => Err("Choose a .csv or .xlsx file".to_string()),
+    }
+}
+
+/// `(512) 555-1234`, from any ten-digit US number with or without a leading 1.
+pub fn normalize_phone(value: &str) -> Result<String, String> {
+    let digits: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = match digits.len() {
+        11 if digits.starts_with('1') => &digits[1..],
+        10 => &digits[..],
+        _ => return Err(format!("Phone {} isn't a 10-digit number", value)),
+    };
+    Ok(format!(
+        "({}) {}-{}",
+        &digits[..3],
+        &digits[3..6],
+        &digits[6..]
+    ))
+}
+
+/// The W-4 filing status a spreadsheet value stands for.
+pub fn normalize_filing_status(value: &str) -> Result<String, String> {
+    Ok(match header_key(value).as_str() {
+        "s" | "single" => "Single",
+        "m" | "mfj" | "married" | "marriedfilingjointly" => "Married Filing Jointly",
+        "mfs" | "marriedfilingseparately" => "Married Filing Separately",
+        "hoh" | "headofhousehold" => "Head of Household",
+        _ => return Err(format!("Unknown filing status {}", value)),
+    }
+    .to_string())
+}
+
+fn parse_pay_type(value: &str) -> Result<PayType, String> {
+    let key = value.trim().to_lowercase().replace([' ', '-'], "_");
+    match key.as_str() {
+        "" | "hourly" => Ok(PayType::Hourly),
+        "salary" | "salaried" | "salary_annual" | "annual" => Ok(PayType::SalaryAnnual),
+        "salary_period" | "per_period" => Ok(PayType::SalaryPerPeriod),
+        _ => Err(format!("Unknown pay type {}", value)),
+    }
+}
+
+/// One spreadsheet row as it would be imported, with everything wrong with it.
+#[derive(Debug, Clone)]
+pub struct ImportRow {
+    /// 1-based, counting the header row, as a spreadsheet shows it.
+    pub line: usize,
+    pub employee: Employee,
+    pub errors: Vec<String>,
+}
+
+fn phone_digits(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// Builds and validates every row, including duplicate checks against existing employees
+/// and earlier rows of the same file.
+pub fn validate_rows(
+    table: &ImportTable,
+    mapping: &[Option<ImportField>],
+    existing: &[Employee],
+    company_id: Option<i64>,
+) -> Vec<ImportRow> {
+    let mut names: HashSet<String> = existing
+        .iter()
+        .map(|employee| employee.name.trim().to_lowercase())
+        .collect();
+    let mut phones: HashSet<String> = existing
+        .iter()
+        .map(|employee| phone_digits(&employee.phone))
+        .filter(|digits| !digits.is_empty())
+        .collect();
+    let mut ssns: HashSet<String> = existing
+        .iter()
+        .map(|employee| employee.ssn.clone())
+        .filter(|ssn| !ssn.is_empty())
+        .collect();
+
+    table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(index, cells)| {
+            let mut employee = Employee {
+                company_id,
+                dependents: "0".to_string(),
+                ..Default::default()
+            };
+            let mut errors = Vec::new();
+            let mut invalid = Vec::new();
+            for (column, field) in mapping.iter().enumerate() {
+                let (Some(field), Some(value)) = (field, cells.get(column)) else {
+                    continue;
+                };
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                match field.normalize(value) {
+                    Ok(value) => field.assign(&mut employee, value),
+                    Err(e) => {
+                        errors.push(e);
+                        invalid.push(*field);
+                    }
+                }
+            }
+
+            for (missing, field) in [
+                (employee.name.is_empty(), ImportField::Name),
+                (employee.position.is_empty(), ImportField::Position),
+                (employee.pay_rate.is_empty(), ImportField::PayRate),
+            ] {
+                if missing && !invalid.contains(&field) {
+                    errors.push(format!("{} is required", field.label()));
+                }
+            }
+            if !employee.name.is_empty() && !names.insert(employee.name.trim().to_lowercase()) {
+                errors.push(format!("{} is already an employee", employee.name));
+            }
+            let digits = phone_digits(&employee.phone);
+            if !digits.is_empty() && !phones.insert(digits) {
+                errors.push(format!("Phone {} is already on file", employee.phone));
+            }
+            if !employee.ssn.is_empty() && !ssns.insert(employee.ssn.clone()) {
+                errors.push("SSN is already on file".to_string());
+            }
+            ImportRow {
+                line: index + 2,
+                employee,
+                errors,
+            }
+        })
+        .collect()
+}
+
+/// Adds every row in one transaction; nothing is added unless every row is valid.
+pub fn import_employees(conn: &Connection, rows: &[ImportRow]) -> Result<usize, String> {
+    if let Some(row) = rows.iter().find(|row| !row.errors.is_empty()) {
+        return Err(format!(
+            "Row {} has errors: {}",
+            row.line,
+            row.errors.join("; ")
+        ));
+    }
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Error starting import: {}", e))?;
+    for row in rows {
+        database::insert_employee(&tx, &row.employee)
+            .map_err(|e| format!("Error importing row {}: {}", row.line, e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Error saving import: {}", e))?;
+    Ok(rows.len())
+}
+
+/// The `PayrollEntry` amounts and tax categories a history column can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryField {
+    Employee,
+    PayDate,
+    Hours,
+    PtoHours,
+    Gross,
+    FederalWithholding,
+    SocialSecurity,
+    Net,
+    Deduction(DeductionType),
+}
+
+impl HistoryField {
+    pub fn all() -> Vec<HistoryField> {
+        let mut fields = vec![
+            HistoryField::Employee,
+            HistoryField::PayDate,
+            HistoryField::Hours,
+            HistoryField::PtoHours,
+            HistoryField::Gross,
+            HistoryField::FederalWithholding,
+            HistoryField::SocialSecurity,
+            HistoryField::Net,
+        ];
+        fields.extend(DeductionType::ALL.map(HistoryField::Deduction));
+        fields
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistoryField::Employee => "Employee Name",
+            HistoryField::PayDate => "Pay Date",
+            HistoryField::Hours => "Hours Worked",
+            HistoryField::PtoHours => "PTO Hours",
+            HistoryField::Gross => "Gross",
+            HistoryField::FederalWithholding => "Federal Withholding",
+            HistoryField::SocialSecurity => "Social Security",
+            HistoryField::Net => "Net",
+            HistoryField::Deduction(deduction_type) => deduction_type.label(),
+        }
+    }
+
+    /// Providers often prefix YTD columns, so `YTD Gross` maps the same as `Gross`.
+    pub fn guess(header: &str) -> Option<Self> {
+        let key = header_key(header);
+        let key = key.strip_prefix("ytd").unwrap_or(&key);
+        Some(match key {
+            "employee" | "employeename" | "name" => HistoryField::Employee,
+            "paydate" | "checkdate" | "dateofpay" | "date" | "asof" => HistoryField::PayDate,
+            "hours" | "hoursworked" | "regularhours" => HistoryField::Hours,
+            "ptohours" => HistoryField::PtoHours,
+            "gross" | "grosspay" | "grosswages" => HistoryField::Gross,
+            "federalwithholding" | "federalincometax" | "fit" | "fedwh" => {
+                HistoryField::FederalWithholding
+            }
+            "socialsecurity" | "fica" | "oasdi" => HistoryField::SocialSecurity,
+            "net" | "netpay" => HistoryField::Net,
+            _ => DeductionType::ALL
+                .into_iter()
+                .find(|deduction_type| {
+                    key == header_key(deduction_type.label())
+                        || key == header_key(deduction_type.as_str())
+                })
+                .map(HistoryField::Deduction)?,
+        })
+    }
+}
+
+/// One historical check, or one employee's YTD balance, as it would be imported.
+#[derive(Debug, Clone)]
+pub struct HistoryRow {
+    /// 1-based, counting the header row, as a spreadsheet shows it.
+    pub line: usize,
+    pub employee_name: String,
+    pub entry: PayrollEntry,
+    pub earnings: EarningLine,
+    pub deductions: Vec<DeductionLine>,
+    pub errors: Vec<String>,
+}
+
+fn parse_amount(field: HistoryField, value: &str) -> Result<f32, String> {
+    match value
+        .trim_start_matches('$')
+        .replace(',', "")
+        .parse::<f32>()
+    {
+        Ok(amount) if amount >= 0.0 => Ok(amount),
+        Ok(_) => Err(format!("{} can't be negative", field.label())),
+        Err(_) => Err(format!("{} {} isn't a number", field.label(), value)),
+    }
+}
+
+/// Builds and validates every row. `balance_date` stands in for a missing pay date, which
+/// suits YTD files that only give balances. Rows already imported, or repeated in the file,
+/// are duplicates: the same employee, date and gross for checks, or the same employee and
+/// year for YTD balances.
+pub fn validate_history(
+    table: &ImportTable,
+    mapping: &[Option<HistoryField>],
+    employees: &[Employee],
+    existing: &[PayrollEntry],
+    imported_as: ImportedAs,
+    balance_date: &str,
+) -> Vec<HistoryRow> {
+    let duplicate_key = |entry: &PayrollEntry| match imported_as {
+        ImportedAs::Check => format!(
+            "{}:{}:{:.2}",
+            entry.employee_id, entry.date_of_pay, entry.gross
+        ),
+        ImportedAs::YtdBalance => format!(
+            "{}:{}",
+            entry.employee_id,
+            entry.date_of_pay.get(..4).unwrap_or_default()
+        ),
+    };
+    let mut seen: HashSet<String> = existing
+        .iter()
+        .filter(|entry| entry.imported == Some(imported_as))
+        .map(duplicate_key)
+        .collect();
+
+    table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(index, cells)| {
+            let mut errors = Vec::new();
+            let cell = |field: HistoryField| {
+                mapping
+                    .iter()
+                    .position(|mapped| *mapped == Some(field))
+                    .and_then(|column| cells.get(column))
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+            };
+            let mut amount =
+                |field: HistoryField| match cell(field).map(|value| parse_amount(field, value)) {
+                    Some(Ok(amount)) => amount,
+                    Some(Err(e)) => {
+                        errors.push(e);
+                        0.0
+                    }
+                    None => 0.0,
+                };
+            let gross = amount(HistoryField::Gross);
+            let hours = amount(HistoryField::Hours);
+            let pto_hours = amount(HistoryField::PtoHours);
+            let withholding = amount(HistoryField::FederalWithholding);
+            let social_security = amount(HistoryField::SocialSecurity);
+            let net = cell(HistoryField::Net).map(|_| amount(HistoryField::Net));
+            let deductions: Vec<DeductionLine> = DeductionType::ALL
+                .into_iter()
+                .filter_map(|deduction_type| {
+                    let amount = amount(HistoryField::Deduction(deduction_type));
+                    let treatment = deduction_type.tax_treatment();
+                    (amount > 0.0).then(|| DeductionLine {
+                        id: 0,
+                        payroll_id: 0,
+                        code: deduction_type.as_str().to_string(),
+                        description: deduction_type.label().to_string(),
+                        amount,
+                        pre_tax_fit: treatment.reduces_fit(),
+                        pre_tax_fica: treatment.reduces_fica(),
+                    })
+                })
+                .collect();
+
+            let employee_name = cell(HistoryField::Employee).unwrap_or_default().to_string();
+            let matches: Vec<&Employee> = employees
+                .iter()
+                .filter(|employee| employee.name.trim().eq_ignore_ascii_case(&employee_name))
+                .collect();
+            let employee_id = match matches.as_slice() {
+                [employee] => employee.id,
+                [] if employee_name.is_empty() => {
+                    errors.push("Employee Name is required".to_string());
+                    0
+                }
+                [] => {
+                    errors.push(format!("No employee is named {}", employee_name));
+                    0
+                }
+                _ => {
+                    errors.push(format!("More than one employee is named {}", employee_name));
+                    0
+                }
+            };
+
+            let date_of_pay = cell(HistoryField::PayDate)
+                .unwrap_or(balance_date.trim())
+                .to_string();
+            if date_of_pay.is_empty() {
+                errors.push("Pay Date is required".to_string());
+            } else if chrono::NaiveDate::parse_from_str(&date_of_pay, "%Y-%m-%d").is_err() {
+                errors.push(format!("Pay date {} isn't YYYY-MM-DD", date_of_pay));
+            }
+            if gross <= 0.0 && cell(HistoryField::Gross).is_none() {
+                errors.push("Gross is required".to_string());
+            }
+            let withheld =
+                withholding + social_security + deductions.iter().map(|d| d.amount).sum::<f32>();
+            if withheld > gross + 0.005 {
+                errors.push("Taxes and deductions are more than gross".to_string());
+            }
+
+            let entry = PayrollEntry {
+                id: 0,
+                date_of_pay,
+                employee_id,
+                hours_worked: hours,
+                gross,
+                withholding,
+                roth_ira: deductions
+                    .iter()
+                    .filter(|d| d.code == DeductionType::RothIra.as_str())
+                    .map(|d| d.amount)
+                    .sum(),
+                social_security,
+                net: net.unwrap_or(((gross - withheld) * 100.0).round() / 100.0),
+                pto_hours,
+                off_cycle_reason: None,
+                imported: Some(imported_as),
+            };
+            if errors.is_empty() && !seen.insert(duplicate_key(&entry)) {
+                errors.push(match imported_as {
+                    ImportedAs::Check => format!(
+                        "This check for {} on {} is already imported",
+                        employee_name, entry.date_of_pay
+                    ),
+                    ImportedAs::YtdBalance => format!(
+                        "{} already has a YTD balance for {}",
+                        employee_name,
+                        &entry.date_of_pay[..4]
+                    ),
+                });
+            }
+
+            // The previous provider's earnings arrive as one straight-time line.
+            let mut earnings = EarningLine::new(EarningType::Regular, hours, 0.0);
+            earnings.rate = if hours > 0.0 { gross / hours } else { 0.0 };
+            earnings.amount = gross;
+            HistoryRow {
+                line: index + 2,
+                employee_name,
+                entry,
+                earnings,
+                deductions,
+                errors,
+            }
+        })
+        .collect()
+}
+
+/// Saves every row as a locked, imported check in one transaction; nothing is saved unless
+/// every row is valid.
+pub fn import_history(conn: &Connection, rows: &[HistoryRow]) -> Result<usize, String> {
+    if let Some(row) = rows.iter().find(|row| !row.errors.is_empty()) {
+        return Err(format!(
+            "Row {} has errors: {}",
+            row.line,
+            row.errors.join("; ")
+        ));
+    }
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| format!("Error starting import: {}", e))?;
+    for row in rows {
+        row.entry
+            .insert_check(
+                &tx,
+                std::slice::from_ref(&row.earnings),
+                &row.deductions,
+                &[],
+            )
+            .map_err(|e| format!("Error importing row {}: {}", row.line, e))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Error saving import: {}", e))?;
+    Ok(rows.len())
+}
+
+/// Path box and Load button. Returns the file's contents once Load is clicked.
+fn render_file_picker(
+    ui: &mut Ui,
+    path: &mut String,
+    hint: &str,
+) -> Option<Result<ImportTable, String>> {
+    ui.label("1. Choose a CSV or Excel file whose first row names the columns.");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(path)
+                .hint_text(hint)
+                .desired_width(400.0),
+        );
+        ui.button("Load")
+            .clicked()
+            .then(|| read_table(Path::new(path.trim())))
+    })
+    .inner
+}
+
+/// One combo per column; returns whether any mapping changed.
+fn render_mapping<F: Copy + PartialEq>(
+    ui: &mut Ui,
+    id: &str,
+    headers: &[String],
+    mapping: &mut [Option<F>],
+    fields: &[F],
+    label: impl Fn(&F) -> &'static str,
+) -> bool {
+    let mut changed = false;
+    egui::Grid::new(id)
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            for (column, header) in headers.iter().enumerate() {
+                ui.label(header);
+                let field = &mut mapping[column];
+                egui::ComboBox::from_id_salt((id, column))
+                    .selected_text(field.as_ref().map_or("Skip", &label))
+                    .show_ui(ui, |ui| {
+                        changed |= ui.selectable_value(field, None, "Skip").changed();
+                        for option in fields {
+                            changed |= ui
+                                .selectable_value(field, Some(*option), label(option))
+                                .changed();
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+    changed
+}
+
+fn problems_label(ui: &mut Ui, errors: &[String]) {
+    if errors.is_empty() {
+        ui.label("OK");
+    } else {
+        ui.colored_label(egui::Color32::RED, errors.join("; "));
+    }
+}
+
+#[derive(Default)]
+pub struct ImportForm {
+    pub open: bool,
+    pub path: String,
+    pub table: Option<ImportTable>,
+    pub mapping: Vec<Option<ImportField>>,
+    /// Validated rows for the preview; rebuilt whenever the mapping changes.
+    pub rows: Vec<ImportRow>,
+    pub status: String,
+}
+
+fn revalidate(conn: &Connection, form: &mut ImportForm, company_id: Option<i64>) {
+    let Some(table) = &form.table else {
+        form.rows.clear();
+        return;
+    };
+    let existing = database::get_all_employees(conn).unwrap_or_default();
+    form.rows = validate_rows(table, &form.mapping, &existing, company_id);
+}
+
+/// The import wizard: choose a file, map its columns, then check the preview and import.
+pub fn render_import(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    let company_id = app.company_id;
+    let form = &mut app.import_form;
+    if !form.open {
+        return;
+    }
+    let mut open = true;
+    let mut mapping_changed = false;
+    egui::Window::new("Import Employees")
+        .open(&mut open)
+        .default_width(700.0)
+        .show(ui.ctx(), |ui| {
+            match render_file_picker(ui, &mut form.path, "/path/to/employees.xlsx") {
+                Some(Ok(table)) => {
+                    form.mapping = table.guess_mapping(ImportField::guess);
+                    form.status = format!("Read {} rows", table.rows.len());
+                    form.table = Some(table);
+                    mapping_changed = true;
+                }
+                Some(Err(e)) => {
+                    form.table = None;
+                    form.rows.clear();
+                    form.status = e;
+                }
+                None => {}
+            }
+            let Some(table) = &form.table else {
+                if !form.status.is_empty() {
+                    ui.label(&form.status);
+                }
+                return;
+            };
+
+            ui.separator();
+            ui.label("2. Match each column to an employee field.");
+            mapping_changed |= render_mapping(
+                ui,
+                "import_mapping_grid",
+                &table.headers,
+                &mut form.mapping,
+                &ImportField::ALL,
+                ImportField::label,
+            );
+
+            ui.separator();
+            let invalid = form
+                .rows
+                .iter()
+                .filter(|row| !row.errors.is_empty())
+                .count();
+            ui.label(format!(
+                "3. Check the preview: {} rows, {} with errors.",
+                form.rows.len(),
+                invalid
+            ));
+            egui::ScrollArea::vertical()
+                .id_salt("import_preview_scroll")
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("import_preview_grid")
+                        .striped(true)
+                        .spacing([10.0, 10.0])
+                        .show(ui, |ui| {
+                            ui.strong("Row");
+                            ui.strong("Name");
+                            ui.strong("Position");
+                            ui.strong("State");
+                            ui.strong("Phone");
+                            ui.strong("Pay Rate");
+                            ui.strong("Problems");
+                            ui.end_row();
+                            for row in &form.rows {
+                                ui.label(row.line.to_string());
+                                ui.label(&row.employee.name);
+                                ui.label(&row.employee.position);
+                                ui.label(&row.employee.state);
+                                ui.label(&row.employee.phone);
+                                ui.label(&row.employee.pay_rate);
+                                problems_label(ui, &row.errors);
+                                ui.end_row();
+                            }
+                        });
+                });
+
+            let ready = invalid == 0 && !form.rows.is_empty();
+            let button = ui
+                .add_enabled(
+                    ready && can_edit_employees,
+                    egui::Button::new(format!("Import {} Employees", form.rows.len())),
+                )
+                .on_disabled_hover_text(if can_edit_employees {
+                    "Fix every row with errors first"
+                } else {
+                    "Your role doesn't allow this"
+                });
+            if button.clicked() {
+                form.status = match import_employees(&app.conn, &form.rows) {
+                    Ok(count) => {
+                        form.table = None;
+                        form.rows.clear();
+                        form.path.clear();
+                        format!("Imported {} employees", count)
+                    }
+                    Err(e) => e,
+                };
+            }
+            if !form.status.is_empty() {
+                ui.label(&form.status);
+            }
+        });
+    if mapping_changed {
+        revalidate(&app.conn, form, company_id);
+    }
+    form.open = open;
+}
+
+/// Opens the import wizard from the Employees panel.
+pub fn render_import_button(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    if users::guarded_button(ui, can_edit_employees, "Import Employees").clicked() {
+        app.import_form.open = true;
+    }
+}
+
+pub struct HistoryImportForm {
+    pub open: bool,
+    pub path: String,
+    pub imported_as: ImportedAs,
+    /// `YYYY-MM-DD` the YTD balances run through, for files without a pay date column.
+    pub balance_date: String,
+    pub table: Option<ImportTable>,
+    pub mapping: Vec<Option<HistoryField>>,
+    /// Validated rows for the preview; rebuilt whenever the mapping or mode changes.
+    pub rows: Vec<HistoryRow>,
+    pub status: String,
+}
+
+impl Default for HistoryImportForm {
+    fn default() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            imported_as: ImportedAs::Check,
+            balance_date: String::new(),
+            table: None,
+            mapping: Vec::new(),
+            rows: Vec::new(),
+            status: String::new(),
+        }
+    }
+}
+
+fn revalidate_history(conn: &Connection, form: &mut HistoryImportForm, company_id: Option<i64>) {
+    let Some(table) = &form.table else {
+        form.rows.clear();
+        return;
+    };
+    let employees = companies::company_employees(conn, company_id).unwrap_or_default();
+    let repo = SqliteRepository::new(conn);
+    let existing: Vec<PayrollEntry> = employees
+        .iter()
+        .flat_map(|employee| repo.checks_for_employee(employee.id).unwrap_or_default())
+        .collect();
+    let balance_date = match form.imported_as {
+        ImportedAs::Check => "",
+        ImportedAs::YtdBalance => form.balance_date.as_str(),
+    };
+    form.rows = validate_history(
+        table,
+        &form.mapping,
+        &employees,
+        &existing,
+        form.imported_as,
+        balance_date,
+    );
+}
+
+/// The payroll history wizard for a mid-year conversion: choose a file of past checks or
+/// YTD balances, map its columns, then check the preview and import.
+pub fn render_history_import(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    let company_id = app.company_id;
+    let form = &mut app.history_import_form;
+    if !form.open {
+        return;
+    }
+    let mut open = true;
+    let mut changed = false;
+    egui::Window::new("Import Payroll History")
+        .open(&mut open)
+        .default_width(800.0)
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .radio_value(
+                        &mut form.imported_as,
+                        ImportedAs::Check,
+                        "One row per check",
+                    )
+                    .changed();
+                changed |= ui
+                    .radio_value(
+                        &mut form.imported_as,
+                        ImportedAs::YtdBalance,
+                        "One YTD balance row per employee",
+                    )
+                    .changed();
+            });
+            if form.imported_as == ImportedAs::YtdBalance {
+                ui.horizontal(|ui| {
+                    ui.label("Balances as of");
+                    changed |= ui
+                        .add(
+                            egui::TextEdit::singleline(&mut form.balance_date)
+                                .hint_text("YYYY-MM-DD")
+                                .desired_width(100.0),
+                        )
+                        .changed();
+                });
+            }
+            match render_file_picker(ui, &mut form.path, "/path/to/payroll_history.csv") {
+                Some(Ok(table)) => {
+                    form.mapping = table.guess_mapping(HistoryField::guess);
+                    form.status = format!("Read {} rows", table.rows.len());
+                    form.table = Some(table);
+                    changed = true;
+                }
+                Some(Err(e)) => {
+                    form.table = None;
+                    form.rows.clear();
+                    form.status = e;
+                }
+                None => {}
+            }
+            let Some(table) = &form.table else {
+                if !form.status.is_empty() {
+                    ui.label(&form.status);
+                }
+                return;
+            };
+
+            ui.separator();
+            ui.label("2. Match each column to a payroll amount or tax category.");
+            changed |= render_mapping(
+                ui,
+                "history_mapping_grid",
+                &table.headers,
+                &mut form.mapping,
+                &HistoryField::all(),
+                HistoryField::label,
+            );
+
+            ui.separator();
+            let invalid = form
+                .rows
+                .iter()
+                .filter(|row| !row.errors.is_empty())
+                .count();
+            ui.label(format!(
+                "3. Check the preview: {} rows, {} with errors. Imported rows count toward YTD \
+                totals and limits but can't be edited or deleted afterwards.",
+                form.rows.len(),
+                invalid
+            ));
+            egui::ScrollArea::vertical()
+                .id_salt("history_preview_scroll")
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("history_preview_grid")
+                        .striped(true)
+                        .spacing([10.0, 10.0])
+                        .show(ui, |ui| {
+                            ui.strong("Row");
+                            ui.strong("Employee");
+                            ui.strong("Pay Date");
+                            ui.strong("Gross");
+                            ui.strong("Withholding");
+                            ui.strong("Social Security");
+                            ui.strong("Deductions");
+                            ui.strong("Net");
+                            ui.strong("Problems");
+                            ui.end_row();
+                            for row in &form.rows {
+                                ui.label(row.line.to_string());
+                                ui.label(&row.employee_name);
+                                ui.label(&row.entry.date_of_pay);
+                                ui.label(format!("{:.2}", row.entry.gross));
+                                ui.label(format!("{:.2}", row.entry.withholding));
+                                ui.label(format!("{:.2}", row.entry.social_security));
+                                ui.label(format!(
+                                    "{:.2}",
+                                    row.deductions.iter().map(|d| d.amount).sum::<f32>()
+                                ));
+                                ui.label(format!("{:.2}", row.entry.net));
+                                problems_label(ui, &row.errors);
+                                ui.end_row();
+                            }
+                        });
+                });
+
+            let ready = invalid == 0 && !form.rows.is_empty();
+            let button = ui
+                .add_enabled(
+                    ready && can_edit_payroll,
+                    egui::Button::new(format!("Import {} Rows", form.rows.len())),
+                )
+                .on_disabled_hover_text(if can_edit_payroll {
+                    "Fix every row with errors first"
+                } else {
+                    "Your role doesn't allow this"
+                });
+            if button.clicked() {
+                form.status = match import_history(&app.conn, &form.rows) {
+                    Ok(count) => {
+                        form.table = None;
+                        form.rows.clear();
+                        form.path.clear();
+                        format!("Imported {} rows of payroll history", count)
+                    }
+                    Err(e) => e,
+                };
+            }
+            if !form.status.is_empty() {
+                ui.label(&form.status);
+            }
+        });
+    if changed {
+        revalidate_history(&app.conn, form, company_id);
+    }
+    form.open = open;
+}
+
+/// Opens the payroll history wizard from the Payroll panel.
+pub fn render_history_button(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    if users::guarded_button(ui, can_edit_payroll, "Import History").clicked() {
+        app.history_import_form.open = true;
+    }
+}
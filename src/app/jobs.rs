@@ -0,0 +1,102 @@
+use crate::app::app::PharmacyApp;
+use poll_promise::Promise;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How far a background job has got, shared between the worker and the UI.
+#[derive(Clone, Default)]
+pub struct Progress {
+    state: Arc<Mutex<(f32, String)>>,
+}
+
+impl Progress {
+    /// `fraction` runs from 0 to 1.
+    pub fn set(&self, fraction: f32, message: &str) {
+        *self.state.lock().expect("Job progress lock poisoned") =
+            (fraction.clamp(0.0, 1.0), message.to_string());
+    }
+
+    pub fn get(&self) -> (f32, String) {
+        self.state
+            .lock()
+            .expect("Job progress lock poisoned")
+            .clone()
+    }
+}
+
+/// Slow work, such as a backup or report, running on its own thread like the update check.
+pub struct Job {
+    pub label: String,
+    pub progress: Progress,
+    promise: Promise<Result<String, String>>,
+}
+
+impl Job {
+    pub fn spawn(
+        label: &str,
+        work: impl FnOnce(&Progress) -> Result<String, String> + Send + 'static,
+    ) -> Self {
+        let progress = Progress::default();
+        let worker_progress = progress.clone();
+        Self {
+            label: label.to_string(),
+            progress,
+            promise: Promise::spawn_thread(label, move || work(&worker_progress)),
+        }
+    }
+
+    /// The finished job's message, or `None` while it is still running.
+    pub fn result(&self) -> Option<&Result<String, String>> {
+        self.promise.ready()
+    }
+}
+
+impl PharmacyApp {
+    pub fn job_running(&self, label: &str) -> bool {
+        self.jobs
+            .iter()
+            .any(|job| job.label == label && job.result().is_none())
+    }
+}
+
+/// Progress of running jobs along the bottom of the window, with the outcome of finished
+/// ones until they are dismissed.
+pub fn render_jobs(app: &mut PharmacyApp, ctx: &egui::Context) {
+    if app.jobs.is_empty() {
+        return;
+    }
+    let mut dismissed = None;
+    egui::TopBottomPanel::bottom("jobs_panel").show(ctx, |ui| {
+        for (index, job) in app.jobs.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.strong(&job.label);
+                match job.result() {
+                    None => {
+                        let (fraction, message) = job.progress.get();
+                        ui.add(
+                            egui::ProgressBar::new(fraction)
+                                .desired_width(200.0)
+                                .show_percentage(),
+                        );
+                        ui.label(message);
+                    }
+                    Some(result) => {
+                        match result {
+                            Ok(message) => ui.label(message),
+                            Err(e) => ui.colored_label(egui::Color32::RED, e),
+                        };
+                        if ui.button("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+                    }
+                }
+            });
+        }
+    });
+    if let Some(index) = dismissed {
+        app.jobs.remove(index);
+    }
+    if app.jobs.iter().any(|job| job.result().is_none()) {
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+}
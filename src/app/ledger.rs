@@ -0,0 +1,642 @@
+use crate::app::app::PharmacyApp;
+use crate::app::companies;
+use crate::app::deductions;
+use crate::app::deductions::DeductionType;
+use crate::app::deposits;
+use crate::app::earnings;
+use crate::app::earnings::EarningType;
+use crate::app::retirement;
+use crate::app::retirement::get_exports_dir;
+use crate::app::retirement::RetirementPlan;
+use crate::app::users;
+use crate::app::users::Permission;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Which side of the journal a mapped account normally lands on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Debit,
+    Credit,
+}
+
+/// One configurable account slot, like `earnings:regular` or `net_pay`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlKey {
+    pub key: String,
+    pub label: String,
+    pub side: Side,
+}
+
+/// Every account a pay run can post to, in the order the mapping editor lists them.
+pub fn gl_keys() -> Vec<GlKey> {
+    let key = |key: String, label: String, side: Side| GlKey { key, label, side };
+    let mut keys = Vec::new();
+    for earning_type in EarningType::ALL {
+        keys.push(key(
+            earning_key(earning_type),
+            format!("{} wages expense", earning_type.label()),
+            Side::Debit,
+        ));
+    }
+    keys.push(key(
+        "tax:federal_withholding".to_string(),
+        "Federal withholding payable".to_string(),
+        Side::Credit,
+    ));
+    keys.push(key(
+        "tax:employee_fica".to_string(),
+        "Employee FICA payable".to_string(),
+        Side::Credit,
+    ));
+    for deduction_type in DeductionType::ALL {
+        keys.push(key(
+            format!("deduction:{}", deduction_type.as_str()),
+            format!("{} payable", deduction_type.label()),
+            Side::Credit,
+        ));
+    }
+    keys.push(key(
+        "deduction:garnishment".to_string(),
+        "Garnishments payable".to_string(),
+        Side::Credit,
+    ));
+    keys.push(key(
+        "employer_tax:fica_expense".to_string(),
+        "Employer FICA expense".to_string(),
+        Side::Debit,
+    ));
+    keys.push(key(
+        "employer_tax:fica_payable".to_string(),
+        "Employer FICA payable".to_string(),
+        Side::Credit,
+    ));
+    keys.push(key(
+        "employer_tax:suta_expense".to_string(),
+        "State unemployment expense".to_string(),
+        Side::Debit,
+    ));
+    keys.push(key(
+        "employer_tax:suta_payable".to_string(),
+        "State unemployment payable".to_string(),
+        Side::Credit,
+    ));
+    for plan in RetirementPlan::ALL {
+        keys.push(key(
+            format!("retirement:{}_expense", plan.as_str()),
+            format!("Employer {} contribution expense", plan.label()),
+            Side::Debit,
+        ));
+        keys.push(key(
+            format!("retirement:{}_payable", plan.as_str()),
+            format!("Employer {} contribution payable", plan.label()),
+            Side::Credit,
+        ));
+    }
+    keys.push(key(
+        "net_pay".to_string(),
+        "Net pay (cash)".to_string(),
+        Side::Credit,
+    ));
+    keys
+}
+
+fn earning_key(earning_type: EarningType) -> String {
+    format!("earnings:{}", earning_type.as_str())
+}
+
+fn deduction_key(code: &str) -> String {
+    if code.starts_with("garnishment:") {
+        "deduction:garnishment".to_string()
+    } else {
+        format!("deduction:{}", code)
+    }
+}
+
+/// Account numbers by key for the company. Mappings are kept per company because each one
+/// has its own books; `None` uses the mapping for an installation without companies.
+pub fn get_mapping(
+    conn: &Connection,
+    company_id: Option<i64>,
+) -> Result<HashMap<String, String>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT key, account FROM gl_accounts WHERE company_id = ?1")?;
+    let mapping = stmt
+        .query_map([company_id.unwrap_or(0)], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(mapping)
+}
+
+pub fn save_mapping(
+    conn: &Connection,
+    company_id: Option<i64>,
+    mapping: &HashMap<String, String>,
+) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    for (key, account) in mapping {
+        tx.execute(
+            "INSERT INTO gl_accounts (company_id, key, account) VALUES (?1, ?2, ?3)
+            ON CONFLICT(company_id, key) DO UPDATE SET account = excluded.account",
+            params![company_id.unwrap_or(0), key, account.trim()],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalLine {
+    pub key: String,
+    pub account: String,
+    pub memo: String,
+    /// Cents, so balancing is exact.
+    pub debit: i64,
+    pub credit: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal {
+    pub date_of_pay: String,
+    pub lines: Vec<JournalLine>,
+}
+
+impl Journal {
+    pub fn total_debits(&self) -> i64 {
+        self.lines.iter().map(|line| line.debit).sum()
+    }
+
+    pub fn total_credits(&self) -> i64 {
+        self.lines.iter().map(|line| line.credit).sum()
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.total_debits() == self.total_credits()
+    }
+
+    /// Labels of accounts the journal uses that have no account number yet.
+    pub fn unmapped(&self) -> Vec<String> {
+        let keys = gl_keys();
+        self.lines
+            .iter()
+            .filter(|line| line.account.is_empty())
+            .map(|line| {
+                keys.iter()
+                    .find(|k| k.key == line.key)
+                    .map_or(line.key.clone(), |k| k.label.clone())
+            })
+            .collect()
+    }
+}
+
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+/// The journal entry for every check paid on `date_of_pay`, one line per account. Wages and
+/// employer costs are debits; withholding, deductions, employer liabilities and net pay are
+/// credits. Employer FICA matches the employee share, and SUTA comes from
+/// `deposits::get_suta_by_check`, capped at the company's wage base. Each check is rounded to the cent on its own before the run is totaled,
+/// the way it was paid.
+pub fn build_journal(
+    conn: &Connection,
+    date_of_pay: &str,
+    company_id: Option<i64>,
+) -> Result<Journal, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, gross, withholding, social_security, net FROM payroll
+        WHERE date_of_pay = ?1 AND (?2 IS NULL OR company_id = ?2) AND imported IS NULL
+        ORDER BY id",
+    )?;
+    let checks = stmt
+        .query_map(params![date_of_pay, company_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, f32>(1)?,
+                row.get::<_, f32>(2)?,
+                row.get::<_, f32>(3)?,
+                row.get::<_, f32>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let suta_by_check = match companies::get_companies(conn)?
+        .into_iter()
+        .find(|company| Some(company.id) == company_id)
+    {
+        Some(company) => deposits::get_suta_by_check(conn, &company)?,
+        None => HashMap::new(),
+    };
+
+    let sides: HashMap<String, Side> = gl_keys()
+        .into_iter()
+        .map(|gl_key| (gl_key.key, gl_key.side))
+        .collect();
+    let mut totals: HashMap<String, i64> = HashMap::new();
+    for (payroll_id, gross, withholding, social_security, net) in checks {
+        let mut amounts: Vec<(String, i64)> = Vec::new();
+        let mut add = |key: String, amount: f32| amounts.push((key, cents(amount as f64)));
+        let lines = earnings::get_earnings_for_check(conn, payroll_id)?;
+        if lines.is_empty() {
+            // Checks entered before earnings lines existed were all regular pay.
+            add(earning_key(EarningType::Regular), gross);
+        }
+        for line in lines {
+            add(earning_key(line.earning_type), line.amount);
+        }
+        add("tax:federal_withholding".to_string(), withholding);
+        add("tax:employee_fica".to_string(), social_security);
+        for deduction in deductions::get_deductions_for_check(conn, payroll_id)? {
+            add(deduction_key(&deduction.code), deduction.amount);
+        }
+        add("employer_tax:fica_expense".to_string(), social_security);
+        add("employer_tax:fica_payable".to_string(), social_security);
+        let suta = suta_by_check.get(&payroll_id).copied().unwrap_or(0.0);
+        add("employer_tax:suta_expense".to_string(), suta);
+        add("employer_tax:suta_payable".to_string(), suta);
+        for contribution in retirement::get_contributions_for_check(conn, payroll_id)? {
+            let plan = contribution.plan.as_str();
+            add(format!("retirement:{}_expense", plan), contribution.amount);
+            add(format!("retirement:{}_payable", plan), contribution.amount);
+        }
+        // Rounding each amount can leave the check up to half a cent per amount off. Net pay
+        // is what remains after everything else, so it takes that remainder; anything larger
+        // is a real mismatch and is left to unbalance the journal.
+        let remainder: i64 = amounts
+            .iter()
+            .map(|(key, amount)| match sides.get(key) {
+                Some(Side::Debit) => *amount,
+                Some(Side::Credit) => -*amount,
+                None => 0,
+            })
+            .sum();
+        let mut net_cents = cents(net as f64);
+        if (remainder - net_cents).abs() * 2 <= amounts.len() as i64 + 1 {
+            net_cents = remainder;
+        }
+        amounts.push(("net_pay".to_string(), net_cents));
+        for (key, amount) in amounts {
+            *totals.entry(key).or_insert(0) += amount;
+        }
+    }
+
+    let mapping = get_mapping(conn, company_id)?;
+    let lines = gl_keys()
+        .into_iter()
+        .filter_map(|gl_key| {
+            let amount = totals.get(&gl_key.key).copied().unwrap_or(0);
+            if amount == 0 {
+                return None;
+            }
+            let (debit, credit) = match gl_key.side {
+                Side::Debit => (amount, 0),
+                Side::Credit => (0, amount),
+            };
+            Some(JournalLine {
+                account: mapping.get(&gl_key.key).cloned().unwrap_or_default(),
+                key: gl_key.key,
+                memo: format!("Payroll {} - {}", date_of_pay, gl_key.label),
+                debit,
+                credit,
+            })
+        })
+        .collect();
+    Ok(Journal {
+        date_of_pay: date_of_pay.to_string(),
+        lines,
+    })
+}
+
+/// Pay dates with checks for the company, newest first. Imported history was already
+/// posted by the previous provider, so it never gets a journal here.
+pub fn get_pay_dates(
+    conn: &Connection,
+    company_id: Option<i64>,
+) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT date_of_pay FROM payroll
+        WHERE (?1 IS NULL OR company_id = ?1) AND imported IS NULL
+        ORDER BY date_of_pay DESC",
+    )?;
+    let dates = stmt
+        .query_map([company_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(dates)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    QuickBooksIif,
+    QuickBooksOnlineCsv,
+    GenericCsv,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::QuickBooksIif,
+        ExportFormat::QuickBooksOnlineCsv,
+        ExportFormat::GenericCsv,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::QuickBooksIif => "qb_iif",
+            ExportFormat::QuickBooksOnlineCsv => "qbo",
+            ExportFormat::GenericCsv => "generic",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::QuickBooksIif => "QuickBooks Desktop (IIF)",
+            ExportFormat::QuickBooksOnlineCsv => "QuickBooks Online (CSV)",
+            ExportFormat::GenericCsv => "Generic CSV",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::QuickBooksIif => "iif",
+            _ => "csv",
+        }
+    }
+}
+
+fn money(cents: i64) -> String {
+    format!(
+        "{}{}.{:02}",
+        if cents < 0 { "-" } else { "" },
+        cents.abs() / 100,
+        cents.abs() % 100
+    )
+}
+
+/// QuickBooks wants US-style dates in both of its import formats.
+fn us_date(date_of_pay: &str) -> String {
+    chrono::NaiveDate::parse_from_str(date_of_pay, "%Y-%m-%d")
+        .map(|date| date.format("%m/%d/%Y").to_string())
+        .unwrap_or_else(|_| date_of_pay.to_string())
+}
+
+/// Renders the journal in `format`. Refuses a journal that doesn't balance or that posts
+/// to an account with no number mapped.
+pub fn journal_file(
+    journal: &Journal,
+    format: ExportFormat,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if journal.lines.is_empty() {
+        return Err(format!("No checks on {}", journal.date_of_pay).into());
+    }
+    if !journal.is_balanced() {
+        return Err(format!(
+            "Journal for {} does not balance: debits {} and credits {}",
+            journal.date_of_pay,
+            money(journal.total_debits()),
+            money(journal.total_credits())
+        )
+        .into());
+    }
+    let unmapped = journal.unmapped();
+    if !unmapped.is_empty() {
+        return Err(format!("Map an account for: {}", unmapped.join(", ")).into());
+    }
+
+    match format {
+        ExportFormat::QuickBooksIif => {
+            // IIF is tab-delimited: the first line is the transaction, the rest are splits,
+            // and credits are negative amounts.
+            let date = us_date(&journal.date_of_pay);
+            let mut file = String::from(
+                "!TRNS\tTRNSTYPE\tDATE\tACCNT\tAMOUNT\tMEMO\n\
+                 !SPL\tTRNSTYPE\tDATE\tACCNT\tAMOUNT\tMEMO\n\
+                 !ENDTRNS\n",
+            );
+            for (index, line) in journal.lines.iter().enumerate() {
+                file.push_str(&format!(
+                    "{}\tGENERAL JOURNAL\t{}\t{}\t{}\t{}\n",
+                    if index == 0 { "TRNS" } else { "SPL" },
+                    date,
+                    line.account,
+                    money(line.debit - line.credit),
+                    line.memo
+                ));
+            }
+            file.push_str("ENDTRNS\n");
+            Ok(file)
+        }
+        ExportFormat::QuickBooksOnlineCsv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record([
+                "Journal No.",
+                "Journal Date",
+                "Account Name",
+                "Debits",
+                "Credits",
+                "Description",
+            ])?;
+            let journal_no = format!("PR-{}", journal.date_of_pay);
+            let date = us_date(&journal.date_of_pay);
+            for line in &journal.lines {
+                writer.write_record([
+                    journal_no.clone(),
+                    date.clone(),
+                    line.account.clone(),
+                    if line.debit > 0 {
+                        money(line.debit)
+                    } else {
+                        String::new()
+                    },
+                    if line.credit > 0 {
+                        money(line.credit)
+                    } else {
+                        String::new()
+                    },
+                    line.memo.clone(),
+                ])?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        ExportFormat::GenericCsv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(["Date", "Account", "Description", "Debit", "Credit"])?;
+            for line in &journal.lines {
+                writer.write_record([
+                    journal.date_of_pay.clone(),
+                    line.account.clone(),
+                    line.memo.clone(),
+                    money(line.debit),
+                    money(line.credit),
+                ])?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+    }
+}
+
+pub fn export_journal(
+    conn: &Connection,
+    date_of_pay: &str,
+    company_id: Option<i64>,
+    format: ExportFormat,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let journal = build_journal(conn, date_of_pay, company_id)?;
+    let file = journal_file(&journal, format)?;
+    let exports_dir = get_exports_dir();
+    std::fs::create_dir_all(&exports_dir)?;
+    let path = exports_dir.join(format!(
+        "journal_{}_{}_{}.{}",
+        company_id.unwrap_or(0),
+        date_of_pay,
+        format.as_str(),
+        format.extension()
+    ));
+    std::fs::write(&path, file)?;
+    Ok(path)
+}
+
+#[derive(Default)]
+pub struct LedgerForm {
+    pub mapping: HashMap<String, String>,
+    /// Which company `mapping` was loaded for.
+    pub loaded_for: Option<Option<i64>>,
+    pub date_of_pay: String,
+    pub status: String,
+}
+
+pub fn render_ledger(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("General Ledger");
+    let form = &mut app.ledger_form;
+    if form.loaded_for != Some(app.company_id) {
+        form.mapping = get_mapping(&app.conn, app.company_id).unwrap_or_default();
+        form.loaded_for = Some(app.company_id);
+        form.date_of_pay.clear();
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::CollapsingHeader::new("Account Mapping").show(ui, |ui| {
+            egui::Grid::new("gl_mapping_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Posting");
+                    ui.strong("Side");
+                    ui.strong("Account");
+                    ui.end_row();
+                    for gl_key in gl_keys() {
+                        ui.label(&gl_key.label);
+                        ui.label(match gl_key.side {
+                            Side::Debit => "Debit",
+                            Side::Credit => "Credit",
+                        });
+                        ui.add(
+                            egui::TextEdit::singleline(form.mapping.entry(gl_key.key).or_default())
+                                .desired_width(200.0),
+                        );
+                        ui.end_row();
+                    }
+                });
+            if users::guarded_button(ui, can_edit_payroll, "Save Mapping").clicked() {
+                form.status = match save_mapping(&app.conn, app.company_id, &form.mapping) {
+                    Ok(()) => "Account mapping saved".to_string(),
+                    Err(e) => format!("Error saving mapping: {}", e),
+                };
+            }
+        });
+
+        ui.add_space(10.0);
+        let pay_dates = get_pay_dates(&app.conn, app.company_id).unwrap_or_default();
+        if pay_dates.is_empty() {
+            ui.label("No pay runs to post");
+        } else {
+            if !pay_dates.contains(&form.date_of_pay) {
+                form.date_of_pay = pay_dates[0].clone();
+            }
+            ui.horizontal(|ui| {
+                ui.label("Pay date");
+                egui::ComboBox::from_id_salt("journal_pay_date")
+                    .selected_text(&form.date_of_pay)
+                    .show_ui(ui, |ui| {
+                        for date in &pay_dates {
+                            ui.selectable_value(&mut form.date_of_pay, date.clone(), date);
+                        }
+                    });
+            });
+
+            match build_journal(&app.conn, &form.date_of_pay, app.company_id) {
+                Ok(journal) => {
+                    egui::Grid::new("journal_grid")
+                        .striped(true)
+                        .spacing([10.0, 10.0])
+                        .show(ui, |ui| {
+                            ui.strong("Account");
+                            ui.strong("Description");
+                            ui.strong("Debit");
+                            ui.strong("Credit");
+                            ui.end_row();
+                            for line in &journal.lines {
+                                if line.account.is_empty() {
+                                    ui.colored_label(egui::Color32::RED, "Unmapped");
+                                } else {
+                                    ui.label(&line.account);
+                                }
+                                ui.label(&line.memo);
+                                ui.label(if line.debit > 0 {
+                                    money(line.debit)
+                                } else {
+                                    String::new()
+                                });
+                                ui.label(if line.credit > 0 {
+                                    money(line.credit)
+                                } else {
+                                    String::new()
+                                });
+                                ui.end_row();
+                            }
+                            ui.strong("");
+                            ui.strong("Total");
+                            ui.strong(money(journal.total_debits()));
+                            ui.strong(money(journal.total_credits()));
+                            ui.end_row();
+                        });
+                    if journal.is_balanced() {
+                        ui.colored_label(egui::Color32::LIGHT_GREEN, "Balanced");
+                    } else {
+                        ui.colored_label(
+                            egui::Color32::RED,
+                            format!(
+                                "Out of balance by {}",
+                                money(journal.total_debits() - journal.total_credits())
+                            ),
+                        );
+                    }
+                }
+                Err(e) => {
+                    ui.label(format!("Error building journal: {}", e));
+                }
+            }
+
+            ui.horizontal(|ui| {
+                for format in ExportFormat::ALL {
+                    if ui.button(format!("Export {}", format.label())).clicked() {
+                        form.status = match export_journal(
+                            &app.conn,
+                            &form.date_of_pay,
+                            app.company_id,
+                            format,
+                        ) {
+                            Ok(path) => format!("Journal saved to {}", path.display()),
+                            Err(e) => format!("Export refused: {}", e),
+                        };
+                    }
+                }
+            });
+        }
+
+        if !form.status.is_empty() {
+            ui.label(&form.status);
+        }
+    });
+}
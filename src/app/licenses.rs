@@ -0,0 +1,584 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::database;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::NaiveDate;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const LICENSE_TYPES: [&str; 5] = [
+    "Pharmacist License",
+    "Pharmacy Technician Registration",
+    "PTCB Certification (CPhT)",
+    "Immunization Certification",
+    "Controlled Substance Registration",
+];
+
+/// Days before expiry at which a credential starts showing up on the Home panel.
+pub const ALERT_THRESHOLDS: [i64; 3] = [30, 60, 90];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct License {
+    pub id: i64,
+    pub employee_id: i32,
+    pub license_type: String,
+    pub license_number: String,
+    pub issuing_state: String,
+    pub issue_date: String,
+    pub expiry_date: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseDocument {
+    pub id: i64,
+    pub license_id: i64,
+    pub file_name: String,
+    pub stored_path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct LicenseAlert {
+    pub license: License,
+    pub employee_name: String,
+    pub days_left: i64,
+}
+
+#[derive(Default)]
+pub struct LicenseForm {
+    pub employee_id: Option<i32>,
+    pub license_type: String,
+    pub license_number: String,
+    pub issuing_state: String,
+    pub issue_date: String,
+    pub expiry_date: String,
+    pub document_path: String,
+    pub status: String,
+}
+
+impl License {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO licenses (
+                employee_id,
+                license_type,
+                license_number,
+                issuing_state,
+                issue_date,
+                expiry_date)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.employee_id,
+                &self.license_type,
+                &self.license_number,
+                &self.issuing_state,
+                &self.issue_date,
+                &self.expiry_date
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(conn, "license", "licenses", id, AuditAction::Insert, None)?;
+        Ok(id)
+    }
+
+    pub fn days_until_expiry(&self, today: NaiveDate) -> Option<i64> {
+        NaiveDate::parse_from_str(&self.expiry_date, "%Y-%m-%d")
+            .ok()
+            .map(|expiry| (expiry - today).num_days())
+    }
+}
+
+impl LicenseAlert {
+    /// The tightest alert window (30, 60 or 90 days) the credential falls into.
+    pub fn threshold(&self) -> Option<i64> {
+        alert_threshold(self.days_left)
+    }
+}
+
+pub fn alert_threshold(days_left: i64) -> Option<i64> {
+    if days_left < 0 {
+        return None;
+    }
+    ALERT_THRESHOLDS.iter().copied().find(|t| days_left <= *t)
+}
+
+fn license_from_row(row: &rusqlite::Row) -> Result<License, rusqlite::Error> {
+    Ok(License {
+        id: row.get(0)?,
+        employee_id: row.get(1)?,
+        license_type: row.get(2)?,
+        license_number: row.get(3)?,
+        issuing_state: row.get(4)?,
+        issue_date: row.get(5)?,
+        expiry_date: row.get(6)?,
+    })
+}
+
+pub fn get_licenses_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<License>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, license_type, license_number, issuing_state, issue_date, expiry_date
+        FROM licenses WHERE employee_id = ?1 ORDER BY expiry_date",
+    )?;
+    let licenses = stmt
+        .query_map([employee_id], license_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(licenses)
+}
+
+/// Every license on file paired with the holder's name and the days left until it expires.
+fn get_licenses_with_days_left(
+    conn: &Connection,
+    today: NaiveDate,
+) -> Result<Vec<LicenseAlert>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT l.id, l.employee_id, l.license_type, l.license_number, l.issuing_state,
+            l.issue_date, l.expiry_date, e.name
+        FROM licenses l JOIN employees e ON e.id = l.employee_id
+        ORDER BY l.expiry_date",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((license_from_row(row)?, row.get::<_, String>(7)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|(license, employee_name)| {
+            license
+                .days_until_expiry(today)
+                .map(|days_left| LicenseAlert {
+                    license,
+                    employee_name,
+                    days_left,
+                })
+        })
+        .collect())
+}
+
+pub fn get_expiry_alerts(
+    conn: &Connection,
+    today: NaiveDate,
+) -> Result<Vec<LicenseAlert>, rusqlite::Error> {
+    Ok(get_licenses_with_days_left(conn, today)?
+        .into_iter()
+        .filter(|alert| alert.threshold().is_some())
+        .collect())
+}
+
+pub fn get_expired_licenses(
+    conn: &Connection,
+    today: NaiveDate,
+) -> Result<Vec<LicenseAlert>, rusqlite::Error> {
+    Ok(get_licenses_with_days_left(conn, today)?
+        .into_iter()
+        .filter(|alert| alert.days_left < 0)
+        .collect())
+}
+
+pub fn delete_license(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    for document in get_documents_for_license(conn, id)? {
+        if let Err(e) = std::fs::remove_file(&document.stored_path) {
+            println!("Error removing document {}: {}", document.stored_path, e);
+        }
+    }
+    let before = audit::snapshot(conn, "licenses", id)?;
+    conn.execute("DELETE FROM license_documents WHERE license_id = ?1", [id])?;
+    conn.execute("DELETE FROM licenses WHERE id = ?1", [id])?;
+    audit::record_row(
+        conn,
+        "license",
+        "licenses",
+        id,
+        AuditAction::Delete,
+        before.as_ref(),
+    )
+}
+
+pub fn get_documents_dir() -> PathBuf {
+    let mut path = database::get_data_dir();
+    path.push("documents");
+    path
+}
+
+/// Copies a scanned document into `documents_dir`, normally `get_documents_dir()`, and links
+/// it to the license. The stored file is named after the document row so scans with the same
+/// file name don't collide.
+pub fn attach_document(
+    conn: &Connection,
+    license_id: i64,
+    source: &Path,
+    documents_dir: &Path,
+) -> Result<i64, Box<dyn std::error::Error>> {
+    let file_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or("Invalid document path")?
+        .to_string();
+
+    std::fs::create_dir_all(documents_dir)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO license_documents (license_id, file_name, stored_path) VALUES (?1, ?2, '')",
+        params![license_id, &file_name],
+    )?;
+    let id = tx.last_insert_rowid();
+    let stored_path = documents_dir.join(format!("license_{}_{}", id, file_name));
+    std::fs::copy(source, &stored_path)?;
+    if let Err(e) = tx
+        .execute(
+            "UPDATE license_documents SET stored_path = ?1 WHERE id = ?2",
+            params![stored_path.to_string_lossy(), id],
+        )
+        .and_then(|_| {
+            audit::record_row(
+                &tx,
+                "license_document",
+                "license_documents",
+                id,
+                AuditAction::Insert,
+                None,
+            )
+        })
+        .and_then(|_| tx.commit())
+    {
+        std::fs::remove_file(&stored_path).ok();
+        return Err(e.into());
+    }
+    Ok(id)
+}
+
+pub fn get_documents_for_license(
+    conn: &Connection,
+    license_id: i64,
+) -> Result<Vec<LicenseDocument>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, license_id, file_name, stored_path FROM license_documents WHERE license_id = ?1",
+    )?;
+    let documents = stmt
+        .query_map([license_id], |row| {
+            Ok(LicenseDocument {
+                id: row.get(0)?,
+                license_id: row.get(1)?,
+                file_name: row.get(2)?,
+                stored_path: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(documents)
+}
+
+pub fn alert_color(days_left: i64) -> egui::Color32 {
+    match alert_threshold(days_left) {
+        None => egui::Color32::RED,
+        Some(30) => egui::Color32::from_rgb(255, 100, 100),
+        Some(60) => egui::Color32::from_rgb(255, 165, 0),
+        Some(_) => egui::Color32::YELLOW,
+    }
+}
+
+fn save_license(app: &mut PharmacyApp) {
+    let form = &mut app.license_form;
+    let Some(employee_id) = form.employee_id else {
+        form.status = "Please select an employee".to_string();
+        return;
+    };
+
+    let mut missing_fields = Vec::new();
+    for (field, field_name) in [
+        (&form.license_type, "License Type"),
+        (&form.license_number, "License Number"),
+        (&form.issuing_state, "Issuing State"),
+    ] {
+        if field.trim().is_empty() {
+            missing_fields.push(field_name);
+        }
+    }
+    if !missing_fields.is_empty() {
+        form.status = format!(
+            "Please enter the following fields: {}",
+            missing_fields.join(", ")
+        );
+        return;
+    }
+
+    let issue_date = NaiveDate::parse_from_str(&form.issue_date, "%Y-%m-%d");
+    let expiry_date = NaiveDate::parse_from_str(&form.expiry_date, "%Y-%m-%d");
+    match (issue_date, expiry_date) {
+        (Ok(issued), Ok(expires)) if expires > issued => {}
+        (Ok(_), Ok(_)) => {
+            form.status = "Expiry date must be after the issue date".to_string();
+            return;
+        }
+        _ => {
+            form.status = "Dates must be in YYYY-MM-DD format".to_string();
+            return;
+        }
+    }
+
+    let license = License {
+        id: 0,
+        employee_id,
+        license_type: form.license_type.clone(),
+        license_number: form.license_number.trim().to_string(),
+        issuing_state: form.issuing_state.trim().to_uppercase(),
+        issue_date: form.issue_date.clone(),
+        expiry_date: form.expiry_date.clone(),
+    };
+
+    match license.save_to_db(&app.conn) {
+        Ok(_) => {
+            form.status = "License added successfully".to_string();
+            form.license_number.clear();
+            form.issuing_state.clear();
+            form.issue_date.clear();
+            form.expiry_date.clear();
+        }
+        Err(e) => form.status = format!("Error saving license: {}", e),
+    }
+}
+
+pub fn render_licenses(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    ui.heading("Licenses & Certifications");
+
+    let employees = match app.cache.company_employees(&app.conn, app.company_id) {
+        Ok(e) => e,
+        Err(e) => {
+            ui.label(format!("Error: {}", e));
+            return;
+        }
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Select Employee");
+        egui::ComboBox::from_id_salt("license_employee_select")
+            .selected_text(
+                employees
+                    .iter()
+                    .find(|emp| Some(emp.id) == app.license_form.employee_id)
+                    .map(|emp| emp.name.clone())
+                    .unwrap_or_else(|| "Select an employee".to_string()),
+            )
+            .show_ui(ui, |ui| {
+                for emp in &employees {
+                    ui.selectable_value(&mut app.license_form.employee_id, Some(emp.id), &emp.name);
+                }
+            });
+    });
+
+    let today = chrono::Local::now().date_naive();
+
+    if let Some(employee_id) = app.license_form.employee_id {
+        ui.separator();
+        let licenses = app
+            .cache
+            .query(&app.conn, format!("licenses:{}", employee_id), |conn| {
+                get_licenses_for_employee(conn, employee_id)
+            });
+        match licenses {
+            Ok(licenses) if licenses.is_empty() => {
+                ui.label("No licenses on file");
+            }
+            Ok(licenses) => {
+                egui::Grid::new("licenses_grid")
+                    .striped(true)
+                    .spacing([10.0, 10.0])
+                    .show(ui, |ui| {
+                        ui.strong("Type");
+                        ui.strong("Number");
+                        ui.strong("State");
+                        ui.strong("Issued");
+                        ui.strong("Expires");
+                        ui.strong("Status");
+                        ui.strong("Documents");
+                        ui.end_row();
+                        for license in &licenses {
+                            ui.label(&license.license_type);
+                            ui.label(&license.license_number);
+                            ui.label(&license.issuing_state);
+                            ui.label(&license.issue_date);
+                            ui.label(&license.expiry_date);
+                            match license.days_until_expiry(today) {
+                                Some(days) if days < 0 => {
+                                    ui.colored_label(egui::Color32::RED, "Expired");
+                                }
+                                Some(days) if alert_threshold(days).is_some() => {
+                                    ui.colored_label(
+                                        alert_color(days),
+                                        format!("Expires in {} days", days),
+                                    );
+                                }
+                                _ => {
+                                    ui.label("Active");
+                                }
+                            }
+
+                            ui.vertical(|ui| {
+                                let documents = app.cache.query(
+                                    &app.conn,
+                                    format!("license_documents:{}", license.id),
+                                    |conn| get_documents_for_license(conn, license.id),
+                                );
+                                match documents {
+                                    Ok(documents) => {
+                                        for document in documents {
+                                            ui.label(&document.file_name)
+                                                .on_hover_text(&document.stored_path);
+                                        }
+                                    }
+                                    Err(e) => {
+                                        ui.label(format!("Error: {}", e));
+                                    }
+                                }
+                                if users::guarded_button(ui, can_edit_employees, "Attach Document")
+                                    .clicked()
+                                {
+                                    let path = PathBuf::from(app.license_form.document_path.trim());
+                                    app.license_form.status = match attach_document(
+                                        &app.conn,
+                                        license.id,
+                                        &path,
+                                        &get_documents_dir(),
+                                    ) {
+                                        Ok(_) => {
+                                            app.license_form.document_path.clear();
+                                            "Document attached".to_string()
+                                        }
+                                        Err(e) => format!("Error attaching document: {}", e),
+                                    };
+                                }
+                            });
+
+                            if users::guarded_button(ui, can_edit_employees, "Delete").clicked() {
+                                delete_license(&app.conn, license.id)
+                                    .unwrap_or_else(|e| println!("Error deleting license: {}", e));
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    ui.label("Document File:")
+                        .on_hover_text("Path to a scanned license, then click Attach Document");
+                    ui.add_sized(
+                        [300.0, 25.0],
+                        egui::TextEdit::singleline(&mut app.license_form.document_path)
+                            .hint_text("/path/to/scan.pdf"),
+                    );
+                });
+            }
+            Err(e) => {
+                ui.label(format!("Error fetching licenses: {}", e));
+            }
+        }
+
+        ui.separator();
+        ui.label("Add License");
+        ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.label("Type");
+                egui::ComboBox::from_id_salt("license_type_select")
+                    .selected_text(if app.license_form.license_type.is_empty() {
+                        "Select a type".to_string()
+                    } else {
+                        app.license_form.license_type.clone()
+                    })
+                    .show_ui(ui, |ui| {
+                        for license_type in LICENSE_TYPES {
+                            ui.selectable_value(
+                                &mut app.license_form.license_type,
+                                license_type.to_string(),
+                                license_type,
+                            );
+                        }
+                    });
+            });
+            ui.vertical(|ui| {
+                ui.label("Number");
+                ui.add_sized(
+                    [120.0, 25.0],
+                    egui::TextEdit::singleline(&mut app.license_form.license_number),
+                );
+            });
+            ui.vertical(|ui| {
+                ui.label("State");
+                ui.add_sized(
+                    [50.0, 25.0],
+                    egui::TextEdit::singleline(&mut app.license_form.issuing_state).hint_text("TX"),
+                );
+            });
+            ui.vertical(|ui| {
+                ui.label("Issued");
+                ui.add_sized(
+                    [100.0, 25.0],
+                    egui::TextEdit::singleline(&mut app.license_form.issue_date)
+                        .hint_text("YYYY-MM-DD"),
+                );
+            });
+            ui.vertical(|ui| {
+                ui.label("Expires");
+                ui.add_sized(
+                    [100.0, 25.0],
+                    egui::TextEdit::singleline(&mut app.license_form.expiry_date)
+                        .hint_text("YYYY-MM-DD"),
+                );
+            });
+        });
+        if users::guarded_button(ui, can_edit_employees, "Save License").clicked() {
+            save_license(app);
+        }
+    }
+
+    if !app.license_form.status.is_empty() {
+        ui.label(&app.license_form.status);
+    }
+
+    ui.add_space(20.0);
+    ui.separator();
+    ui.heading("Expired Credentials Report");
+    let expired = app
+        .cache
+        .query(&app.conn, format!("expired_licenses:{}", today), |conn| {
+            get_expired_licenses(conn, today)
+        });
+    match expired {
+        Ok(expired) if expired.is_empty() => {
+            ui.label("No expired credentials");
+        }
+        Ok(expired) => {
+            egui::Grid::new("expired_licenses_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Employee");
+                    ui.strong("Type");
+                    ui.strong("Number");
+                    ui.strong("State");
+                    ui.strong("Expired On");
+                    ui.strong("Days Overdue");
+                    ui.end_row();
+                    for alert in &expired {
+                        ui.label(&alert.employee_name);
+                        ui.label(&alert.license.license_type);
+                        ui.label(&alert.license.license_number);
+                        ui.label(&alert.license.issuing_state);
+                        ui.label(&alert.license.expiry_date);
+                        ui.colored_label(egui::Color32::RED, format!("{}", -alert.days_left));
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching expired credentials: {}", e));
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::earnings::EarningType;
+use crate::app::employee::Employee;
+use crate::app::users;
+use crate::app::users::Permission;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Location {
+    pub id: i64,
+    pub name: String,
+    pub address: String,
+    pub active: bool,
+}
+
+impl Location {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO locations (name, address, active) VALUES (?1, ?2, ?3)",
+            params![&self.name, &self.address, self.active],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(conn, "location", "locations", id, AuditAction::Insert, None)?;
+        Ok(id)
+    }
+}
+
+pub fn get_locations(conn: &Connection) -> Result<Vec<Location>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, name, address, active FROM locations ORDER BY name")?;
+    let locations = stmt
+        .query_map([], |row| {
+            Ok(Location {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                address: row.get(2)?,
+                active: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(locations)
+}
+
+pub fn set_location_active(
+    conn: &Connection,
+    id: i64,
+    active: bool,
+) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "locations", id)?;
+    conn.execute(
+        "UPDATE locations SET active = ?1 WHERE id = ?2",
+        params![active, id],
+    )?;
+    audit::record_row(
+        conn,
+        "location",
+        "locations",
+        id,
+        AuditAction::Update,
+        before.as_ref(),
+    )
+}
+
+pub fn set_home_location(
+    conn: &Connection,
+    employee_id: i32,
+    location_id: Option<i64>,
+) -> Result<(), rusqlite::Error> {
+    let old_location: Option<i64> = conn.query_row(
+        "SELECT home_location_id FROM employees WHERE id = ?1",
+        params![employee_id],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "UPDATE employees SET home_location_id = ?1 WHERE id = ?2",
+        params![location_id, employee_id],
+    )?;
+    audit::record(
+        conn,
+        "employee",
+        employee_id as i64,
+        AuditAction::Update,
+        Some(&serde_json::json!({ "home_location_id": old_location })),
+        Some(&serde_json::json!({ "home_location_id": location_id })),
+    )
+}
+
+pub fn location_name(locations: &[Location], id: Option<i64>) -> String {
+    id.and_then(|id| locations.iter().find(|l| l.id == id))
+        .map_or("Unassigned".to_string(), |l| l.name.clone())
+}
+
+/// Keeps only employees whose home location matches the filter; `None` shows everyone.
+pub fn filter_employees(employees: Vec<Employee>, filter: Option<i64>) -> Vec<Employee> {
+    match filter {
+        Some(location_id) => employees
+            .into_iter()
+            .filter(|e| e.home_location_id == Some(location_id))
+            .collect(),
+        None => employees,
+    }
+}
+
+/// Location picker; `None` is shown as `none_label`.
+pub fn location_combo(
+    ui: &mut Ui,
+    id_salt: impl std::hash::Hash,
+    locations: &[Location],
+    selected: &mut Option<i64>,
+    none_label: &str,
+) -> bool {
+    let before = *selected;
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(match selected {
+            Some(_) => location_name(locations, *selected),
+            None => none_label.to_string(),
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, none_label);
+            for location in locations.iter().filter(|l| l.active) {
+                ui.selectable_value(selected, Some(location.id), &location.name);
+            }
+        });
+    *selected != before
+}
+
+/// The location filter shared by the Employees and Payroll panels.
+pub fn render_location_filter(app: &mut PharmacyApp, ui: &mut Ui, id_salt: &str) -> bool {
+    let locations = app
+        .cache
+        .query(&app.conn, "locations".to_string(), get_locations)
+        .unwrap_or_default();
+    if locations.is_empty() {
+        return false;
+    }
+    ui.label("Location");
+    location_combo(
+        ui,
+        id_salt,
+        &locations,
+        &mut app.location_filter,
+        "All locations",
+    )
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaborCost {
+    pub location_id: Option<i64>,
+    pub hours: f32,
+    pub wages: f32,
+    pub employer_contributions: f32,
+}
+
+impl LaborCost {
+    pub fn total(&self) -> f32 {
+        self.wages + self.employer_contributions
+    }
+}
+
+/// Wages and hours per location for checks paid between `from` and `to`. Each earnings line
+/// counts toward the location it was allocated to, or the employee's home location when it
+/// wasn't, and employer contributions on a check are spread across locations in proportion
+/// to the wages.
+pub fn get_labor_costs(
+    conn: &Connection,
+    from: &str,
+    to: &str,
+    company_id: Option<i64>,
+) -> Result<Vec<LaborCost>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT er.payroll_id, COALESCE(er.location_id, e.home_location_id), er.earning_type,
+            er.hours, er.amount
+        FROM earnings er
+        JOIN payroll p ON p.id = er.payroll_id
+        JOIN employees e ON e.id = p.employee_id
+        WHERE p.date_of_pay BETWEEN ?1 AND ?2 AND (?3 IS NULL OR p.company_id = ?3)",
+    )?;
+    let lines = stmt
+        .query_map(params![from, to, company_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f32>(3)?,
+                row.get::<_, f32>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT c.payroll_id, SUM(c.amount) FROM employer_contributions c
+        JOIN payroll p ON p.id = c.payroll_id
+        WHERE p.date_of_pay BETWEEN ?1 AND ?2 AND (?3 IS NULL OR p.company_id = ?3)
+        GROUP BY c.payroll_id",
+    )?;
+    let contributions: HashMap<i64, f32> = stmt
+        .query_map(params![from, to, company_id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut check_gross: HashMap<i64, f32> = HashMap::new();
+    for (payroll_id, _, _, _, amount) in &lines {
+        *check_gross.entry(*payroll_id).or_insert(0.0) += amount;
+    }
+
+    let mut costs: Vec<LaborCost> = Vec::new();
+    for (payroll_id, location_id, earning_type, hours, amount) in lines {
+        let index = match costs.iter().position(|c| c.location_id == location_id) {
+            Some(index) => index,
+            None => {
+                costs.push(LaborCost {
+                    location_id,
+                    ..Default::default()
+                });
+                costs.len() - 1
+            }
+        };
+        let cost = &mut costs[index];
+        if EarningType::parse(&earning_type).is_worked() {
+            cost.hours += hours;
+        }
+        cost.wages += amount;
+        let gross = check_gross.get(&payroll_id).copied().unwrap_or(0.0);
+        if gross > 0.0 {
+            cost.employer_contributions +=
+                contributions.get(&payroll_id).copied().unwrap_or(0.0) * amount / gross;
+        }
+    }
+    Ok(costs)
+}
+
+#[derive(Default)]
+pub struct LocationForm {
+    pub location: Location,
+    pub report_from: String,
+    pub report_to: String,
+    pub status: String,
+}
+
+pub fn render_locations(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    let can_view_payroll = app.can(Permission::ViewPayroll);
+    ui.heading("Locations");
+    let locations = app
+        .cache
+        .query(&app.conn, "locations".to_string(), get_locations)
+        .unwrap_or_default();
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        if locations.is_empty() {
+            ui.label("No locations");
+        } else {
+            egui::Grid::new("locations_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Name");
+                    ui.strong("Address");
+                    ui.strong("Status");
+                    ui.end_row();
+                    for location in &locations {
+                        ui.label(&location.name);
+                        ui.label(&location.address);
+                        ui.label(if location.active { "Open" } else { "Closed" });
+                        let label = if location.active { "Close" } else { "Reopen" };
+                        if users::guarded_button(ui, can_edit_employees, label).clicked() {
+                            set_location_active(&app.conn, location.id, !location.active)
+                                .unwrap_or_else(|e| println!("Error updating location: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+
+        let form = &mut app.location_form;
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut form.location.name);
+            ui.label("Address");
+            ui.text_edit_singleline(&mut form.location.address);
+            if users::guarded_button(ui, can_edit_employees, "Add Location").clicked() {
+                if form.location.name.trim().is_empty() {
+                    form.status = "Location name is required".to_string();
+                } else {
+                    form.location.active = true;
+                    form.status = match form.location.save_to_db(&app.conn) {
+                        Ok(_) => {
+                            form.location = Location::default();
+                            "Location added".to_string()
+                        }
+                        Err(e) => format!("Error saving location: {}", e),
+                    };
+                }
+            }
+        });
+
+        // Wages by location can be one person's pay, so it takes payroll access.
+        if can_view_payroll {
+            ui.add_space(20.0);
+            ui.separator();
+            ui.heading("Labor Cost by Location");
+            ui.horizontal(|ui| {
+                ui.label("From");
+                ui.add(
+                    egui::TextEdit::singleline(&mut form.report_from)
+                        .hint_text("YYYY-MM-DD")
+                        .desired_width(100.0),
+                );
+                ui.label("To");
+                ui.add(
+                    egui::TextEdit::singleline(&mut form.report_to)
+                        .hint_text("YYYY-MM-DD")
+                        .desired_width(100.0),
+                );
+            });
+            if form.report_from.is_empty() || form.report_to.is_empty() {
+                ui.label("Enter a pay date range");
+            } else {
+                match get_labor_costs(
+                    &app.conn,
+                    &form.report_from,
+                    &form.report_to,
+                    app.company_id,
+                ) {
+                    Ok(costs) if costs.is_empty() => {
+                        ui.label("No wages in this range");
+                    }
+                    Ok(costs) => {
+                        egui::Grid::new("labor_cost_grid")
+                            .striped(true)
+                            .spacing([10.0, 10.0])
+                            .show(ui, |ui| {
+                                ui.strong("Location");
+                                ui.strong("Hours");
+                                ui.strong("Wages");
+                                ui.strong("Employer Contributions");
+                                ui.strong("Total Cost");
+                                ui.end_row();
+                                for cost in &costs {
+                                    ui.label(location_name(&locations, cost.location_id));
+                                    ui.label(format!("{:.2}", cost.hours));
+                                    ui.label(format!("{:.2}", cost.wages));
+                                    ui.label(format!("{:.2}", cost.employer_contributions));
+                                    ui.label(format!("{:.2}", cost.total()));
+                                    ui.end_row();
+                                }
+                            });
+                    }
+                    Err(e) => {
+                        ui.label(format!("Error fetching labor costs: {}", e));
+                    }
+                }
+            }
+        }
+
+        if !form.status.is_empty() {
+            ui.label(&form.status);
+        }
+    });
+}
@@ -1,9 +1,32 @@
 pub mod admin;
+#[allow(clippy::module_inception)]
 pub mod app;
+pub mod audit;
+pub mod backup;
+pub mod cache;
+pub mod companies;
+pub mod continuing_education;
 pub mod database;
+pub mod deductions;
+pub mod deposits;
+pub mod earnings;
 pub mod employee;
+pub mod garnishments;
 pub mod home;
+pub mod import;
+pub mod jobs;
+pub mod ledger;
+pub mod licenses;
+pub mod locations;
 pub mod payroll;
+pub mod pto;
+pub mod repository;
+pub mod retirement;
+pub mod retro;
+pub mod security;
+pub mod session;
 pub mod settings;
+pub mod ssn;
 pub mod stockservice;
 pub mod update;
+pub mod users;
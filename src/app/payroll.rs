@@ -1,13 +1,120 @@
-use crate::app::app::get_available_fridays;
 use crate::app::app::get_fridays_of_year;
 use crate::app::app::PharmacyApp;
-use crate::app::database;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::cache::CachedRepository;
+use crate::app::deductions;
+use crate::app::deductions::DeductionLine;
+use crate::app::deductions::DeductionType;
+use crate::app::earnings;
+use crate::app::earnings::EarningLine;
+use crate::app::earnings::EarningType;
+use crate::app::earnings::SUPPLEMENTAL_FEDERAL_RATE;
+use crate::app::employee::Employee;
+use crate::app::garnishments;
+use crate::app::import;
+use crate::app::locations;
+use crate::app::locations::Location;
+use crate::app::pto;
+use crate::app::repository::{EmployeeRepository, PayrollRepository};
+use crate::app::retirement;
+use crate::app::retirement::EmployerContribution;
+use crate::app::retro;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::Datelike;
+use chrono::NaiveDate;
 use egui::Ui;
 use rusqlite::params;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OffCycleReason {
+    FinalPay,
+    Bonus,
+    Commission,
+    Correction,
+    Manual,
+}
+
+impl OffCycleReason {
+    pub const ALL: [OffCycleReason; 5] = [
+        OffCycleReason::FinalPay,
+        OffCycleReason::Bonus,
+        OffCycleReason::Commission,
+        OffCycleReason::Correction,
+        OffCycleReason::Manual,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OffCycleReason::FinalPay => "final_pay",
+            OffCycleReason::Bonus => "bonus",
+            OffCycleReason::Commission => "commission",
+            OffCycleReason::Correction => "correction",
+            OffCycleReason::Manual => "manual",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "final_pay" => OffCycleReason::FinalPay,
+            "bonus" => OffCycleReason::Bonus,
+            "commission" => OffCycleReason::Commission,
+            "correction" => OffCycleReason::Correction,
+            _ => OffCycleReason::Manual,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OffCycleReason::FinalPay => "Final pay",
+            OffCycleReason::Bonus => "Bonus",
+            OffCycleReason::Commission => "Commission",
+            OffCycleReason::Correction => "Correction",
+            OffCycleReason::Manual => "Manual check",
+        }
+    }
+
+    /// Bonus and commission checks are paid separately from regular wages, so their federal
+    /// withholding defaults to the flat supplemental rate (Pub. 15, section 7).
+    pub fn is_supplemental(&self) -> bool {
+        matches!(self, OffCycleReason::Bonus | OffCycleReason::Commission)
+    }
+}
+
+/// How a check brought over from the previous payroll provider was kept there.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ImportedAs {
+    Check,
+    YtdBalance,
+}
+
+impl ImportedAs {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportedAs::Check => "check",
+            ImportedAs::YtdBalance => "ytd",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "ytd" => ImportedAs::YtdBalance,
+            _ => ImportedAs::Check,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportedAs::Check => "Imported check",
+            ImportedAs::YtdBalance => "Imported YTD balance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PayrollEntry {
     pub id: i64,
     pub date_of_pay: String,
@@ -18,6 +125,11 @@ pub struct PayrollEntry {
     pub roth_ira: f32,
     pub social_security: f32,
     pub net: f32,
+    pub pto_hours: f32,
+    /// `None` for checks on the regular Friday schedule.
+    pub off_cycle_reason: Option<OffCycleReason>,
+    /// Set on history from a previous provider. It counts toward YTD but can't be changed.
+    pub imported: Option<ImportedAs>,
 }
 
 impl PayrollEntry {
@@ -31,7 +143,11 @@ impl PayrollEntry {
                 withholding,
                 social_security,
                 roth_ira,
-                net) 
+                net,
+                pto_hours,
+                off_cycle_reason,
+                imported,
+                company_id) 
             VALUES (
                 ?1,
                 ?2,
@@ -40,28 +156,111 @@ impl PayrollEntry {
                 ?5,
                 ?6,
                 ?7,
-                ?8)",
+                ?8,
+                ?9,
+                ?10,
+                ?11,
+                (SELECT company_id FROM employees WHERE id = ?2))",
             params![
                 &self.date_of_pay,
-                &self.employee_id + 1,
+                &self.employee_id,
                 &self.hours_worked,
                 self.gross,
                 self.withholding,
                 self.social_security,
                 self.roth_ira,
-                self.net
+                self.net,
+                self.pto_hours,
+                self.off_cycle_reason.map(|reason| reason.as_str()),
+                self.imported.map(|imported| imported.as_str())
             ],
         ) {
             Ok(_) => Ok(conn.last_insert_rowid()),
             Err(e) => Err(e),
         }
     }
+
+    /// Saves the check header together with its earnings, deduction and employer-cost lines.
+    /// PTO accruals and the PTO taken from `pto_policy_id` post in the same transaction, and
+    /// a retro line settles the employee's pending retro, so neither can be lost or doubled
+    /// by a failed save.
+    pub fn save_check(
+        &self,
+        conn: &Connection,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+        pto_policy_id: Option<i64>,
+    ) -> Result<i64, rusqlite::Error> {
+        let tx = conn.unchecked_transaction()?;
+        let payroll_id = self.insert_check(&tx, lines, deductions, contributions)?;
+        pto::post_payroll(
+            &tx,
+            self.employee_id,
+            payroll_id,
+            &self.date_of_pay,
+            self.hours_worked,
+            self.pto_hours,
+            pto_policy_id,
+        )?;
+        let retro_paid: f32 = lines
+            .iter()
+            .filter(|line| line.earning_type == EarningType::Retro)
+            .map(|line| line.amount)
+            .sum();
+        if retro_paid > 0.0 {
+            retro::mark_paid(&tx, self.employee_id, payroll_id, retro_paid)?;
+        }
+        tx.commit()?;
+        Ok(payroll_id)
+    }
+
+    /// `save_check` without its own transaction, for callers saving several checks in one.
+    pub fn insert_check(
+        &self,
+        conn: &Connection,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+    ) -> Result<i64, rusqlite::Error> {
+        let payroll_id = self.save_to_db(conn)?;
+        for line in lines {
+            line.save_to_db(conn, payroll_id)?;
+        }
+        for deduction in deductions {
+            deduction.save_to_db(conn, payroll_id)?;
+        }
+        for contribution in contributions {
+            contribution.save_to_db(conn, payroll_id)?;
+        }
+        let after = serde_json::json!({ "check": self, "earnings": lines });
+        audit::record(
+            conn,
+            "payroll",
+            payroll_id,
+            AuditAction::Insert,
+            None,
+            Some(&after),
+        )?;
+        Ok(payroll_id)
+    }
+
+    pub fn check_type_label(&self) -> String {
+        if let Some(imported) = self.imported {
+            return imported.label().to_string();
+        }
+        match self.off_cycle_reason {
+            Some(reason) => format!("Off-cycle: {}", reason.label()),
+            None => "Regular".to_string(),
+        }
+    }
 }
 
 pub fn render_payroll(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
     let fridays = get_fridays_of_year();
 
-    let employees = match database::get_all_employees(&app.conn) {
+    let employees = match app.cache.company_employees(&app.conn, app.company_id) {
         Ok(employees) => employees,
         Err(e) => {
             ui.label(format!("Error fetching employees {}", e));
@@ -69,6 +268,24 @@ pub fn render_payroll(app: &mut PharmacyApp, ui: &mut Ui) {
         }
     };
 
+    ui.horizontal(|ui| {
+        if locations::render_location_filter(app, ui, "payroll_location_filter") {
+            app.selected_employee_index = 0;
+        }
+        import::render_history_button(app, ui);
+    });
+    import::render_history_import(app, ui);
+    let employees = locations::filter_employees(employees, app.location_filter);
+    if employees.is_empty() {
+        ui.label("No employees at this location");
+        return;
+    }
+    app.selected_employee_index = app.selected_employee_index.min(employees.len() - 1);
+    let location_list = app
+        .cache
+        .query(&app.conn, "locations".to_string(), locations::get_locations)
+        .unwrap_or_default();
+
     let employee_names: Vec<String> = employees.iter().map(|e| e.name.clone()).collect();
 
     ui.vertical(|ui| {
@@ -95,17 +312,43 @@ pub fn render_payroll(app: &mut PharmacyApp, ui: &mut Ui) {
     });
 
     let selected_employee = &employees[app.selected_employee_index];
-    let pay_rate: f32 = selected_employee.pay_rate.parse().unwrap_or(0.0);
-    let available_fridays = get_available_fridays(&app.conn, selected_employee.id);
+    let pay_rate = selected_employee.hourly_rate();
+    let overtime_eligible = selected_employee.overtime_eligible();
+    let available_fridays = app.cache.available_fridays(&app.conn, selected_employee.id);
     if !available_fridays.contains(&app.selected_friday) && !available_fridays.is_empty() {
         app.selected_friday = available_fridays[0].clone();
     }
 
-    //calculate the values
-    let gross = calculate_gross(app.hours_worked, pay_rate);
-    let withholding = calculate_withholding(gross);
-    let social_security = calculate_social_security(gross);
-    let net = calculate_net(gross, withholding, social_security);
+    if app.earning_lines_employee_id != Some(selected_employee.id) {
+        let base_type = if selected_employee.pay_type.is_salaried() {
+            EarningType::Salary
+        } else {
+            EarningType::Regular
+        };
+        app.earning_lines = vec![EarningLine::new(base_type, 0.0, pay_rate)];
+        let retro_owed = app
+            .repo()
+            .pending_retro(selected_employee.id)
+            .unwrap_or(0.0);
+        if retro_owed > 0.0 {
+            let mut line = EarningLine::new(EarningType::Retro, 0.0, pay_rate);
+            line.amount = retro_owed;
+            app.earning_lines.push(line);
+        }
+        app.earning_lines_employee_id = Some(selected_employee.id);
+    }
+
+    let pto_policies = app
+        .cache
+        .query(
+            &app.conn,
+            format!("pto_policies:{}", selected_employee.id),
+            |conn| pto::get_policies_for_employee(conn, selected_employee.id),
+        )
+        .unwrap_or_default();
+    if !pto_policies.iter().any(|p| Some(p.id) == app.pto_policy_id) {
+        app.pto_policy_id = pto_policies.first().map(|p| p.id);
+    }
 
     ui.vertical(|ui| {
         ui.horizontal(|ui| {
@@ -130,57 +373,214 @@ pub fn render_payroll(app: &mut PharmacyApp, ui: &mut Ui) {
             });
 
             ui.vertical(|ui| {
-                ui.label("Hours");
-                ui.add(egui::DragValue::new(&mut app.hours_worked).speed(0.1));
+                ui.checkbox(&mut app.off_cycle, "Off-cycle check");
+                if app.off_cycle {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut app.off_cycle_date)
+                            .hint_text("YYYY-MM-DD")
+                            .desired_width(100.0),
+                    );
+                    egui::ComboBox::from_id_salt("off_cycle_reason_select")
+                        .selected_text(app.off_cycle_reason.label())
+                        .show_ui(ui, |ui| {
+                            for reason in OffCycleReason::ALL {
+                                ui.selectable_value(
+                                    &mut app.off_cycle_reason,
+                                    reason,
+                                    reason.label(),
+                                );
+                            }
+                        });
+                    if app.off_cycle_reason.is_supplemental() {
+                        ui.small(format!(
+                            "Federal withholding at the {:.0}% supplemental rate",
+                            SUPPLEMENTAL_FEDERAL_RATE * 100.0
+                        ));
+                    }
+                }
             });
 
-            ui.vertical(|ui| {
-                ui.label("Gross");
-                ui.add(egui::Label::new(format!("{:.2}", gross)));
-            });
-            ui.vertical(|ui| {
-                ui.label("Withholding");
-                ui.add(egui::Label::new(format!("{:.2}", withholding)));
-            });
+            if !pto_policies.is_empty() {
+                ui.vertical(|ui| {
+                    ui.label("PTO Policy");
+                    egui::ComboBox::from_id_salt("pto_policy_select")
+                        .selected_text(
+                            pto_policies
+                                .iter()
+                                .find(|p| Some(p.id) == app.pto_policy_id)
+                                .map(|p| p.name.clone())
+                                .unwrap_or_default(),
+                        )
+                        .show_ui(ui, |ui| {
+                            for policy in &pto_policies {
+                                ui.selectable_value(
+                                    &mut app.pto_policy_id,
+                                    Some(policy.id),
+                                    &policy.name,
+                                );
+                            }
+                        });
+                    if let Some(policy) = pto_policies
+                        .iter()
+                        .find(|p| Some(p.id) == app.pto_policy_id)
+                    {
+                        let today = chrono::Local::now().date_naive();
+                        let key = format!("pto:{}:{}:{}", selected_employee.id, policy.id, today);
+                        let (balance, approved) = app
+                            .cache
+                            .query(&app.conn, key, |conn| {
+                                Ok((
+                                    pto::get_balance(conn, selected_employee.id, policy, today)?,
+                                    pto::get_unpaid_approved_hours(
+                                        conn,
+                                        selected_employee.id,
+                                        policy.id,
+                                    )?,
+                                ))
+                            })
+                            .unwrap_or((0.0, 0.0));
+                        ui.small(format!("Balance {:.2}, approved {:.2}", balance, approved));
+                    }
+                });
+            }
+        });
 
-            ui.vertical(|ui| {
-                ui.label("Social Security");
-                ui.add(egui::Label::new(format!("{:.2}", social_security)));
-            });
+        render_earnings_editor(app, ui, pay_rate, overtime_eligible, &location_list);
+        egui::CollapsingHeader::new("Retro Pay").show(ui, |ui| {
+            retro::render_retro(app, ui, selected_employee);
+        });
+    });
+
+    // Off-cycle checks can land on any date, including a Friday that already has a regular check.
+    let date_of_pay = if app.off_cycle {
+        app.off_cycle_date.trim().to_string()
+    } else {
+        app.selected_friday.clone()
+    };
+    let parsed_date = chrono::NaiveDate::parse_from_str(&date_of_pay, "%Y-%m-%d");
+    let off_cycle_date_valid = parsed_date.is_ok();
+    if let Ok(date) = parsed_date {
+        price_salary_lines(&mut app.earning_lines, selected_employee, date);
+    }
+    let has_overtime = app
+        .earning_lines
+        .iter()
+        .any(|line| line.earning_type == EarningType::Overtime);
+
+    let off_cycle_reason = app.off_cycle.then_some(app.off_cycle_reason);
+    let PricedCheck {
+        lines: check_lines,
+        deductions: deduction_lines,
+        totals,
+        contributions,
+    } = match price_check(
+        &CachedRepository::new(&app.conn, &mut app.cache),
+        selected_employee.id,
+        &date_of_pay,
+        &app.earning_lines,
+        off_cycle_reason,
+    ) {
+        Ok(priced) => priced,
+        Err(e) => {
+            ui.label(format!("Error working out the check: {}", e));
+            return;
+        }
+    };
 
+    ui.horizontal(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Gross");
+            ui.add(egui::Label::new(format!("{:.2}", totals.gross)));
+        });
+        ui.vertical(|ui| {
+            ui.label("Withholding");
+            ui.add(egui::Label::new(format!("{:.2}", totals.withholding)));
+        });
+
+        ui.vertical(|ui| {
+            ui.label("Social Security");
+            ui.add(egui::Label::new(format!("{:.2}", totals.social_security)));
+        });
+
+        for deduction in &deduction_lines {
             ui.vertical(|ui| {
-                ui.label("Roth IRA");
-                ui.add(egui::DragValue::new(&mut app.roth_ira).speed(0.1));
+                ui.label(&deduction.description);
+                ui.add(egui::Label::new(format!("{:.2}", deduction.amount)));
             });
+        }
+
+        ui.vertical(|ui| {
+            ui.label("Net");
+            ui.add(egui::Label::new(format!("{:.2}", totals.net)));
+        });
 
+        for contribution in &contributions {
             ui.vertical(|ui| {
-                ui.label("Net");
-                ui.add(egui::Label::new(format!("{:.2}", net)));
+                ui.label(&contribution.description);
+                ui.add(egui::Label::new(format!("{:.2}", contribution.amount)));
             });
-        });
+        }
     });
 
-    let save_button = ui.add_enabled(!available_fridays.is_empty(), egui::Button::new("Save"));
+    let can_save = if app.off_cycle {
+        off_cycle_date_valid
+    } else {
+        !available_fridays.is_empty()
+    };
+    let can_save = can_save && (overtime_eligible || !has_overtime);
+    let save_button = ui.add_enabled(can_save && can_edit_payroll, egui::Button::new("Save"));
+    if !overtime_eligible && has_overtime {
+        ui.small("FLSA exempt employees are not paid overtime");
+    }
+    if app.off_cycle && !off_cycle_date_valid {
+        ui.small("Enter the off-cycle pay date as YYYY-MM-DD");
+    }
+    if !app.payroll_status.is_empty() {
+        ui.label(&app.payroll_status);
+    }
     if save_button.clicked() {
-        let _entry = PayrollEntry {
-            date_of_pay: app.selected_friday.clone(),
-            gross,
-            net,
-            employee_id: app.selected_employee_index as i32,
-            hours_worked: app.hours_worked,
-            withholding,
-            roth_ira: app.roth_ira,
-            social_security,
+        let entry = PayrollEntry {
+            date_of_pay,
+            gross: totals.gross,
+            net: totals.net,
+            employee_id: selected_employee.id,
+            hours_worked: earnings::worked_hours(&app.earning_lines),
+            withholding: totals.withholding,
+            roth_ira: deduction_lines
+                .iter()
+                .filter(|d| d.code == DeductionType::RothIra.as_str())
+                .map(|d| d.amount)
+                .sum(),
+            social_security: totals.social_security,
+            pto_hours: earnings::hours_of_type(&app.earning_lines, EarningType::Pto),
+            off_cycle_reason,
+            imported: None,
             id: 0,
         };
 
-        let _res = _entry.save_to_db(&app.conn);
+        app.payroll_status = match app.repo().save_check(
+            &entry,
+            &check_lines,
+            &deduction_lines,
+            &contributions,
+            app.pto_policy_id,
+        ) {
+            Ok(_) => {
+                app.earning_lines_employee_id = None;
+                app.off_cycle = false;
+                "Check saved".to_string()
+            }
+            Err(e) => format!("Error saving check: {}", e),
+        };
         app.refresh_available_fridays();
     }
     ui.add_space(20.0);
     ui.separator();
     ui.label("Payroll History");
-    let payroll_entries = match database::get_payroll_by_id(&app.conn, selected_employee.id) {
+    let payroll_entries = match app
+        .cache
+        .checks_for_employee(&app.conn, selected_employee.id)
+    {
         Ok(e) => e,
         Err(e) => {
             ui.label(format!("Error: {}", e));
@@ -196,37 +596,372 @@ pub fn render_payroll(app: &mut PharmacyApp, ui: &mut Ui) {
                 .striped(true)
                 .spacing([10.0, 10.0])
                 .show(ui, |ui| {
+                    ui.label("");
                     ui.strong("Date of Pay");
                     ui.strong("Gross");
                     ui.strong("Net");
                     //ui.strong("Employee Name");
                     ui.strong("Hours Worked");
+                    ui.strong("PTO Hours");
                     ui.strong("Withholding");
                     ui.strong("Roth IRA");
                     ui.strong("Social Security");
+                    ui.strong("Type");
                     ui.end_row();
                     for entry in &payroll_entries {
-                        ui.label(format!("{}", entry.date_of_pay));
+                        let expanded = app.expanded_payroll_id == Some(entry.id);
+                        if ui.small_button(if expanded { "▾" } else { "▸" }).clicked() {
+                            app.expanded_payroll_id = if expanded { None } else { Some(entry.id) };
+                        }
+                        ui.label(&entry.date_of_pay);
                         ui.label(format!("{:.2}", entry.gross));
                         ui.label(format!("{:.2}", entry.net));
                         ui.label(format!("{}", entry.hours_worked));
+                        ui.label(format!("{}", entry.pto_hours));
                         ui.label(format!("{:.2}", entry.withholding));
                         ui.label(format!("{:.2}", entry.roth_ira));
                         ui.label(format!("{:.2}", entry.social_security));
+                        if entry.imported.is_some() {
+                            ui.colored_label(egui::Color32::LIGHT_BLUE, entry.check_type_label());
+                        } else if entry.off_cycle_reason.is_some() {
+                            ui.colored_label(egui::Color32::YELLOW, entry.check_type_label());
+                        } else {
+                            ui.label(entry.check_type_label());
+                        }
 
-                        if ui.button("Delete").clicked() {
-                            database::delete_payroll_entry(&app.conn, entry.id).unwrap_or_else(
-                                |e| println!("Error deleting payroll entry: {}", e),
+                        if entry.imported.is_some() {
+                            ui.label("Locked").on_hover_text(
+                                "History imported from the previous provider can't be changed",
                             );
+                        } else if users::guarded_button(ui, can_edit_payroll, "Delete").clicked() {
+                            app.repo().delete_check(entry.id).unwrap_or_else(|e| {
+                                println!("Error deleting payroll entry: {}", e)
+                            });
                             app.refresh_available_fridays();
                         }
                         ui.end_row();
+
+                        if expanded {
+                            render_earnings_breakdown(app, ui, entry, &location_list);
+                        }
                     }
                 });
         });
     }
 }
 
+fn render_earnings_editor(
+    app: &mut PharmacyApp,
+    ui: &mut Ui,
+    pay_rate: f32,
+    overtime_eligible: bool,
+    location_list: &[Location],
+) {
+    ui.label("Earnings");
+    let mut remove = None;
+    egui::Grid::new("earning_lines_grid")
+        .striped(true)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            ui.strong("Type");
+            ui.strong("Hours");
+            ui.strong("Rate");
+            ui.strong("Amount");
+            if !location_list.is_empty() {
+                ui.strong("Location");
+            }
+            ui.end_row();
+            for (index, line) in app.earning_lines.iter_mut().enumerate() {
+                let previous_type = line.earning_type;
+                egui::ComboBox::from_id_salt(("earning_type", index))
+                    .selected_text(line.earning_type.label())
+                    .show_ui(ui, |ui| {
+                        for earning_type in EarningType::ALL {
+                            if earning_type == EarningType::Overtime && !overtime_eligible {
+                                continue;
+                            }
+                            ui.selectable_value(
+                                &mut line.earning_type,
+                                earning_type,
+                                earning_type.label(),
+                            );
+                        }
+                    });
+                if line.earning_type != previous_type {
+                    let location_id = line.location_id;
+                    *line = EarningLine::new(line.earning_type, line.hours, pay_rate);
+                    line.location_id = location_id;
+                }
+
+                if line.earning_type.is_hourly() {
+                    ui.add(
+                        egui::DragValue::new(&mut line.hours)
+                            .speed(0.1)
+                            .range(0.0..=200.0),
+                    );
+                    ui.add(egui::DragValue::new(&mut line.rate).speed(0.05).prefix("$"));
+                    line.recalculate();
+                    ui.label(format!("{:.2}", line.amount));
+                } else if line.earning_type == EarningType::Salary {
+                    ui.add(
+                        egui::DragValue::new(&mut line.hours)
+                            .speed(0.1)
+                            .range(0.0..=200.0),
+                    );
+                    ui.label("-");
+                    ui.label(format!("{:.2}", line.amount));
+                } else {
+                    ui.label("-");
+                    ui.label("-");
+                    ui.add(
+                        egui::DragValue::new(&mut line.amount)
+                            .speed(1.0)
+                            .prefix("$"),
+                    );
+                }
+                if !location_list.is_empty() {
+                    locations::location_combo(
+                        ui,
+                        ("earning_location", index),
+                        location_list,
+                        &mut line.location_id,
+                        "Home",
+                    );
+                }
+                if ui.button("Remove").clicked() {
+                    remove = Some(index);
+                }
+                ui.end_row();
+            }
+        });
+    if let Some(index) = remove {
+        app.earning_lines.remove(index);
+    }
+    if ui.button("Add Earning").clicked() {
+        app.earning_lines
+            .push(EarningLine::new(EarningType::Regular, 0.0, pay_rate));
+    }
+}
+
+fn render_earnings_breakdown(
+    app: &mut PharmacyApp,
+    ui: &mut Ui,
+    entry: &PayrollEntry,
+    location_list: &[Location],
+) {
+    let repo = CachedRepository::new(&app.conn, &mut app.cache);
+    let lines = repo.earnings_for_check(entry.id).unwrap_or_default();
+    if lines.is_empty() {
+        ui.label("");
+        ui.label(format!(
+            "Regular: {} hrs, {:.2} (entered before earnings lines)",
+            entry.hours_worked, entry.gross
+        ));
+        ui.end_row();
+        return;
+    }
+    for line in &lines {
+        ui.label("");
+        ui.label(line.earning_type.label());
+        ui.label(format!("{:.2}", line.amount));
+        if line.earning_type.is_hourly() {
+            ui.label(format!("{} hrs @ {:.2}", line.hours, line.rate));
+        } else if line.earning_type.tracks_hours() {
+            ui.label(format!("{} hrs", line.hours));
+        } else {
+            ui.label("");
+        }
+        let mut treatment = Vec::new();
+        if line.supplemental {
+            treatment.push("Supplemental 22%");
+        } else if line.fit_taxable {
+            treatment.push("FIT");
+        }
+        if line.fica_taxable {
+            treatment.push("FICA");
+        }
+        let mut treatment = treatment.join(", ");
+        if line.location_id.is_some() {
+            treatment = format!(
+                "{} at {}",
+                treatment,
+                locations::location_name(location_list, line.location_id)
+            );
+        }
+        ui.label(treatment);
+        ui.end_row();
+    }
+    for deduction in repo.deductions_for_check(entry.id).unwrap_or_default() {
+        ui.label("");
+        ui.label(&deduction.description);
+        ui.label(format!("-{:.2}", deduction.amount));
+        ui.label("");
+        ui.label(match (deduction.pre_tax_fit, deduction.pre_tax_fica) {
+            (true, true) => "Pre-tax FIT, FICA",
+            (true, false) => "Pre-tax FIT",
+            _ => "Post-tax",
+        });
+        ui.end_row();
+    }
+    for contribution in repo.contributions_for_check(entry.id).unwrap_or_default() {
+        ui.label("");
+        ui.label(&contribution.description);
+        ui.label(format!("{:.2}", contribution.amount));
+        ui.label("");
+        ui.label("Employer cost");
+        ui.end_row();
+    }
+}
+
+/// Taxable wage bases and taxes for one paycheck, built up from its earnings and deduction lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckTotals {
+    pub gross: f32,
+    pub regular_fit_wages: f32,
+    pub supplemental_fit_wages: f32,
+    pub fica_wages: f32,
+    pub withholding: f32,
+    pub social_security: f32,
+    pub total_deductions: f32,
+    pub net: f32,
+}
+
+pub fn calculate_check(lines: &[EarningLine], deductions: &[DeductionLine]) -> CheckTotals {
+    let sum = |filter: &dyn Fn(&EarningLine) -> bool| -> f32 {
+        lines
+            .iter()
+            .filter(|line| filter(line))
+            .map(|line| line.amount)
+            .sum()
+    };
+    let gross = sum(&|_| true);
+    let pre_tax_fit: f32 = deductions
+        .iter()
+        .filter(|d| d.pre_tax_fit)
+        .map(|d| d.amount)
+        .sum();
+    let pre_tax_fica: f32 = deductions
+        .iter()
+        .filter(|d| d.pre_tax_fica)
+        .map(|d| d.amount)
+        .sum();
+    let total_deductions: f32 = deductions.iter().map(|d| d.amount).sum();
+
+    // Pre-tax deductions come out of regular wages first, then supplemental wages.
+    let regular_fit = sum(&|line| line.fit_taxable && !line.supplemental);
+    let supplemental_fit = sum(&|line| line.fit_taxable && line.supplemental);
+    let regular_fit_wages = (regular_fit - pre_tax_fit).max(0.0);
+    let supplemental_fit_wages = (supplemental_fit - (pre_tax_fit - regular_fit).max(0.0)).max(0.0);
+    let fica_wages = (sum(&|line| line.fica_taxable) - pre_tax_fica).max(0.0);
+
+    let withholding = calculate_withholding(regular_fit_wages)
+        + calculate_supplemental_withholding(supplemental_fit_wages);
+    let social_security = calculate_social_security(fica_wages);
+
+    CheckTotals {
+        gross,
+        regular_fit_wages,
+        supplemental_fit_wages,
+        fica_wages,
+        withholding,
+        social_security,
+        total_deductions,
+        net: calculate_net(gross, withholding, social_security) - total_deductions,
+    }
+}
+
+/// The check's earnings as they are taxed. Every line on an off-cycle bonus or commission
+/// check is supplemental, whatever its type.
+pub fn lines_for_check(
+    lines: &[EarningLine],
+    off_cycle_reason: Option<OffCycleReason>,
+) -> Vec<EarningLine> {
+    let supplemental = off_cycle_reason.is_some_and(|reason| reason.is_supplemental());
+    lines
+        .iter()
+        .map(|line| EarningLine {
+            supplemental: line.supplemental || supplemental,
+            ..line.clone()
+        })
+        .collect()
+}
+
+/// Sets salary lines to the employee's salary for the pay period ending `date_of_pay`.
+pub fn price_salary_lines(lines: &mut [EarningLine], employee: &Employee, date_of_pay: NaiveDate) {
+    let salary = employee.period_salary(date_of_pay);
+    for line in lines
+        .iter_mut()
+        .filter(|line| line.earning_type == EarningType::Salary)
+    {
+        line.amount = salary;
+    }
+}
+
+/// A check worked out from its earnings, ready to save.
+#[derive(Debug, Clone, Default)]
+pub struct PricedCheck {
+    /// The earnings as they are taxed.
+    pub lines: Vec<EarningLine>,
+    /// Elected deductions, then garnishments.
+    pub deductions: Vec<DeductionLine>,
+    pub totals: CheckTotals,
+    pub contributions: Vec<EmployerContribution>,
+}
+
+/// Works out the employee's check for `lines` paid on `date_of_pay`: salary for the period,
+/// elected deductions within their annual limits, garnishments against what is left, and the
+/// employer's retirement contributions.
+pub fn price_check(
+    repo: &(impl EmployeeRepository + PayrollRepository),
+    employee_id: i32,
+    date_of_pay: &str,
+    lines: &[EarningLine],
+    off_cycle_reason: Option<OffCycleReason>,
+) -> Result<PricedCheck, rusqlite::Error> {
+    let mut lines = lines_for_check(lines, off_cycle_reason);
+    let year = match NaiveDate::parse_from_str(date_of_pay, "%Y-%m-%d") {
+        Ok(date) => {
+            price_salary_lines(&mut lines, &repo.employee(employee_id)?, date);
+            date.year()
+        }
+        Err(_) => chrono::Local::now().year(),
+    };
+    let gross: f32 = lines.iter().map(|line| line.amount).sum();
+    let mut deduction_lines = deductions::calculate_deductions(
+        &repo.deduction_elections(employee_id)?,
+        gross,
+        &repo.deduction_ytd(employee_id, year)?,
+    );
+    let mut totals = calculate_check(&lines, &deduction_lines);
+    let disposable =
+        garnishments::disposable_earnings(totals.gross, totals.withholding, totals.social_security);
+    let garnishment_lines = garnishments::calculate_garnishments(
+        &repo.garnishment_orders(employee_id)?,
+        &repo.garnishment_paid()?,
+        disposable,
+        totals.net,
+    );
+    if !garnishment_lines.is_empty() {
+        deduction_lines.extend(garnishment_lines);
+        totals = calculate_check(&lines, &deduction_lines);
+    }
+    let contributions = retirement::calculate_contributions(
+        &repo.retirement_rules(employee_id)?,
+        gross,
+        &deduction_lines,
+    );
+    Ok(PricedCheck {
+        lines,
+        deductions: deduction_lines,
+        totals,
+        contributions,
+    })
+}
+
+pub fn calculate_supplemental_withholding(supplemental_wages: f32) -> f32 {
+    supplemental_wages * SUPPLEMENTAL_FEDERAL_RATE
+}
+
 pub fn calculate_gross(hours_worked: f32, pay_rate: f32) -> f32 {
     hours_worked * pay_rate
 }
@@ -0,0 +1,1071 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::Datelike;
+use chrono::NaiveDate;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+pub const LEAVE_TYPES: [&str; 2] = ["Vacation", "Sick"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AccrualMethod {
+    PerHourWorked,
+    PerPayPeriod,
+}
+
+impl AccrualMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccrualMethod::PerHourWorked => "per_hour_worked",
+            AccrualMethod::PerPayPeriod => "per_pay_period",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "per_pay_period" => AccrualMethod::PerPayPeriod,
+            _ => AccrualMethod::PerHourWorked,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AccrualMethod::PerHourWorked => "Per hour worked",
+            AccrualMethod::PerPayPeriod => "Per pay period",
+        }
+    }
+}
+
+/// An accrual rule. Caps and limits are `None` when the policy doesn't impose one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtoPolicy {
+    pub id: i64,
+    pub name: String,
+    pub leave_type: String,
+    pub accrual_method: AccrualMethod,
+    /// Hours earned per `per_hours_worked` hours, or per paycheck for `PerPayPeriod`.
+    pub accrual_hours: f32,
+    pub per_hours_worked: f32,
+    pub annual_accrual_cap: Option<f32>,
+    pub balance_cap: Option<f32>,
+    pub carryover_limit: Option<f32>,
+    /// Lets usage take the balance below zero, for policies that advance leave.
+    pub allow_negative: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtoLedgerEntry {
+    pub id: i64,
+    pub employee_id: i32,
+    pub policy_id: i64,
+    pub entry_date: String,
+    pub hours: f32,
+    pub kind: String,
+    pub payroll_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtoRequest {
+    pub id: i64,
+    pub employee_id: i32,
+    pub policy_id: i64,
+    pub start_date: String,
+    pub end_date: String,
+    pub hours: f32,
+    pub status: String,
+    pub note: String,
+    pub payroll_id: Option<i64>,
+}
+
+pub const STATUS_PENDING: &str = "Pending";
+pub const STATUS_APPROVED: &str = "Approved";
+pub const STATUS_DENIED: &str = "Denied";
+
+/// State paid-sick-leave laws expressed as ready-made policy configurations.
+pub fn state_sick_leave_presets() -> Vec<PtoPolicy> {
+    let sick = |name: &str, per: f32, annual: Option<f32>, cap: Option<f32>, carry: Option<f32>| {
+        PtoPolicy {
+            id: 0,
+            name: name.to_string(),
+            leave_type: "Sick".to_string(),
+            accrual_method: AccrualMethod::PerHourWorked,
+            accrual_hours: 1.0,
+            per_hours_worked: per,
+            annual_accrual_cap: annual,
+            balance_cap: cap,
+            carryover_limit: carry,
+            allow_negative: false,
+        }
+    };
+    vec![
+        sick("California Paid Sick Leave", 30.0, None, Some(80.0), None),
+        sick(
+            "Arizona Earned Paid Sick Time",
+            30.0,
+            Some(40.0),
+            None,
+            Some(40.0),
+        ),
+        sick(
+            "Colorado Healthy Families and Workplaces",
+            30.0,
+            Some(48.0),
+            None,
+            Some(48.0),
+        ),
+        sick("Washington Paid Sick Leave", 40.0, None, None, Some(40.0)),
+        sick("New York Paid Sick Leave", 30.0, Some(56.0), None, None),
+    ]
+}
+
+impl Default for PtoPolicy {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::new(),
+            leave_type: "Vacation".to_string(),
+            accrual_method: AccrualMethod::PerPayPeriod,
+            accrual_hours: 0.0,
+            per_hours_worked: 30.0,
+            annual_accrual_cap: None,
+            balance_cap: None,
+            carryover_limit: None,
+            allow_negative: false,
+        }
+    }
+}
+
+impl PtoPolicy {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO pto_policies (
+                name,
+                leave_type,
+                accrual_method,
+                accrual_hours,
+                per_hours_worked,
+                annual_accrual_cap,
+                balance_cap,
+                carryover_limit,
+                allow_negative)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                &self.name,
+                &self.leave_type,
+                self.accrual_method.as_str(),
+                self.accrual_hours,
+                self.per_hours_worked,
+                self.annual_accrual_cap,
+                self.balance_cap,
+                self.carryover_limit,
+                self.allow_negative
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(
+            conn,
+            "pto_policy",
+            "pto_policies",
+            id,
+            AuditAction::Insert,
+            None,
+        )?;
+        Ok(id)
+    }
+
+    /// Hours earned on one paycheck, after the annual and balance caps are applied.
+    pub fn accrual_for_check(
+        &self,
+        hours_worked: f32,
+        balance: f32,
+        accrued_this_year: f32,
+    ) -> f32 {
+        let mut accrual = match self.accrual_method {
+            AccrualMethod::PerHourWorked if self.per_hours_worked > 0.0 => {
+                hours_worked * self.accrual_hours / self.per_hours_worked
+            }
+            AccrualMethod::PerHourWorked => 0.0,
+            AccrualMethod::PerPayPeriod => self.accrual_hours,
+        };
+        if let Some(cap) = self.annual_accrual_cap {
+            accrual = accrual.min(cap - accrued_this_year);
+        }
+        if let Some(cap) = self.balance_cap {
+            accrual = accrual.min(cap - balance);
+        }
+        accrual.max(0.0)
+    }
+
+    pub fn describe(&self) -> String {
+        let rate = match self.accrual_method {
+            AccrualMethod::PerHourWorked => format!(
+                "{:.2} hr per {:.0} hrs worked",
+                self.accrual_hours, self.per_hours_worked
+            ),
+            AccrualMethod::PerPayPeriod => format!("{:.2} hrs per pay period", self.accrual_hours),
+        };
+        let limit = |value: Option<f32>| value.map_or("none".to_string(), |v| format!("{:.0}", v));
+        format!(
+            "{} | annual cap {} | balance cap {} | carryover {}{}",
+            rate,
+            limit(self.annual_accrual_cap),
+            limit(self.balance_cap),
+            limit(self.carryover_limit),
+            if self.allow_negative {
+                " | may go negative"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+fn policy_from_row(row: &rusqlite::Row) -> Result<PtoPolicy, rusqlite::Error> {
+    Ok(PtoPolicy {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        leave_type: row.get(2)?,
+        accrual_method: AccrualMethod::parse(&row.get::<_, String>(3)?),
+        accrual_hours: row.get(4)?,
+        per_hours_worked: row.get(5)?,
+        annual_accrual_cap: row.get(6)?,
+        balance_cap: row.get(7)?,
+        carryover_limit: row.get(8)?,
+        allow_negative: row.get(9)?,
+    })
+}
+
+pub fn get_policies(conn: &Connection) -> Result<Vec<PtoPolicy>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, leave_type, accrual_method, accrual_hours, per_hours_worked,
+            annual_accrual_cap, balance_cap, carryover_limit, allow_negative
+        FROM pto_policies ORDER BY name",
+    )?;
+    let policies = stmt
+        .query_map([], policy_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(policies)
+}
+
+pub fn get_policies_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<PtoPolicy>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.name, p.leave_type, p.accrual_method, p.accrual_hours, p.per_hours_worked,
+            p.annual_accrual_cap, p.balance_cap, p.carryover_limit, p.allow_negative
+        FROM pto_policies p JOIN pto_enrollments e ON e.policy_id = p.id
+        WHERE e.employee_id = ?1 ORDER BY p.name",
+    )?;
+    let policies = stmt
+        .query_map([employee_id], policy_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(policies)
+}
+
+pub fn delete_policy(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "pto_policies", id)?;
+    conn.execute("DELETE FROM pto_enrollments WHERE policy_id = ?1", [id])?;
+    conn.execute("DELETE FROM pto_policies WHERE id = ?1", [id])?;
+    audit::record_row(
+        conn,
+        "pto_policy",
+        "pto_policies",
+        id,
+        AuditAction::Delete,
+        before.as_ref(),
+    )
+}
+
+pub fn set_enrollment(
+    conn: &Connection,
+    employee_id: i32,
+    policy_id: i64,
+    enrolled: bool,
+) -> Result<(), rusqlite::Error> {
+    let changed = if enrolled {
+        conn.execute(
+            "INSERT OR IGNORE INTO pto_enrollments (employee_id, policy_id) VALUES (?1, ?2)",
+            params![employee_id, policy_id],
+        )?
+    } else {
+        conn.execute(
+            "DELETE FROM pto_enrollments WHERE employee_id = ?1 AND policy_id = ?2",
+            params![employee_id, policy_id],
+        )?
+    };
+    if changed == 0 {
+        return Ok(());
+    }
+    // Enrollments have no id of their own, so they are logged against the employee.
+    let enrollment = serde_json::json!({ "policy_id": policy_id });
+    let (action, before, after) = if enrolled {
+        (AuditAction::Insert, None, Some(&enrollment))
+    } else {
+        (AuditAction::Delete, Some(&enrollment), None)
+    };
+    audit::record(
+        conn,
+        "pto_enrollment",
+        employee_id as i64,
+        action,
+        before,
+        after,
+    )
+}
+
+pub fn get_ledger(
+    conn: &Connection,
+    employee_id: i32,
+    policy_id: i64,
+) -> Result<Vec<PtoLedgerEntry>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, policy_id, entry_date, hours, kind, payroll_id
+        FROM pto_ledger WHERE employee_id = ?1 AND policy_id = ?2 ORDER BY entry_date, id",
+    )?;
+    let entries = stmt
+        .query_map(params![employee_id, policy_id], |row| {
+            Ok(PtoLedgerEntry {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                policy_id: row.get(2)?,
+                entry_date: row.get(3)?,
+                hours: row.get(4)?,
+                kind: row.get(5)?,
+                payroll_id: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
+
+fn add_ledger_entry(
+    conn: &Connection,
+    employee_id: i32,
+    policy_id: i64,
+    entry_date: &str,
+    hours: f32,
+    kind: &str,
+    payroll_id: Option<i64>,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO pto_ledger (employee_id, policy_id, entry_date, hours, kind, payroll_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![employee_id, policy_id, entry_date, hours, kind, payroll_id],
+    )?;
+    Ok(())
+}
+
+/// Replays the ledger up to `as_of`, trimming the balance to the carryover limit at each new year.
+pub fn balance_as_of(entries: &[PtoLedgerEntry], policy: &PtoPolicy, as_of: NaiveDate) -> f32 {
+    let mut balance = 0.0;
+    let mut year = None;
+    for entry in entries {
+        let Ok(date) = NaiveDate::parse_from_str(&entry.entry_date, "%Y-%m-%d") else {
+            continue;
+        };
+        if date > as_of {
+            break;
+        }
+        if year.is_some_and(|y| y < date.year()) {
+            if let Some(limit) = policy.carryover_limit {
+                balance = f32::min(balance, limit);
+            }
+        }
+        year = Some(date.year());
+        balance += entry.hours;
+    }
+    if year.is_some_and(|y| y < as_of.year()) {
+        if let Some(limit) = policy.carryover_limit {
+            balance = f32::min(balance, limit);
+        }
+    }
+    balance
+}
+
+fn accrued_in_year(entries: &[PtoLedgerEntry], year: i32) -> f32 {
+    entries
+        .iter()
+        .filter(|entry| entry.kind == "accrual" && entry.entry_date.starts_with(&year.to_string()))
+        .map(|entry| entry.hours)
+        .sum()
+}
+
+pub fn get_balance(
+    conn: &Connection,
+    employee_id: i32,
+    policy: &PtoPolicy,
+    as_of: NaiveDate,
+) -> Result<f32, rusqlite::Error> {
+    let entries = get_ledger(conn, employee_id, policy.id)?;
+    Ok(balance_as_of(&entries, policy, as_of))
+}
+
+/// Posts accruals for every enrolled policy and the PTO taken on a saved paycheck. Runs in
+/// the transaction that saves the check, so a rejected posting rolls the check back. The
+/// hours come from `pto_policy_id` and can't exceed its balance unless the policy allows a
+/// negative balance. Approved requests are linked to the check oldest first, as far as its
+/// hours cover them; the rest wait for a later check.
+pub fn post_payroll(
+    conn: &Connection,
+    employee_id: i32,
+    payroll_id: i64,
+    date_of_pay: &str,
+    hours_worked: f32,
+    pto_hours: f32,
+    pto_policy_id: Option<i64>,
+) -> Result<(), rusqlite::Error> {
+    let Ok(pay_date) = NaiveDate::parse_from_str(date_of_pay, "%Y-%m-%d") else {
+        return Ok(());
+    };
+    let policies = get_policies_for_employee(conn, employee_id)?;
+    if pto_hours > 0.0 && !policies.iter().any(|p| Some(p.id) == pto_policy_id) {
+        return Err(rejected(
+            "PTO hours need a policy the employee is enrolled in".to_string(),
+        ));
+    }
+
+    for policy in policies {
+        let entries = get_ledger(conn, employee_id, policy.id)?;
+        let mut balance = balance_as_of(&entries, &policy, pay_date);
+
+        if pto_hours > 0.0 && Some(policy.id) == pto_policy_id {
+            if pto_hours > balance + 0.005 && !policy.allow_negative {
+                return Err(rejected(format!(
+                    "Insufficient {} balance: {:.2} hours available, {:.2} on this check",
+                    policy.name, balance, pto_hours
+                )));
+            }
+            add_ledger_entry(
+                conn,
+                employee_id,
+                policy.id,
+                date_of_pay,
+                -pto_hours,
+                "usage",
+                Some(payroll_id),
+            )?;
+            balance -= pto_hours;
+            link_requests(
+                conn,
+                employee_id,
+                policy.id,
+                payroll_id,
+                date_of_pay,
+                pto_hours,
+            )?;
+        }
+
+        let accrual = policy.accrual_for_check(
+            hours_worked,
+            balance,
+            accrued_in_year(&entries, pay_date.year()),
+        );
+        if accrual > 0.0 {
+            add_ledger_entry(
+                conn,
+                employee_id,
+                policy.id,
+                date_of_pay,
+                accrual,
+                "accrual",
+                Some(payroll_id),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// A posting the database would accept but payroll rules don't, reported like a constraint.
+fn rejected(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+        Some(message),
+    )
+}
+
+/// Marks the approved requests the check's PTO hours pay for.
+fn link_requests(
+    conn: &Connection,
+    employee_id: i32,
+    policy_id: i64,
+    payroll_id: i64,
+    date_of_pay: &str,
+    pto_hours: f32,
+) -> Result<(), rusqlite::Error> {
+    let requests = {
+        let mut stmt = conn.prepare(
+            "SELECT id, hours FROM pto_requests
+            WHERE employee_id = ?1 AND policy_id = ?2 AND status = ?3
+                AND payroll_id IS NULL AND start_date <= ?4
+            ORDER BY start_date, id",
+        )?;
+        let requests = stmt
+            .query_map(
+                params![employee_id, policy_id, STATUS_APPROVED, date_of_pay],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f32>(1)?)),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        requests
+    };
+    let mut remaining = pto_hours;
+    for (id, hours) in requests {
+        if hours > remaining + 0.005 {
+            break;
+        }
+        conn.execute(
+            "UPDATE pto_requests SET payroll_id = ?1 WHERE id = ?2",
+            params![payroll_id, id],
+        )?;
+        remaining -= hours;
+    }
+    Ok(())
+}
+
+/// Undoes the accruals and usage a paycheck posted, so deleting a check restores balances.
+pub fn reverse_payroll(conn: &Connection, payroll_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM pto_ledger WHERE payroll_id = ?1", [payroll_id])?;
+    conn.execute(
+        "UPDATE pto_requests SET payroll_id = NULL WHERE payroll_id = ?1",
+        [payroll_id],
+    )?;
+    Ok(())
+}
+
+impl PtoRequest {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO pto_requests (
+                employee_id,
+                policy_id,
+                start_date,
+                end_date,
+                hours,
+                status,
+                note)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                self.employee_id,
+                self.policy_id,
+                &self.start_date,
+                &self.end_date,
+                self.hours,
+                &self.status,
+                &self.note
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(
+            conn,
+            "pto_request",
+            "pto_requests",
+            id,
+            AuditAction::Insert,
+            None,
+        )?;
+        Ok(id)
+    }
+}
+
+pub fn get_requests(conn: &Connection) -> Result<Vec<PtoRequest>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, policy_id, start_date, end_date, hours, status, note, payroll_id
+        FROM pto_requests ORDER BY start_date DESC",
+    )?;
+    let requests = stmt
+        .query_map([], |row| {
+            Ok(PtoRequest {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                policy_id: row.get(2)?,
+                start_date: row.get(3)?,
+                end_date: row.get(4)?,
+                hours: row.get(5)?,
+                status: row.get(6)?,
+                note: row.get(7)?,
+                payroll_id: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(requests)
+}
+
+/// Approved hours not yet paid on a check, for the payroll screen to pick up.
+pub fn get_unpaid_approved_hours(
+    conn: &Connection,
+    employee_id: i32,
+    policy_id: i64,
+) -> Result<f32, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(hours), 0) FROM pto_requests
+        WHERE employee_id = ?1 AND policy_id = ?2 AND status = ?3 AND payroll_id IS NULL",
+        params![employee_id, policy_id, STATUS_APPROVED],
+        |row| row.get(0),
+    )
+}
+
+/// Approves a pending request if the employee has enough balance to cover it
+/// alongside any other approved hours that haven't been paid yet.
+pub fn approve_request(conn: &Connection, request: &PtoRequest) -> Result<(), String> {
+    let policy = get_policies(conn)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|p| p.id == request.policy_id)
+        .ok_or("Policy no longer exists")?;
+    let as_of = NaiveDate::parse_from_str(&request.start_date, "%Y-%m-%d")
+        .map_err(|_| "Request has an invalid start date")?;
+    let balance =
+        get_balance(conn, request.employee_id, &policy, as_of).map_err(|e| e.to_string())?;
+    let committed = get_unpaid_approved_hours(conn, request.employee_id, policy.id)
+        .map_err(|e| e.to_string())?;
+
+    if request.hours > balance - committed && !policy.allow_negative {
+        return Err(format!(
+            "Insufficient balance: {:.2} hours available, {:.2} requested",
+            balance - committed,
+            request.hours
+        ));
+    }
+    set_request_status(conn, request.id, STATUS_APPROVED).map_err(|e| e.to_string())
+}
+
+pub fn set_request_status(conn: &Connection, id: i64, status: &str) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "pto_requests", id)?;
+    conn.execute(
+        "UPDATE pto_requests SET status = ?1 WHERE id = ?2",
+        params![status, id],
+    )?;
+    audit::record_row(
+        conn,
+        "pto_request",
+        "pto_requests",
+        id,
+        AuditAction::Update,
+        before.as_ref(),
+    )
+}
+
+pub struct PtoForm {
+    pub policy: PtoPolicy,
+    pub has_annual_cap: bool,
+    pub has_balance_cap: bool,
+    pub has_carryover_limit: bool,
+    pub employee_id: Option<i32>,
+    pub request_policy_id: Option<i64>,
+    pub request_start: String,
+    pub request_end: String,
+    pub request_hours: f32,
+    pub request_note: String,
+    pub status: String,
+}
+
+impl Default for PtoForm {
+    fn default() -> Self {
+        Self {
+            policy: PtoPolicy::default(),
+            has_annual_cap: false,
+            has_balance_cap: false,
+            has_carryover_limit: false,
+            employee_id: None,
+            request_policy_id: None,
+            request_start: String::new(),
+            request_end: String::new(),
+            request_hours: 8.0,
+            request_note: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+impl PtoForm {
+    fn load_preset(&mut self, preset: PtoPolicy) {
+        self.has_annual_cap = preset.annual_accrual_cap.is_some();
+        self.has_balance_cap = preset.balance_cap.is_some();
+        self.has_carryover_limit = preset.carryover_limit.is_some();
+        self.policy = preset;
+    }
+
+    fn policy_to_save(&self) -> PtoPolicy {
+        let mut policy = self.policy.clone();
+        let or_zero = |value: Option<f32>| Some(value.unwrap_or(0.0));
+        policy.annual_accrual_cap = if self.has_annual_cap {
+            or_zero(policy.annual_accrual_cap)
+        } else {
+            None
+        };
+        policy.balance_cap = if self.has_balance_cap {
+            or_zero(policy.balance_cap)
+        } else {
+            None
+        };
+        policy.carryover_limit = if self.has_carryover_limit {
+            or_zero(policy.carryover_limit)
+        } else {
+            None
+        };
+        policy
+    }
+}
+
+pub fn render_time_off(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Time Off");
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        render_requests(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_balances(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_policies(app, ui);
+        if !app.pto_form.status.is_empty() {
+            ui.label(&app.pto_form.status);
+        }
+    });
+}
+
+fn render_requests(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    ui.heading("Requests");
+    let employees = app
+        .cache
+        .company_employees(&app.conn, app.company_id)
+        .unwrap_or_default();
+    let policies = get_policies(&app.conn).unwrap_or_default();
+    let employee_name = |id: i32| {
+        employees
+            .iter()
+            .find(|e| e.id == id)
+            .map_or("Unknown".to_string(), |e| e.name.clone())
+    };
+    let policy_name = |id: i64| {
+        policies
+            .iter()
+            .find(|p| p.id == id)
+            .map_or("Unknown".to_string(), |p| p.name.clone())
+    };
+
+    match get_requests(&app.conn) {
+        Ok(requests) if requests.is_empty() => {
+            ui.label("No time off requests");
+        }
+        Ok(requests) => {
+            egui::Grid::new("pto_requests_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Employee");
+                    ui.strong("Policy");
+                    ui.strong("Dates");
+                    ui.strong("Hours");
+                    ui.strong("Note");
+                    ui.strong("Status");
+                    ui.end_row();
+                    for request in &requests {
+                        ui.label(employee_name(request.employee_id));
+                        ui.label(policy_name(request.policy_id));
+                        ui.label(format!("{} to {}", request.start_date, request.end_date));
+                        ui.label(format!("{:.2}", request.hours));
+                        ui.label(&request.note);
+                        if request.payroll_id.is_some() {
+                            ui.label("Paid");
+                        } else {
+                            ui.label(&request.status);
+                        }
+                        if request.status == STATUS_PENDING {
+                            if users::guarded_button(ui, can_edit_employees, "Approve").clicked() {
+                                app.pto_form.status = match approve_request(&app.conn, request) {
+                                    Ok(()) => "Request approved".to_string(),
+                                    Err(e) => e,
+                                };
+                            }
+                            if users::guarded_button(ui, can_edit_employees, "Deny").clicked() {
+                                set_request_status(&app.conn, request.id, STATUS_DENIED)
+                                    .unwrap_or_else(|e| println!("Error denying request: {}", e));
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching requests: {}", e));
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.label("New Request");
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("pto_request_employee")
+            .selected_text(
+                app.pto_form
+                    .employee_id
+                    .map_or("Employee".to_string(), employee_name),
+            )
+            .show_ui(ui, |ui| {
+                for emp in &employees {
+                    ui.selectable_value(&mut app.pto_form.employee_id, Some(emp.id), &emp.name);
+                }
+            });
+        let enrolled = app
+            .pto_form
+            .employee_id
+            .map(|id| get_policies_for_employee(&app.conn, id).unwrap_or_default())
+            .unwrap_or_default();
+        egui::ComboBox::from_id_salt("pto_request_policy")
+            .selected_text(
+                app.pto_form
+                    .request_policy_id
+                    .map_or("Policy".to_string(), policy_name),
+            )
+            .show_ui(ui, |ui| {
+                for policy in &enrolled {
+                    ui.selectable_value(
+                        &mut app.pto_form.request_policy_id,
+                        Some(policy.id),
+                        &policy.name,
+                    );
+                }
+            });
+        ui.add_sized(
+            [100.0, 25.0],
+            egui::TextEdit::singleline(&mut app.pto_form.request_start).hint_text("Start"),
+        );
+        ui.add_sized(
+            [100.0, 25.0],
+            egui::TextEdit::singleline(&mut app.pto_form.request_end).hint_text("End"),
+        );
+        ui.label("Hours");
+        ui.add(
+            egui::DragValue::new(&mut app.pto_form.request_hours)
+                .speed(0.5)
+                .range(0.0..=400.0),
+        );
+        ui.add_sized(
+            [150.0, 25.0],
+            egui::TextEdit::singleline(&mut app.pto_form.request_note).hint_text("Note"),
+        );
+        if users::guarded_button(ui, can_edit_employees, "Submit").clicked() {
+            submit_request(app);
+        }
+    });
+}
+
+fn submit_request(app: &mut PharmacyApp) {
+    let form = &mut app.pto_form;
+    let (Some(employee_id), Some(policy_id)) = (form.employee_id, form.request_policy_id) else {
+        form.status = "Select an employee and policy".to_string();
+        return;
+    };
+    let start = NaiveDate::parse_from_str(&form.request_start, "%Y-%m-%d");
+    let end = NaiveDate::parse_from_str(&form.request_end, "%Y-%m-%d");
+    match (start, end) {
+        (Ok(start), Ok(end)) if end >= start => {}
+        (Ok(_), Ok(_)) => {
+            form.status = "End date must be on or after the start date".to_string();
+            return;
+        }
+        _ => {
+            form.status = "Dates must be in YYYY-MM-DD format".to_string();
+            return;
+        }
+    }
+    if form.request_hours <= 0.0 {
+        form.status = "Requested hours must be greater than zero".to_string();
+        return;
+    }
+
+    let request = PtoRequest {
+        id: 0,
+        employee_id,
+        policy_id,
+        start_date: form.request_start.clone(),
+        end_date: form.request_end.clone(),
+        hours: form.request_hours,
+        status: STATUS_PENDING.to_string(),
+        note: form.request_note.trim().to_string(),
+        payroll_id: None,
+    };
+    form.status = match request.save_to_db(&app.conn) {
+        Ok(_) => {
+            form.request_start.clear();
+            form.request_end.clear();
+            form.request_note.clear();
+            "Request submitted".to_string()
+        }
+        Err(e) => format!("Error saving request: {}", e),
+    };
+}
+
+fn render_balances(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Balances & Enrollment");
+    let employees = app
+        .cache
+        .company_employees(&app.conn, app.company_id)
+        .unwrap_or_default();
+    let policies = get_policies(&app.conn).unwrap_or_default();
+    if policies.is_empty() {
+        ui.label("Add a policy below to start tracking balances");
+        return;
+    }
+    let today = chrono::Local::now().date_naive();
+
+    egui::Grid::new("pto_balances_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Employee");
+            for policy in &policies {
+                ui.strong(&policy.name);
+            }
+            ui.end_row();
+            for emp in &employees {
+                ui.label(&emp.name);
+                let enrolled = get_policies_for_employee(&app.conn, emp.id).unwrap_or_default();
+                for policy in &policies {
+                    let mut is_enrolled = enrolled.iter().any(|p| p.id == policy.id);
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut is_enrolled, "").changed() {
+                            set_enrollment(&app.conn, emp.id, policy.id, is_enrolled)
+                                .unwrap_or_else(|e| println!("Error updating enrollment: {}", e));
+                        }
+                        if is_enrolled {
+                            let balance =
+                                get_balance(&app.conn, emp.id, policy, today).unwrap_or(0.0);
+                            ui.label(format!("{:.2} hrs", balance));
+                        }
+                    });
+                }
+                ui.end_row();
+            }
+        });
+}
+
+fn render_policies(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("Policies");
+    match get_policies(&app.conn) {
+        Ok(policies) => {
+            egui::Grid::new("pto_policies_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    for policy in &policies {
+                        ui.label(&policy.name);
+                        ui.label(&policy.leave_type);
+                        ui.label(policy.describe());
+                        if users::guarded_button(ui, can_edit_payroll, "Delete").clicked() {
+                            delete_policy(&app.conn, policy.id)
+                                .unwrap_or_else(|e| println!("Error deleting policy: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching policies: {}", e));
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.horizontal(|ui| {
+        ui.label("New Policy");
+        egui::ComboBox::from_id_salt("pto_preset_select")
+            .selected_text("Load state sick leave preset")
+            .show_ui(ui, |ui| {
+                for preset in state_sick_leave_presets() {
+                    if ui.selectable_label(false, &preset.name).clicked() {
+                        app.pto_form.load_preset(preset);
+                    }
+                }
+            });
+    });
+
+    let form = &mut app.pto_form;
+    ui.horizontal(|ui| {
+        ui.add_sized(
+            [200.0, 25.0],
+            egui::TextEdit::singleline(&mut form.policy.name).hint_text("Policy name"),
+        );
+        egui::ComboBox::from_id_salt("pto_leave_type")
+            .selected_text(form.policy.leave_type.clone())
+            .show_ui(ui, |ui| {
+                for leave_type in LEAVE_TYPES {
+                    ui.selectable_value(
+                        &mut form.policy.leave_type,
+                        leave_type.to_string(),
+                        leave_type,
+                    );
+                }
+            });
+        egui::ComboBox::from_id_salt("pto_accrual_method")
+            .selected_text(form.policy.accrual_method.label())
+            .show_ui(ui, |ui| {
+                for method in [AccrualMethod::PerHourWorked, AccrualMethod::PerPayPeriod] {
+                    ui.selectable_value(&mut form.policy.accrual_method, method, method.label());
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("Accrue");
+        ui.add(
+            egui::DragValue::new(&mut form.policy.accrual_hours)
+                .speed(0.05)
+                .range(0.0..=80.0),
+        );
+        if form.policy.accrual_method == AccrualMethod::PerHourWorked {
+            ui.label("hours per");
+            ui.add(
+                egui::DragValue::new(&mut form.policy.per_hours_worked)
+                    .speed(1.0)
+                    .range(1.0..=520.0),
+            );
+            ui.label("hours worked");
+        } else {
+            ui.label("hours per pay period");
+        }
+    });
+    ui.horizontal(|ui| {
+        for (enabled, value, label) in [
+            (
+                &mut form.has_annual_cap,
+                &mut form.policy.annual_accrual_cap,
+                "Annual accrual cap",
+            ),
+            (
+                &mut form.has_balance_cap,
+                &mut form.policy.balance_cap,
+                "Balance cap",
+            ),
+            (
+                &mut form.has_carryover_limit,
+                &mut form.policy.carryover_limit,
+                "Carryover limit",
+            ),
+        ] {
+            ui.checkbox(enabled, label);
+            if *enabled {
+                let amount = value.get_or_insert(0.0);
+                ui.add(egui::DragValue::new(amount).speed(1.0).range(0.0..=2080.0));
+            }
+        }
+        ui.checkbox(&mut form.policy.allow_negative, "Allow negative balance");
+    });
+    if users::guarded_button(ui, can_edit_payroll, "Save Policy").clicked() {
+        if form.policy.name.trim().is_empty() {
+            form.status = "Please enter a policy name".to_string();
+        } else {
+            form.status = match form.policy_to_save().save_to_db(&app.conn) {
+                Ok(_) => {
+                    form.load_preset(PtoPolicy::default());
+                    "Policy saved".to_string()
+                }
+                Err(e) => format!("Error saving policy: {}", e),
+            };
+        }
+    }
+}
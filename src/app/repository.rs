@@ -0,0 +1,509 @@
+use crate::app::app::PharmacyApp;
+use crate::app::database;
+use crate::app::deductions;
+use crate::app::deductions::{DeductionElection, DeductionLine, DeductionType};
+use crate::app::earnings;
+use crate::app::earnings::{EarningLine, EarningType};
+use crate::app::employee::Employee;
+use crate::app::garnishments;
+use crate::app::garnishments::GarnishmentOrder;
+use crate::app::payroll::PayrollEntry;
+use crate::app::retirement;
+use crate::app::retirement::{EmployerContribution, EmployerContributionRule};
+use crate::app::retro;
+use rusqlite::Connection;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Employee records as the panels and payroll engine see them, whatever stores them.
+pub trait EmployeeRepository {
+    fn employee(&self, id: i32) -> Result<Employee, rusqlite::Error>;
+    fn employees(&self) -> Result<Vec<Employee>, rusqlite::Error>;
+    /// Stores a new employee and returns its id.
+    fn add_employee(&self, employee: &Employee) -> Result<i64, rusqlite::Error>;
+    fn update_pay_setup(&self, employee: &Employee) -> Result<(), rusqlite::Error>;
+}
+
+/// Paychecks and everything the payroll engine reads to work one out. PTO balances,
+/// locations and the setup screens for elections, orders and plans still use the connection.
+pub trait PayrollRepository {
+    /// Every check for the employee, oldest first.
+    fn checks_for_employee(&self, employee_id: i32) -> Result<Vec<PayrollEntry>, rusqlite::Error>;
+    /// Fridays already used by the employee's regular checks.
+    fn pay_dates_for_employee(&self, employee_id: i32) -> Result<Vec<String>, rusqlite::Error>;
+    fn earnings_for_check(&self, payroll_id: i64) -> Result<Vec<EarningLine>, rusqlite::Error>;
+    /// Stores the check with its lines, posts its PTO against `pto_policy_id`, and returns
+    /// the new check id.
+    fn save_check(
+        &self,
+        entry: &PayrollEntry,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+        pto_policy_id: Option<i64>,
+    ) -> Result<i64, rusqlite::Error>;
+    fn delete_check(&self, id: i64) -> Result<(), rusqlite::Error>;
+    fn deductions_for_check(&self, payroll_id: i64) -> Result<Vec<DeductionLine>, rusqlite::Error>;
+    fn contributions_for_check(
+        &self,
+        payroll_id: i64,
+    ) -> Result<Vec<EmployerContribution>, rusqlite::Error>;
+    /// The employee's deduction elections, active or not.
+    fn deduction_elections(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<DeductionElection>, rusqlite::Error>;
+    /// Amounts withheld per deduction limit group on the employee's checks paid in `year`.
+    fn deduction_ytd(
+        &self,
+        employee_id: i32,
+        year: i32,
+    ) -> Result<HashMap<String, f32>, rusqlite::Error>;
+    /// The employee's garnishment orders, active or not.
+    fn garnishment_orders(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<GarnishmentOrder>, rusqlite::Error>;
+    /// Total withheld so far against each garnishment order, keyed by order id.
+    fn garnishment_paid(&self) -> Result<HashMap<i64, f32>, rusqlite::Error>;
+    /// Employer contribution rules the employee is enrolled in.
+    fn retirement_rules(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<EmployerContributionRule>, rusqlite::Error>;
+    /// Approved retro pay not yet paid on a check.
+    fn pending_retro(&self, employee_id: i32) -> Result<f32, rusqlite::Error>;
+}
+
+/// The app's database.
+pub struct SqliteRepository<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> SqliteRepository<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+}
+
+impl PharmacyApp {
+    pub fn repo(&self) -> SqliteRepository<'_> {
+        SqliteRepository::new(&self.conn)
+    }
+}
+
+impl EmployeeRepository for SqliteRepository<'_> {
+    fn employee(&self, id: i32) -> Result<Employee, rusqlite::Error> {
+        database::get_employee_by_id(self.conn, id)
+    }
+
+    fn employees(&self) -> Result<Vec<Employee>, rusqlite::Error> {
+        database::get_all_employees(self.conn)
+    }
+
+    fn add_employee(&self, employee: &Employee) -> Result<i64, rusqlite::Error> {
+        database::insert_employee(self.conn, employee)
+    }
+
+    fn update_pay_setup(&self, employee: &Employee) -> Result<(), rusqlite::Error> {
+        database::update_pay_setup(self.conn, employee)
+    }
+}
+
+impl PayrollRepository for SqliteRepository<'_> {
+    fn checks_for_employee(&self, employee_id: i32) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
+        database::get_payroll_by_id(self.conn, employee_id)
+    }
+
+    fn pay_dates_for_employee(&self, employee_id: i32) -> Result<Vec<String>, rusqlite::Error> {
+        database::get_payroll_dates_for_employee(self.conn, employee_id)
+    }
+
+    fn earnings_for_check(&self, payroll_id: i64) -> Result<Vec<EarningLine>, rusqlite::Error> {
+        earnings::get_earnings_for_check(self.conn, payroll_id)
+    }
+
+    fn save_check(
+        &self,
+        entry: &PayrollEntry,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+        pto_policy_id: Option<i64>,
+    ) -> Result<i64, rusqlite::Error> {
+        entry.save_check(self.conn, lines, deductions, contributions, pto_policy_id)
+    }
+
+    fn delete_check(&self, id: i64) -> Result<(), rusqlite::Error> {
+        database::delete_payroll_entry(self.conn, id)
+    }
+
+    fn deductions_for_check(&self, payroll_id: i64) -> Result<Vec<DeductionLine>, rusqlite::Error> {
+        deductions::get_deductions_for_check(self.conn, payroll_id)
+    }
+
+    fn contributions_for_check(
+        &self,
+        payroll_id: i64,
+    ) -> Result<Vec<EmployerContribution>, rusqlite::Error> {
+        retirement::get_contributions_for_check(self.conn, payroll_id)
+    }
+
+    fn deduction_elections(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<DeductionElection>, rusqlite::Error> {
+        deductions::get_elections_for_employee(self.conn, employee_id)
+    }
+
+    fn deduction_ytd(
+        &self,
+        employee_id: i32,
+        year: i32,
+    ) -> Result<HashMap<String, f32>, rusqlite::Error> {
+        deductions::get_ytd_by_limit_group(self.conn, employee_id, year)
+    }
+
+    fn garnishment_orders(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<GarnishmentOrder>, rusqlite::Error> {
+        garnishments::get_orders_for_employee(self.conn, employee_id)
+    }
+
+    fn garnishment_paid(&self) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+        garnishments::get_amounts_paid(self.conn)
+    }
+
+    fn retirement_rules(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<EmployerContributionRule>, rusqlite::Error> {
+        retirement::get_rules_for_employee(self.conn, employee_id)
+    }
+
+    fn pending_retro(&self, employee_id: i32) -> Result<f32, rusqlite::Error> {
+        retro::get_pending_amount(self.conn, employee_id)
+    }
+}
+
+/// Keeps everything in memory so payroll logic can be exercised without SQL. Elections,
+/// garnishment orders, retirement enrollments and pending retro are set up directly; checks
+/// keep their earnings, deductions and contributions. Missing records fail with
+/// `QueryReturnedNoRows`, as they do in SQLite. Only the tests build one.
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct InMemoryRepository {
+    employees: RefCell<Vec<Employee>>,
+    checks: RefCell<Vec<StoredCheck>>,
+    elections: RefCell<Vec<DeductionElection>>,
+    garnishment_orders: RefCell<Vec<GarnishmentOrder>>,
+    enrollments: RefCell<Vec<(i32, EmployerContributionRule)>>,
+    retro: RefCell<Vec<PendingRetro>>,
+}
+
+struct StoredCheck {
+    entry: PayrollEntry,
+    lines: Vec<EarningLine>,
+    deductions: Vec<DeductionLine>,
+    contributions: Vec<EmployerContribution>,
+}
+
+struct PendingRetro {
+    employee_id: i32,
+    amount: f32,
+    payroll_id: Option<i64>,
+}
+
+#[allow(dead_code)]
+impl InMemoryRepository {
+    pub fn add_election(&self, election: DeductionElection) {
+        self.elections.borrow_mut().push(election);
+    }
+
+    pub fn add_garnishment_order(&self, order: GarnishmentOrder) {
+        self.garnishment_orders.borrow_mut().push(order);
+    }
+
+    pub fn enroll(&self, employee_id: i32, rule: EmployerContributionRule) {
+        self.enrollments.borrow_mut().push((employee_id, rule));
+    }
+
+    /// Queues approved retro for the employee's next check.
+    pub fn add_pending_retro(&self, employee_id: i32, amount: f32) {
+        self.retro.borrow_mut().push(PendingRetro {
+            employee_id,
+            amount,
+            payroll_id: None,
+        });
+    }
+}
+
+impl EmployeeRepository for InMemoryRepository {
+    fn employee(&self, id: i32) -> Result<Employee, rusqlite::Error> {
+        self.employees
+            .borrow()
+            .iter()
+            .find(|employee| employee.id == id)
+            .cloned()
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    fn employees(&self) -> Result<Vec<Employee>, rusqlite::Error> {
+        Ok(self.employees.borrow().clone())
+    }
+
+    fn add_employee(&self, employee: &Employee) -> Result<i64, rusqlite::Error> {
+        let mut employees = self.employees.borrow_mut();
+        let id = employees
+            .iter()
+            .map(|employee| employee.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        employees.push(Employee {
+            id,
+            ..employee.clone()
+        });
+        Ok(id as i64)
+    }
+
+    fn update_pay_setup(&self, employee: &Employee) -> Result<(), rusqlite::Error> {
+        let mut employees = self.employees.borrow_mut();
+        let stored = employees
+            .iter_mut()
+            .find(|stored| stored.id == employee.id)
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        stored.pay_rate = employee.pay_rate.clone();
+        stored.pay_type = employee.pay_type;
+        stored.flsa_exempt = employee.flsa_exempt;
+        stored.hire_date = employee.hire_date.clone();
+        stored.termination_date = employee.termination_date.clone();
+        Ok(())
+    }
+}
+
+impl PayrollRepository for InMemoryRepository {
+    fn checks_for_employee(&self, employee_id: i32) -> Result<Vec<PayrollEntry>, rusqlite::Error> {
+        let mut checks: Vec<PayrollEntry> = self
+            .checks
+            .borrow()
+            .iter()
+            .filter(|check| check.entry.employee_id == employee_id)
+            .map(|check| check.entry.clone())
+            .collect();
+        checks.sort_by(|a, b| a.date_of_pay.cmp(&b.date_of_pay));
+        Ok(checks)
+    }
+
+    fn pay_dates_for_employee(&self, employee_id: i32) -> Result<Vec<String>, rusqlite::Error> {
+        Ok(self
+            .checks
+            .borrow()
+            .iter()
+            .map(|check| &check.entry)
+            .filter(|entry| {
+                entry.employee_id == employee_id
+                    && entry.off_cycle_reason.is_none()
+                    && entry.imported.is_none()
+            })
+            .map(|entry| entry.date_of_pay.clone())
+            .collect())
+    }
+
+    fn earnings_for_check(&self, payroll_id: i64) -> Result<Vec<EarningLine>, rusqlite::Error> {
+        Ok(self
+            .checks
+            .borrow()
+            .iter()
+            .find(|check| check.entry.id == payroll_id)
+            .map(|check| check.lines.clone())
+            .unwrap_or_default())
+    }
+
+    fn save_check(
+        &self,
+        entry: &PayrollEntry,
+        lines: &[EarningLine],
+        deductions: &[DeductionLine],
+        contributions: &[EmployerContribution],
+        _pto_policy_id: Option<i64>,
+    ) -> Result<i64, rusqlite::Error> {
+        let mut checks = self.checks.borrow_mut();
+        let id = checks.iter().map(|check| check.entry.id).max().unwrap_or(0) + 1;
+        checks.push(StoredCheck {
+            entry: PayrollEntry {
+                id,
+                ..entry.clone()
+            },
+            lines: lines
+                .iter()
+                .map(|line| EarningLine {
+                    payroll_id: id,
+                    ..line.clone()
+                })
+                .collect(),
+            deductions: deductions
+                .iter()
+                .map(|deduction| DeductionLine {
+                    payroll_id: id,
+                    ..deduction.clone()
+                })
+                .collect(),
+            contributions: contributions
+                .iter()
+                .map(|contribution| EmployerContribution {
+                    payroll_id: id,
+                    ..contribution.clone()
+                })
+                .collect(),
+        });
+
+        // Settles pending retro oldest first, as far as the check's retro lines cover it.
+        let mut remaining: f32 = lines
+            .iter()
+            .filter(|line| line.earning_type == EarningType::Retro)
+            .map(|line| line.amount)
+            .sum();
+        for owed in self
+            .retro
+            .borrow_mut()
+            .iter_mut()
+            .filter(|owed| owed.employee_id == entry.employee_id && owed.payroll_id.is_none())
+        {
+            if owed.amount > remaining + 0.005 {
+                break;
+            }
+            remaining -= owed.amount;
+            owed.payroll_id = Some(id);
+        }
+        Ok(id)
+    }
+
+    fn delete_check(&self, id: i64) -> Result<(), rusqlite::Error> {
+        let mut checks = self.checks.borrow_mut();
+        if checks
+            .iter()
+            .any(|check| check.entry.id == id && check.entry.imported.is_some())
+        {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+                Some("Imported payroll history can't be changed".to_string()),
+            ));
+        }
+        checks.retain(|check| check.entry.id != id);
+        for owed in self.retro.borrow_mut().iter_mut() {
+            if owed.payroll_id == Some(id) {
+                owed.payroll_id = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn deductions_for_check(&self, payroll_id: i64) -> Result<Vec<DeductionLine>, rusqlite::Error> {
+        Ok(self
+            .checks
+            .borrow()
+            .iter()
+            .find(|check| check.entry.id == payroll_id)
+            .map(|check| check.deductions.clone())
+            .unwrap_or_default())
+    }
+
+    fn contributions_for_check(
+        &self,
+        payroll_id: i64,
+    ) -> Result<Vec<EmployerContribution>, rusqlite::Error> {
+        Ok(self
+            .checks
+            .borrow()
+            .iter()
+            .find(|check| check.entry.id == payroll_id)
+            .map(|check| check.contributions.clone())
+            .unwrap_or_default())
+    }
+
+    fn deduction_elections(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<DeductionElection>, rusqlite::Error> {
+        Ok(self
+            .elections
+            .borrow()
+            .iter()
+            .filter(|election| election.employee_id == employee_id)
+            .cloned()
+            .collect())
+    }
+
+    fn deduction_ytd(
+        &self,
+        employee_id: i32,
+        year: i32,
+    ) -> Result<HashMap<String, f32>, rusqlite::Error> {
+        let year = year.to_string();
+        let mut ytd = HashMap::new();
+        for check in self.checks.borrow().iter().filter(|check| {
+            check.entry.employee_id == employee_id && check.entry.date_of_pay.starts_with(&year)
+        }) {
+            for deduction in &check.deductions {
+                if let Some(deduction_type) = DeductionType::parse(&deduction.code) {
+                    *ytd.entry(deduction_type.limit_group().to_string())
+                        .or_insert(0.0) += deduction.amount;
+                }
+            }
+        }
+        Ok(ytd)
+    }
+
+    fn garnishment_orders(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<GarnishmentOrder>, rusqlite::Error> {
+        Ok(self
+            .garnishment_orders
+            .borrow()
+            .iter()
+            .filter(|order| order.employee_id == employee_id)
+            .cloned()
+            .collect())
+    }
+
+    fn garnishment_paid(&self) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+        let mut paid = HashMap::new();
+        for deduction in self
+            .checks
+            .borrow()
+            .iter()
+            .flat_map(|check| &check.deductions)
+        {
+            if let Some(order_id) = garnishments::order_for_code(&deduction.code) {
+                *paid.entry(order_id).or_insert(0.0) += deduction.amount;
+            }
+        }
+        Ok(paid)
+    }
+
+    fn retirement_rules(
+        &self,
+        employee_id: i32,
+    ) -> Result<Vec<EmployerContributionRule>, rusqlite::Error> {
+        Ok(self
+            .enrollments
+            .borrow()
+            .iter()
+            .filter(|(enrolled, _)| *enrolled == employee_id)
+            .map(|(_, rule)| rule.clone())
+            .collect())
+    }
+
+    fn pending_retro(&self, employee_id: i32) -> Result<f32, rusqlite::Error> {
+        Ok(self
+            .retro
+            .borrow()
+            .iter()
+            .filter(|owed| owed.employee_id == employee_id && owed.payroll_id.is_none())
+            .map(|owed| owed.amount)
+            .sum())
+    }
+}
@@ -0,0 +1,700 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::database;
+use crate::app::deductions::DeductionLine;
+use crate::app::deductions::DeductionType;
+use crate::app::users;
+use crate::app::users::Permission;
+use chrono::Datelike;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RetirementPlan {
+    Plan401k,
+    SimpleIra,
+}
+
+impl RetirementPlan {
+    pub const ALL: [RetirementPlan; 2] = [RetirementPlan::Plan401k, RetirementPlan::SimpleIra];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetirementPlan::Plan401k => "401k",
+            RetirementPlan::SimpleIra => "simple_ira",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "simple_ira" => RetirementPlan::SimpleIra,
+            _ => RetirementPlan::Plan401k,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RetirementPlan::Plan401k => "401(k)",
+            RetirementPlan::SimpleIra => "SIMPLE IRA",
+        }
+    }
+
+    /// Employee deduction types whose deferrals go into this plan.
+    pub fn deferral_types(&self) -> &'static [DeductionType] {
+        match self {
+            RetirementPlan::Plan401k => &[DeductionType::Traditional401k, DeductionType::Roth401k],
+            RetirementPlan::SimpleIra => &[DeductionType::SimpleIra],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ContributionFormula {
+    /// `rate`% of the employee's deferral, counting deferrals up to `pay_cap_percent`% of gross.
+    Match,
+    /// `rate`% of gross whether or not the employee defers, like the SIMPLE IRA 2% option.
+    Nonelective,
+}
+
+impl ContributionFormula {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContributionFormula::Match => "match",
+            ContributionFormula::Nonelective => "nonelective",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "nonelective" => ContributionFormula::Nonelective,
+            _ => ContributionFormula::Match,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmployerContributionRule {
+    pub id: i64,
+    pub name: String,
+    pub plan: RetirementPlan,
+    pub formula: ContributionFormula,
+    pub rate: f32,
+    pub pay_cap_percent: f32,
+    pub active: bool,
+    pub company_id: Option<i64>,
+}
+
+impl Default for EmployerContributionRule {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::new(),
+            plan: RetirementPlan::Plan401k,
+            formula: ContributionFormula::Match,
+            rate: 100.0,
+            pay_cap_percent: 3.0,
+            active: true,
+            company_id: None,
+        }
+    }
+}
+
+impl EmployerContributionRule {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO employer_contribution_rules (
+                name,
+                plan,
+                formula,
+                rate,
+                pay_cap_percent,
+                active,
+                company_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                &self.name,
+                self.plan.as_str(),
+                self.formula.as_str(),
+                self.rate,
+                self.pay_cap_percent,
+                self.active,
+                self.company_id
+            ],
+        )?;
+        let id = conn.last_insert_rowid();
+        audit::record_row(
+            conn,
+            "retirement_rule",
+            "employer_contribution_rules",
+            id,
+            AuditAction::Insert,
+            None,
+        )?;
+        Ok(id)
+    }
+
+    pub fn describe(&self) -> String {
+        match self.formula {
+            ContributionFormula::Match => format!(
+                "{:.0}% match up to {:.1}% of pay",
+                self.rate, self.pay_cap_percent
+            ),
+            ContributionFormula::Nonelective => format!("{:.1}% nonelective", self.rate),
+        }
+    }
+}
+
+/// An employer-paid amount on a check. It is a cost to the pharmacy and never reduces net pay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmployerContribution {
+    pub id: i64,
+    pub payroll_id: i64,
+    pub rule_id: i64,
+    pub plan: RetirementPlan,
+    pub description: String,
+    pub amount: f32,
+}
+
+impl EmployerContribution {
+    pub fn save_to_db(&self, conn: &Connection, payroll_id: i64) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO employer_contributions (
+                payroll_id,
+                rule_id,
+                plan,
+                description,
+                amount)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                payroll_id,
+                self.rule_id,
+                self.plan.as_str(),
+                &self.description,
+                self.amount
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+fn rule_from_row(row: &rusqlite::Row) -> Result<EmployerContributionRule, rusqlite::Error> {
+    Ok(EmployerContributionRule {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        plan: RetirementPlan::parse(&row.get::<_, String>(2)?),
+        formula: ContributionFormula::parse(&row.get::<_, String>(3)?),
+        rate: row.get(4)?,
+        pay_cap_percent: row.get(5)?,
+        active: row.get(6)?,
+        company_id: row.get(7)?,
+    })
+}
+
+/// The company's rules, enrolled employees or not.
+pub fn get_rules(
+    conn: &Connection,
+    company_id: Option<i64>,
+) -> Result<Vec<EmployerContributionRule>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, plan, formula, rate, pay_cap_percent, active, company_id
+        FROM employer_contribution_rules WHERE company_id IS ?1 ORDER BY id",
+    )?;
+    let rules = stmt
+        .query_map([company_id], rule_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rules)
+}
+
+/// Rules the employee is enrolled in from their own company.
+pub fn get_rules_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<EmployerContributionRule>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.name, r.plan, r.formula, r.rate, r.pay_cap_percent, r.active, r.company_id
+        FROM employer_contribution_rules r
+        JOIN retirement_enrollments re ON re.rule_id = r.id
+        JOIN employees e ON e.id = re.employee_id
+        WHERE re.employee_id = ?1 AND r.company_id IS e.company_id ORDER BY r.id",
+    )?;
+    let rules = stmt
+        .query_map([employee_id], rule_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rules)
+}
+
+pub fn set_enrollment(
+    conn: &Connection,
+    employee_id: i32,
+    rule_id: i64,
+    enrolled: bool,
+) -> Result<(), rusqlite::Error> {
+    let changed = if enrolled {
+        conn.execute(
+            "INSERT OR IGNORE INTO retirement_enrollments (employee_id, rule_id) VALUES (?1, ?2)",
+            params![employee_id, rule_id],
+        )?
+    } else {
+        conn.execute(
+            "DELETE FROM retirement_enrollments WHERE employee_id = ?1 AND rule_id = ?2",
+            params![employee_id, rule_id],
+        )?
+    };
+    if changed == 0 {
+        return Ok(());
+    }
+    // Enrollments have no id of their own, so they are logged against the employee.
+    let enrollment = serde_json::json!({ "rule_id": rule_id });
+    let (action, before, after) = if enrolled {
+        (AuditAction::Insert, None, Some(&enrollment))
+    } else {
+        (AuditAction::Delete, Some(&enrollment), None)
+    };
+    audit::record(
+        conn,
+        "retirement_enrollment",
+        employee_id as i64,
+        action,
+        before,
+        after,
+    )
+}
+
+pub fn set_rule_active(conn: &Connection, id: i64, active: bool) -> Result<(), rusqlite::Error> {
+    let before = audit::snapshot(conn, "employer_contribution_rules", id)?;
+    conn.execute(
+        "UPDATE employer_contribution_rules SET active = ?1 WHERE id = ?2",
+        params![active, id],
+    )?;
+    audit::record_row(
+        conn,
+        "retirement_rule",
+        "employer_contribution_rules",
+        id,
+        AuditAction::Update,
+        before.as_ref(),
+    )
+}
+
+pub fn get_contributions_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<Vec<EmployerContribution>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, payroll_id, rule_id, plan, description, amount
+        FROM employer_contributions WHERE payroll_id = ?1 ORDER BY id",
+    )?;
+    let contributions = stmt
+        .query_map([payroll_id], |row| {
+            Ok(EmployerContribution {
+                id: row.get(0)?,
+                payroll_id: row.get(1)?,
+                rule_id: row.get(2)?,
+                plan: RetirementPlan::parse(&row.get::<_, String>(3)?),
+                description: row.get(4)?,
+                amount: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(contributions)
+}
+
+pub fn delete_contributions_for_check(
+    conn: &Connection,
+    payroll_id: i64,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM employer_contributions WHERE payroll_id = ?1",
+        [payroll_id],
+    )?;
+    Ok(())
+}
+
+/// Applies each active rule to a check's gross and the employee's deferrals on that check.
+/// Pass the employee's own rules from `get_rules_for_employee`.
+pub fn calculate_contributions(
+    rules: &[EmployerContributionRule],
+    gross: f32,
+    deductions: &[DeductionLine],
+) -> Vec<EmployerContribution> {
+    rules
+        .iter()
+        .filter(|rule| rule.active)
+        .filter_map(|rule| {
+            let amount = match rule.formula {
+                ContributionFormula::Match => {
+                    let deferral: f32 = deductions
+                        .iter()
+                        .filter(|d| {
+                            rule.plan
+                                .deferral_types()
+                                .iter()
+                                .any(|t| t.as_str() == d.code)
+                        })
+                        .map(|d| d.amount)
+                        .sum();
+                    deferral.min(gross * rule.pay_cap_percent / 100.0) * rule.rate / 100.0
+                }
+                ContributionFormula::Nonelective => gross * rule.rate / 100.0,
+            };
+            let amount = (amount * 100.0).round() / 100.0;
+            (amount > 0.0).then(|| EmployerContribution {
+                id: 0,
+                payroll_id: 0,
+                rule_id: rule.id,
+                plan: rule.plan,
+                description: format!("Employer {} ({})", rule.plan.label(), rule.name),
+                amount,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanTotal {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub employee_deferrals: f32,
+    pub employer_contributions: f32,
+}
+
+impl PlanTotal {
+    pub fn total(&self) -> f32 {
+        self.employee_deferrals + self.employer_contributions
+    }
+}
+
+/// Employee deferrals and employer contributions into `plan` per employee for checks paid
+/// between `from` and `to` inclusive. Employees with nothing in the plan are left out.
+pub fn get_plan_totals(
+    conn: &Connection,
+    plan: RetirementPlan,
+    from: &str,
+    to: &str,
+    company_id: Option<i64>,
+) -> Result<Vec<PlanTotal>, rusqlite::Error> {
+    let codes = plan
+        .deferral_types()
+        .iter()
+        .map(|t| format!("'{}'", t.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT e.id, e.name,
+            COALESCE((SELECT SUM(d.amount) FROM deduction_lines d
+                JOIN payroll p ON p.id = d.payroll_id
+                WHERE p.employee_id = e.id AND p.date_of_pay BETWEEN ?1 AND ?2
+                AND (?4 IS NULL OR p.company_id = ?4)
+                AND d.code IN ({})), 0),
+            COALESCE((SELECT SUM(c.amount) FROM employer_contributions c
+                JOIN payroll p ON p.id = c.payroll_id
+                WHERE p.employee_id = e.id AND p.date_of_pay BETWEEN ?1 AND ?2
+                AND (?4 IS NULL OR p.company_id = ?4)
+                AND c.plan = ?3), 0)
+        FROM employees e ORDER BY e.name",
+        codes
+    ))?;
+    let totals = stmt
+        .query_map(params![from, to, plan.as_str(), company_id], |row| {
+            Ok(PlanTotal {
+                employee_id: row.get(0)?,
+                employee_name: row.get(1)?,
+                employee_deferrals: row.get(2)?,
+                employer_contributions: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(totals
+        .into_iter()
+        .filter(|total| total.total() > 0.0)
+        .collect())
+}
+
+/// Remittance file for the plan custodian covering the checks paid on `date_of_pay`.
+pub fn remittance_csv(
+    plan: RetirementPlan,
+    date_of_pay: &str,
+    totals: &[PlanTotal],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Plan",
+        "Pay Date",
+        "Employee ID",
+        "Employee Name",
+        "Employee Deferrals",
+        "Employer Contributions",
+        "Total Remitted",
+    ])?;
+    for total in totals {
+        writer.write_record([
+            plan.label().to_string(),
+            date_of_pay.to_string(),
+            total.employee_id.to_string(),
+            total.employee_name.clone(),
+            format!("{:.2}", total.employee_deferrals),
+            format!("{:.2}", total.employer_contributions),
+            format!("{:.2}", total.total()),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+pub fn get_exports_dir() -> PathBuf {
+    let mut path = database::get_data_dir();
+    path.push("exports");
+    path
+}
+
+pub fn export_remittance(
+    conn: &Connection,
+    plan: RetirementPlan,
+    date_of_pay: &str,
+    company_id: Option<i64>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let totals = get_plan_totals(conn, plan, date_of_pay, date_of_pay, company_id)?;
+    if totals.is_empty() {
+        return Err(format!("No {} contributions on {}", plan.label(), date_of_pay).into());
+    }
+    let exports_dir = get_exports_dir();
+    std::fs::create_dir_all(&exports_dir)?;
+    let path = exports_dir.join(format!("remittance_{}_{}.csv", plan.as_str(), date_of_pay));
+    std::fs::write(&path, remittance_csv(plan, date_of_pay, &totals)?)?;
+    Ok(path)
+}
+
+pub struct RetirementForm {
+    pub rule: EmployerContributionRule,
+    pub report_plan: RetirementPlan,
+    pub report_year: i32,
+    pub remittance_date: String,
+    pub status: String,
+}
+
+impl Default for RetirementForm {
+    fn default() -> Self {
+        Self {
+            rule: EmployerContributionRule::default(),
+            report_plan: RetirementPlan::Plan401k,
+            report_year: chrono::Local::now().year(),
+            remittance_date: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn render_retirement(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Retirement Plans");
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        render_rules(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_enrollment(app, ui);
+        ui.add_space(20.0);
+        ui.separator();
+        render_reports(app, ui);
+        if !app.retirement_form.status.is_empty() {
+            ui.label(&app.retirement_form.status);
+        }
+    });
+}
+
+fn render_rules(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("Employer Contribution Rules");
+    match get_rules(&app.conn, app.company_id) {
+        Ok(rules) if rules.is_empty() => {
+            ui.label("No employer contribution rules");
+        }
+        Ok(rules) => {
+            egui::Grid::new("employer_rules_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Name");
+                    ui.strong("Plan");
+                    ui.strong("Formula");
+                    ui.strong("Status");
+                    ui.end_row();
+                    for rule in &rules {
+                        ui.label(&rule.name);
+                        ui.label(rule.plan.label());
+                        ui.label(rule.describe());
+                        ui.label(if rule.active { "Active" } else { "Stopped" });
+                        let label = if rule.active { "Stop" } else { "Resume" };
+                        if users::guarded_button(ui, can_edit_payroll, label).clicked() {
+                            set_rule_active(&app.conn, rule.id, !rule.active)
+                                .unwrap_or_else(|e| println!("Error updating rule: {}", e));
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching rules: {}", e));
+        }
+    }
+
+    let form = &mut app.retirement_form;
+    ui.horizontal(|ui| {
+        ui.label("Name");
+        ui.text_edit_singleline(&mut form.rule.name);
+        egui::ComboBox::from_id_salt("retirement_plan_select")
+            .selected_text(form.rule.plan.label())
+            .show_ui(ui, |ui| {
+                for plan in RetirementPlan::ALL {
+                    ui.selectable_value(&mut form.rule.plan, plan, plan.label());
+                }
+            });
+        ui.selectable_value(&mut form.rule.formula, ContributionFormula::Match, "Match");
+        ui.selectable_value(
+            &mut form.rule.formula,
+            ContributionFormula::Nonelective,
+            "Nonelective",
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rate %");
+        ui.add(
+            egui::DragValue::new(&mut form.rule.rate)
+                .speed(0.5)
+                .range(0.0..=100.0),
+        );
+        if form.rule.formula == ContributionFormula::Match {
+            ui.label("Up to % of pay");
+            ui.add(
+                egui::DragValue::new(&mut form.rule.pay_cap_percent)
+                    .speed(0.5)
+                    .range(0.0..=100.0),
+            );
+        }
+        if users::guarded_button(ui, can_edit_payroll, "Add Rule").clicked() {
+            if form.rule.name.trim().is_empty() {
+                form.status = "Rule name is required".to_string();
+            } else {
+                form.rule.company_id = app.company_id;
+                form.status = match form.rule.save_to_db(&app.conn) {
+                    Ok(_) => {
+                        form.rule = EmployerContributionRule::default();
+                        "Rule added".to_string()
+                    }
+                    Err(e) => format!("Error saving rule: {}", e),
+                };
+            }
+        }
+    });
+}
+
+fn render_enrollment(app: &mut PharmacyApp, ui: &mut Ui) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    ui.heading("Enrollment");
+    let rules = get_rules(&app.conn, app.company_id).unwrap_or_default();
+    if rules.is_empty() {
+        ui.label("Add a rule above to enroll employees");
+        return;
+    }
+    let employees = app
+        .cache
+        .company_employees(&app.conn, app.company_id)
+        .unwrap_or_default();
+
+    egui::Grid::new("retirement_enrollment_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Employee");
+            for rule in &rules {
+                ui.strong(&rule.name);
+            }
+            ui.end_row();
+            for emp in &employees {
+                ui.label(&emp.name);
+                let enrolled = get_rules_for_employee(&app.conn, emp.id).unwrap_or_default();
+                for rule in &rules {
+                    let mut is_enrolled = enrolled.iter().any(|r| r.id == rule.id);
+                    let response = ui
+                        .add_enabled(
+                            can_edit_payroll,
+                            egui::Checkbox::without_text(&mut is_enrolled),
+                        )
+                        .on_disabled_hover_text("Your role doesn't allow this");
+                    if response.changed() {
+                        set_enrollment(&app.conn, emp.id, rule.id, is_enrolled)
+                            .unwrap_or_else(|e| println!("Error updating enrollment: {}", e));
+                    }
+                }
+                ui.end_row();
+            }
+        });
+}
+
+fn render_reports(app: &mut PharmacyApp, ui: &mut Ui) {
+    let form = &mut app.retirement_form;
+    ui.heading("Contributions Report");
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("retirement_report_plan")
+            .selected_text(form.report_plan.label())
+            .show_ui(ui, |ui| {
+                for plan in RetirementPlan::ALL {
+                    ui.selectable_value(&mut form.report_plan, plan, plan.label());
+                }
+            });
+        ui.label("Year");
+        ui.add(egui::DragValue::new(&mut form.report_year).range(2000..=2100));
+    });
+
+    let from = format!("{}-01-01", form.report_year);
+    let to = format!("{}-12-31", form.report_year);
+    match get_plan_totals(&app.conn, form.report_plan, &from, &to, app.company_id) {
+        Ok(totals) if totals.is_empty() => {
+            ui.label("No contributions for this plan and year");
+        }
+        Ok(totals) => {
+            egui::Grid::new("retirement_ytd_grid")
+                .striped(true)
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.strong("Employee");
+                    ui.strong("Employee Deferrals");
+                    ui.strong("Employer Contributions");
+                    ui.strong("Total");
+                    ui.end_row();
+                    for total in &totals {
+                        ui.label(&total.employee_name);
+                        ui.label(format!("{:.2}", total.employee_deferrals));
+                        ui.label(format!("{:.2}", total.employer_contributions));
+                        ui.label(format!("{:.2}", total.total()));
+                        ui.end_row();
+                    }
+                });
+        }
+        Err(e) => {
+            ui.label(format!("Error fetching contributions: {}", e));
+        }
+    }
+
+    ui.add_space(10.0);
+    ui.horizontal(|ui| {
+        ui.label("Remittance for pay date");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.remittance_date)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+        if ui.button("Export Remittance File").clicked() {
+            form.status = match export_remittance(
+                &app.conn,
+                form.report_plan,
+                &form.remittance_date,
+                app.company_id,
+            ) {
+                Ok(path) => format!("Remittance saved to {}", path.display()),
+                Err(e) => format!("Error exporting remittance: {}", e),
+            };
+        }
+    });
+}
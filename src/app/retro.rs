@@ -0,0 +1,432 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::cache::CachedRepository;
+use crate::app::database;
+use crate::app::earnings::EarningLine;
+use crate::app::earnings::EarningType;
+use crate::app::employee::Employee;
+use crate::app::employee::PayType;
+use crate::app::payroll;
+use crate::app::repository::{EmployeeRepository, PayrollRepository, SqliteRepository};
+use crate::app::users::Permission;
+use chrono::NaiveDate;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What one past check paid for its hourly and salary work and what it would have paid at the
+/// new rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetroCheck {
+    pub payroll_id: i64,
+    pub date_of_pay: String,
+    pub hours: f32,
+    pub paid: f32,
+    pub owed: f32,
+}
+
+impl RetroCheck {
+    pub fn difference(&self) -> f32 {
+        self.owed - self.paid
+    }
+}
+
+/// A retro amount waiting for the employee's next check, or already paid on `payroll_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetroAdjustment {
+    pub id: i64,
+    pub employee_id: i32,
+    pub new_rate: f32,
+    pub effective_date: String,
+    pub amount: f32,
+    pub payroll_id: Option<i64>,
+}
+
+impl RetroAdjustment {
+    pub fn save_to_db(&self, conn: &Connection) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO retro_adjustments (
+                employee_id,
+                new_rate,
+                effective_date,
+                amount,
+                payroll_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.employee_id,
+                self.new_rate,
+                &self.effective_date,
+                self.amount,
+                self.payroll_id
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+}
+
+/// Reprices the earnings on one check as if `repriced` had been the employee's pay setup.
+/// Hourly lines are paid at its hourly rate and keep their overtime and holiday premiums,
+/// salary lines get its salary for the period, and flat amounts like bonuses are left alone.
+/// Checks saved before earnings lines existed are treated as straight-time hours, or as one
+/// salary period for salaried employees.
+pub fn reprice_check(
+    lines: &[EarningLine],
+    hours_worked: f32,
+    gross: f32,
+    date_of_pay: NaiveDate,
+    repriced: &Employee,
+) -> (f32, f32, f32) {
+    if lines.is_empty() {
+        let owed = if repriced.pay_type.is_salaried() {
+            repriced.period_salary(date_of_pay)
+        } else {
+            payroll::calculate_gross(hours_worked, repriced.hourly_rate())
+        };
+        return (hours_worked, gross, owed);
+    }
+    lines
+        .iter()
+        .filter(|line| line.earning_type.tracks_hours())
+        .fold((0.0, 0.0, 0.0), |(hours, paid, owed), line| {
+            let repriced_amount = if line.earning_type == EarningType::Salary {
+                repriced.period_salary(date_of_pay)
+            } else {
+                let rate = repriced.hourly_rate() * line.earning_type.rate_multiplier();
+                payroll::calculate_gross(line.hours, rate)
+            };
+            (
+                hours + line.hours,
+                paid + line.amount,
+                owed + repriced_amount,
+            )
+        })
+}
+
+/// Every check for the employee paid on or after `effective_date`, repriced at `new_rate`.
+/// The rate is in the employee's own pay units: hourly, per year or per period. Imported
+/// history is skipped, since it was paid before this system. `covered` holds retro already
+/// approved for a check, which counts as paid so the same hours aren't paid twice.
+pub fn calculate_retro(
+    repo: &(impl EmployeeRepository + PayrollRepository),
+    employee_id: i32,
+    new_rate: f32,
+    effective_date: &str,
+    covered: &HashMap<i64, f32>,
+) -> Result<Vec<RetroCheck>, rusqlite::Error> {
+    let repriced = Employee {
+        pay_rate: new_rate.to_string(),
+        ..repo.employee(employee_id)?
+    };
+    let mut checks = Vec::new();
+    for entry in repo.checks_for_employee(employee_id)? {
+        if entry.date_of_pay.as_str() < effective_date || entry.imported.is_some() {
+            continue;
+        }
+        let Ok(date_of_pay) = NaiveDate::parse_from_str(&entry.date_of_pay, "%Y-%m-%d") else {
+            continue;
+        };
+        let lines = repo.earnings_for_check(entry.id)?;
+        let (hours, paid, owed) = reprice_check(
+            &lines,
+            entry.hours_worked,
+            entry.gross,
+            date_of_pay,
+            &repriced,
+        );
+        let paid = paid + covered.get(&entry.id).copied().unwrap_or(0.0);
+        checks.push(RetroCheck {
+            payroll_id: entry.id,
+            date_of_pay: entry.date_of_pay,
+            hours,
+            paid,
+            owed,
+        });
+    }
+    Ok(checks)
+}
+
+pub fn total_retro(checks: &[RetroCheck]) -> f32 {
+    let total: f32 = checks.iter().map(|check| check.difference()).sum();
+    (total * 100.0).round() / 100.0
+}
+
+/// Records the retro amount for the next pay run and moves the employee to the new rate. A
+/// rate decrease that would leave the employee owing money back is refused; recover an
+/// overpayment with a deduction instead.
+pub fn approve_retro(
+    conn: &Connection,
+    employee_id: i32,
+    new_rate: f32,
+    effective_date: &str,
+) -> Result<RetroAdjustment, rusqlite::Error> {
+    let checks = calculate_retro(
+        &SqliteRepository::new(conn),
+        employee_id,
+        new_rate,
+        effective_date,
+        &get_covered_amounts(conn, employee_id)?,
+    )?;
+    let mut adjustment = RetroAdjustment {
+        id: 0,
+        employee_id,
+        new_rate,
+        effective_date: effective_date.to_string(),
+        amount: total_retro(&checks),
+        payroll_id: None,
+    };
+    if adjustment.amount < 0.0 {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+            Some(format!(
+                "The new rate would take back {:.2}; recover overpayments with a deduction",
+                -adjustment.amount
+            )),
+        ));
+    }
+
+    let old_rate = database::get_employee_by_id(conn, employee_id)?.pay_rate;
+    let tx = conn.unchecked_transaction()?;
+    adjustment.id = adjustment.save_to_db(&tx)?;
+    for check in &checks {
+        tx.execute(
+            "INSERT INTO retro_adjustment_checks (adjustment_id, payroll_id, amount)
+            VALUES (?1, ?2, ?3)",
+            params![adjustment.id, check.payroll_id, check.difference()],
+        )?;
+    }
+    tx.execute(
+        "UPDATE employees SET pay_rate = ?1 WHERE id = ?2",
+        params![new_rate.to_string(), employee_id],
+    )?;
+    audit::record(
+        &tx,
+        "employee",
+        employee_id as i64,
+        AuditAction::Update,
+        Some(&serde_json::json!({ "pay_rate": old_rate })),
+        Some(&serde_json::json!({ "pay_rate": new_rate, "retro_adjustment_id": adjustment.id })),
+    )?;
+    tx.commit()?;
+    Ok(adjustment)
+}
+
+pub fn get_adjustments_for_employee(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<Vec<RetroAdjustment>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, employee_id, new_rate, effective_date, amount, payroll_id
+        FROM retro_adjustments WHERE employee_id = ?1 ORDER BY id",
+    )?;
+    let adjustments = stmt
+        .query_map([employee_id], |row| {
+            Ok(RetroAdjustment {
+                id: row.get(0)?,
+                employee_id: row.get(1)?,
+                new_rate: row.get(2)?,
+                effective_date: row.get(3)?,
+                amount: row.get(4)?,
+                payroll_id: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(adjustments)
+}
+
+/// Retro already approved for each of the employee's checks, by payroll id.
+pub fn get_covered_amounts(
+    conn: &Connection,
+    employee_id: i32,
+) -> Result<HashMap<i64, f32>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT c.payroll_id, SUM(c.amount) FROM retro_adjustment_checks c
+        JOIN retro_adjustments a ON a.id = c.adjustment_id
+        WHERE a.employee_id = ?1
+        GROUP BY c.payroll_id",
+    )?;
+    let covered = stmt
+        .query_map([employee_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(covered)
+}
+
+/// Retro owed to the employee that hasn't been put on a check yet.
+pub fn get_pending_amount(conn: &Connection, employee_id: i32) -> Result<f32, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(amount), 0) FROM retro_adjustments
+        WHERE employee_id = ?1 AND payroll_id IS NULL",
+        [employee_id],
+        |row| row.get(0),
+    )
+}
+
+/// Marks the employee's pending retro as paid on `payroll_id`, oldest first, as far as
+/// `paid` covers it. Adjustments the check's retro lines didn't fully pay stay pending.
+pub fn mark_paid(
+    conn: &Connection,
+    employee_id: i32,
+    payroll_id: i64,
+    paid: f32,
+) -> Result<(), rusqlite::Error> {
+    let mut remaining = paid;
+    for adjustment in get_adjustments_for_employee(conn, employee_id)? {
+        if adjustment.payroll_id.is_some() {
+            continue;
+        }
+        if adjustment.amount > remaining + 0.005 {
+            break;
+        }
+        remaining -= adjustment.amount;
+        conn.execute(
+            "UPDATE retro_adjustments SET payroll_id = ?1 WHERE id = ?2",
+            params![payroll_id, adjustment.id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Puts retro paid on a deleted check back in the queue for the next pay run.
+pub fn release_check(conn: &Connection, payroll_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE retro_adjustments SET payroll_id = NULL WHERE payroll_id = ?1",
+        [payroll_id],
+    )?;
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct RetroForm {
+    pub new_rate: f32,
+    pub effective_date: String,
+    pub status: String,
+}
+
+pub fn render_retro(app: &mut PharmacyApp, ui: &mut Ui, employee: &Employee) {
+    let can_edit_payroll = app.can(Permission::EditPayroll);
+    let form = &mut app.retro_form;
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "Current rate {} ({})",
+            employee.pay_rate,
+            employee.pay_type.label()
+        ));
+        ui.label("New rate");
+        let speed = if employee.pay_type == PayType::SalaryAnnual {
+            100.0
+        } else {
+            0.05
+        };
+        ui.add(
+            egui::DragValue::new(&mut form.new_rate)
+                .speed(speed)
+                .prefix("$"),
+        )
+        .on_hover_text("In the same units as the current rate");
+        ui.label("Effective");
+        ui.add(
+            egui::TextEdit::singleline(&mut form.effective_date)
+                .hint_text("YYYY-MM-DD")
+                .desired_width(100.0),
+        );
+    });
+
+    if chrono::NaiveDate::parse_from_str(&form.effective_date, "%Y-%m-%d").is_ok()
+        && form.new_rate > 0.0
+    {
+        let covered = app.cache.query(
+            &app.conn,
+            format!("retro_covered:{}", employee.id),
+            |conn| get_covered_amounts(conn, employee.id),
+        );
+        let checks = covered.and_then(|covered| {
+            calculate_retro(
+                &CachedRepository::new(&app.conn, &mut app.cache),
+                employee.id,
+                form.new_rate,
+                &form.effective_date,
+                &covered,
+            )
+        });
+        match checks {
+            Ok(checks) if checks.is_empty() => {
+                ui.label("No checks on or after the effective date");
+            }
+            Ok(checks) => {
+                egui::Grid::new("retro_checks_grid")
+                    .striped(true)
+                    .spacing([10.0, 10.0])
+                    .show(ui, |ui| {
+                        ui.strong("Date of Pay");
+                        ui.strong("Hours");
+                        ui.strong("Paid");
+                        ui.strong("At New Rate");
+                        ui.strong("Difference");
+                        ui.end_row();
+                        for check in &checks {
+                            ui.label(&check.date_of_pay);
+                            ui.label(format!("{}", check.hours));
+                            ui.label(format!("{:.2}", check.paid));
+                            ui.label(format!("{:.2}", check.owed));
+                            ui.label(format!("{:.2}", check.difference()));
+                            ui.end_row();
+                        }
+                    });
+                let total = total_retro(&checks);
+                ui.strong(format!("Retro owed: {:.2}", total));
+                if ui
+                    .add_enabled(
+                        total > 0.0 && can_edit_payroll,
+                        egui::Button::new("Approve Retro Pay"),
+                    )
+                    .clicked()
+                {
+                    form.status = match approve_retro(
+                        &app.conn,
+                        employee.id,
+                        form.new_rate,
+                        &form.effective_date,
+                    ) {
+                        Ok(adjustment) => {
+                            // Rebuild the earnings lines so the retro line shows up on this run.
+                            app.earning_lines_employee_id = None;
+                            format!("{:.2} retro added to the next check", adjustment.amount)
+                        }
+                        Err(e) => format!("Error saving retro pay: {}", e),
+                    };
+                }
+            }
+            Err(e) => {
+                ui.label(format!("Error calculating retro pay: {}", e));
+            }
+        }
+    } else {
+        ui.label("Enter a new rate and an effective date as YYYY-MM-DD");
+    }
+
+    let adjustments = app.cache.query(
+        &app.conn,
+        format!("retro_adjustments:{}", employee.id),
+        |conn| get_adjustments_for_employee(conn, employee.id),
+    );
+    if let Ok(adjustments) = adjustments {
+        for adjustment in &adjustments {
+            ui.small(format!(
+                "{:.2} at ${:.2} from {} - {}",
+                adjustment.amount,
+                adjustment.new_rate,
+                adjustment.effective_date,
+                if adjustment.payroll_id.is_some() {
+                    "paid"
+                } else {
+                    "pending next check"
+                }
+            ));
+        }
+    }
+    if !app.retro_form.status.is_empty() {
+        ui.label(&app.retro_form.status);
+    }
+}
@@ -0,0 +1,590 @@
+use crate::app::app::PharmacyApp;
+use crate::app::database;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Columns sealed with the field key. They stay encrypted inside the database file and its
+/// backups, and the UI masks them until someone reveals them.
+pub const SENSITIVE_COLUMNS: &[(&str, &str)] = &[
+    ("employees", "address"),
+    ("employees", "phone"),
+    ("employees", "ssn"),
+];
+
+/// Sealed values look like `enc1:<key id>:<base64 of nonce and ciphertext>`.
+const SEALED_PREFIX: &str = "enc1:";
+pub const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// The field keys of an unlocked database, set once at startup and after a key rotation.
+static KEYRING: RwLock<Option<Keyring>> = RwLock::new(None);
+
+/// The unlocked database file and its passphrase, so background jobs can open their own
+/// keyed connection instead of working from a copy.
+static DATABASE: RwLock<Option<(PathBuf, String)>> = RwLock::new(None);
+
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Error deriving key: {}", e))?;
+    Ok(key)
+}
+
+pub fn random_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// AES-256-GCM with a fresh nonce, returned in front of the ciphertext.
+pub fn seal_bytes(key: &[u8; 32], plain: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plain)
+        .map_err(|_| "Error encrypting data")?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+pub fn open_bytes(key: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if sealed.len() < NONCE_LEN {
+        return Err("Encrypted data is too short".into());
+    }
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    Ok(cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Wrong key or damaged data")?)
+}
+
+/// Every field key the database has used. New values are sealed with the newest key; older
+/// keys are kept so backups taken before a rotation can still be read after a restore.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: BTreeMap<i64, [u8; 32]>,
+}
+
+impl Keyring {
+    /// Blank values stay blank so an empty field doesn't look filled in.
+    pub fn seal(&self, plain: &str) -> String {
+        match self.keys.last_key_value() {
+            Some((id, key)) if !plain.is_empty() => {
+                let sealed = seal_bytes(key, plain.as_bytes()).expect("Failed to encrypt field");
+                format!("{}{}:{}", SEALED_PREFIX, id, BASE64.encode(sealed))
+            }
+            _ => plain.to_string(),
+        }
+    }
+
+    /// Values written before the database was encrypted are passed through unchanged.
+    pub fn open(&self, value: &str) -> Result<String, Box<dyn Error>> {
+        let Some(rest) = value.strip_prefix(SEALED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let (id, data) = rest.split_once(':').ok_or("Malformed encrypted field")?;
+        let key = self
+            .keys
+            .get(&id.parse::<i64>()?)
+            .ok_or("Field key is not available")?;
+        Ok(String::from_utf8(open_bytes(key, &BASE64.decode(data)?)?)?)
+    }
+}
+
+pub fn install_keyring(keyring: Keyring) {
+    *KEYRING.write().expect("Keyring lock poisoned") = Some(keyring);
+}
+
+pub fn install_database(path: &Path, passphrase: &str) {
+    *DATABASE.write().expect("Database lock poisoned") =
+        Some((path.to_path_buf(), passphrase.to_string()));
+}
+
+/// A new keyed connection to the unlocked database, for a worker thread. A `Connection`
+/// can't be shared with the UI thread, and copying the database out would leave it
+/// unencrypted on disk.
+pub fn open_worker_connection() -> Result<Connection, Box<dyn Error>> {
+    let database = DATABASE.read().expect("Database lock poisoned").clone();
+    let (path, passphrase) = database.ok_or("The database is locked")?;
+    open_encrypted(&path, &passphrase)
+}
+
+/// Seals a sensitive column value for writing. Without an unlocked keyring the value is
+/// written as is.
+pub fn seal_field(plain: &str) -> String {
+    match KEYRING.read().expect("Keyring lock poisoned").as_ref() {
+        Some(keyring) => keyring.seal(plain),
+        None => plain.to_string(),
+    }
+}
+
+/// Opens a sensitive column value read from the database.
+pub fn open_field(value: &str) -> String {
+    let keyring = KEYRING.read().expect("Keyring lock poisoned");
+    let opened = match keyring.as_ref() {
+        Some(keyring) => keyring.open(value),
+        None => Keyring::default().open(value),
+    };
+    opened.unwrap_or_else(|_| "[encrypted]".to_string())
+}
+
+pub fn mask(value: &str) -> String {
+    if value.is_empty() {
+        String::new()
+    } else {
+        "\u{2022}".repeat(8)
+    }
+}
+
+fn load_keyring(conn: &Connection, passphrase: &str) -> Result<Keyring, Box<dyn Error>> {
+    let mut stmt = conn.prepare("SELECT id, salt, wrapped_key FROM field_keys ORDER BY id")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Vec<u8>>(1)?,
+                row.get::<_, Vec<u8>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut keyring = Keyring::default();
+    for (id, salt, wrapped) in rows {
+        let kek = derive_key(passphrase, &salt)?;
+        let key = open_bytes(&kek, &wrapped).map_err(|_| "Wrong passphrase")?;
+        keyring
+            .keys
+            .insert(id, key.try_into().map_err(|_| "Damaged field key")?);
+    }
+    Ok(keyring)
+}
+
+/// Wraps `key` under the passphrase and stores it, as a new row when `id` is `None`.
+fn store_key(
+    conn: &Connection,
+    id: Option<i64>,
+    key: &[u8; 32],
+    passphrase: &str,
+) -> Result<i64, Box<dyn Error>> {
+    let salt = random_salt();
+    let wrapped = seal_bytes(&derive_key(passphrase, &salt)?, key)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO field_keys (id, salt, wrapped_key) VALUES (?1, ?2, ?3)",
+        params![id, salt.to_vec(), wrapped],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn new_field_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Re-seals every sensitive column with the keyring's newest key.
+fn reseal_columns(conn: &Connection, keyring: &Keyring) -> Result<(), Box<dyn Error>> {
+    for (table, column) in SENSITIVE_COLUMNS {
+        let values = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, {} FROM {} WHERE {} IS NOT NULL",
+                column, table, column
+            ))?;
+            let values = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            values
+        };
+        for (id, value) in values {
+            conn.execute(
+                &format!("UPDATE {} SET {} = ?1 WHERE id = ?2", table, column),
+                params![keyring.seal(&keyring.open(&value)?), id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the field keys with the admin passphrase. The first unlock creates a key and seals
+/// the values that were stored in plain text.
+pub fn unlock(conn: &Connection, passphrase: &str) -> Result<Keyring, Box<dyn Error>> {
+    let mut keyring = load_keyring(conn, passphrase)?;
+    if keyring.keys.is_empty() {
+        let tx = conn.unchecked_transaction()?;
+        let key = new_field_key();
+        let id = store_key(&tx, None, &key, passphrase)?;
+        keyring.keys.insert(id, key);
+        reseal_columns(&tx, &keyring)?;
+        tx.commit()?;
+    }
+    Ok(keyring)
+}
+
+/// Key rotation: adds a new field key, re-seals every sensitive value with it, wraps all
+/// field keys under the new passphrase and finally re-encrypts the database file itself.
+pub fn rotate_keys(
+    conn: &Connection,
+    current: &str,
+    new_passphrase: &str,
+) -> Result<Keyring, Box<dyn Error>> {
+    if new_passphrase.len() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Use at least {} characters", MIN_PASSPHRASE_LEN).into());
+    }
+    let mut keyring = load_keyring(conn, current)?;
+    if keyring.keys.is_empty() {
+        return Err("The database has not been unlocked yet".into());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    for (id, key) in &keyring.keys {
+        store_key(&tx, Some(*id), key, new_passphrase)?;
+    }
+    let key = new_field_key();
+    let id = store_key(&tx, None, &key, new_passphrase)?;
+    keyring.keys.insert(id, key);
+    reseal_columns(&tx, &keyring)?;
+    tx.commit()?;
+
+    conn.pragma_update(None, "rekey", new_passphrase)?;
+    Ok(keyring)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DbState {
+    New,
+    Plaintext,
+    Encrypted,
+}
+
+pub fn db_state(path: &Path) -> DbState {
+    if !path.exists() {
+        return DbState::New;
+    }
+    let readable = Connection::open(path).and_then(|conn| {
+        conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+            row.get::<_, i64>(0)
+        })
+    });
+    match readable {
+        Ok(_) => DbState::Plaintext,
+        Err(_) => DbState::Encrypted,
+    }
+}
+
+/// Opens the database file with SQLCipher, creating it when it doesn't exist yet.
+pub fn open_encrypted(path: &Path, passphrase: &str) -> Result<Connection, Box<dyn Error>> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "key", passphrase)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|_| "Wrong passphrase")?;
+    Ok(conn)
+}
+
+/// One-time migration of an existing plain-text database file to SQLCipher.
+pub fn encrypt_plaintext(path: &Path, passphrase: &str) -> Result<(), Box<dyn Error>> {
+    let encrypted = path.with_extension("db.encrypting");
+    if encrypted.exists() {
+        fs::remove_file(&encrypted)?;
+    }
+    {
+        let conn = Connection::open(path)?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            params![encrypted.to_string_lossy(), passphrase],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        conn.execute("DETACH DATABASE encrypted", [])?;
+    }
+    fs::rename(&encrypted, path)?;
+    Ok(())
+}
+
+/// Writes a copy of the live database to a new SQLCipher file keyed with `passphrase`.
+/// SQLCipher refuses the online backup API between differently keyed databases, so this
+/// goes through `sqlcipher_export`. Sensitive columns stay sealed in the copy.
+pub fn export_encrypted(
+    conn: &Connection,
+    path: &Path,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    conn.execute(
+        "ATTACH DATABASE ?1 AS copy KEY ?2",
+        params![path.to_string_lossy(), passphrase],
+    )?;
+    let exported = conn.query_row("SELECT sqlcipher_export('copy')", [], |_| Ok(()));
+    conn.execute("DETACH DATABASE copy", [])?;
+    exported?;
+    Ok(())
+}
+
+/// Append-only logs that a restore must not roll back, so they are never replaced.
+const KEPT_TABLES: [&str; 2] = ["audit_log", "sensitive_access_log"];
+
+/// Replaces every table in the live database with the contents of the database file at
+/// `path`, opened with `passphrase`, in one transaction. The file itself is only read. The
+/// live field keys are kept: they include every key the copy could have been sealed with,
+/// already wrapped under today's passphrase. The audit and access logs are kept too, so
+/// nothing logged since the copy was taken is lost.
+pub fn import_database(
+    conn: &Connection,
+    path: &Path,
+    passphrase: &str,
+) -> Result<(), Box<dyn Error>> {
+    let live_keys = {
+        let mut stmt = conn.prepare("SELECT id, salt, wrapped_key FROM field_keys")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows
+    };
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS restored KEY ?2",
+        params![path.to_string_lossy(), passphrase],
+    )?;
+    let import = || -> Result<(), Box<dyn Error>> {
+        let tx = conn.unchecked_transaction()?;
+        let tables = {
+            let mut stmt = tx.prepare(
+                "SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+            )?;
+            let tables = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            tables
+        };
+        for table in tables
+            .iter()
+            .filter(|table| !KEPT_TABLES.contains(&table.as_str()))
+        {
+            tx.execute(&format!("DROP TABLE main.\"{}\"", table), [])?;
+        }
+        // Tables come before their indexes. Triggers are left to initialize_tables.
+        let schema = {
+            let mut stmt = tx.prepare(
+                "SELECT type, name, tbl_name, sql FROM restored.sqlite_master
+                WHERE type IN ('table', 'index') AND sql IS NOT NULL
+                    AND name NOT LIKE 'sqlite_%'
+                ORDER BY type = 'index'",
+            )?;
+            let schema = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            schema
+        };
+        for (kind, name, table, sql) in schema {
+            if KEPT_TABLES.contains(&table.as_str()) {
+                continue;
+            }
+            tx.execute_batch(&sql)?;
+            if kind == "table" {
+                tx.execute(
+                    &format!(
+                        "INSERT INTO main.\"{}\" SELECT * FROM restored.\"{}\"",
+                        name, name
+                    ),
+                    [],
+                )?;
+            }
+        }
+        // Tables added since the copy was taken are created again.
+        database::initialize_tables(&tx)?;
+        if !live_keys.is_empty() {
+            tx.execute("DELETE FROM field_keys", [])?;
+            for (id, salt, wrapped) in &live_keys {
+                tx.execute(
+                    "INSERT INTO field_keys (id, salt, wrapped_key) VALUES (?1, ?2, ?3)",
+                    params![id, salt, wrapped],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    };
+    let imported = import();
+    conn.execute("DETACH DATABASE restored", [])?;
+    imported
+}
+
+/// Shown before the main window: unlocks the database, or sets the passphrase the first time
+/// and encrypts an existing plain-text file.
+pub struct UnlockApp {
+    db_path: PathBuf,
+    state: DbState,
+    passphrase: String,
+    confirm: String,
+    status: String,
+    app: Option<PharmacyApp>,
+}
+
+impl Default for UnlockApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnlockApp {
+    pub fn new() -> Self {
+        let db_path = database::get_db_path();
+        let state = db_state(&db_path);
+        Self {
+            db_path,
+            state,
+            passphrase: String::new(),
+            confirm: String::new(),
+            status: String::new(),
+            app: None,
+        }
+    }
+
+    fn open(&self) -> Result<PharmacyApp, Box<dyn Error>> {
+        if self.state != DbState::Encrypted {
+            if self.passphrase.len() < MIN_PASSPHRASE_LEN {
+                return Err(format!("Use at least {} characters", MIN_PASSPHRASE_LEN).into());
+            }
+            if self.passphrase != self.confirm {
+                return Err("Passphrases don't match".into());
+            }
+        }
+        if self.state == DbState::Plaintext {
+            encrypt_plaintext(&self.db_path, &self.passphrase)?;
+        }
+        let conn = open_encrypted(&self.db_path, &self.passphrase)?;
+        database::initialize_tables(&conn)?;
+        install_keyring(unlock(&conn, &self.passphrase)?);
+        install_database(&self.db_path, &self.passphrase);
+        Ok(PharmacyApp::new(conn))
+    }
+}
+
+impl eframe::App for UnlockApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(app) = &mut self.app {
+            app.update(ctx, frame);
+            return;
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Med Arts Payroll");
+            ui.label(match self.state {
+                DbState::Encrypted => "Enter the admin passphrase to unlock the payroll database.",
+                DbState::Plaintext => {
+                    "Choose an admin passphrase. The existing payroll database will be encrypted with it."
+                }
+                DbState::New => "Choose an admin passphrase for the new payroll database.",
+            });
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "The passphrase cannot be recovered. Without it the data and encrypted backups are lost.",
+            );
+            let mut submit = false;
+            egui::Grid::new("unlock_grid")
+                .spacing([10.0, 10.0])
+                .show(ui, |ui| {
+                    ui.label("Passphrase");
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut self.passphrase).password(true),
+                    );
+                    submit |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+                    if self.state != DbState::Encrypted {
+                        ui.label("Confirm");
+                        let response =
+                            ui.add(egui::TextEdit::singleline(&mut self.confirm).password(true));
+                        submit |=
+                            response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        ui.end_row();
+                    }
+                });
+            if ui.button("Unlock").clicked() || submit {
+                match self.open() {
+                    Ok(app) => {
+                        self.passphrase.clear();
+                        self.confirm.clear();
+                        self.app = Some(app);
+                    }
+                    Err(e) => self.status = e.to_string(),
+                }
+            }
+            if !self.status.is_empty() {
+                ui.colored_label(egui::Color32::RED, &self.status);
+            }
+        });
+    }
+}
+
+#[derive(Default)]
+pub struct PassphraseForm {
+    pub current: String,
+    pub new_passphrase: String,
+    pub confirm: String,
+    pub status: String,
+}
+
+pub fn render_key_rotation(app: &mut PharmacyApp, ui: &mut egui::Ui) {
+    ui.label("Changing the passphrase also replaces the field key and re-encrypts the database.");
+    let form = &mut app.passphrase_form;
+    egui::Grid::new("passphrase_grid")
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.label("Current passphrase");
+            ui.add(egui::TextEdit::singleline(&mut form.current).password(true));
+            ui.end_row();
+            ui.label("New passphrase");
+            ui.add(egui::TextEdit::singleline(&mut form.new_passphrase).password(true));
+            ui.end_row();
+            ui.label("Confirm");
+            ui.add(egui::TextEdit::singleline(&mut form.confirm).password(true));
+            ui.end_row();
+        });
+    if ui.button("Rotate Keys").clicked() {
+        form.status = if form.new_passphrase != form.confirm {
+            "New passphrases don't match".to_string()
+        } else {
+            match rotate_keys(&app.conn, &form.current, &form.new_passphrase) {
+                Ok(keyring) => {
+                    install_keyring(keyring);
+                    install_database(&database::get_db_path(), &form.new_passphrase);
+                    form.current.clear();
+                    form.new_passphrase.clear();
+                    form.confirm.clear();
+                    "Keys rotated. Use the new passphrase from now on.".to_string()
+                }
+                Err(e) => format!("Key rotation failed: {}", e),
+            }
+        };
+    }
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::app::app::PharmacyApp;
+use crate::app::users;
+use egui::Ui;
+use std::time::{Duration, Instant};
+
+/// Locks the screen of a signed-in user after a stretch without input. Only the panels are
+/// hidden; every form on `PharmacyApp` keeps its contents until the session is unlocked, or
+/// is cleared if someone else signs in instead.
+pub struct SessionLock {
+    pub last_activity: Instant,
+    pub locked: bool,
+    /// Password or PIN typed on the lock screen.
+    pub secret: String,
+    /// New PIN typed in Settings.
+    pub pin: String,
+    pub status: String,
+}
+
+impl Default for SessionLock {
+    fn default() -> Self {
+        Self {
+            last_activity: Instant::now(),
+            locked: false,
+            secret: String::new(),
+            pin: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+/// The idle period before locking, or `None` when locking is turned off.
+pub fn idle_limit(minutes: u32) -> Option<Duration> {
+    (minutes > 0).then(|| Duration::from_secs(u64::from(minutes) * 60))
+}
+
+pub fn is_idle(last_activity: Instant, now: Instant, minutes: u32) -> bool {
+    idle_limit(minutes).is_some_and(|limit| now.saturating_duration_since(last_activity) >= limit)
+}
+
+/// Called at the start of every frame. egui only repaints on input, so a repaint is
+/// scheduled for when the timeout would run out.
+pub fn track_activity(app: &mut PharmacyApp, ctx: &egui::Context) {
+    let now = Instant::now();
+    let session = &mut app.session;
+    if app.current_user.is_none() || session.locked {
+        return;
+    }
+    if ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving()) {
+        session.last_activity = now;
+    }
+    let Some(limit) = idle_limit(app.ui_settings.idle_lock_minutes) else {
+        return;
+    };
+    if is_idle(
+        session.last_activity,
+        now,
+        app.ui_settings.idle_lock_minutes,
+    ) {
+        session.locked = true;
+        session.secret.clear();
+        session.status.clear();
+    } else {
+        ctx.request_repaint_after(limit - now.duration_since(session.last_activity));
+    }
+}
+
+/// Shown instead of the panels while the session is locked.
+pub fn render_lock_screen(app: &mut PharmacyApp, ui: &mut Ui) {
+    let Some(user) = app.current_user.clone() else {
+        return;
+    };
+    let session = &mut app.session;
+    ui.heading("Session Locked");
+    ui.label(format!(
+        "Locked after {} minutes without activity. Enter the password or PIN for {} to continue.",
+        app.ui_settings.idle_lock_minutes, user.username
+    ));
+    let response = ui.add(
+        egui::TextEdit::singleline(&mut session.secret)
+            .password(true)
+            .hint_text("Password or PIN"),
+    );
+    let submit = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    let mut switch_user = false;
+    ui.horizontal(|ui| {
+        if ui.button("Unlock").clicked() || submit {
+            if users::verify_unlock(&app.conn, user.id, &session.secret) {
+                *session = SessionLock::default();
+            } else {
+                session.status = "Wrong password or PIN".to_string();
+            }
+            session.secret.clear();
+        }
+        if ui.button("Switch User").clicked() {
+            switch_user = true;
+        }
+    });
+    if switch_user {
+        app.sign_out();
+        return;
+    }
+    if !app.session.status.is_empty() {
+        ui.label(&app.session.status);
+    }
+}
+
+/// Lets the signed-in user set the PIN that unlocks their session.
+pub fn render_pin_settings(app: &mut PharmacyApp, ui: &mut Ui) {
+    let Some(user_id) = app.current_user.as_ref().map(|user| user.id) else {
+        return;
+    };
+    let session = &mut app.session;
+    ui.horizontal(|ui| {
+        ui.label("Unlock PIN");
+        ui.add(
+            egui::TextEdit::singleline(&mut session.pin)
+                .password(true)
+                .hint_text("4-8 digits")
+                .desired_width(100.0),
+        );
+        if ui.button("Set PIN").clicked() {
+            session.status = match users::set_pin(&app.conn, user_id, &session.pin) {
+                Ok(()) if session.pin.is_empty() => "PIN removed".to_string(),
+                Ok(()) => "PIN saved".to_string(),
+                Err(e) => e,
+            };
+            session.pin.clear();
+        }
+    });
+    if !session.status.is_empty() {
+        ui.label(&session.status);
+    }
+}
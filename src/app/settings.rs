@@ -1,4 +1,7 @@
 use crate::app::app::PharmacyApp;
+use crate::app::companies;
+use crate::app::session;
+use crate::app::users::Permission;
 use eframe::egui;
 use serde::Deserialize;
 use serde::Serialize;
@@ -10,6 +13,13 @@ pub struct UiSettings {
     pub font_size: f32,
     pub button_size: [f32; 2],
     pub text_input_size: [f32; 2],
+    /// Minutes without input before the session locks; 0 turns locking off.
+    #[serde(default = "default_idle_lock_minutes")]
+    pub idle_lock_minutes: u32,
+}
+
+fn default_idle_lock_minutes() -> u32 {
+    10
 }
 
 impl Default for UiSettings {
@@ -18,6 +28,7 @@ impl Default for UiSettings {
             font_size: 16.0,
             button_size: [200.0, 40.0],
             text_input_size: [200.0, 40.0],
+            idle_lock_minutes: default_idle_lock_minutes(),
         }
     }
 }
@@ -57,13 +68,14 @@ impl UiSettings {
     }
 }
 
-fn get_config_dir() -> PathBuf {
+pub fn get_config_dir() -> PathBuf {
     let mut config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
     config_dir.push("pharmacy_app");
     config_dir
 }
 
 pub fn render_settings(app: &mut PharmacyApp, ui: &mut egui::Ui) {
+    let can_manage_system = app.can(Permission::ManageSystem);
     ui.heading("Settings Panel");
     ui.label("Manage App settings here");
     ui.separator();
@@ -77,6 +89,20 @@ pub fn render_settings(app: &mut PharmacyApp, ui: &mut egui::Ui) {
 
     ui.separator();
 
+    // The idle lock protects every user's session, so only administrators can change it.
+    ui.label("Lock After Inactivity");
+    settings_modified |= ui
+        .add_enabled(
+            can_manage_system,
+            egui::Slider::new(&mut app.ui_settings.idle_lock_minutes, 0..=120)
+                .text("Minutes (0 = never)"),
+        )
+        .on_disabled_hover_text("Your role doesn't allow this")
+        .changed();
+    session::render_pin_settings(app, ui);
+
+    ui.separator();
+
     if ui.button("Save Settings").clicked() || settings_modified {
         if let Err(e) = app.ui_settings.save() {
             ui.colored_label(egui::Color32::RED, format!("Error saving settings: {}", e));
@@ -86,9 +112,17 @@ pub fn render_settings(app: &mut PharmacyApp, ui: &mut egui::Ui) {
     }
 
     if ui.button("Reset to Defaults").clicked() {
+        let idle_lock_minutes = app.ui_settings.idle_lock_minutes;
         app.ui_settings = UiSettings::default();
+        if !can_manage_system {
+            app.ui_settings.idle_lock_minutes = idle_lock_minutes;
+        }
         if let Err(e) = app.ui_settings.save() {
             ui.colored_label(egui::Color32::RED, format!("Error saving settings: {}", e));
         }
     }
+
+    ui.add_space(20.0);
+    ui.separator();
+    egui::ScrollArea::vertical().show(ui, |ui| companies::render_employer_profile(app, ui));
 }
@@ -0,0 +1,195 @@
+use crate::app::app::PharmacyApp;
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use crate::app::database;
+use crate::app::employee::Employee;
+use crate::app::repository::{EmployeeRepository, SqliteRepository};
+use crate::app::security;
+use crate::app::users;
+use crate::app::users::Permission;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+
+/// Numbers the SSA has voided after they were published in advertising.
+const VOIDED_SSNS: [&str; 2] = ["078-05-1120", "219-09-9999"];
+
+/// Formats an SSN as `AAA-GG-SSSS` after checking it against the SSA's structural rules:
+/// no area 000, 666 or 900-999, no group 00 and no serial 0000.
+pub fn validate_ssn(value: &str) -> Result<String, String> {
+    let digits: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
+    let extra = value
+        .chars()
+        .any(|c| !c.is_ascii_digit() && c != '-' && c != ' ');
+    if digits.len() != 9 || extra {
+        return Err("SSN must be 9 digits".to_string());
+    }
+    let (area, rest) = digits.split_at(3);
+    let (group, serial) = rest.split_at(2);
+    if area == "000" || area == "666" || area.starts_with('9') {
+        return Err(format!("{} is not a valid SSN area number", area));
+    }
+    if group == "00" {
+        return Err("SSN group number can't be 00".to_string());
+    }
+    if serial == "0000" {
+        return Err("SSN serial number can't be 0000".to_string());
+    }
+    let ssn = format!("{}-{}-{}", area, group, serial);
+    if VOIDED_SSNS.contains(&ssn.as_str()) {
+        return Err(format!("{} was voided by the SSA", ssn));
+    }
+    Ok(ssn)
+}
+
+/// Shows only the last four digits, as on pay stubs.
+pub fn mask_ssn(ssn: &str) -> String {
+    if ssn.len() < 4 {
+        return "Not on file".to_string();
+    }
+    format!("***-**-{}", &ssn[ssn.len() - 4..])
+}
+
+/// Another employee already using `ssn`. The column is sealed with a fresh nonce per value,
+/// so the comparison happens after opening each one.
+pub fn find_duplicate(
+    conn: &Connection,
+    ssn: &str,
+    exclude_id: Option<i32>,
+) -> Result<Option<Employee>, rusqlite::Error> {
+    Ok(SqliteRepository::new(conn)
+        .employees()?
+        .into_iter()
+        .find(|employee| Some(employee.id) != exclude_id && employee.ssn == ssn))
+}
+
+/// Validates, checks for duplicates and stores the sealed SSN.
+pub fn save_ssn(conn: &Connection, employee_id: i32, value: &str) -> Result<String, String> {
+    let ssn = validate_ssn(value)?;
+    match find_duplicate(conn, &ssn, Some(employee_id)) {
+        Ok(Some(other)) => return Err(format!("SSN is already on file for {}", other.name)),
+        Ok(None) => {}
+        Err(e) => return Err(format!("Error checking for duplicates: {}", e)),
+    }
+    let previous = database::get_employee_by_id(conn, employee_id)
+        .map(|employee| employee.ssn)
+        .unwrap_or_default();
+    conn.execute(
+        "UPDATE employees SET ssn = ?1 WHERE id = ?2",
+        params![security::seal_field(&ssn), employee_id],
+    )
+    .map_err(|e| format!("Error saving SSN: {}", e))?;
+    let masked = |ssn: &str| serde_json::json!({ "ssn": if ssn.is_empty() { String::new() } else { mask_ssn(ssn) } });
+    audit::record(
+        conn,
+        "employee",
+        employee_id as i64,
+        AuditAction::Update,
+        Some(&masked(&previous)),
+        Some(&masked(&ssn)),
+    )
+    .map_err(|e| format!("Error writing audit log: {}", e))?;
+    Ok(ssn)
+}
+
+/// Every unmasking of a sensitive field is recorded with who did it.
+pub fn log_reveal(
+    conn: &Connection,
+    employee_id: i32,
+    field: &str,
+    username: &str,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO sensitive_access_log (accessed_at, employee_id, field, username)
+        VALUES (?1, ?2, ?3, ?4)",
+        params![
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            employee_id,
+            field,
+            username
+        ],
+    )?;
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct SsnForm {
+    pub employee_id: Option<i32>,
+    pub ssn: String,
+    /// Revealing needs the user's password again.
+    pub password: String,
+    pub revealed: bool,
+    pub status: String,
+}
+
+pub fn render_ssn(app: &mut PharmacyApp, ui: &mut Ui, employee: &Employee) {
+    let can_edit_employees = app.can(Permission::EditEmployees);
+    let can_reveal = app.can(Permission::RevealSensitive);
+    let username = app.current_username();
+    let form = &mut app.ssn_form;
+    if form.employee_id != Some(employee.id) {
+        *form = SsnForm {
+            employee_id: Some(employee.id),
+            ..Default::default()
+        };
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("SSN");
+        if form.revealed {
+            ui.label(&employee.ssn);
+            if ui.button("Hide").clicked() {
+                form.revealed = false;
+            }
+        } else {
+            ui.label(mask_ssn(&employee.ssn));
+            if !employee.ssn.is_empty() && can_reveal {
+                ui.add(
+                    egui::TextEdit::singleline(&mut form.password)
+                        .password(true)
+                        .hint_text("Your password")
+                        .desired_width(150.0),
+                );
+                if ui.button("Reveal").clicked() {
+                    let confirmed =
+                        users::authenticate(&app.conn, &username, &form.password).is_some();
+                    form.status = if !confirmed {
+                        "Wrong password".to_string()
+                    } else {
+                        match log_reveal(&app.conn, employee.id, "ssn", &username) {
+                            Ok(()) => {
+                                form.revealed = true;
+                                String::new()
+                            }
+                            Err(e) => format!("Error logging reveal: {}", e),
+                        }
+                    };
+                    form.password.clear();
+                }
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut form.ssn)
+                .hint_text("123-45-6789")
+                .desired_width(120.0),
+        );
+        if users::guarded_button(ui, can_edit_employees, "Save SSN").clicked() {
+            form.status = match save_ssn(&app.conn, employee.id, &form.ssn) {
+                Ok(ssn) => {
+                    form.ssn.clear();
+                    app.selected_employee = Some(Employee {
+                        ssn,
+                        ..employee.clone()
+                    });
+                    "SSN saved".to_string()
+                }
+                Err(e) => e,
+            };
+        }
+    });
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+}
@@ -74,7 +74,7 @@ impl SerializableStockData {
         //let fetched_at = Instant::now() - Duration::from_secs(age);
         let fetched_at = Instant::now()
             .checked_sub(Duration::from_secs(safe_age))
-            .unwrap_or_else(|| Instant::now());
+            .unwrap_or_else(Instant::now);
 
         StockData {
             quote: self.quote.clone(),
@@ -84,9 +84,15 @@ impl SerializableStockData {
     }
 }
 
+impl Default for StockService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StockService {
     pub fn instance() -> &'static StockService {
-        STOCK_SERVICE.get_or_init(|| StockService::new())
+        STOCK_SERVICE.get_or_init(StockService::new)
     }
 
     pub fn new() -> Self {
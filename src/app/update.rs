@@ -8,6 +8,7 @@ use serde_json::Value;
 
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct ReleaseInfo {
     tag_name: Option<String>,
@@ -0,0 +1,548 @@
+use crate::app::app::{ActivePanel, PharmacyApp};
+use crate::app::audit;
+use crate::app::audit::AuditAction;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use egui::Ui;
+use rusqlite::params;
+use rusqlite::Connection;
+
+const MIN_PASSWORD_LEN: usize = 8;
+const PIN_DIGITS: std::ops::RangeInclusive<usize> = 4..=8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Owner,
+    PayrollAdmin,
+    Manager,
+    ReadOnly,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [
+        Role::Owner,
+        Role::PayrollAdmin,
+        Role::Manager,
+        Role::ReadOnly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::PayrollAdmin => "payroll_admin",
+            Role::Manager => "manager",
+            Role::ReadOnly => "read_only",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "owner" => Role::Owner,
+            "payroll_admin" => Role::PayrollAdmin,
+            "manager" => Role::Manager,
+            _ => Role::ReadOnly,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::Owner => "Owner",
+            Role::PayrollAdmin => "Payroll Admin",
+            Role::Manager => "Manager",
+            Role::ReadOnly => "Read-only",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            Role::Owner => true,
+            Role::PayrollAdmin => matches!(
+                permission,
+                Permission::ViewPayroll
+                    | Permission::EditPayroll
+                    | Permission::EditEmployees
+                    | Permission::RevealSensitive
+            ),
+            // Managers look after staff records, licenses and time off, but not pay.
+            Role::Manager => matches!(permission, Permission::EditEmployees),
+            Role::ReadOnly => matches!(permission, Permission::ViewPayroll),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    /// Wages, paychecks, tax and benefit reports.
+    ViewPayroll,
+    /// Posting and deleting checks, deductions, garnishments, retirement and ledger setup.
+    EditPayroll,
+    /// Employee records, licenses, CE, time off and locations.
+    EditEmployees,
+    /// Unmasking SSNs.
+    RevealSensitive,
+    /// Companies, backups, encryption keys and updates.
+    ManageSystem,
+    ManageUsers,
+}
+
+/// The permission needed to open each panel.
+pub fn view_permission(panel: ActivePanel) -> Option<Permission> {
+    match panel {
+        ActivePanel::Admin => Some(Permission::ManageUsers),
+        ActivePanel::Payroll
+        | ActivePanel::Retirement
+        | ActivePanel::Garnishments
+        | ActivePanel::Companies
+        | ActivePanel::Ledger => Some(Permission::ViewPayroll),
+        ActivePanel::Home
+        | ActivePanel::Employees
+        | ActivePanel::Settings
+        | ActivePanel::Licenses
+        | ActivePanel::ContinuingEducation
+        | ActivePanel::TimeOff
+        | ActivePanel::Locations => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    pub active: bool,
+}
+
+impl PharmacyApp {
+    /// Whether the signed-in user holds `permission`. Nobody does before signing in.
+    pub fn can(&self, permission: Permission) -> bool {
+        self.current_user
+            .as_ref()
+            .is_some_and(|user| user.role.can(permission))
+    }
+
+    pub fn current_username(&self) -> String {
+        self.current_user
+            .as_ref()
+            .map_or(String::new(), |user| user.username.clone())
+    }
+}
+
+/// A button that is greyed out for roles without the permission behind it.
+pub fn guarded_button(ui: &mut Ui, allowed: bool, text: &str) -> egui::Response {
+    ui.add_enabled(allowed, egui::Button::new(text))
+        .on_disabled_hover_text("Your role doesn't allow this")
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Error hashing password: {}", e))
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok()
+    })
+}
+
+fn validate_password(password: &str) -> Result<(), String> {
+    if password.len() < MIN_PASSWORD_LEN {
+        return Err(format!(
+            "Passwords need at least {} characters",
+            MIN_PASSWORD_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn add_user(
+    conn: &Connection,
+    username: &str,
+    password: &str,
+    role: Role,
+) -> Result<i64, String> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err("Enter a username".to_string());
+    }
+    validate_password(password)?;
+    let taken: bool = conn
+        .query_row(
+            "SELECT COUNT(*) > 0 FROM users WHERE username = ?1 COLLATE NOCASE",
+            [username],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if taken {
+        return Err(format!("{} is already taken", username));
+    }
+    conn.execute(
+        "INSERT INTO users (username, password_hash, role, active) VALUES (?1, ?2, ?3, 1)",
+        params![username, hash_password(password)?, role.as_str()],
+    )
+    .map_err(|e| format!("Error adding user: {}", e))?;
+    let id = conn.last_insert_rowid();
+    let after = serde_json::json!({ "username": username, "role": role.as_str(), "active": true });
+    audit::record(conn, "user", id, AuditAction::Insert, None, Some(&after))
+        .map_err(|e| format!("Error writing audit log: {}", e))?;
+    Ok(id)
+}
+
+pub fn get_users(conn: &Connection) -> Result<Vec<User>, rusqlite::Error> {
+    let mut stmt =
+        conn.prepare("SELECT id, username, role, active FROM users ORDER BY username")?;
+    let users = stmt
+        .query_map([], |row| {
+            Ok(User {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                role: Role::parse(&row.get::<_, String>(2)?),
+                active: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(users)
+}
+
+/// The active user with these credentials.
+pub fn authenticate(conn: &Connection, username: &str, password: &str) -> Option<User> {
+    let (id, hash): (i64, String) = conn
+        .query_row(
+            "SELECT id, password_hash FROM users WHERE username = ?1 COLLATE NOCASE AND active = 1",
+            [username.trim()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok()?;
+    if !verify_password(&hash, password) {
+        return None;
+    }
+    get_users(conn).ok()?.into_iter().find(|user| user.id == id)
+}
+
+/// There must always be an active owner left to manage users.
+fn check_owner_remains(
+    conn: &Connection,
+    user_id: i64,
+    role: Role,
+    active: bool,
+) -> Result<(), String> {
+    let other_owners: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM users WHERE role = 'owner' AND active = 1 AND id != ?1",
+            [user_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if other_owners == 0 && !(role == Role::Owner && active) {
+        return Err("There must be at least one active owner".to_string());
+    }
+    Ok(())
+}
+
+pub fn update_user(
+    conn: &Connection,
+    user_id: i64,
+    role: Role,
+    active: bool,
+) -> Result<(), String> {
+    check_owner_remains(conn, user_id, role, active)?;
+    let before = get_users(conn)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|user| user.id == user_id)
+        .map(|user| serde_json::json!({ "role": user.role.as_str(), "active": user.active }));
+    conn.execute(
+        "UPDATE users SET role = ?1, active = ?2 WHERE id = ?3",
+        params![role.as_str(), active, user_id],
+    )
+    .map_err(|e| format!("Error saving user: {}", e))?;
+    let after = serde_json::json!({ "role": role.as_str(), "active": active });
+    audit::record(
+        conn,
+        "user",
+        user_id,
+        AuditAction::Update,
+        before.as_ref(),
+        Some(&after),
+    )
+    .map_err(|e| format!("Error writing audit log: {}", e))?;
+    Ok(())
+}
+
+pub fn set_password(conn: &Connection, user_id: i64, password: &str) -> Result<(), String> {
+    validate_password(password)?;
+    conn.execute(
+        "UPDATE users SET password_hash = ?1 WHERE id = ?2",
+        params![hash_password(password)?, user_id],
+    )
+    .map_err(|e| format!("Error saving password: {}", e))?;
+    // Only the fact of the change is logged, never the hash.
+    let after = serde_json::json!({ "password": "changed" });
+    audit::record(
+        conn,
+        "user",
+        user_id,
+        AuditAction::Update,
+        None,
+        Some(&after),
+    )
+    .map_err(|e| format!("Error writing audit log: {}", e))?;
+    Ok(())
+}
+
+/// A short numeric PIN that can unlock an idle session but never signs anyone in.
+/// An empty PIN removes it.
+pub fn set_pin(conn: &Connection, user_id: i64, pin: &str) -> Result<(), String> {
+    let pin_hash = if pin.is_empty() {
+        String::new()
+    } else if PIN_DIGITS.contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit()) {
+        hash_password(pin)?
+    } else {
+        return Err(format!(
+            "PINs are {} to {} digits",
+            PIN_DIGITS.start(),
+            PIN_DIGITS.end()
+        ));
+    };
+    conn.execute(
+        "UPDATE users SET pin_hash = ?1 WHERE id = ?2",
+        params![&pin_hash, user_id],
+    )
+    .map_err(|e| format!("Error saving PIN: {}", e))?;
+    let after = serde_json::json!({ "pin": if pin.is_empty() { "removed" } else { "changed" } });
+    audit::record(
+        conn,
+        "user",
+        user_id,
+        AuditAction::Update,
+        None,
+        Some(&after),
+    )
+    .map_err(|e| format!("Error writing audit log: {}", e))?;
+    Ok(())
+}
+
+/// Whether `secret` is the password or PIN of an active user, for unlocking their session.
+pub fn verify_unlock(conn: &Connection, user_id: i64, secret: &str) -> bool {
+    let hashes: Option<(String, String)> = conn
+        .query_row(
+            "SELECT password_hash, pin_hash FROM users WHERE id = ?1 AND active = 1",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok();
+    hashes.is_some_and(|(password_hash, pin_hash)| {
+        verify_password(&password_hash, secret)
+            || (!pin_hash.is_empty() && verify_password(&pin_hash, secret))
+    })
+}
+
+#[derive(Default)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+    pub confirm: String,
+    pub status: String,
+}
+
+/// Shown instead of the panels until someone signs in. The first run sets up the owner.
+pub fn render_login(app: &mut PharmacyApp, ui: &mut Ui) {
+    let first_run = get_users(&app.conn).is_ok_and(|users| users.is_empty());
+    let form = &mut app.login_form;
+    ui.heading(if first_run {
+        "Create the Owner Account"
+    } else {
+        "Sign In"
+    });
+    if first_run {
+        ui.label("The owner manages users and can do everything else in the app.");
+    }
+    let mut submit = false;
+    egui::Grid::new("login_grid")
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.label("Username");
+            ui.text_edit_singleline(&mut form.username);
+            ui.end_row();
+            ui.label("Password");
+            let response = ui.add(egui::TextEdit::singleline(&mut form.password).password(true));
+            submit |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            ui.end_row();
+            if first_run {
+                ui.label("Confirm");
+                ui.add(egui::TextEdit::singleline(&mut form.confirm).password(true));
+                ui.end_row();
+            }
+        });
+
+    if ui
+        .button(if first_run { "Create Owner" } else { "Sign In" })
+        .clicked()
+        || submit
+    {
+        let result = if first_run {
+            if form.password != form.confirm {
+                Err("Passwords don't match".to_string())
+            } else {
+                add_user(&app.conn, &form.username, &form.password, Role::Owner).and_then(|_| {
+                    authenticate(&app.conn, &form.username, &form.password)
+                        .ok_or_else(|| "Error signing in".to_string())
+                })
+            }
+        } else {
+            authenticate(&app.conn, &form.username, &form.password)
+                .ok_or_else(|| "Wrong username or password".to_string())
+        };
+        match result {
+            Ok(user) => {
+                *form = LoginForm::default();
+                audit::set_actor(&user.username);
+                app.current_user = Some(user);
+                app.active_panel = ActivePanel::Home;
+            }
+            Err(e) => {
+                form.password.clear();
+                form.status = e;
+            }
+        }
+    }
+    if !form.status.is_empty() {
+        ui.colored_label(egui::Color32::RED, &form.status);
+    }
+}
+
+#[derive(Default)]
+pub struct UserForm {
+    pub username: String,
+    pub password: String,
+    pub role: Option<Role>,
+    /// User whose password is being reset.
+    pub reset_id: Option<i64>,
+    pub reset_password: String,
+    pub status: String,
+}
+
+pub fn render_users(app: &mut PharmacyApp, ui: &mut Ui) {
+    ui.heading("Users");
+    let users = match get_users(&app.conn) {
+        Ok(users) => users,
+        Err(e) => {
+            ui.label(format!("Error loading users: {}", e));
+            return;
+        }
+    };
+    let current_id = app.current_user.as_ref().map(|user| user.id);
+    let form = &mut app.user_form;
+
+    egui::Grid::new("users_grid")
+        .striped(true)
+        .spacing([10.0, 10.0])
+        .show(ui, |ui| {
+            ui.strong("Username");
+            ui.strong("Role");
+            ui.strong("Active");
+            ui.strong("");
+            ui.end_row();
+            for user in &users {
+                ui.label(&user.username);
+                let mut role = user.role;
+                egui::ComboBox::from_id_salt(("user_role", user.id))
+                    .selected_text(role.label())
+                    .show_ui(ui, |ui| {
+                        for option in Role::ALL {
+                            ui.selectable_value(&mut role, option, option.label());
+                        }
+                    });
+                let mut active = user.active;
+                ui.checkbox(&mut active, "");
+                if role != user.role || active != user.active {
+                    form.status = match update_user(&app.conn, user.id, role, active) {
+                        Ok(()) => {
+                            if Some(user.id) == current_id {
+                                app.current_user = Some(User {
+                                    role,
+                                    active,
+                                    ..user.clone()
+                                });
+                            }
+                            format!("{} updated", user.username)
+                        }
+                        Err(e) => e,
+                    };
+                }
+                if ui.button("Reset Password").clicked() {
+                    form.reset_id = Some(user.id);
+                    form.reset_password.clear();
+                }
+                ui.end_row();
+            }
+        });
+
+    if let Some(reset_id) = form.reset_id {
+        let name = users
+            .iter()
+            .find(|user| user.id == reset_id)
+            .map_or(String::new(), |user| user.username.clone());
+        ui.horizontal(|ui| {
+            ui.label(format!("New password for {}", name));
+            ui.add(egui::TextEdit::singleline(&mut form.reset_password).password(true));
+            if ui.button("Save").clicked() {
+                form.status = match set_password(&app.conn, reset_id, &form.reset_password) {
+                    Ok(()) => {
+                        form.reset_id = None;
+                        format!("Password changed for {}", name)
+                    }
+                    Err(e) => e,
+                };
+                form.reset_password.clear();
+            }
+            if ui.button("Cancel").clicked() {
+                form.reset_id = None;
+                form.reset_password.clear();
+            }
+        });
+    }
+
+    ui.separator();
+    ui.label("Add User");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut form.username)
+                .hint_text("Username")
+                .desired_width(150.0),
+        );
+        ui.add(
+            egui::TextEdit::singleline(&mut form.password)
+                .password(true)
+                .hint_text("Password")
+                .desired_width(150.0),
+        );
+        let role = form.role.get_or_insert(Role::ReadOnly);
+        egui::ComboBox::from_id_salt("new_user_role")
+            .selected_text(role.label())
+            .show_ui(ui, |ui| {
+                for option in Role::ALL {
+                    ui.selectable_value(role, option, option.label());
+                }
+            });
+        if ui.button("Add").clicked() {
+            let role = *role;
+            form.status = match add_user(&app.conn, &form.username, &form.password, role) {
+                Ok(_) => {
+                    let added = format!("Added {} as {}", form.username.trim(), role.label());
+                    *form = UserForm::default();
+                    added
+                }
+                Err(e) => e,
+            };
+        }
+    });
+    if !form.status.is_empty() {
+        ui.label(&form.status);
+    }
+}
@@ -2,44 +2,58 @@ pub mod app;
 
 #[cfg(test)]
 mod tests {
+    use crate::app::app;
     use crate::app::app::PharmacyApp;
+    use crate::app::audit;
+    use crate::app::audit::{AuditAction, AuditFilter};
+    use crate::app::backup;
+    use crate::app::backup::BackupSettings;
+    use crate::app::cache::{CachedRepository, DataCache};
+    use crate::app::companies;
+    use crate::app::companies::Company;
+    use crate::app::continuing_education;
     use crate::app::database;
-    use crate::app::employee::Employee;
+    use crate::app::deductions;
+    use crate::app::deductions::{DeductionElection, DeductionMethod, DeductionType};
+    use crate::app::deposits;
+    use crate::app::deposits::{DepositKind, DepositSchedule, Liability};
+    use crate::app::earnings;
+    use crate::app::earnings::{EarningLine, EarningType};
+    use crate::app::employee::{Employee, PayType};
+    use crate::app::garnishments;
+    use crate::app::garnishments::{GarnishmentOrder, GarnishmentType};
+    use crate::app::import;
+    use crate::app::import::{HistoryField, ImportField};
+    use crate::app::jobs;
+    use crate::app::ledger;
+    use crate::app::ledger::ExportFormat;
+    use crate::app::licenses;
+    use crate::app::locations;
+    use crate::app::locations::Location;
     use crate::app::payroll;
+    use crate::app::payroll::{ImportedAs, OffCycleReason};
+    use crate::app::pto;
+    use crate::app::repository::{
+        EmployeeRepository, InMemoryRepository, PayrollRepository, SqliteRepository,
+    };
+    use crate::app::retirement;
+    use crate::app::retirement::{ContributionFormula, EmployerContributionRule, RetirementPlan};
+    use crate::app::retro;
+    use crate::app::security;
+    use crate::app::security::DbState;
+    use crate::app::session;
+    use crate::app::ssn;
+    use crate::app::users;
+    use crate::app::users::{Permission, Role};
+    use chrono::Datelike;
+    use chrono::NaiveDate;
     use rusqlite::Connection;
     use rusqlite::Result;
+    use std::time::{Duration, Instant};
 
     fn setup_test_db() -> Result<Connection> {
         let conn = Connection::open_in_memory().expect("Failed to create test database");
-        conn.execute(
-            "CREATE TABLE 
-            employees (
-                id INTEGER PRIMARY KEY,
-                name TEXT,
-                position TEXT,
-                address TEXT,
-                city TEXT,
-                state TEXT,
-                phone TEXT,
-                filing_status TEXT,
-                dependents TEXT,
-                pay_rate TEXT)",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE  payroll (
-            id INTEGER PRIMARY KEY,
-            employee_id INTEGER,
-            hours_worked REAL,
-            date_of_pay TEXT,
-            gross REAL,
-            withholding REAL,
-            social_security REAL,   
-            net REAL,
-            roth_ira REAL, FOREIGN KEY(employee_id) REFERENCES employees(id))",
-            [],
-        )?;
+        database::initialize_tables(&conn)?;
 
         conn.execute(
             "INSERT INTO employees (
@@ -198,6 +212,9 @@ mod tests {
         let result = database::get_payroll_by_id(&conn, 1).expect("Failed to select payroll entry");
         let hours = result[0].hours_worked;
         assert_eq!(hours, 8.0, "Payroll entry should have been selected");
+        assert_eq!(result[0].date_of_pay, "2023-07-01");
+        assert_eq!(result[0].net, 625.0);
+        assert!(result[0].imported.is_none());
     }
 
     #[test]
@@ -287,6 +304,7 @@ mod tests {
                     filing_status: row.get(7)?,
                     dependents: row.get(8)?,
                     pay_rate: row.get(9)?,
+                    ..Default::default()
                 })
             })
             .unwrap()
@@ -303,8 +321,7 @@ mod tests {
     fn create_test_app() -> PharmacyApp {
         let conn = setup_test_db().expect("Failed to create test database");
 
-        let mut app = PharmacyApp::new();
-        app.conn = conn;
+        let mut app = PharmacyApp::new(conn);
 
         app.employees = vec![];
         app
@@ -312,7 +329,7 @@ mod tests {
 
     #[test]
     fn test_get_all_employees() {
-        let mut app = create_test_app();
+        let app = create_test_app();
         // Call get_all_employees()
         let employees = database::get_all_employees(&app.conn).expect("Failed to fetch employees");
 
@@ -326,5 +343,2210 @@ mod tests {
         for emp in &employees {
             println!("Name: {}, Position: {}", emp.name, emp.position);
         }
+
+        // A row that can't be read is an error, not an employee quietly left off the list.
+        app.conn
+            .execute("INSERT INTO employees (id, name) VALUES (3, NULL)", [])
+            .unwrap();
+        assert!(database::get_all_employees(&app.conn).is_err());
+    }
+
+    fn add_test_license(conn: &Connection, employee_id: i32, number: &str, expiry_date: &str) {
+        licenses::License {
+            id: 0,
+            employee_id,
+            license_type: "Pharmacist License".to_string(),
+            license_number: number.to_string(),
+            issuing_state: "TX".to_string(),
+            issue_date: "2020-01-01".to_string(),
+            expiry_date: expiry_date.to_string(),
+        }
+        .save_to_db(conn)
+        .expect("Failed to save license");
+    }
+
+    #[test]
+    fn test_license_alert_thresholds() {
+        assert_eq!(licenses::alert_threshold(0), Some(30));
+        assert_eq!(licenses::alert_threshold(30), Some(30));
+        assert_eq!(licenses::alert_threshold(31), Some(60));
+        assert_eq!(licenses::alert_threshold(90), Some(90));
+        assert_eq!(licenses::alert_threshold(91), None);
+        assert_eq!(licenses::alert_threshold(-1), None);
+    }
+
+    #[test]
+    fn test_license_expiry_alerts_and_expired_report() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        add_test_license(&conn, 1, "RPH-1", "2025-01-20");
+        add_test_license(&conn, 1, "RPH-2", "2025-03-15");
+        add_test_license(&conn, 2, "RPH-3", "2026-01-01");
+        add_test_license(&conn, 2, "RPH-4", "2024-12-01");
+
+        let alerts = licenses::get_expiry_alerts(&conn, today).expect("Failed to get alerts");
+        let numbers: Vec<&str> = alerts
+            .iter()
+            .map(|a| a.license.license_number.as_str())
+            .collect();
+        assert_eq!(numbers, vec!["RPH-1", "RPH-2"]);
+        assert_eq!(alerts[0].threshold(), Some(30));
+        assert_eq!(alerts[1].threshold(), Some(90));
+
+        let expired = licenses::get_expired_licenses(&conn, today).expect("Failed to get expired");
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].employee_name, "Ryan");
+        assert_eq!(expired[0].days_left, -31);
+    }
+
+    #[test]
+    fn test_license_documents_with_the_same_name_are_kept_apart() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let license_id = licenses::License {
+            id: 0,
+            employee_id: 1,
+            license_type: "Pharmacist License".to_string(),
+            license_number: "RPH-9".to_string(),
+            issuing_state: "TX".to_string(),
+            issue_date: "2024-01-01".to_string(),
+            expiry_date: "2026-12-31".to_string(),
+        }
+        .save_to_db(&conn)
+        .expect("Failed to save license");
+        let dir = std::env::temp_dir().join(format!("med_arts_licenses_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("scan.pdf");
+        std::fs::write(&source, b"scan").unwrap();
+        let documents_dir = dir.join("documents");
+        licenses::attach_document(&conn, license_id, &source, &documents_dir).unwrap();
+        licenses::attach_document(&conn, license_id, &source, &documents_dir).unwrap();
+
+        let documents = licenses::get_documents_for_license(&conn, license_id).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_ne!(documents[0].stored_path, documents[1].stored_path);
+        assert!(documents.iter().all(|document| document
+            .stored_path
+            .starts_with(&*documents_dir.to_string_lossy())));
+        licenses::delete_license(&conn, license_id).unwrap();
+        for document in &documents {
+            assert!(!std::path::Path::new(&document.stored_path).exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn add_test_ce_record(
+        conn: &Connection,
+        employee_id: i32,
+        topic: &str,
+        hours: f32,
+        date: &str,
+    ) {
+        continuing_education::CeRecord {
+            id: 0,
+            employee_id,
+            course_name: format!("{} course", topic),
+            provider: "ACPE".to_string(),
+            hours,
+            topic: topic.to_string(),
+            completion_date: date.to_string(),
+            certificate_path: String::new(),
+        }
+        .save_to_db(conn)
+        .expect("Failed to save CE record");
+    }
+
+    #[test]
+    fn test_ce_rollup_counts_only_hours_inside_the_license_cycle() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        add_test_license(&conn, 1, "RPH-1", "2026-12-31");
+        add_test_ce_record(&conn, 1, "General", 20.0, "2023-06-01");
+        add_test_ce_record(&conn, 1, "Pharmacy Law", 2.0, "2024-02-01");
+        add_test_ce_record(&conn, 1, "General", 15.0, "2019-06-01");
+
+        let rollups = continuing_education::get_ce_rollups(&conn).expect("Failed to roll up CE");
+        assert_eq!(rollups.len(), 1);
+        let rollup = &rollups[0];
+        let total = rollup
+            .topics
+            .iter()
+            .find(|t| t.topic == continuing_education::TOTAL_TOPIC)
+            .unwrap();
+        assert_eq!(total.completed, 22.0);
+        // 30 total required, 22 earned; law is covered but patient safety still needs an hour.
+        assert_eq!(rollup.hours_short(), 8.0);
+    }
+
+    #[test]
+    fn test_ce_rollup_topic_shortfall_exceeds_total_shortfall() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        add_test_license(&conn, 2, "RPH-2", "2026-12-31");
+        add_test_ce_record(&conn, 2, "General", 30.0, "2024-03-01");
+
+        let rollups = continuing_education::get_ce_rollups(&conn).expect("Failed to roll up CE");
+        assert_eq!(rollups[0].hours_short(), 2.0);
+    }
+
+    #[test]
+    fn test_ce_certificates_are_kept_apart_and_requirements_seed_once() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let dir = std::env::temp_dir().join(format!("med_arts_ce_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("certificate.pdf");
+        std::fs::write(&source, b"certificate").unwrap();
+        let documents_dir = dir.join("documents");
+        let record = continuing_education::CeRecord {
+            id: 0,
+            employee_id: 1,
+            course_name: "Law update".to_string(),
+            provider: "ACPE".to_string(),
+            hours: 2.0,
+            topic: "Pharmacy Law".to_string(),
+            completion_date: "2026-03-01".to_string(),
+            certificate_path: String::new(),
+        };
+        for _ in 0..2 {
+            continuing_education::add_ce_record(&conn, &record, Some(&source), &documents_dir)
+                .unwrap();
+        }
+
+        // Same file name twice is stored twice, and deleting a record removes only its copy.
+        let records = continuing_education::get_ce_records_for_employee(&conn, 1).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_ne!(records[0].certificate_path, records[1].certificate_path);
+        for record in &records {
+            assert!(record
+                .certificate_path
+                .starts_with(&*documents_dir.to_string_lossy()));
+            continuing_education::delete_ce_record(&conn, record.id).unwrap();
+            assert!(!std::path::Path::new(&record.certificate_path).exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let requirements = continuing_education::get_requirements(&conn).unwrap();
+        for requirement in &requirements {
+            continuing_education::delete_requirement(&conn, requirement.id).unwrap();
+        }
+        database::initialize_tables(&conn).unwrap();
+        assert!(continuing_education::get_requirements(&conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pto_accrual_respects_annual_and_balance_caps() {
+        let policy = pto::state_sick_leave_presets()
+            .into_iter()
+            .find(|p| p.name.starts_with("Arizona"))
+            .unwrap();
+        assert_eq!(policy.accrual_for_check(60.0, 0.0, 0.0), 2.0);
+        assert_eq!(policy.accrual_for_check(60.0, 0.0, 39.0), 1.0);
+        assert_eq!(policy.accrual_for_check(60.0, 0.0, 40.0), 0.0);
+
+        let capped = pto::PtoPolicy {
+            balance_cap: Some(80.0),
+            ..pto::state_sick_leave_presets().remove(0)
+        };
+        assert_eq!(capped.accrual_for_check(90.0, 79.5, 0.0), 0.5);
+    }
+
+    #[test]
+    fn test_pto_balance_applies_carryover_at_year_end() {
+        let policy = pto::PtoPolicy {
+            carryover_limit: Some(40.0),
+            ..pto::PtoPolicy::default()
+        };
+        let entry = |date: &str, hours: f32| pto::PtoLedgerEntry {
+            id: 0,
+            employee_id: 1,
+            policy_id: 1,
+            entry_date: date.to_string(),
+            hours,
+            kind: "accrual".to_string(),
+            payroll_id: None,
+        };
+        let entries = vec![entry("2024-06-07", 60.0), entry("2025-01-10", 4.0)];
+
+        let dec = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let jan = NaiveDate::from_ymd_opt(2025, 1, 2).unwrap();
+        let feb = NaiveDate::from_ymd_opt(2025, 2, 1).unwrap();
+        assert_eq!(pto::balance_as_of(&entries, &policy, dec), 60.0);
+        assert_eq!(pto::balance_as_of(&entries, &policy, jan), 40.0);
+        assert_eq!(pto::balance_as_of(&entries, &policy, feb), 44.0);
+    }
+
+    #[test]
+    fn test_posting_and_deleting_payroll_updates_pto_balance() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let policy = pto::PtoPolicy {
+            name: "Vacation".to_string(),
+            accrual_hours: 4.0,
+            ..pto::PtoPolicy::default()
+        };
+        let policy_id = policy.save_to_db(&conn).expect("Failed to save policy");
+        pto::set_enrollment(&conn, 1, policy_id, true).expect("Failed to enroll");
+        let policy = pto::PtoPolicy {
+            id: policy_id,
+            ..policy
+        };
+        let as_of = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+
+        pto::post_payroll(&conn, 1, 100, "2024-03-01", 40.0, 0.0, Some(policy_id))
+            .expect("Failed to post payroll");
+        pto::post_payroll(&conn, 1, 101, "2024-03-08", 32.0, 3.0, Some(policy_id))
+            .expect("Failed to post payroll");
+        assert_eq!(pto::get_balance(&conn, 1, &policy, as_of).unwrap(), 5.0);
+
+        database::delete_payroll_entry(&conn, 101).expect("Failed to delete payroll entry");
+        assert_eq!(pto::get_balance(&conn, 1, &policy, as_of).unwrap(), 4.0);
+
+        for (start, hours) in [("2024-03-11", 2.0), ("2024-03-12", 8.0)] {
+            pto::PtoRequest {
+                id: 0,
+                employee_id: 1,
+                policy_id,
+                start_date: start.to_string(),
+                end_date: start.to_string(),
+                hours,
+                status: pto::STATUS_APPROVED.to_string(),
+                note: String::new(),
+                payroll_id: None,
+            }
+            .save_to_db(&conn)
+            .unwrap();
+        }
+        let check = |pto_hours: f32| payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2024-03-15".to_string(),
+            employee_id: 1,
+            hours_worked: 40.0,
+            gross: 800.0,
+            withholding: 0.0,
+            roth_ira: 0.0,
+            social_security: 0.0,
+            net: 800.0,
+            pto_hours,
+            off_cycle_reason: None,
+            imported: None,
+        };
+        // Only the request the check's 2 hours pay for is linked to it.
+        let payroll_id = check(2.0)
+            .save_check(&conn, &[], &[], &[], Some(policy_id))
+            .unwrap();
+        let linked: Vec<Option<i64>> = pto::get_requests(&conn)
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|request| request.payroll_id)
+            .collect();
+        assert_eq!(linked, [Some(payroll_id), None]);
+        assert_eq!(pto::get_balance(&conn, 1, &policy, as_of).unwrap(), 6.0);
+
+        // More hours than the balance roll the whole check back.
+        let checks = database::get_payroll_by_id(&conn, 1).unwrap().len();
+        let err = check(10.0)
+            .save_check(&conn, &[], &[], &[], Some(policy_id))
+            .unwrap_err();
+        assert!(err.to_string().contains("Insufficient Vacation balance"));
+        assert!(check(2.0).save_check(&conn, &[], &[], &[], None).is_err());
+        assert_eq!(database::get_payroll_by_id(&conn, 1).unwrap().len(), checks);
+        assert_eq!(pto::get_balance(&conn, 1, &policy, as_of).unwrap(), 6.0);
+
+        conn.execute("UPDATE pto_policies SET allow_negative = 1", [])
+            .unwrap();
+        check(10.0)
+            .save_check(&conn, &[], &[], &[], Some(policy_id))
+            .unwrap();
+        let policy = pto::get_policies(&conn).unwrap().remove(0);
+        assert_eq!(pto::get_balance(&conn, 1, &policy, as_of).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_calculate_check_uses_flat_rate_for_supplemental_wages() {
+        let mut bonus = EarningLine::new(EarningType::Bonus, 0.0, 20.0);
+        bonus.amount = 500.0;
+        let lines = vec![
+            EarningLine::new(EarningType::Regular, 40.0, 20.0),
+            EarningLine::new(EarningType::Overtime, 2.0, 20.0),
+            bonus,
+        ];
+
+        let totals = payroll::calculate_check(&lines, &[]);
+        assert_eq!(totals.gross, 1360.0);
+        assert_eq!(totals.regular_fit_wages, 860.0);
+        assert_eq!(totals.supplemental_fit_wages, 500.0);
+        assert_eq!(totals.withholding, 860.0 * 0.2 + 500.0 * 0.22);
+        assert_eq!(totals.social_security, 1360.0 * 0.075);
+    }
+
+    #[test]
+    fn test_off_cycle_bonus_and_commission_checks_use_supplemental_rate() {
+        let lines = vec![EarningLine::new(EarningType::Regular, 40.0, 25.0)];
+        let withholding = |reason| {
+            payroll::calculate_check(&payroll::lines_for_check(&lines, reason), &[]).withholding
+        };
+        assert_eq!(withholding(None), 1000.0 * 0.2);
+        assert_eq!(withholding(Some(OffCycleReason::Correction)), 1000.0 * 0.2);
+        assert_eq!(withholding(Some(OffCycleReason::Bonus)), 1000.0 * 0.22);
+        assert_eq!(withholding(Some(OffCycleReason::Commission)), 1000.0 * 0.22);
+        assert_eq!(
+            OffCycleReason::parse(OffCycleReason::Commission.as_str()),
+            OffCycleReason::Commission
+        );
+    }
+
+    #[test]
+    fn test_save_check_with_earnings_lines() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let lines = vec![
+            EarningLine::new(EarningType::Regular, 32.0, 25.0),
+            EarningLine::new(EarningType::Holiday, 8.0, 25.0),
+        ];
+        let totals = payroll::calculate_check(&lines, &[]);
+        let entry = payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2024-07-05".to_string(),
+            employee_id: 2,
+            hours_worked: 40.0,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        };
+        let payroll_id = entry
+            .save_check(&conn, &lines, &[], &[], None)
+            .expect("Failed to save check");
+
+        let saved = earnings::get_earnings_for_check(&conn, payroll_id).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[1].earning_type, EarningType::Holiday);
+        assert_eq!(saved[1].amount, 300.0);
+        assert_eq!(
+            database::get_payroll_by_id(&conn, 2).unwrap()[0].gross,
+            1100.0
+        );
+
+        database::delete_payroll_entry(&conn, payroll_id).expect("Failed to delete check");
+        assert!(earnings::get_earnings_for_check(&conn, payroll_id)
+            .unwrap()
+            .is_empty());
+    }
+
+    fn election(deduction_type: DeductionType, amount: f32) -> DeductionElection {
+        DeductionElection {
+            id: 0,
+            employee_id: 1,
+            deduction_type,
+            method: DeductionMethod::FixedAmount,
+            amount,
+            catch_up_eligible: false,
+            family_coverage: false,
+            active: true,
+        }
+    }
+
+    #[test]
+    fn test_deductions_capped_at_annual_limit_with_catch_up() {
+        let mut traditional = election(DeductionType::Traditional401k, 1000.0);
+        traditional.catch_up_eligible = true;
+        let roth = election(DeductionType::Roth401k, 500.0);
+        let mut ytd = std::collections::HashMap::new();
+        ytd.insert("401k".to_string(), 32_000.0);
+
+        // 24,500 + 8,000 catch-up leaves 500 for the year, shared by both 401(k) elections.
+        let lines = deductions::calculate_deductions(&[traditional, roth], 3000.0, &ytd);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].code, "401k");
+        assert_eq!(lines[0].amount, 500.0);
+    }
+
+    #[test]
+    fn test_pre_tax_deductions_reduce_taxable_wages() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        election(DeductionType::Traditional401k, 100.0)
+            .save_to_db(&conn)
+            .expect("Failed to save election");
+        election(DeductionType::Section125Health, 50.0)
+            .save_to_db(&conn)
+            .expect("Failed to save election");
+        election(DeductionType::RothIra, 25.0)
+            .save_to_db(&conn)
+            .expect("Failed to save election");
+
+        let lines = vec![EarningLine::new(EarningType::Regular, 40.0, 25.0)];
+        let priced =
+            payroll::price_check(&SqliteRepository::new(&conn), 1, "2026-03-06", &lines, None)
+                .unwrap();
+        assert_eq!(priced.deductions.len(), 3);
+
+        let totals = priced.totals;
+        assert_eq!(totals.regular_fit_wages, 850.0);
+        assert_eq!(totals.fica_wages, 950.0);
+        assert_eq!(totals.total_deductions, 175.0);
+        assert_eq!(totals.net, 1000.0 - 850.0 * 0.2 - 950.0 * 0.075 - 175.0);
+    }
+
+    #[test]
+    fn test_employer_match_caps_deferral_at_percent_of_pay() {
+        let rules = vec![
+            EmployerContributionRule {
+                id: 1,
+                name: "Safe harbor".to_string(),
+                ..Default::default()
+            },
+            EmployerContributionRule {
+                id: 2,
+                name: "SIMPLE 2%".to_string(),
+                plan: RetirementPlan::SimpleIra,
+                formula: ContributionFormula::Nonelective,
+                rate: 2.0,
+                ..Default::default()
+            },
+        ];
+        let deductions = deductions::calculate_deductions(
+            &[election(DeductionType::Traditional401k, 100.0)],
+            2000.0,
+            &std::collections::HashMap::new(),
+        );
+
+        // 100% match on deferrals up to 3% of 2,000 pays 60, not the full 100 deferred.
+        let contributions = retirement::calculate_contributions(&rules, 2000.0, &deductions);
+        assert_eq!(contributions.len(), 2);
+        assert_eq!(contributions[0].amount, 60.0);
+        assert_eq!(contributions[1].plan, RetirementPlan::SimpleIra);
+        assert_eq!(contributions[1].amount, 40.0);
+    }
+
+    #[test]
+    fn test_remittance_lists_deferrals_and_employer_contributions() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let rule = EmployerContributionRule {
+            name: "Match".to_string(),
+            ..Default::default()
+        };
+        let rule_id = rule.save_to_db(&conn).expect("Failed to save rule");
+        retirement::set_enrollment(&conn, 1, rule_id, true).unwrap();
+
+        let lines = vec![EarningLine::new(EarningType::Regular, 40.0, 25.0)];
+        let deduction_lines = deductions::calculate_deductions(
+            &[election(DeductionType::Traditional401k, 50.0)],
+            1000.0,
+            &std::collections::HashMap::new(),
+        );
+        let contributions = retirement::calculate_contributions(
+            &retirement::get_rules_for_employee(&conn, 1).unwrap(),
+            1000.0,
+            &deduction_lines,
+        );
+        let totals = payroll::calculate_check(&lines, &deduction_lines);
+        let entry = payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-01-09".to_string(),
+            employee_id: 1,
+            hours_worked: 40.0,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        };
+        entry
+            .save_check(&conn, &lines, &deduction_lines, &contributions, None)
+            .expect("Failed to save check");
+
+        let plan_totals = retirement::get_plan_totals(
+            &conn,
+            RetirementPlan::Plan401k,
+            "2026-01-01",
+            "2026-12-31",
+            None,
+        )
+        .unwrap();
+        assert_eq!(plan_totals.len(), 1);
+        assert_eq!(plan_totals[0].employee_deferrals, 50.0);
+        assert_eq!(plan_totals[0].employer_contributions, 30.0);
+
+        let csv = retirement::remittance_csv(RetirementPlan::Plan401k, "2026-01-09", &plan_totals)
+            .unwrap();
+        assert!(csv.lines().nth(1).unwrap().ends_with(",50.00,30.00,80.00"));
+    }
+
+    #[test]
+    fn test_contribution_rules_apply_only_to_enrolled_employees_of_their_company() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let first = companies::add_company(
+            &conn,
+            &Company {
+                legal_name: "Med Arts Pharmacy Inc".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let second = companies::add_company(
+            &conn,
+            &Company {
+                legal_name: "Westside Drug LLC".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE employees SET company_id = ?1 WHERE id = 2",
+            [second],
+        )
+        .unwrap();
+
+        let first_rule = EmployerContributionRule {
+            name: "Safe harbor".to_string(),
+            company_id: Some(first),
+            ..Default::default()
+        }
+        .save_to_db(&conn)
+        .unwrap();
+        let second_rule = EmployerContributionRule {
+            name: "SIMPLE 2%".to_string(),
+            plan: RetirementPlan::SimpleIra,
+            formula: ContributionFormula::Nonelective,
+            rate: 2.0,
+            company_id: Some(second),
+            ..Default::default()
+        }
+        .save_to_db(&conn)
+        .unwrap();
+        assert_eq!(retirement::get_rules(&conn, Some(first)).unwrap().len(), 1);
+        assert_eq!(retirement::get_rules(&conn, Some(second)).unwrap().len(), 1);
+
+        // Nobody is covered until enrolled, and enrolling in another company's rule does nothing.
+        assert!(retirement::get_rules_for_employee(&conn, 1)
+            .unwrap()
+            .is_empty());
+        retirement::set_enrollment(&conn, 1, first_rule, true).unwrap();
+        retirement::set_enrollment(&conn, 1, second_rule, true).unwrap();
+        retirement::set_enrollment(&conn, 2, second_rule, true).unwrap();
+
+        let rules = retirement::get_rules_for_employee(&conn, 1).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, first_rule);
+        let contributions = retirement::calculate_contributions(&rules, 1000.0, &[]);
+        assert!(contributions.is_empty());
+
+        let rules = retirement::get_rules_for_employee(&conn, 2).unwrap();
+        assert_eq!(rules.len(), 1);
+        let contributions = retirement::calculate_contributions(&rules, 1000.0, &[]);
+        assert_eq!(contributions.len(), 1);
+        assert_eq!(contributions[0].rule_id, second_rule);
+        assert_eq!(contributions[0].amount, 20.0);
+
+        retirement::set_enrollment(&conn, 2, second_rule, false).unwrap();
+        assert!(retirement::get_rules_for_employee(&conn, 2)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_garnishments_follow_priority_and_ccpa_limits() {
+        let support = GarnishmentOrder {
+            id: 3,
+            employee_id: 1,
+            agency: "State SDU".to_string(),
+            amount_per_check: 350.0,
+            supports_other_family: true,
+            ..Default::default()
+        };
+        let creditor = GarnishmentOrder {
+            id: 1,
+            employee_id: 1,
+            garnishment_type: GarnishmentType::Creditor,
+            agency: "Acme Collections".to_string(),
+            amount_per_check: 200.0,
+            total_owed: Some(1000.0),
+            ..Default::default()
+        };
+        let mut paid = std::collections::HashMap::new();
+        paid.insert(1, 950.0);
+
+        // Support is held to 50% of 800 disposable; the creditor's 25% limit is already used up.
+        let lines = garnishments::calculate_garnishments(
+            &[creditor.clone(), support.clone()],
+            &paid,
+            800.0,
+            800.0,
+        );
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].code, "garnishment:3");
+        assert_eq!(lines[0].amount, 350.0);
+
+        // Without support the creditor could take 25% of 800, but only 50 is left on the balance.
+        let lines = garnishments::calculate_garnishments(&[creditor], &paid, 800.0, 800.0);
+        assert_eq!(lines[0].amount, 50.0);
+        let lines = garnishments::calculate_garnishments(
+            &[GarnishmentOrder {
+                garnishment_type: GarnishmentType::Creditor,
+                amount_per_check: 500.0,
+                ..Default::default()
+            }],
+            &paid,
+            250.0,
+            250.0,
+        );
+        assert_eq!(lines[0].amount, 250.0 - 30.0 * 7.25);
+    }
+
+    #[test]
+    fn test_garnishment_balance_and_agency_remittance() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let order = GarnishmentOrder {
+            employee_id: 1,
+            garnishment_type: GarnishmentType::Creditor,
+            agency: "Acme Collections".to_string(),
+            case_number: "CV-1".to_string(),
+            amount_per_check: 100.0,
+            total_owed: Some(150.0),
+            ..Default::default()
+        };
+        order.save_to_db(&conn).expect("Failed to save order");
+
+        let lines = vec![EarningLine::new(EarningType::Regular, 40.0, 25.0)];
+        for date in ["2026-02-06", "2026-02-13"] {
+            let priced =
+                payroll::price_check(&SqliteRepository::new(&conn), 1, date, &lines, None).unwrap();
+            let (garnishment_lines, totals) = (priced.deductions, priced.totals);
+            let entry = payroll::PayrollEntry {
+                id: 0,
+                date_of_pay: date.to_string(),
+                employee_id: 1,
+                hours_worked: 40.0,
+                gross: totals.gross,
+                withholding: totals.withholding,
+                roth_ira: 0.0,
+                social_security: totals.social_security,
+                net: totals.net,
+                pto_hours: 0.0,
+                off_cycle_reason: None,
+                imported: None,
+            };
+            entry
+                .save_check(&conn, &lines, &garnishment_lines, &[], None)
+                .expect("Failed to save check");
+        }
+
+        let orders = garnishments::get_orders_for_employee(&conn, 1).unwrap();
+        let paid = garnishments::get_amounts_paid(&conn).unwrap();
+        assert_eq!(
+            garnishments::remaining_balance(&orders[0], &paid),
+            Some(0.0)
+        );
+
+        let remittances =
+            garnishments::get_agency_remittances(&conn, "2026-02-01", "2026-02-28", None).unwrap();
+        assert_eq!(remittances.len(), 1);
+        assert_eq!(remittances[0].agency, "Acme Collections");
+        assert_eq!(remittances[0].amount, 150.0);
+    }
+
+    #[test]
+    fn test_off_cycle_check_does_not_use_up_regular_friday() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let lines = vec![EarningLine::new(EarningType::Regular, 16.0, 20.0)];
+        let totals = payroll::calculate_check(&lines, &[]);
+        let final_pay = payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-03-13".to_string(),
+            employee_id: 2,
+            hours_worked: 16.0,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: Some(payroll::OffCycleReason::FinalPay),
+            imported: None,
+        };
+        final_pay
+            .save_check(&conn, &lines, &[], &[], None)
+            .expect("Failed to save off-cycle check");
+
+        assert!(database::get_payroll_dates_for_employee(&conn, 2)
+            .unwrap()
+            .is_empty());
+        let history = database::get_payroll_by_id(&conn, 2).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].check_type_label(), "Off-cycle: Final pay");
+    }
+
+    fn save_test_check(
+        conn: &Connection,
+        employee_id: i32,
+        date_of_pay: &str,
+        lines: &[EarningLine],
+    ) -> i64 {
+        let totals = payroll::calculate_check(lines, &[]);
+        payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: date_of_pay.to_string(),
+            employee_id,
+            hours_worked: earnings::worked_hours(lines),
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        }
+        .save_check(conn, lines, &[], &[], None)
+        .expect("Failed to save check")
+    }
+
+    #[test]
+    fn test_retro_pay_reprices_checks_since_effective_date() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let mut bonus = EarningLine::new(EarningType::Bonus, 0.0, 20.0);
+        bonus.amount = 100.0;
+        save_test_check(
+            &conn,
+            2,
+            "2026-04-03",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+        save_test_check(
+            &conn,
+            2,
+            "2026-04-10",
+            &[
+                EarningLine::new(EarningType::Regular, 40.0, 20.0),
+                EarningLine::new(EarningType::Overtime, 4.0, 20.0),
+                bonus,
+            ],
+        );
+
+        // Only the second check is affected: 40 hrs * $2 plus 4 OT hrs * $3; the bonus is untouched.
+        let adjustment = retro::approve_retro(&conn, 2, 22.0, "2026-04-06").unwrap();
+        assert_eq!(adjustment.amount, 92.0);
+        assert_eq!(
+            database::get_employee_by_id(&conn, 2).unwrap().pay_rate,
+            "22"
+        );
+        assert_eq!(retro::get_pending_amount(&conn, 2).unwrap(), 92.0);
+
+        let mut retro_line = EarningLine::new(EarningType::Retro, 0.0, 22.0);
+        retro_line.amount = 92.0;
+        assert!(retro_line.supplemental);
+        let payroll_id = save_test_check(&conn, 2, "2026-04-17", &[retro_line]);
+        assert_eq!(retro::get_pending_amount(&conn, 2).unwrap(), 0.0);
+
+        database::delete_payroll_entry(&conn, payroll_id).expect("Failed to delete check");
+        assert_eq!(retro::get_pending_amount(&conn, 2).unwrap(), 92.0);
+
+        // Approving the same raise again pays nothing more; a further raise pays only the step.
+        let again = retro::approve_retro(&conn, 2, 22.0, "2026-04-06").unwrap();
+        assert_eq!(again.amount, 0.0);
+        let further = retro::approve_retro(&conn, 2, 23.0, "2026-04-06").unwrap();
+        assert_eq!(further.amount, 46.0);
+
+        // A check that pays only part of the queue settles only what it covered.
+        assert_eq!(retro::get_pending_amount(&conn, 2).unwrap(), 138.0);
+        let mut partial = EarningLine::new(EarningType::Retro, 0.0, 23.0);
+        partial.amount = 100.0;
+        save_test_check(&conn, 2, "2026-04-24", &[partial]);
+        assert_eq!(retro::get_pending_amount(&conn, 2).unwrap(), 46.0);
+
+        // A rate cut would claw pay back, which retro doesn't do.
+        assert!(retro::approve_retro(&conn, 2, 21.0, "2026-04-06").is_err());
+        assert_eq!(
+            database::get_employee_by_id(&conn, 2).unwrap().pay_rate,
+            "23"
+        );
+    }
+
+    #[test]
+    fn test_retro_for_salaried_employee_uses_their_own_pay_units() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let mut employee = database::get_employee_by_id(&conn, 2).unwrap();
+        employee.pay_type = PayType::SalaryAnnual;
+        employee.pay_rate = "52000".to_string();
+        database::update_pay_setup(&conn, &employee).unwrap();
+        let employee = database::get_employee_by_id(&conn, 2).unwrap();
+
+        // 2026 has 52 Fridays, so a week is 1,000, and overtime at 25/hr is 37.50.
+        let date = NaiveDate::from_ymd_opt(2026, 4, 10).unwrap();
+        let mut salary = EarningLine::new(EarningType::Salary, 40.0, employee.hourly_rate());
+        salary.amount = employee.period_salary(date);
+        let overtime = EarningLine::new(EarningType::Overtime, 2.0, employee.hourly_rate());
+        save_test_check(&conn, 2, "2026-04-10", &[salary, overtime]);
+
+        // A raise to 62,400 a year: 200 more salary plus 2 OT hrs * $7.50 more.
+        let checks = retro::calculate_retro(
+            &SqliteRepository::new(&conn),
+            2,
+            62_400.0,
+            "2026-04-06",
+            &std::collections::HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].hours, 42.0);
+        assert_eq!(retro::total_retro(&checks), 215.0);
+
+        let adjustment = retro::approve_retro(&conn, 2, 62_400.0, "2026-04-06").unwrap();
+        assert_eq!(adjustment.amount, 215.0);
+        let employee = database::get_employee_by_id(&conn, 2).unwrap();
+        assert_eq!(employee.pay_type, PayType::SalaryAnnual);
+        assert_eq!(employee.pay_rate, "62400");
+    }
+
+    #[test]
+    fn test_salary_split_across_pay_calendar_and_prorated() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let mut employee = database::get_employee_by_id(&conn, 1).unwrap();
+        assert_eq!(employee.pay_type, PayType::Hourly);
+        employee.pay_type = PayType::SalaryAnnual;
+        employee.pay_rate = "53000".to_string();
+        employee.flsa_exempt = true;
+        employee.hire_date = "2027-01-13".to_string();
+        employee.termination_date = "2027-03-02".to_string();
+        database::update_pay_setup(&conn, &employee).expect("Failed to update pay setup");
+        let employee = database::get_employee_by_id(&conn, 1).unwrap();
+
+        // 2027 has 53 Fridays, so a full week is 1,000.
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(employee.period_salary(date("2027-02-05")), 1000.0);
+        // Hired on a Wednesday: three of five weekdays.
+        assert_eq!(employee.period_salary(date("2027-01-15")), 600.0);
+        // Terminated on a Tuesday: two of five weekdays, then nothing after.
+        assert_eq!(employee.period_salary(date("2027-03-05")), 400.0);
+        assert_eq!(employee.period_salary(date("2027-03-12")), 0.0);
+        assert!(!employee.overtime_eligible());
+
+        let per_period = Employee {
+            pay_type: PayType::SalaryPerPeriod,
+            pay_rate: "1200".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(per_period.period_salary(date("2027-02-05")), 1200.0);
+        assert_eq!(per_period.hourly_rate(), 30.0);
+        assert!(per_period.overtime_eligible());
+    }
+
+    #[test]
+    fn test_labor_cost_split_by_allocated_location() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let location = |name: &str| Location {
+            name: name.to_string(),
+            active: true,
+            ..Default::default()
+        };
+        let main_street = location("Main Street").save_to_db(&conn).unwrap();
+        let uptown = location("Uptown").save_to_db(&conn).unwrap();
+        locations::set_home_location(&conn, 1, Some(main_street)).unwrap();
+
+        let employees = database::get_all_employees(&conn).unwrap();
+        let at_main = locations::filter_employees(employees.clone(), Some(main_street));
+        assert_eq!(at_main.len(), 1);
+        assert_eq!(at_main[0].id, 1);
+        assert!(locations::filter_employees(employees.clone(), Some(uptown)).is_empty());
+        assert_eq!(locations::filter_employees(employees, None).len(), 2);
+
+        // 30 hours at home, 10 covering a shift uptown.
+        let home = EarningLine::new(EarningType::Regular, 30.0, 20.0);
+        let mut covered = EarningLine::new(EarningType::Regular, 10.0, 20.0);
+        covered.location_id = Some(uptown);
+        let lines = vec![home, covered];
+        let totals = payroll::calculate_check(&lines, &[]);
+        let contributions = vec![retirement::EmployerContribution {
+            id: 0,
+            payroll_id: 0,
+            rule_id: 0,
+            plan: RetirementPlan::Plan401k,
+            description: "Match".to_string(),
+            amount: 40.0,
+        }];
+        payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-05-01".to_string(),
+            employee_id: 1,
+            hours_worked: 40.0,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        }
+        .save_check(&conn, &lines, &[], &contributions, None)
+        .expect("Failed to save check");
+
+        let costs = locations::get_labor_costs(&conn, "2026-05-01", "2026-05-31", None).unwrap();
+        assert_eq!(costs.len(), 2);
+        let cost = |id: i64| costs.iter().find(|c| c.location_id == Some(id)).unwrap();
+        assert_eq!(cost(main_street).hours, 30.0);
+        assert_eq!(cost(main_street).wages, 600.0);
+        assert_eq!(cost(main_street).employer_contributions, 30.0);
+        assert_eq!(cost(uptown).hours, 10.0);
+        assert_eq!(cost(uptown).total(), 210.0);
+    }
+
+    #[test]
+    fn test_companies_scope_employees_and_year_end_totals() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let lines = vec![EarningLine::new(EarningType::Regular, 40.0, 20.0)];
+        let deduction_lines = deductions::calculate_deductions(
+            &[election(DeductionType::Traditional401k, 100.0)],
+            800.0,
+            &std::collections::HashMap::new(),
+        );
+        let totals = payroll::calculate_check(&lines, &deduction_lines);
+        payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-06-05".to_string(),
+            employee_id: 1,
+            hours_worked: 40.0,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        }
+        .save_check(&conn, &lines, &deduction_lines, &[], None)
+        .expect("Failed to save check");
+
+        // The first company takes over everything entered before companies existed.
+        let company = |name: &str, ein: &str| Company {
+            legal_name: name.to_string(),
+            ein: ein.to_string(),
+            ..Default::default()
+        };
+        let downtown = companies::add_company(&conn, &company("Downtown Rx LLC", "12-3456789"))
+            .expect("Failed to add company");
+        let eastside = companies::add_company(&conn, &company("Eastside Rx LLC", "98-7654321"))
+            .expect("Failed to add company");
+        assert_eq!(
+            companies::company_employees(&conn, Some(downtown))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(companies::company_employees(&conn, Some(eastside))
+            .unwrap()
+            .is_empty());
+
+        conn.execute(
+            "UPDATE employees SET company_id = ?1 WHERE id = 2",
+            [eastside],
+        )
+        .unwrap();
+        save_test_check(&conn, 2, "2026-06-05", &lines);
+
+        let summaries = companies::get_year_end_summaries(&conn, downtown, 2026).unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].employee_id, 1);
+        assert_eq!(summaries[0].gross, 800.0);
+        assert_eq!(summaries[0].federal_wages, 700.0);
+        assert_eq!(summaries[0].fica_wages, 800.0);
+        let eastside_summaries = companies::get_year_end_summaries(&conn, eastside, 2026).unwrap();
+        assert_eq!(eastside_summaries.len(), 1);
+        assert_eq!(eastside_summaries[0].employee_id, 2);
+        assert!(companies::get_year_end_summaries(&conn, downtown, 2025)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_employer_profile_validation_and_round_trip() {
+        assert_eq!(companies::normalize_ein("123456789").unwrap(), "12-3456789");
+        assert_eq!(
+            companies::normalize_ein(" 12-3456789 ").unwrap(),
+            "12-3456789"
+        );
+        assert!(companies::normalize_ein("123-456789").is_err());
+        assert!(companies::normalize_ein("12-345678").is_err());
+        assert!(companies::normalize_ein("07-3456789").is_err());
+
+        let mut profile = Company {
+            legal_name: "Med Arts Pharmacy Inc".to_string(),
+            ein: "12-345678".to_string(),
+            phone: "555-0100".to_string(),
+            state: "Texas".to_string(),
+            zip: "7870".to_string(),
+            suta_rate: 20.0,
+            ..Default::default()
+        };
+        assert_eq!(profile.validate().len(), 5);
+
+        profile.ein = "741234567".to_string();
+        profile.phone = "(512) 555-0100".to_string();
+        profile.state = "tx".to_string();
+        profile.zip = "78701-1234".to_string();
+        profile.suta_rate = 2.7;
+        profile.fiscal_year_start_month = 7;
+        assert!(profile.validate().is_empty());
+        assert_eq!(profile.ein, "74-1234567");
+        assert_eq!(profile.state, "TX");
+
+        let (start, end) = profile.fiscal_year(2027);
+        assert_eq!(start.to_string(), "2026-07-01");
+        assert_eq!(end.to_string(), "2027-06-30");
+
+        let conn = setup_test_db().expect("Failed to create test database");
+        profile.id = companies::add_company(&conn, &profile).unwrap();
+        profile.dba = "Med Arts".to_string();
+        profile.logo = vec![0x89, b'P', b'N', b'G'];
+        profile.update_in_db(&conn).unwrap();
+        let saved = &companies::get_companies(&conn).unwrap()[0];
+        assert_eq!(saved.dba, "Med Arts");
+        assert_eq!(saved.suta_rate, 2.7);
+        assert_eq!(saved.logo.len(), 4);
+        assert_eq!(saved.fiscal_year_start_month, 7);
+    }
+
+    #[test]
+    fn test_pay_run_journal_balances_and_refuses_bad_exports() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let company_id = companies::add_company(
+            &conn,
+            &Company {
+                legal_name: "Med Arts Pharmacy Inc".to_string(),
+                ein: "74-1234567".to_string(),
+                suta_rate: 2.7,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let company = Some(company_id);
+
+        let lines = vec![EarningLine::new(EarningType::Regular, 37.5, 13.33)];
+        let deduction_lines = deductions::calculate_deductions(
+            &[election(DeductionType::Traditional401k, 25.0)],
+            499.88,
+            &std::collections::HashMap::new(),
+        );
+        let rule = EmployerContributionRule {
+            name: "Match".to_string(),
+            company_id: company,
+            ..Default::default()
+        };
+        let rule_id = rule.save_to_db(&conn).unwrap();
+        retirement::set_enrollment(&conn, 1, rule_id, true).unwrap();
+        let contributions = retirement::calculate_contributions(
+            &retirement::get_rules_for_employee(&conn, 1).unwrap(),
+            499.88,
+            &deduction_lines,
+        );
+        let totals = payroll::calculate_check(&lines, &deduction_lines);
+        payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-07-10".to_string(),
+            employee_id: 1,
+            hours_worked: 37.5,
+            gross: totals.gross,
+            withholding: totals.withholding,
+            roth_ira: 0.0,
+            social_security: totals.social_security,
+            net: totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        }
+        .save_check(&conn, &lines, &deduction_lines, &contributions, None)
+        .expect("Failed to save check");
+        save_test_check(
+            &conn,
+            2,
+            "2026-07-10",
+            &[EarningLine::new(EarningType::Regular, 12.25, 17.15)],
+        );
+
+        let journal = ledger::build_journal(&conn, "2026-07-10", company).unwrap();
+        assert!(journal.is_balanced());
+        // 499.88 + 210.09 of wages, employer FICA to match, 2.7% SUTA and a 3% of pay match.
+        assert_eq!(journal.total_debits(), 70997 + 5325 + 1917 + 1500);
+        let file = ledger::journal_file(&journal, ExportFormat::GenericCsv);
+        assert!(file.unwrap_err().to_string().starts_with("Map an account"));
+
+        let mapping = ledger::gl_keys()
+            .iter()
+            .enumerate()
+            .map(|(index, gl_key)| (gl_key.key.clone(), format!("{}", 5000 + index)))
+            .collect();
+        ledger::save_mapping(&conn, company, &mapping).unwrap();
+        let journal = ledger::build_journal(&conn, "2026-07-10", company).unwrap();
+        let iif = ledger::journal_file(&journal, ExportFormat::QuickBooksIif).unwrap();
+        assert!(iif.contains("TRNS\tGENERAL JOURNAL\t07/10/2026\t5000\t709.97\t"));
+        assert!(iif.trim_end().ends_with("ENDTRNS"));
+        let qbo = ledger::journal_file(&journal, ExportFormat::QuickBooksOnlineCsv).unwrap();
+        assert!(qbo
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("PR-2026-07-10,07/10/2026,5000,709.97,,"));
+        assert!(ledger::journal_file(&journal, ExportFormat::GenericCsv).is_ok());
+
+        conn.execute(
+            "UPDATE payroll SET net = net + 0.25 WHERE employee_id = 2",
+            [],
+        )
+        .unwrap();
+        let journal = ledger::build_journal(&conn, "2026-07-10", company).unwrap();
+        assert!(!journal.is_balanced());
+        let refused = ledger::journal_file(&journal, ExportFormat::QuickBooksIif);
+        assert!(refused
+            .unwrap_err()
+            .to_string()
+            .contains("does not balance"));
+    }
+
+    #[test]
+    fn test_pay_run_journal_rounds_each_check_before_totaling() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        // Totaled unrounded across the run, these checks come out a cent apart.
+        for (employee_id, hours, rate) in [(1, 11.7, 17.19), (2, 3.9, 21.13), (3, 5.5, 14.41)] {
+            save_test_check(
+                &conn,
+                employee_id,
+                "2026-08-07",
+                &[EarningLine::new(EarningType::Regular, hours, rate)],
+            );
+        }
+        let journal = ledger::build_journal(&conn, "2026-08-07", None).unwrap();
+        assert!(journal.is_balanced());
+        let amount = |key: &str| {
+            journal
+                .lines
+                .iter()
+                .find(|line| line.key == key)
+                .map(|line| line.debit + line.credit)
+                .unwrap()
+        };
+        assert_eq!(amount("earnings:regular"), 20112 + 8241 + 7925);
+        assert_eq!(amount("tax:federal_withholding"), 4022 + 1648 + 1585);
+        assert_eq!(amount("tax:employee_fica"), 1508 + 618 + 594);
+        // The first check's net is 145.814, but its rounded lines leave 145.82.
+        assert_eq!(amount("net_pay"), 14582 + 5975 + 5746);
+    }
+
+    #[test]
+    fn test_federal_deposit_due_dates_and_next_day_rule() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let liability = |s: &str, federal: f32| Liability {
+            date_of_pay: date(s),
+            federal,
+            state_unemployment: 0.0,
+        };
+
+        // Both January checks are due by the 15th of February, a Sunday, so the Monday.
+        let january = [
+            liability("2026-01-09", 500.0),
+            liability("2026-01-23", 700.0),
+        ];
+        let monthly = deposits::federal_deposits(&january, DepositSchedule::Monthly);
+        assert_eq!(monthly.len(), 1);
+        assert_eq!(monthly[0].amount, 1200.0);
+        assert_eq!(monthly[0].due_date, date("2026-02-16"));
+
+        // A Friday payday is due the following Wednesday.
+        let semiweekly = deposits::federal_deposits(&january, DepositSchedule::Semiweekly);
+        assert_eq!(semiweekly.len(), 2);
+        assert_eq!(semiweekly[0].due_date, date("2026-01-14"));
+        assert_eq!(semiweekly[1].due_date, date("2026-01-28"));
+
+        // Reaching $100,000 makes the month's liability due the next business day, and the
+        // monthly depositor is semiweekly from then on.
+        let march = [
+            liability("2026-03-06", 60_000.0),
+            liability("2026-03-13", 50_000.0),
+            liability("2026-03-20", 1_000.0),
+        ];
+        let next_day = deposits::federal_deposits(&march, DepositSchedule::Monthly);
+        assert_eq!(next_day.len(), 2);
+        assert!(next_day[0].next_day);
+        assert_eq!(next_day[0].amount, 110_000.0);
+        assert_eq!(next_day[0].due_date, date("2026-03-16"));
+        assert!(!next_day[1].next_day);
+        assert_eq!(next_day[1].due_date, date("2026-03-25"));
+    }
+
+    #[test]
+    fn test_tax_deposits_from_payroll_and_recording_payment() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let company_id = companies::add_company(
+            &conn,
+            &Company {
+                legal_name: "Med Arts Pharmacy Inc".to_string(),
+                ein: "74-1234567".to_string(),
+                suta_rate: 2.7,
+                suta_wage_base: 1_000.0,
+                deposit_schedule: DepositSchedule::Semiweekly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let other_company = companies::add_company(
+            &conn,
+            &Company {
+                legal_name: "Westside Drug LLC".to_string(),
+                suta_rate: 2.7,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE employees SET company_id = ?1 WHERE id = 2",
+            [other_company],
+        )
+        .unwrap();
+        save_test_check(
+            &conn,
+            1,
+            "2026-04-03",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+        save_test_check(
+            &conn,
+            2,
+            "2026-04-03",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+
+        // The fixture's 2023 checks have deposits of their own.
+        let deposits_for_2026 = || {
+            deposits::get_deposits(&conn, company_id)
+                .unwrap()
+                .into_iter()
+                .filter(|deposit| deposit.first_pay_date.year() == 2026)
+                .collect::<Vec<_>>()
+        };
+        let due = deposits_for_2026();
+        assert_eq!(due.len(), 2);
+        // 160 withheld plus 60 of FICA from each side.
+        assert_eq!(due[0].kind, DepositKind::Federal);
+        assert_eq!(due[0].amount, 280.0);
+        assert_eq!(due[0].due_date.to_string(), "2026-04-08");
+        assert_eq!(due[1].kind, DepositKind::StateUnemployment);
+        assert_eq!(due[1].amount, 21.6);
+        assert_eq!(due[1].due_date.to_string(), "2026-07-31");
+
+        deposits::record_payment(&conn, company_id, &due[0], "2026-04-07").unwrap();
+        let due = deposits_for_2026();
+        assert_eq!(due[0].outstanding(), 0.0);
+        assert_eq!(due[0].paid_date.as_deref(), Some("2026-04-07"));
+        assert_eq!(due[1].outstanding(), 21.6);
+
+        // SUTA stops once the employee's wages for the year reach the 1,000 wage base.
+        save_test_check(
+            &conn,
+            1,
+            "2026-04-10",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+        let due = deposits_for_2026();
+        assert_eq!(due.last().unwrap().kind, DepositKind::StateUnemployment);
+        assert_eq!(due.last().unwrap().amount, 27.0);
+        // The journal accrues the same capped 5.40 for that run.
+        let journal = ledger::build_journal(&conn, "2026-04-10", Some(company_id)).unwrap();
+        let suta_payable = journal
+            .lines
+            .iter()
+            .find(|line| line.key == "employer_tax:suta_payable")
+            .unwrap();
+        assert_eq!(suta_payable.credit, 540);
+
+        // The other company's check and payments stay with it.
+        let other = deposits::get_deposits(&conn, other_company).unwrap();
+        assert_eq!(other.len(), 2);
+        assert_eq!(other[0].amount, 280.0);
+        assert_eq!(other[0].outstanding(), 280.0);
+    }
+
+    #[test]
+    fn test_backup_rotation_keeps_daily_weekly_and_monthly() {
+        let settings = BackupSettings {
+            keep_daily: 3,
+            keep_weekly: 3,
+            keep_monthly: 2,
+            ..Default::default()
+        };
+        // A backup every day from January through March.
+        let start = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap();
+        let dates: Vec<NaiveDate> = start.iter_days().take(90).collect();
+        let mut keep: Vec<String> = backup::backups_to_keep(&dates, &settings)
+            .into_iter()
+            .map(|date| date.to_string())
+            .collect();
+        keep.sort();
+        assert_eq!(
+            keep,
+            vec![
+                // Newest in February, then the Sundays ending the two weeks before this one.
+                "2026-02-28",
+                "2026-03-22",
+                "2026-03-29",
+                "2026-03-30",
+                "2026-03-31",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encrypted_backup_restores_after_integrity_check() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let dir = std::env::temp_dir().join(format!("med_arts_backups_{}", std::process::id()));
+        let date = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let progress = jobs::Progress::default();
+        assert!(backup::write_backup(&conn, &dir, date, "", &progress).is_err());
+        backup::write_backup(&conn, &dir, date, "correct horse", &progress).unwrap();
+        assert_eq!(progress.get().0, 0.8);
+
+        let backups = backup::list_backups(&dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert!(!std::fs::read(&backups[0].path)
+            .unwrap()
+            .windows(15)
+            .any(|w| w == b"SQLite format 3"));
+
+        let restored = Connection::open_in_memory().unwrap();
+        database::initialize_tables(&restored).unwrap();
+        assert!(backup::restore_backup(&restored, &backups[0], "wrong").is_err());
+        assert!(backup::restore_backup(&restored, &backups[0], "").is_err());
+        audit::record(
+            &restored,
+            "employee",
+            7,
+            AuditAction::Delete,
+            Some(&serde_json::json!({ "name": "Logged after the backup" })),
+            None,
+        )
+        .unwrap();
+        backup::restore_backup(&restored, &backups[0], "correct horse").unwrap();
+        let payroll_rows: i64 = restored
+            .query_row("SELECT COUNT(*) FROM payroll", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(payroll_rows, 2);
+        assert!(restored.execute("DELETE FROM audit_log", []).is_err());
+
+        // The live log keeps what was logged since the backup, then notes the restore.
+        let entries = audit::get_entries(&restored, &AuditFilter::default()).unwrap();
+        assert!(entries.iter().any(|entry| entry.entity_id == 7));
+        assert!(entries.iter().any(|entry| entry.entity == "database"));
+        assert_eq!(audit::verify_chain(&restored).unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_database_encryption_field_sealing_and_key_rotation() {
+        let dir = std::env::temp_dir().join(format!("med_arts_security_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("employees.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            database::initialize_tables(&conn).unwrap();
+            conn.execute(
+                "INSERT INTO employees (name, address, phone) VALUES ('Bob', '456 Main St', '555-1234')",
+                [],
+            )
+            .unwrap();
+        }
+        assert_eq!(security::db_state(&path), DbState::Plaintext);
+
+        security::encrypt_plaintext(&path, "first passphrase").unwrap();
+        assert_eq!(security::db_state(&path), DbState::Encrypted);
+        assert!(security::open_encrypted(&path, "wrong passphrase").is_err());
+
+        let raw_address = |conn: &Connection| -> String {
+            conn.query_row("SELECT address FROM employees", [], |row| row.get(0))
+                .unwrap()
+        };
+        let conn = security::open_encrypted(&path, "first passphrase").unwrap();
+        let keyring = security::unlock(&conn, "first passphrase").unwrap();
+        let sealed = raw_address(&conn);
+        assert!(sealed.starts_with("enc1:"));
+        assert_eq!(keyring.open(&sealed).unwrap(), "456 Main St");
+        assert!(security::unlock(&conn, "wrong passphrase").is_err());
+
+        let rotated =
+            security::rotate_keys(&conn, "first passphrase", "second passphrase").unwrap();
+        assert_ne!(raw_address(&conn), sealed);
+        assert_eq!(rotated.open(&raw_address(&conn)).unwrap(), "456 Main St");
+        // Older keys stay on the ring for backups sealed before the rotation.
+        assert_eq!(rotated.open(&sealed).unwrap(), "456 Main St");
+        drop(conn);
+
+        assert!(security::open_encrypted(&path, "first passphrase").is_err());
+        let conn = security::open_encrypted(&path, "second passphrase").unwrap();
+        let keyring = security::unlock(&conn, "second passphrase").unwrap();
+        assert_eq!(keyring.open(&raw_address(&conn)).unwrap(), "456 Main St");
+        drop(conn);
+
+        // Background jobs open their own keyed connection rather than a plain copy.
+        security::install_database(&path, "second passphrase");
+        let worker = std::thread::spawn(|| {
+            let conn = security::open_worker_connection().unwrap();
+            conn.query_row("SELECT COUNT(*) FROM employees", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap()
+        });
+        assert_eq!(worker.join().unwrap(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ssn_validation_masking_and_duplicates() {
+        assert_eq!(ssn::validate_ssn("123 45 6789").unwrap(), "123-45-6789");
+        for invalid in [
+            "000-12-3456",
+            "666-12-3456",
+            "912-34-5678",
+            "123-00-4567",
+            "123-45-0000",
+            "078-05-1120",
+            "12-345-678",
+            "123-45-678x",
+        ] {
+            assert!(
+                ssn::validate_ssn(invalid).is_err(),
+                "{} should be invalid",
+                invalid
+            );
+        }
+        assert_eq!(ssn::mask_ssn("123-45-6789"), "***-**-6789");
+        assert_eq!(ssn::mask_ssn(""), "Not on file");
+
+        let conn = setup_test_db().expect("Failed to create test database");
+        assert_eq!(ssn::save_ssn(&conn, 1, "123456789").unwrap(), "123-45-6789");
+        // Saving the same number again for the same employee is fine.
+        assert!(ssn::save_ssn(&conn, 1, "123-45-6789").is_ok());
+        assert_eq!(
+            ssn::save_ssn(&conn, 2, "123-45-6789").unwrap_err(),
+            "SSN is already on file for Bob"
+        );
+        let bob = database::get_employee_by_id(&conn, 1).unwrap();
+        assert_eq!(bob.ssn, "123-45-6789");
+
+        ssn::log_reveal(&conn, 1, "ssn", "owner").unwrap();
+        let reveals: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sensitive_access_log WHERE employee_id = 1 AND username = 'owner'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(reveals, 1);
+    }
+
+    #[test]
+    fn test_users_login_roles_and_last_owner() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let owner = users::add_user(&conn, "dana", "owner password", Role::Owner).unwrap();
+        let clerk = users::add_user(&conn, "sam", "clerk password", Role::ReadOnly).unwrap();
+        assert!(users::add_user(&conn, "Dana", "another password", Role::Manager).is_err());
+        assert!(users::add_user(&conn, "kim", "short", Role::Manager).is_err());
+
+        let hash: String = conn
+            .query_row(
+                "SELECT password_hash FROM users WHERE id = ?1",
+                [owner],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert_eq!(
+            users::authenticate(&conn, "DANA", "owner password").map(|user| user.role),
+            Some(Role::Owner)
+        );
+        assert!(users::authenticate(&conn, "dana", "wrong password").is_none());
+
+        assert!(Role::PayrollAdmin.can(Permission::EditPayroll));
+        assert!(!Role::PayrollAdmin.can(Permission::ManageUsers));
+        assert!(Role::Manager.can(Permission::EditEmployees));
+        assert!(!Role::Manager.can(Permission::ViewPayroll));
+        assert!(Role::ReadOnly.can(Permission::ViewPayroll));
+        assert!(!Role::ReadOnly.can(Permission::EditPayroll));
+
+        let mut app = PharmacyApp::new(conn);
+        assert!(!app.can(Permission::ViewPayroll));
+        app.current_user = users::authenticate(&app.conn, "sam", "clerk password");
+        assert!(app.can(Permission::ViewPayroll));
+        assert!(!app.can(Permission::EditPayroll));
+
+        // The only owner can't be demoted or deactivated.
+        assert!(users::update_user(&app.conn, owner, Role::Manager, true).is_err());
+        assert!(users::update_user(&app.conn, owner, Role::Owner, false).is_err());
+        users::update_user(&app.conn, clerk, Role::Owner, true).unwrap();
+        users::update_user(&app.conn, owner, Role::Manager, true).unwrap();
+        users::update_user(&app.conn, clerk, Role::ReadOnly, false).unwrap_err();
+        assert!(users::authenticate(&app.conn, "sam", "clerk password").is_some());
+    }
+
+    #[test]
+    fn test_audit_log_records_changes_and_detects_tampering() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let mut employee = database::get_employee_by_id(&conn, 1).unwrap();
+        employee.pay_rate = "21.5".to_string();
+        database::update_pay_setup(&conn, &employee).unwrap();
+        database::delete_payroll_entry(&conn, 1).unwrap();
+        users::add_user(&conn, "dana", "owner password", Role::Owner).unwrap();
+
+        let entries = audit::get_entries(&conn, &AuditFilter::default()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].actor, "system");
+        assert!(entries[0].before_json.contains("\"pay_rate\""));
+        assert!(entries[0].after_json.contains("21.5"));
+        assert_eq!(entries[1].entity, "payroll");
+        assert_eq!(entries[1].action, AuditAction::Delete);
+        assert!(entries[1].after_json.is_empty());
+        assert_eq!(entries[2].prev_hash, entries[1].hash);
+
+        let deletes = AuditFilter {
+            action: Some(AuditAction::Delete),
+            ..Default::default()
+        };
+        assert_eq!(audit::get_entries(&conn, &deletes).unwrap().len(), 1);
+        let csv = audit::audit_csv(&entries).unwrap();
+        assert!(csv.starts_with("ID,Time,Actor,Entity,Entity ID,Action,Before,After,Hash"));
+
+        assert_eq!(audit::verify_chain(&conn).unwrap(), None);
+        assert!(conn
+            .execute("UPDATE audit_log SET actor = 'someone' WHERE id = 1", [])
+            .is_err());
+        assert!(conn
+            .execute("DELETE FROM audit_log WHERE id = 1", [])
+            .is_err());
+
+        // Editing the file directly gets past the triggers but not the hash chain.
+        conn.execute_batch("DROP TRIGGER audit_log_no_update")
+            .unwrap();
+        conn.execute(
+            "UPDATE audit_log SET after_json = '{}' WHERE id = ?1",
+            [entries[1].id],
+        )
+        .unwrap();
+        assert_eq!(audit::verify_chain(&conn).unwrap(), Some(entries[1].id));
+    }
+
+    #[test]
+    fn test_audit_log_covers_employer_and_benefit_changes() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let mut company = Company {
+            legal_name: "Main Street Pharmacy LLC".to_string(),
+            ein: "12-3456789".to_string(),
+            ..Default::default()
+        };
+        company.id = companies::add_company(&conn, &company).unwrap();
+        company.ein = "98-7654321".to_string();
+        company.update_in_db(&conn).unwrap();
+        let location_id = Location {
+            name: "Main Street".to_string(),
+            active: true,
+            ..Default::default()
+        }
+        .save_to_db(&conn)
+        .unwrap();
+        locations::set_home_location(&conn, 1, Some(location_id)).unwrap();
+        let order_id = GarnishmentOrder {
+            employee_id: 1,
+            agency: "State Disbursement Unit".to_string(),
+            ..Default::default()
+        }
+        .save_to_db(&conn)
+        .unwrap();
+        garnishments::set_order_active(&conn, order_id, false).unwrap();
+        let policy_id = pto::PtoPolicy::default().save_to_db(&conn).unwrap();
+        pto::set_enrollment(&conn, 1, policy_id, true).unwrap();
+        // Enrolling twice changes nothing, so it logs nothing.
+        pto::set_enrollment(&conn, 1, policy_id, true).unwrap();
+        pto::delete_policy(&conn, policy_id).unwrap();
+
+        let entries = audit::get_entries(&conn, &AuditFilter::default()).unwrap();
+        let logged: Vec<(&str, AuditAction)> = entries
+            .iter()
+            .map(|entry| (entry.entity.as_str(), entry.action))
+            .collect();
+        assert_eq!(
+            logged,
+            [
+                ("company", AuditAction::Insert),
+                ("company", AuditAction::Update),
+                ("company", AuditAction::Update),
+                ("location", AuditAction::Insert),
+                ("employee", AuditAction::Update),
+                ("garnishment_order", AuditAction::Insert),
+                ("garnishment_order", AuditAction::Update),
+                ("pto_policy", AuditAction::Insert),
+                ("pto_enrollment", AuditAction::Insert),
+                ("pto_policy", AuditAction::Delete),
+            ]
+        );
+        // The first company takes over the employees saved before it existed.
+        assert!(entries[1].after_json.contains("\"employees\":2"));
+        assert!(entries[2].before_json.contains("12-3456789"));
+        assert!(entries[2].after_json.contains("98-7654321"));
+        assert!(entries[4].after_json.contains(&location_id.to_string()));
+        assert!(entries[6].before_json.contains("\"active\":1"));
+        assert!(entries[6].after_json.contains("\"active\":0"));
+        assert!(entries[9].after_json.is_empty());
+        assert_eq!(audit::verify_chain(&conn).unwrap(), None);
+    }
+
+    #[test]
+    fn test_idle_session_locks_and_unlocks_with_password_or_pin() {
+        let conn = setup_test_db().expect("Failed to create test database");
+        let id = users::add_user(&conn, "dana", "owner password", Role::Owner).unwrap();
+        assert!(users::set_pin(&conn, id, "12a4").is_err());
+        assert!(users::set_pin(&conn, id, "123").is_err());
+        users::set_pin(&conn, id, "2468").unwrap();
+        assert!(users::verify_unlock(&conn, id, "owner password"));
+        assert!(users::verify_unlock(&conn, id, "2468"));
+        assert!(!users::verify_unlock(&conn, id, "1357"));
+        users::set_pin(&conn, id, "").unwrap();
+        assert!(!users::verify_unlock(&conn, id, "2468"));
+
+        let now = Instant::now();
+        let eleven_minutes_ago = now - Duration::from_secs(11 * 60);
+        assert!(session::is_idle(eleven_minutes_ago, now, 10));
+        assert!(!session::is_idle(eleven_minutes_ago, now, 15));
+        assert!(!session::is_idle(eleven_minutes_ago, now, 0));
+
+        let mut app = PharmacyApp::new(conn);
+        app.ui_settings.idle_lock_minutes = 10;
+        app.current_user = users::authenticate(&app.conn, "dana", "owner password");
+        app.show_add_employee_popup = true;
+        app.employee_name = "Half typed".to_string();
+        let ctx = egui::Context::default();
+        session::track_activity(&mut app, &ctx);
+        assert!(!app.session.locked);
+
+        app.session.last_activity = eleven_minutes_ago;
+        session::track_activity(&mut app, &ctx);
+        assert!(app.session.locked);
+        assert!(app.show_add_employee_popup);
+        assert_eq!(app.employee_name, "Half typed");
+
+        // Someone else signing in at the lock screen doesn't inherit the open forms.
+        app.sign_out();
+        assert!(app.current_user.is_none());
+        assert!(!app.session.locked);
+        assert!(!app.show_add_employee_popup);
+        assert!(app.employee_name.is_empty());
+    }
+
+    /// The same checks run against both repositories, so they can't drift apart.
+    fn exercise_repository(repo: &(impl EmployeeRepository + PayrollRepository)) {
+        let id = repo
+            .add_employee(&Employee {
+                name: "Pat".to_string(),
+                position: "Technician".to_string(),
+                pay_rate: "20".to_string(),
+                ..Default::default()
+            })
+            .unwrap() as i32;
+        let mut pat = repo.employee(id).unwrap();
+        assert_eq!(pat.name, "Pat");
+        pat.hire_date = "2026-01-05".to_string();
+        repo.update_pay_setup(&pat).unwrap();
+        assert_eq!(repo.employee(id).unwrap().hire_date, "2026-01-05");
+        assert!(repo.employees().unwrap().iter().any(|e| e.id == id));
+        assert!(repo.employee(9999).is_err());
+
+        let check = |date: &str, off_cycle_reason| payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: date.to_string(),
+            employee_id: id,
+            hours_worked: 40.0,
+            gross: 800.0,
+            withholding: 0.0,
+            roth_ira: 0.0,
+            social_security: 0.0,
+            net: 800.0,
+            pto_hours: 0.0,
+            off_cycle_reason,
+            imported: None,
+        };
+        let lines = [EarningLine::new(EarningType::Regular, 40.0, 20.0)];
+        let later = repo
+            .save_check(&check("2026-04-10", None), &lines, &[], &[], None)
+            .unwrap();
+        repo.save_check(&check("2026-04-03", None), &lines, &[], &[], None)
+            .unwrap();
+        repo.save_check(
+            &check("2026-04-10", Some(payroll::OffCycleReason::Bonus)),
+            &[],
+            &[],
+            &[],
+            None,
+        )
+        .unwrap();
+
+        let dates: Vec<String> = repo
+            .checks_for_employee(id)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.date_of_pay)
+            .collect();
+        assert_eq!(dates, ["2026-04-03", "2026-04-10", "2026-04-10"]);
+        let mut used = repo.pay_dates_for_employee(id).unwrap();
+        used.sort();
+        assert_eq!(used, ["2026-04-03", "2026-04-10"]);
+        assert_eq!(repo.earnings_for_check(later).unwrap()[0].payroll_id, later);
+
+        let checks = retro::calculate_retro(
+            repo,
+            id,
+            22.0,
+            "2026-04-06",
+            &std::collections::HashMap::new(),
+        )
+        .unwrap();
+        // Both checks paid on the 10th are repriced: 2 x 40 hrs x $2.
+        assert_eq!(retro::total_retro(&checks), 160.0);
+
+        repo.delete_check(later).unwrap();
+        assert_eq!(repo.checks_for_employee(id).unwrap().len(), 2);
+
+        let imported = repo
+            .save_check(
+                &payroll::PayrollEntry {
+                    imported: Some(ImportedAs::YtdBalance),
+                    ..check("2026-04-24", None)
+                },
+                &lines,
+                &[],
+                &[],
+                None,
+            )
+            .unwrap();
+        assert!(repo.delete_check(imported).is_err());
+        assert_eq!(repo.checks_for_employee(id).unwrap().len(), 3);
+        assert_eq!(repo.pay_dates_for_employee(id).unwrap().len(), 1);
+        // Imported history was paid before this system and never gets retro.
+        let checks = retro::calculate_retro(
+            repo,
+            id,
+            22.0,
+            "2026-04-06",
+            &std::collections::HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(retro::total_retro(&checks), 80.0);
+    }
+
+    #[test]
+    fn test_check_is_priced_from_the_repository_alone() {
+        let repo = InMemoryRepository::default();
+        let id = repo
+            .add_employee(&Employee {
+                name: "Pat".to_string(),
+                pay_rate: "25".to_string(),
+                ..Default::default()
+            })
+            .unwrap() as i32;
+        repo.add_election(DeductionElection {
+            id: 1,
+            employee_id: id,
+            deduction_type: DeductionType::Traditional401k,
+            method: DeductionMethod::FixedAmount,
+            amount: 100.0,
+            catch_up_eligible: false,
+            family_coverage: false,
+            active: true,
+        });
+        repo.add_garnishment_order(GarnishmentOrder {
+            id: 7,
+            employee_id: id,
+            garnishment_type: GarnishmentType::Creditor,
+            amount_per_check: 100.0,
+            total_owed: Some(150.0),
+            ..Default::default()
+        });
+        repo.enroll(
+            id,
+            EmployerContributionRule {
+                id: 1,
+                name: "Match".to_string(),
+                ..Default::default()
+            },
+        );
+        repo.add_pending_retro(id, 40.0);
+
+        let mut retro_line = EarningLine::new(EarningType::Retro, 0.0, 25.0);
+        retro_line.amount = repo.pending_retro(id).unwrap();
+        let lines = vec![
+            EarningLine::new(EarningType::Regular, 40.0, 25.0),
+            retro_line,
+        ];
+        let first = payroll::price_check(&repo, id, "2026-05-01", &lines, None).unwrap();
+        assert_eq!(first.totals.gross, 1040.0);
+        assert_eq!(first.deductions.len(), 2);
+        assert_eq!(first.deductions[1].amount, 100.0);
+        // 100% match up to 3% of pay.
+        assert_eq!(first.contributions[0].amount, 31.2);
+        let check = payroll::PayrollEntry {
+            id: 0,
+            date_of_pay: "2026-05-01".to_string(),
+            employee_id: id,
+            hours_worked: 40.0,
+            gross: first.totals.gross,
+            withholding: first.totals.withholding,
+            roth_ira: 0.0,
+            social_security: first.totals.social_security,
+            net: first.totals.net,
+            pto_hours: 0.0,
+            off_cycle_reason: None,
+            imported: None,
+        };
+        let first_id = repo
+            .save_check(
+                &check,
+                &first.lines,
+                &first.deductions,
+                &first.contributions,
+                None,
+            )
+            .unwrap();
+        assert_eq!(repo.pending_retro(id).unwrap(), 0.0);
+        assert_eq!(repo.deductions_for_check(first_id).unwrap().len(), 2);
+
+        // Only the 50 left on the order comes out of the next check.
+        let second = payroll::price_check(&repo, id, "2026-05-08", &lines[..1], None).unwrap();
+        assert_eq!(second.deductions[1].amount, 50.0);
+        assert_eq!(repo.deduction_ytd(id, 2026).unwrap()["401k"], 100.0);
+        repo.delete_check(first_id).unwrap();
+        assert_eq!(repo.pending_retro(id).unwrap(), 40.0);
+    }
+
+    #[test]
+    fn test_sqlite_and_in_memory_repositories_behave_alike() {
+        exercise_repository(&InMemoryRepository::default());
+        let conn = setup_test_db().expect("Failed to create test database");
+        exercise_repository(&SqliteRepository::new(&conn));
+    }
+
+    #[test]
+    fn test_data_cache_reuses_queries_until_a_write_and_jobs_report_progress() {
+        let path = std::env::temp_dir().join(format!("med_arts_cache_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        database::initialize_tables(&conn).unwrap();
+        let mut cache = DataCache::default();
+        save_test_check(
+            &conn,
+            1,
+            "2026-01-02",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+        assert_eq!(cache.checks_for_employee(&conn, 1).unwrap().len(), 1);
+        assert!(cache.company_employees(&conn, None).unwrap().is_empty());
+
+        // A write on another connection isn't seen, which shows the answer came from the cache.
+        let other = Connection::open(&path).unwrap();
+        database::insert_employee(
+            &other,
+            &Employee {
+                name: "Pat".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(cache.company_employees(&conn, None).unwrap().is_empty());
+
+        // Any write on the app's connection clears it.
+        save_test_check(
+            &conn,
+            1,
+            "2026-01-09",
+            &[EarningLine::new(EarningType::Regular, 40.0, 20.0)],
+        );
+        assert_eq!(cache.checks_for_employee(&conn, 1).unwrap().len(), 2);
+        assert_eq!(cache.company_employees(&conn, None).unwrap().len(), 1);
+        assert_eq!(
+            cache.available_fridays(&conn, 1),
+            app::unused_fridays(&["2026-01-02".to_string(), "2026-01-09".to_string()])
+        );
+
+        // Other reads, and reads through the cached repository, are held the same way.
+        let orders = |cache: &mut DataCache| {
+            CachedRepository::new(&conn, cache)
+                .garnishment_orders(1)
+                .unwrap()
+                .len()
+        };
+        let location_count = |cache: &mut DataCache| {
+            cache
+                .query(&conn, "locations".to_string(), locations::get_locations)
+                .unwrap()
+                .len()
+        };
+        assert_eq!(orders(&mut cache), 0);
+        assert_eq!(location_count(&mut cache), 0);
+        GarnishmentOrder {
+            employee_id: 1,
+            ..Default::default()
+        }
+        .save_to_db(&other)
+        .unwrap();
+        Location {
+            name: "Main Street".to_string(),
+            ..Default::default()
+        }
+        .save_to_db(&other)
+        .unwrap();
+        assert_eq!(orders(&mut cache), 0);
+        assert_eq!(location_count(&mut cache), 0);
+        locations::set_home_location(&conn, 1, None).unwrap();
+        assert_eq!(orders(&mut cache), 1);
+        assert_eq!(location_count(&mut cache), 1);
+        drop(other);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+
+        let job = jobs::Job::spawn("Test job", |progress| {
+            progress.set(0.5, "Halfway");
+            Ok("Done".to_string())
+        });
+        while job.result().is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(job.result(), Some(&Ok("Done".to_string())));
+        assert_eq!(job.progress.get(), (0.5, "Halfway".to_string()));
+    }
+
+    #[test]
+    fn test_employee_import_maps_validates_and_inserts_together() {
+        let conn = setup_test_db().unwrap();
+        let table = import::parse_csv(
+            "Employee Name,Job Title,State,Phone Number,Filing Status,Hourly Rate,Notes\n\
+             Alice,Technician,tx,1 (512) 555-1234,MFJ,$22.50,\n\
+             Carol,Cashier,TX,512-555-9999,hoh,15,\n",
+        )
+        .unwrap();
+        let mut mapping = table.guess_mapping(ImportField::guess);
+        assert_eq!(
+            mapping,
+            vec![
+                Some(ImportField::Name),
+                Some(ImportField::Position),
+                Some(ImportField::State),
+                Some(ImportField::Phone),
+                Some(ImportField::FilingStatus),
+                None,
+                None,
+            ]
+        );
+        mapping[5] = Some(ImportField::PayRate);
+
+        let existing = database::get_all_employees(&conn).unwrap();
+        let rows = import::validate_rows(&table, &mapping, &existing, None);
+        assert!(rows.iter().all(|row| row.errors.is_empty()));
+        assert_eq!(rows[0].employee.state, "TX");
+        assert_eq!(rows[0].employee.phone, "(512) 555-1234");
+        assert_eq!(rows[0].employee.filing_status, "Married Filing Jointly");
+        assert_eq!(rows[0].employee.pay_rate, "22.5");
+
+        let bad = import::parse_csv(
+            "Name,Position,State,Phone,Filing Status,Pay Rate\n\
+             bob,Clerk,TX,512-555-0000,single,15\n\
+             Dana,Clerk,ZZ,(987) 654-3210,widowed,-3\n\
+             Erin,,TX,555-1234,single,12\n\
+             Erin,Clerk,TX,512-555-7777,single,12\n",
+        )
+        .unwrap();
+        let rows = import::validate_rows(
+            &bad,
+            &bad.guess_mapping(ImportField::guess),
+            &existing,
+            None,
+        );
+        let errors: Vec<String> = rows.iter().map(|row| row.errors.join("; ")).collect();
+        assert_eq!(errors[0], "bob is already an employee");
+        assert_eq!(
+            errors[1],
+            "ZZ isn't a state code; Unknown filing status widowed; \
+             Pay rate -3 isn't a positive amount; Phone (987) 654-3210 is already on file"
+        );
+        assert_eq!(
+            errors[2],
+            "Phone 555-1234 isn't a 10-digit number; Position is required"
+        );
+        assert_eq!(errors[3], "Erin is already an employee");
+        assert_eq!(rows[3].line, 5);
+
+        // Nothing goes in while any row has errors.
+        assert!(import::import_employees(&conn, &rows).is_err());
+        assert_eq!(database::get_all_employees(&conn).unwrap().len(), 2);
+
+        let rows = import::validate_rows(&table, &mapping, &existing, Some(1));
+        assert_eq!(import::import_employees(&conn, &rows), Ok(2));
+        let employees = database::get_all_employees(&conn).unwrap();
+        let alice = employees.iter().find(|e| e.name == "Alice").unwrap();
+        assert_eq!(alice.company_id, Some(1));
+        assert_eq!(alice.phone, "(512) 555-1234");
+
+        // Importing the same file again finds them all as duplicates.
+        let rows = import::validate_rows(&table, &mapping, &employees, None);
+        assert!(rows.iter().all(|row| !row.errors.is_empty()));
+    }
+
+    #[test]
+    fn test_payroll_history_import_counts_toward_ytd_and_is_locked() {
+        let conn = setup_test_db().unwrap();
+        let employees = database::get_all_employees(&conn).unwrap();
+        let table = import::parse_csv(
+            "Employee,Check Date,Hours,Gross,Federal Withholding,Social Security,401(k),HSA\n\
+             bob,2026-01-09,80,2000,300,150,100,50\n\
+             Bob,2026-01-23,80,2000,300,150,100,50\n",
+        )
+        .unwrap();
+        let mapping = table.guess_mapping(HistoryField::guess);
+        assert_eq!(mapping[1], Some(HistoryField::PayDate));
+        assert_eq!(
+            mapping[6],
+            Some(HistoryField::Deduction(
+                deductions::DeductionType::Traditional401k
+            ))
+        );
+        let rows =
+            import::validate_history(&table, &mapping, &employees, &[], ImportedAs::Check, "");
+        assert!(rows.iter().all(|row| row.errors.is_empty()));
+        assert_eq!(rows[0].entry.employee_id, 1);
+        assert_eq!(rows[0].entry.net, 1400.0);
+
+        let bad = import::parse_csv(
+            "Employee,Pay Date,Gross,Federal Withholding\n\
+             Zed,2026-01-09,100,0\n\
+             Bob,01/09/2026,100,0\n\
+             Bob,2026-01-09,100,150\n",
+        )
+        .unwrap();
+        let bad_rows = import::validate_history(
+            &bad,
+            &bad.guess_mapping(HistoryField::guess),
+            &employees,
+            &[],
+            ImportedAs::Check,
+            "",
+        );
+        let errors: Vec<String> = bad_rows.iter().map(|row| row.errors.join("; ")).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "No employee is named Zed",
+                "Pay date 01/09/2026 isn't YYYY-MM-DD",
+                "Taxes and deductions are more than gross",
+            ]
+        );
+        assert!(import::import_history(&conn, &bad_rows).is_err());
+
+        assert_eq!(import::import_history(&conn, &rows), Ok(2));
+        let ytd = deductions::get_ytd_by_limit_group(&conn, 1, 2026).unwrap();
+        assert_eq!(ytd.get("401k"), Some(&200.0));
+        assert_eq!(ytd.get("hsa"), Some(&100.0));
+
+        // Imported checks don't use up Fridays, create deposits or get a journal here.
+        let repo = SqliteRepository::new(&conn);
+        assert!(!repo
+            .pay_dates_for_employee(1)
+            .unwrap()
+            .contains(&"2026-01-09".to_string()));
+        assert!(ledger::get_pay_dates(&conn, None)
+            .unwrap()
+            .iter()
+            .all(|date| !date.starts_with("2026")));
+        let company = Company {
+            id: companies::add_company(&conn, &Company::default()).unwrap(),
+            suta_rate: 2.7,
+            ..Default::default()
+        };
+        assert!(deposits::get_liabilities(&conn, &company)
+            .unwrap()
+            .iter()
+            .all(|liability| liability.date_of_pay.year() != 2026));
+
+        // They can't be deleted or edited, but can still be assigned to a company.
+        let imported = repo
+            .checks_for_employee(1)
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.imported == Some(ImportedAs::Check))
+            .unwrap();
+        assert_eq!(imported.check_type_label(), "Imported check");
+        assert!(repo.delete_check(imported.id).is_err());
+        assert_eq!(repo.earnings_for_check(imported.id).unwrap().len(), 1);
+        assert!(conn
+            .execute(
+                "UPDATE earnings SET amount = 1 WHERE payroll_id = ?1",
+                [imported.id]
+            )
+            .is_err());
+        assert!(conn
+            .execute(
+                "UPDATE earnings SET payroll_id = ?1 WHERE payroll_id != ?1",
+                [imported.id]
+            )
+            .is_err());
+        conn.execute(
+            "INSERT INTO deduction_lines (payroll_id, code, description, amount)
+             VALUES (?1, 'ROTH', 'Roth IRA', 25)",
+            [imported.id],
+        )
+        .unwrap();
+        assert!(conn
+            .execute(
+                "UPDATE deduction_lines SET amount = 0 WHERE payroll_id = ?1",
+                [imported.id]
+            )
+            .is_err());
+        assert!(conn
+            .execute("UPDATE payroll SET gross = 1 WHERE id = ?1", [imported.id])
+            .is_err());
+        conn.execute(
+            "UPDATE payroll SET company_id = 1 WHERE id = ?1",
+            [imported.id],
+        )
+        .unwrap();
+
+        // Importing the same file again finds every check as a duplicate.
+        let existing = repo.checks_for_employee(1).unwrap();
+        let again = import::validate_history(
+            &table,
+            &mapping,
+            &employees,
+            &existing,
+            ImportedAs::Check,
+            "",
+        );
+        assert!(again.iter().all(|row| !row.errors.is_empty()));
+
+        // YTD balances take the form's date and allow one row per employee and year.
+        let balances =
+            import::parse_csv("Name,YTD Gross,YTD Roth IRA\nRyan,12000,500\nRyan,1,0\n").unwrap();
+        let rows = import::validate_history(
+            &balances,
+            &balances.guess_mapping(HistoryField::guess),
+            &employees,
+            &[],
+            ImportedAs::YtdBalance,
+            "2026-03-31",
+        );
+        assert!(rows[0].errors.is_empty());
+        assert_eq!(rows[0].entry.date_of_pay, "2026-03-31");
+        assert_eq!(rows[0].entry.roth_ira, 500.0);
+        assert_eq!(
+            rows[1].errors,
+            vec!["Ryan already has a YTD balance for 2026"]
+        );
     }
 }
@@ -1,5 +1,5 @@
+use app::security::UnlockApp;
 use eframe::Error;
-use med_arts::app::app::PharmacyApp;
 mod app;
 
 fn main() -> eframe::Result<(), Error> {
@@ -17,6 +17,6 @@ fn main() -> eframe::Result<(), Error> {
     eframe::run_native(
         "Med Arts Payroll",
         options,
-        Box::new(|_cc| Ok(Box::new(PharmacyApp::new()))),
+        Box::new(|_cc| Ok(Box::new(UnlockApp::new()))),
     )
 }